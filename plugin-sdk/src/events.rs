@@ -0,0 +1,399 @@
+//! Typed hook payloads.
+//!
+//! The host sends every event's data as string `values`. Each struct here
+//! is one hook's values with the keys and types documented in
+//! `wit/plugin.wit`; [`Event::parse`] picks the right one for an event.
+
+use crate::{Hook, HookEvent, ServerHook, ServerPreHook};
+
+/// The typed `values` of one hook.
+pub trait Payload: Sized {
+    /// WIT name of the hook, e.g. `ip-change`. `schedule` for schedule hooks.
+    const NAME: &'static str;
+
+    /// Parse from an event's `values`. Unknown keys are ignored.
+    fn from_values(values: &[(String, String)]) -> Result<Self, String>;
+
+    /// Convert back to `values`, omitting absent optional keys.
+    fn to_values(&self) -> Vec<(String, String)>;
+}
+
+/// A field type that can be read from and written to an event value.
+pub trait Value: Sized {
+    /// Parse the value for `key`, or `None` if the key is absent.
+    fn parse(key: &str, value: Option<&str>) -> Result<Self, String>;
+    fn render(&self) -> Option<String>;
+}
+
+impl Value for String {
+    fn parse(key: &str, value: Option<&str>) -> Result<Self, String> {
+        value
+            .map(str::to_string)
+            .ok_or_else(|| format!("missing value '{key}'"))
+    }
+
+    fn render(&self) -> Option<String> {
+        Some(self.clone())
+    }
+}
+
+impl Value for bool {
+    fn parse(key: &str, value: Option<&str>) -> Result<Self, String> {
+        match String::parse(key, value)?.as_str() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            other => Err(format!("value '{key}' is not a boolean: {other}")),
+        }
+    }
+
+    fn render(&self) -> Option<String> {
+        Some(self.to_string())
+    }
+}
+
+macro_rules! number_value {
+    ($($ty:ty),*) => {$(
+        impl Value for $ty {
+            fn parse(key: &str, value: Option<&str>) -> Result<Self, String> {
+                let value = String::parse(key, value)?;
+                value
+                    .parse()
+                    .map_err(|_| format!("value '{key}' is not a number: {value}"))
+            }
+
+            fn render(&self) -> Option<String> {
+                Some(self.to_string())
+            }
+        }
+    )*};
+}
+
+number_value!(u64, i64);
+
+impl<T: Value> Value for Option<T> {
+    fn parse(key: &str, value: Option<&str>) -> Result<Self, String> {
+        value.map(|value| T::parse(key, Some(value))).transpose()
+    }
+
+    fn render(&self) -> Option<String> {
+        self.as_ref().and_then(Value::render)
+    }
+}
+
+fn lookup<'a>(values: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    values
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.as_str())
+}
+
+// The host's hook name table, shared so the two cannot drift apart.
+#[allow(dead_code)]
+mod names {
+    include!("../../src/plugin/hook_names.rs");
+}
+
+/// WIT name of a hook, as in [`Payload::NAME`].
+pub fn hook_name(hook: &Hook) -> &'static str {
+    match hook {
+        Hook::Server(hook) => names::server_hook_name(hook),
+        Hook::ServerPre(hook) => names::pre_hook_name(hook),
+        Hook::Schedule(_) => "schedule",
+    }
+}
+
+macro_rules! payloads {
+    ($(
+        $(#[$meta:meta])*
+        $name:ident = $wit:literal {
+            $($(#[$field_meta:meta])* $field:ident: $ty:ty,)*
+        }
+    )*) => {
+        $(
+            $(#[$meta])*
+            #[derive(Debug, Clone, PartialEq, Eq)]
+            pub struct $name {
+                $($(#[$field_meta])* pub $field: $ty,)*
+            }
+
+            impl Payload for $name {
+                const NAME: &'static str = $wit;
+
+                fn from_values(values: &[(String, String)]) -> Result<Self, String> {
+                    Ok(Self {
+                        $($field: Value::parse(
+                            stringify!($field),
+                            lookup(values, stringify!($field)),
+                        )?,)*
+                    })
+                }
+
+                fn to_values(&self) -> Vec<(String, String)> {
+                    let mut values = Vec::new();
+                    $(if let Some(value) = Value::render(&self.$field) {
+                        values.push((stringify!($field).to_string(), value));
+                    })*
+                    values
+                }
+            }
+        )*
+
+        #[cfg(test)]
+        const PAYLOAD_NAMES: &[&str] = &[$($wit,)*];
+
+        /// A hook event with typed values.
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub enum Event {
+            $($name($name),)*
+        }
+
+        impl Event {
+            /// Parse an event's values according to its hook.
+            pub fn parse(event: &HookEvent) -> Result<Self, String> {
+                match hook_name(&event.hook) {
+                    $($wit => $name::from_values(&event.values).map(Event::$name),)*
+                    name => Err(format!("no payload for hook {name}")),
+                }
+            }
+        }
+    };
+}
+
+payloads! {
+    /// A user's refresh token was used from a new IP address.
+    IpChange = "ip-change" {
+        user_uuid: String,
+        old_ip: String,
+        new_ip: String,
+    }
+
+    /// A new (not yet activated) account was created.
+    UserRegistered = "user-registered" {
+        user_uuid: String,
+        username: String,
+    }
+
+    /// An account was activated by registering or claiming a passkey.
+    UserActivated = "user-activated" {
+        user_uuid: String,
+        username: String,
+        /// `register` or `claim`.
+        method: String,
+    }
+
+    /// A passkey login completed and a session was issued.
+    LoginSucceeded = "login-succeeded" {
+        user_uuid: String,
+        username: String,
+        passkey_id: String,
+        ip: Option<String>,
+    }
+
+    /// A passkey login was rejected.
+    LoginFailed = "login-failed" {
+        reason: String,
+        ip: Option<String>,
+    }
+
+    /// A session was ended via logout.
+    Logout = "logout" {
+        user_uuid: String,
+        jti: String,
+    }
+
+    /// One or all refresh tokens were revoked.
+    TokenRevoked = "token-revoked" {
+        /// Who revoked the tokens.
+        user_uuid: String,
+        /// `single` or `all`.
+        scope: String,
+        count: u64,
+        /// Only for scope `single`.
+        jti: Option<String>,
+    }
+
+    /// An account was deleted.
+    UserDeleted = "user-deleted" {
+        user_uuid: String,
+        username: String,
+        /// The actor; absent for pending-account cleanup.
+        deleted_by: Option<String>,
+    }
+
+    PostCreated = "post-created" {
+        user_uuid: String,
+        post_uuid: String,
+        parent_uuid: Option<String>,
+        encrypted: bool,
+    }
+
+    PostUpdated = "post-updated" {
+        user_uuid: String,
+        post_uuid: String,
+        revision: u64,
+    }
+
+    PostMoved = "post-moved" {
+        user_uuid: String,
+        post_uuid: String,
+        parent_uuid: Option<String>,
+        position: i64,
+    }
+
+    PostDeleted = "post-deleted" {
+        user_uuid: String,
+        post_uuid: String,
+        children_deleted: u64,
+    }
+
+    AttachmentUploaded = "attachment-uploaded" {
+        user_uuid: String,
+        attachment_uuid: String,
+        size: u64,
+        encrypted: bool,
+        kind: String,
+        mime_type: Option<String>,
+        /// An identical image already existed and its UUID was returned.
+        reused: bool,
+    }
+
+    /// An attachment was removed because no post references it anymore.
+    AttachmentDeleted = "attachment-deleted" {
+        user_uuid: String,
+        attachment_uuid: String,
+    }
+
+    /// A passkey was verified; a session is about to be issued.
+    PreLogin = "pre-login" {
+        user_uuid: String,
+        username: String,
+        ip: Option<String>,
+    }
+
+    /// A new account is about to be created.
+    PreRegister = "pre-register" {
+        username: String,
+        ip: Option<String>,
+    }
+
+    /// An attachment is about to be stored.
+    PreUpload = "pre-upload" {
+        user_uuid: String,
+        size: u64,
+        encrypted: bool,
+        kind: String,
+        mime_type: Option<String>,
+    }
+
+    /// An expired access token is about to be renewed from a refresh token.
+    PreTokenRefresh = "pre-token-refresh" {
+        user_uuid: String,
+        username: String,
+        ip: String,
+        old_ip: Option<String>,
+    }
+
+    /// A schedule hook fired.
+    Scheduled = "schedule" {
+        /// Unix seconds of the planned run time.
+        scheduled_at: u64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HookTarget, Schedule};
+
+    fn values(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parse_event_by_hook() {
+        let event = HookEvent {
+            hook: Hook::Server(ServerHook::IpChange),
+            time: 0,
+            target: HookTarget::Server,
+            values: values(&[
+                ("user_uuid", "u"),
+                ("old_ip", "1.1.1.1"),
+                ("new_ip", "2.2.2.2"),
+            ]),
+        };
+        assert_eq!(
+            Event::parse(&event).unwrap(),
+            Event::IpChange(IpChange {
+                user_uuid: "u".into(),
+                old_ip: "1.1.1.1".into(),
+                new_ip: "2.2.2.2".into(),
+            })
+        );
+
+        let event = HookEvent {
+            hook: Hook::Schedule(Schedule::Interval(60)),
+            time: 0,
+            target: HookTarget::Schedule,
+            values: values(&[("scheduled_at", "1700000000")]),
+        };
+        assert_eq!(
+            Event::parse(&event).unwrap(),
+            Event::Scheduled(Scheduled {
+                scheduled_at: 1_700_000_000
+            })
+        );
+    }
+
+    #[test]
+    fn every_payload_names_a_hook() {
+        for &name in PAYLOAD_NAMES {
+            let hook = match name {
+                "schedule" => Hook::Schedule(Schedule::Interval(60)),
+                _ => names::parse_server_hook(name)
+                    .map(Hook::Server)
+                    .or_else(|| names::parse_pre_hook(name).map(Hook::ServerPre))
+                    .unwrap_or_else(|| panic!("no hook named {name}")),
+            };
+            assert_eq!(hook_name(&hook), name);
+        }
+    }
+
+    #[test]
+    fn optional_and_typed_values() {
+        let created = PostCreated::from_values(&values(&[
+            ("user_uuid", "u"),
+            ("post_uuid", "p"),
+            ("encrypted", "true"),
+        ]))
+        .unwrap();
+        assert_eq!(created.parent_uuid, None);
+        assert!(created.encrypted);
+
+        let err = PostCreated::from_values(&values(&[
+            ("user_uuid", "u"),
+            ("post_uuid", "p"),
+            ("encrypted", "yes"),
+        ]))
+        .unwrap_err();
+        assert!(err.contains("encrypted"), "got: {err}");
+
+        let err = PostUpdated::from_values(&values(&[("user_uuid", "u")])).unwrap_err();
+        assert!(err.contains("missing value 'post_uuid'"), "got: {err}");
+    }
+
+    #[test]
+    fn to_values_round_trip() {
+        let revoked = TokenRevoked {
+            user_uuid: "u".into(),
+            scope: "all".into(),
+            count: 3,
+            jti: None,
+        };
+        let values = revoked.to_values();
+        assert_eq!(values.len(), 3, "absent optional keys are omitted");
+        assert_eq!(TokenRevoked::from_values(&values).unwrap(), revoked);
+    }
+}
@@ -0,0 +1,55 @@
+//! Write Crowchiper plugins in Rust.
+//!
+//! The crate generates the bindings for the `plugin` world in
+//! `wit/plugin.wit`, so a plugin only implements [`Guest`] and exports it:
+//!
+//! ```ignore
+//! use crowchiper_plugin_sdk::events::{Event, IpChange};
+//! use crowchiper_plugin_sdk::*;
+//!
+//! struct Notify;
+//!
+//! impl Guest for Notify {
+//!     fn config(_vars: Vec<(String, String)>) -> PluginConfig {
+//!         PluginConfig {
+//!             name: "notify".to_string(),
+//!             version: "1.0.0".to_string(),
+//!             target: HookTarget::Server,
+//!             hooks: vec![Hook::Server(ServerHook::IpChange)],
+//!             http: None,
+//!         }
+//!     }
+//!
+//!     fn on_hook(event: HookEvent) -> Result<(), String> {
+//!         if let Event::IpChange(IpChange { user_uuid, new_ip, .. }) = Event::parse(&event)? {
+//!             info!("{user_uuid} is now at {new_ip}");
+//!         }
+//!         Ok(())
+//!     }
+//!
+//!     fn http_handler(_req: HttpRequest) -> Result<HttpResponse, String> {
+//!         Err("no http routes".to_string())
+//!     }
+//! }
+//!
+//! export_plugin!(Notify);
+//! ```
+//!
+//! Build with `cargo build --target wasm32-wasip2 --release` and load the
+//! `.wasm` with `--plugin`. With the `testing` feature, [`testing`] runs the
+//! built plugin in the server's own runtime from ordinary `cargo test`s.
+
+wit_bindgen::generate!({
+    world: "plugin",
+    path: "../wit/plugin.wit",
+    pub_export_macro: true,
+    export_macro_name: "export_plugin",
+    default_bindings_module: "crowchiper_plugin_sdk",
+});
+
+pub use self::crowchiper::plugin::{http, kv};
+
+pub mod events;
+pub mod logging;
+#[cfg(feature = "testing")]
+pub mod testing;
@@ -0,0 +1,51 @@
+//! Logging facade over the host `log` import.
+//!
+//! Messages show up in the server log attributed to the plugin. Use the
+//! [`debug!`](crate::debug), [`info!`](crate::info), [`warn!`](crate::warn)
+//! and [`error!`](crate::error) macros for `format!`-style messages.
+
+use crate::LogLevel;
+
+pub fn debug(msg: &str) {
+    crate::log(LogLevel::Debug, msg);
+}
+
+pub fn info(msg: &str) {
+    crate::log(LogLevel::Info, msg);
+}
+
+pub fn warn(msg: &str) {
+    crate::log(LogLevel::Warn, msg);
+}
+
+pub fn error(msg: &str) {
+    crate::log(LogLevel::Error, msg);
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::logging::debug(&::std::format!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::logging::info(&::std::format!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        $crate::logging::warn(&::std::format!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::logging::error(&::std::format!($($arg)*))
+    };
+}
@@ -0,0 +1,123 @@
+//! Run a built plugin in the server's runtime from `cargo test`.
+//!
+//! ```ignore
+//! use crowchiper_plugin_sdk::events::PreLogin;
+//! use crowchiper_plugin_sdk::testing::TestPlugin;
+//!
+//! #[tokio::test]
+//! async fn denies_mallory() {
+//!     let plugin = TestPlugin::load("target/wasm32-wasip2/release/my_plugin.wasm")
+//!         .await
+//!         .unwrap();
+//!     let err = plugin
+//!         .fire(PreLogin {
+//!             user_uuid: "u".into(),
+//!             username: "mallory".into(),
+//!             ip: None,
+//!         })
+//!         .await
+//!         .unwrap_err();
+//!     assert!(err.to_string().contains("mallory"));
+//! }
+//! ```
+
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ::crowchiper::db::Database;
+use ::crowchiper::plugin::{
+    Hook, HookEvent, HookTarget, LoadOptions, PluginError, PluginRuntime, PluginSpec,
+    parse_plugin_spec,
+};
+
+use crate::events::Payload;
+
+/// A plugin loaded the way the server loads it, with a scratch in-memory
+/// key-value store.
+pub struct TestPlugin {
+    runtime: PluginRuntime,
+    db: Database,
+}
+
+impl TestPlugin {
+    /// Load the component at `path` with no permissions and default limits.
+    pub async fn load(path: impl AsRef<Path>) -> Result<Self, PluginError> {
+        let path = path.as_ref().display().to_string();
+        Self::load_spec(&path).await
+    }
+
+    /// Load a plugin from a `--plugin` spec, e.g. `plugin.wasm:net=example.com`.
+    pub async fn load_spec(spec: &str) -> Result<Self, PluginError> {
+        let spec = parse_plugin_spec(spec).map_err(PluginError::Load)?;
+        Self::load_parsed(&spec).await
+    }
+
+    async fn load_parsed(spec: &PluginSpec) -> Result<Self, PluginError> {
+        let db = Database::open(":memory:")
+            .await
+            .map_err(|e| PluginError::Load(format!("failed to open scratch database: {e}")))?;
+        let runtime = PluginRuntime::load_with_limits(
+            &spec.path,
+            &spec.permissions,
+            &spec.config,
+            spec.hook_timeout,
+            spec.limits,
+            &LoadOptions::default(),
+        )
+        .await?
+        .with_fail_closed(&spec.fail_closed)
+        .with_breaker(spec.breaker)
+        .with_kv(db.clone(), spec.kv_quota);
+        Ok(Self { runtime, db })
+    }
+
+    /// Fire the hook matching `P` with `payload` as its values.
+    ///
+    /// Like the server, only hooks the plugin registered for are delivered;
+    /// any other hook is an error. A pre-hook denial is [`PluginError::Hook`].
+    pub async fn fire<P: Payload>(&self, payload: P) -> Result<(), PluginError> {
+        self.fire_values(P::NAME, payload.to_values()).await
+    }
+
+    /// Fire the hook named `name` (e.g. `ip-change`) with raw values.
+    pub async fn fire_values(
+        &self,
+        name: &str,
+        values: Vec<(String, String)>,
+    ) -> Result<(), PluginError> {
+        let hook = self.runtime.registered_hook(name).cloned().ok_or_else(|| {
+            PluginError::Runtime(format!(
+                "plugin {} does not register hook {name}",
+                self.runtime.name()
+            ))
+        })?;
+        let target = match hook {
+            Hook::Schedule(_) => HookTarget::Schedule,
+            Hook::Server(_) | Hook::ServerPre(_) => HookTarget::Server,
+        };
+        let event = HookEvent {
+            hook,
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            target,
+            values,
+        };
+        self.runtime.call_hook(&event).await
+    }
+
+    /// Read a key the plugin stored through the `kv` import.
+    pub async fn kv_get(&self, key: &str) -> Option<Vec<u8>> {
+        self.db
+            .plugin_kv()
+            .get(self.runtime.name(), key)
+            .await
+            .expect("scratch kv store failed")
+    }
+
+    /// The underlying runtime, e.g. for its stats or config.
+    pub fn runtime(&self) -> &PluginRuntime {
+        &self.runtime
+    }
+}
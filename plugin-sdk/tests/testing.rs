@@ -0,0 +1,55 @@
+//! Tests for the `testing` harness, run against the server's test plugins.
+//! Build them first with `tests/plugins/build.sh`.
+
+use std::path::PathBuf;
+
+use crowchiper_plugin_sdk::events::{IpChange, PreLogin, UserRegistered};
+use crowchiper_plugin_sdk::testing::TestPlugin;
+
+fn wasm_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("../tests/plugins/wasm")
+        .join(format!("{name}.wasm"))
+}
+
+#[tokio::test]
+async fn test_fire_typed_payload() {
+    let plugin = TestPlugin::load(wasm_path("hook-echo")).await.unwrap();
+    plugin
+        .fire(IpChange {
+            user_uuid: "user-1".into(),
+            old_ip: "1.1.1.1".into(),
+            new_ip: "2.2.2.2".into(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(plugin.runtime().stats().calls(), 1);
+}
+
+#[tokio::test]
+async fn test_pre_hook_denial() {
+    let plugin = TestPlugin::load(wasm_path("pre-hook")).await.unwrap();
+    let login = |username: &str| PreLogin {
+        user_uuid: "user-1".into(),
+        username: username.into(),
+        ip: Some("127.0.0.1".into()),
+    };
+
+    plugin.fire(login("alice")).await.unwrap();
+    let err = plugin.fire(login("mallory")).await.unwrap_err();
+    assert!(err.to_string().contains("mallory"), "got: {err}");
+}
+
+#[tokio::test]
+async fn test_unregistered_hook_is_not_delivered() {
+    let plugin = TestPlugin::load(wasm_path("pre-hook")).await.unwrap();
+    let err = plugin
+        .fire(UserRegistered {
+            user_uuid: "user-1".into(),
+            username: "alice".into(),
+        })
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("user-registered"), "got: {err}");
+    assert_eq!(plugin.runtime().stats().calls(), 0);
+}
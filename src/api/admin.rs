@@ -2,14 +2,24 @@
 //!
 //! All endpoints require admin role.
 
-use axum::{Json, Router, extract::State, response::IntoResponse, routing::get};
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::{get, post, put},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use super::error::{ApiError, ResultExt};
+use super::quota::{UsageResponse, usage_response};
 use crate::auth::{AdminOnly, Auth, ServerSettings};
 use crate::db::Database;
 use crate::impl_has_auth_backend;
 use crate::jwt::JwtConfig;
+use crate::plugin::{CallStats, PluginError, PluginManager, PluginRuntime, parse_plugin_spec};
 
 /// State for admin endpoints.
 #[derive(Clone)]
@@ -24,6 +34,13 @@ impl_has_auth_backend!(AdminState);
 pub fn router(state: AdminState) -> Router {
     Router::new()
         .route("/users", get(list_users))
+        .route("/users/{uuid}/usage", get(get_user_usage))
+        .route("/users/{uuid}/quota", put(set_user_quota))
+        .route("/quota", get(get_default_quota).put(set_default_quota))
+        .route("/plugins", get(list_plugins).post(load_plugin))
+        .route("/plugins/{name}/enable", post(enable_plugin))
+        .route("/plugins/{name}/disable", post(disable_plugin))
+        .route("/plugins/{name}/reload", post(reload_plugin))
         .with_state(state)
 }
 
@@ -41,3 +58,334 @@ async fn list_users(
 
     Ok(Json(users))
 }
+
+#[derive(Serialize, Deserialize)]
+struct DefaultQuota {
+    /// Quota in bytes of users without their own, null for unlimited
+    default_quota: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct SetUserQuotaRequest {
+    /// Quota in bytes, null to use the default
+    quota: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct UserUsageResponse {
+    #[serde(flatten)]
+    usage: UsageResponse,
+    /// The user's own quota, null if the default applies
+    user_quota: Option<u64>,
+}
+
+/// Get the default storage quota.
+async fn get_default_quota(
+    State(state): State<AdminState>,
+    _auth: Auth<AdminOnly>,
+) -> Result<impl IntoResponse, ApiError> {
+    let default_quota = state
+        .db
+        .quotas()
+        .default_quota()
+        .await
+        .db_err("Failed to get default quota")?;
+    Ok(Json(DefaultQuota { default_quota }))
+}
+
+/// Set the storage quota of users without their own.
+async fn set_default_quota(
+    State(state): State<AdminState>,
+    auth: Auth<AdminOnly>,
+    Json(payload): Json<DefaultQuota>,
+) -> Result<impl IntoResponse, ApiError> {
+    state
+        .db
+        .quotas()
+        .set_default_quota(payload.default_quota)
+        .await
+        .db_err("Failed to set default quota")?;
+    tracing::info!(quota = ?payload.default_quota, admin = %auth.claims.sub, "Default storage quota changed by admin");
+    Ok(Json(payload))
+}
+
+/// Look up a user's id by UUID.
+async fn user_id(state: &AdminState, uuid: &str) -> Result<i64, ApiError> {
+    let user = state
+        .db
+        .users()
+        .get_by_uuid(uuid)
+        .await
+        .db_err("Failed to get user")?
+        .ok_or_else(|| ApiError::not_found("User not found"))?;
+    Ok(user.id)
+}
+
+async fn user_usage(state: &AdminState, user_id: i64) -> Result<UserUsageResponse, ApiError> {
+    let user_quota = state
+        .db
+        .quotas()
+        .user_quota(user_id)
+        .await
+        .db_err("Failed to get quota")?;
+    Ok(UserUsageResponse {
+        usage: usage_response(&state.db, user_id).await?,
+        user_quota,
+    })
+}
+
+/// Get a user's storage usage and quota.
+async fn get_user_usage(
+    State(state): State<AdminState>,
+    _auth: Auth<AdminOnly>,
+    Path(uuid): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let user_id = user_id(&state, &uuid).await?;
+    Ok(Json(user_usage(&state, user_id).await?))
+}
+
+/// Set a user's own storage quota, or clear it to use the default.
+async fn set_user_quota(
+    State(state): State<AdminState>,
+    auth: Auth<AdminOnly>,
+    Path(uuid): Path<String>,
+    Json(payload): Json<SetUserQuotaRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let user_id = user_id(&state, &uuid).await?;
+    state
+        .db
+        .quotas()
+        .set_user_quota(user_id, payload.quota)
+        .await
+        .db_err("Failed to set quota")?;
+    tracing::info!(user = %uuid, quota = ?payload.quota, admin = %auth.claims.sub, "Storage quota changed by admin");
+    Ok(Json(user_usage(&state, user_id).await?))
+}
+
+#[derive(Serialize)]
+struct PluginInfo {
+    name: String,
+    version: String,
+    enabled: bool,
+    path: String,
+    hooks: Vec<String>,
+    permissions: Vec<String>,
+    calls: u64,
+    errors: u64,
+    timeouts: u64,
+    /// Instances recreated after a timeout.
+    reloads: u64,
+    /// Calls skipped while the circuit breaker was open.
+    rejected: u64,
+    fuel_consumed: u64,
+    /// Unix seconds of the most recent hook or HTTP call.
+    last_call_at: Option<u64>,
+    /// Circuit breaker setting, e.g. `5/60` or `off`.
+    breaker: String,
+    consecutive_failures: u32,
+    /// Unix milliseconds until which calls are skipped, if the breaker is open.
+    circuit_open_until: Option<u64>,
+    /// Counters per hook (`http` for the HTTP handler).
+    calls_by_hook: BTreeMap<String, CallInfo>,
+}
+
+#[derive(Serialize)]
+struct CallInfo {
+    calls: u64,
+    errors: u64,
+    failures: u64,
+    timeouts: u64,
+    fuel_consumed: u64,
+    latency_sum_ms: u64,
+    latency_buckets: Vec<LatencyBucket>,
+}
+
+#[derive(Serialize)]
+struct LatencyBucket {
+    /// Upper bound in milliseconds; `null` for the overflow bucket.
+    le_ms: Option<u64>,
+    count: u64,
+}
+
+impl From<CallStats> for CallInfo {
+    fn from(stats: CallStats) -> Self {
+        Self {
+            calls: stats.calls,
+            errors: stats.errors,
+            failures: stats.failures,
+            timeouts: stats.timeouts,
+            fuel_consumed: stats.fuel_consumed,
+            latency_sum_ms: stats.latency.sum_ms(),
+            latency_buckets: stats
+                .latency
+                .buckets()
+                .map(|(le_ms, count)| LatencyBucket { le_ms, count })
+                .collect(),
+        }
+    }
+}
+
+impl PluginInfo {
+    fn new(plugin: &PluginRuntime, enabled: bool) -> Self {
+        let stats = plugin.stats();
+        Self {
+            name: plugin.name().to_string(),
+            version: plugin.version().to_string(),
+            enabled,
+            path: plugin.path().display().to_string(),
+            hooks: plugin.hooks().iter().map(|h| format!("{h:?}")).collect(),
+            permissions: plugin
+                .permissions()
+                .iter()
+                .map(ToString::to_string)
+                .collect(),
+            calls: stats.calls(),
+            errors: stats.errors(),
+            timeouts: stats.timeouts(),
+            reloads: stats.reloads(),
+            rejected: stats.rejected(),
+            fuel_consumed: stats.fuel_consumed(),
+            last_call_at: stats.last_call_at(),
+            breaker: plugin.breaker().to_string(),
+            consecutive_failures: stats.consecutive_failures(),
+            circuit_open_until: stats.circuit_open_until(),
+            calls_by_hook: stats
+                .by_call()
+                .into_iter()
+                .map(|(label, stats)| (label, stats.into()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct LoadPluginRequest {
+    /// Same format as `--plugin`, with the path relative to `--plugin-dir`.
+    spec: String,
+}
+
+/// Map a plugin management error to a response. Load and config errors are
+/// returned in full since only admins can reach these endpoints.
+fn plugin_error(e: PluginError) -> ApiError {
+    match e {
+        PluginError::NotFound(_) => ApiError::not_found("Plugin not found"),
+        PluginError::AlreadyLoaded(_) => ApiError::conflict(e.to_string()),
+        _ => {
+            tracing::warn!(error = %e, "Plugin management request failed");
+            ApiError::bad_request(e.to_string())
+        }
+    }
+}
+
+fn plugin_manager(state: &AdminState) -> Result<&PluginManager, ApiError> {
+    state
+        .settings
+        .plugin_manager
+        .as_deref()
+        .ok_or_else(|| ApiError::not_found("Plugin not found"))
+}
+
+/// List all loaded plugins, enabled or not.
+async fn list_plugins(
+    State(state): State<AdminState>,
+    _auth: Auth<AdminOnly>,
+) -> Result<impl IntoResponse, ApiError> {
+    let plugins: Vec<PluginInfo> = state
+        .settings
+        .plugin_manager
+        .as_ref()
+        .map(|manager| {
+            manager
+                .list()
+                .iter()
+                .map(|status| PluginInfo::new(&status.plugin, status.enabled))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(Json(plugins))
+}
+
+/// Load a new plugin from the plugin directory.
+async fn load_plugin(
+    State(state): State<AdminState>,
+    auth: Auth<AdminOnly>,
+    Json(payload): Json<LoadPluginRequest>,
+) -> Result<impl IntoResponse, ApiError> {
+    let manager = state
+        .settings
+        .plugin_manager
+        .as_deref()
+        .filter(|manager| manager.plugin_dir().is_some())
+        .ok_or_else(|| ApiError::bad_request("Loading plugins at runtime is not enabled"))?;
+    let spec = parse_plugin_spec(&payload.spec).map_err(ApiError::bad_request)?;
+
+    let plugin = manager
+        .load(&spec, state.db.clone())
+        .await
+        .map_err(plugin_error)?;
+    tracing::info!(
+        plugin = %plugin.name(),
+        version = %plugin.version(),
+        admin = %auth.claims.sub,
+        "Plugin loaded by admin"
+    );
+
+    Ok((StatusCode::CREATED, Json(PluginInfo::new(&plugin, true))))
+}
+
+async fn enable_plugin(
+    State(state): State<AdminState>,
+    auth: Auth<AdminOnly>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    set_enabled(&state, &auth, &name, true).await
+}
+
+async fn disable_plugin(
+    State(state): State<AdminState>,
+    auth: Auth<AdminOnly>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    set_enabled(&state, &auth, &name, false).await
+}
+
+async fn set_enabled(
+    state: &AdminState,
+    auth: &Auth<AdminOnly>,
+    name: &str,
+    enabled: bool,
+) -> Result<Json<PluginInfo>, ApiError> {
+    let manager = plugin_manager(state)?;
+    manager
+        .set_enabled(name, enabled)
+        .await
+        .map_err(plugin_error)?;
+    tracing::info!(plugin = %name, enabled, admin = %auth.claims.sub, "Plugin state changed by admin");
+
+    let status = manager
+        .list()
+        .into_iter()
+        .find(|status| status.plugin.name() == name)
+        .ok_or_else(|| ApiError::not_found("Plugin not found"))?;
+    Ok(Json(PluginInfo::new(&status.plugin, status.enabled)))
+}
+
+/// Reload a plugin from disk. In-flight calls finish on the old instance.
+async fn reload_plugin(
+    State(state): State<AdminState>,
+    auth: Auth<AdminOnly>,
+    Path(name): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let manager = plugin_manager(&state)?;
+    let plugin = manager.reload(&name).await.map_err(plugin_error)?;
+    tracing::info!(
+        plugin = %name,
+        version = %plugin.version(),
+        admin = %auth.claims.sub,
+        "Plugin reloaded by admin"
+    );
+
+    let enabled = manager.get(&name).is_some();
+    Ok(Json(PluginInfo::new(&plugin, enabled)))
+}
@@ -1,62 +1,497 @@
-//! Attachments API for encrypted image uploads.
+//! Attachments API for encrypted image and file uploads.
 //!
 //! All endpoints require JWT authentication.
-//! Uses binary streaming instead of base64 for efficiency.
+//! Uses binary streaming instead of base64 for efficiency. Large attachments
+//! can be sent in resumable chunks, see [`uploads`]. Unencrypted images are
+//! checked and stripped of metadata by the server, which also renders their
+//! thumbnails if the client does not, see [`crate::images`].
+
+mod uploads;
 
 use axum::{
     Router,
     body::Body,
-    extract::{DefaultBodyLimit, Multipart, Path, State},
-    http::{HeaderMap, StatusCode, header},
-    response::IntoResponse,
-    routing::{get, post},
+    extract::{DefaultBodyLimit, Multipart, Path, Query, State, multipart::Field},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::{get, head, post},
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
+use tracing::{error, warn};
 
+use super::download::{attachment_disposition, blob_response};
 use super::error::{ApiError, ResultExt};
+use super::quota::check_quota;
 use crate::auth::{AnyRole, Auth, ServerSettings};
-use crate::db::{Database, attachments::CreateAttachmentInput};
+use crate::db::attachments::{
+    AttachmentKind, AttachmentStore, BlobRef, CreateAttachmentInput, CreateFileAttachmentInput,
+};
+use crate::db::{AttachmentSummary, Database, DeleteAttachmentResult, PostRef};
+use crate::images::{self, ImageError, ThumbnailSize};
 use crate::impl_has_auth_backend;
 use crate::jwt::JwtConfig;
+use crate::plugin::{ServerHook, ServerPreHook, check_server_pre_hook, spawn_server_hook};
 
 /// Encryption version 0 means unencrypted data
 const UNENCRYPTED_VERSION: i32 = 0;
 
+/// MIME type of files uploaded without one.
+const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+/// Longest accepted filename, in bytes. Encrypted names are base64 and
+/// longer than the original.
+const MAX_FILENAME_LENGTH: usize = 1024;
+
+const MAX_MIME_TYPE_LENGTH: usize = 127;
+
+/// Longest accepted IV, in characters. Clients send 12-byte AES-GCM IVs,
+/// 16 characters in base64url.
+const MAX_IV_LENGTH: usize = 64;
+
+/// Maximum number of attachments listed in a single response.
+const MAX_LIST_LIMIT: i64 = 200;
+
+const KB: usize = 1024;
+const MB: usize = 1024 * KB;
+
+/// Maximum upload sizes in bytes, per attachment type.
+#[derive(Debug, Clone)]
+pub struct AttachmentLimits {
+    pub image: usize,
+    /// Frontend targets: sm=100KB, md=200KB, lg=400KB + encryption overhead
+    pub thumb_sm: usize,
+    pub thumb_md: usize,
+    pub thumb_lg: usize,
+    /// Files whose MIME type has no entry in `file_types`
+    pub file: usize,
+    /// Limits by MIME type: an exact type (`application/pdf`) or a whole
+    /// top-level type (`audio/*`). Exact entries win.
+    pub file_types: Vec<(String, usize)>,
+}
+
+impl Default for AttachmentLimits {
+    fn default() -> Self {
+        Self {
+            image: 10 * MB,
+            thumb_sm: 150 * KB,
+            thumb_md: 250 * KB,
+            thumb_lg: 500 * KB,
+            file: 25 * MB,
+            file_types: vec![("audio/*".to_string(), 50 * MB)],
+        }
+    }
+}
+
+impl AttachmentLimits {
+    /// Limit for a file of the given (normalized) MIME type.
+    pub fn for_file(&self, mime_type: &str) -> usize {
+        let wildcard = mime_type
+            .split_once('/')
+            .map(|(kind, _)| format!("{kind}/*"))
+            .unwrap_or_default();
+        let lookup = |pattern: &str| {
+            self.file_types
+                .iter()
+                .find(|(t, _)| t == pattern)
+                .map(|(_, limit)| *limit)
+        };
+        lookup(mime_type)
+            .or_else(|| lookup(&wildcard))
+            .unwrap_or(self.file)
+    }
+
+    /// Largest image or file any type may upload.
+    fn max_data(&self) -> usize {
+        self.file_types
+            .iter()
+            .map(|(_, limit)| *limit)
+            .chain([self.image, self.file])
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Largest request body an upload within these limits can need.
+    fn body_limit(&self) -> usize {
+        // Thumbnails plus 1MB for multipart framing and text fields
+        self.max_data() + self.thumb_sm + self.thumb_md + self.thumb_lg + MB
+    }
+}
+
+/// `10MB`, `150KB` or a plain byte count, for error messages.
+fn format_size(bytes: usize) -> String {
+    if bytes >= MB && bytes % MB == 0 {
+        format!("{}MB", bytes / MB)
+    } else if bytes >= KB && bytes % KB == 0 {
+        format!("{}KB", bytes / KB)
+    } else {
+        format!("{bytes} bytes")
+    }
+}
+
 /// State for attachments endpoints.
 #[derive(Clone)]
 pub struct AttachmentsState {
     pub db: Database,
     pub jwt: Arc<JwtConfig>,
     pub settings: ServerSettings,
+    pub limits: Arc<AttachmentLimits>,
 }
 
 impl_has_auth_backend!(AttachmentsState);
 
 pub fn router(state: AttachmentsState) -> Router {
+    let body_limit = state.limits.body_limit();
     Router::new()
-        .route("/", post(upload_attachment))
-        .route("/{uuid}", get(get_attachment))
+        .route("/", get(list_attachments).post(upload_attachment))
+        .route("/uploads", post(uploads::create_upload))
+        .route(
+            "/uploads/{id}",
+            head(uploads::upload_status)
+                .patch(uploads::upload_chunk)
+                .delete(uploads::abort_upload),
+        )
+        .route("/uploads/{id}/finalize", post(uploads::finalize_upload))
+        .route("/{uuid}", get(get_attachment).delete(delete_attachment))
+        .route("/{uuid}/info", get(get_attachment_info))
+        .route("/{uuid}/posts", get(get_attachment_posts))
+        .route("/{uuid}/download", get(download_attachment))
         .route("/{uuid}/thumbnails", get(get_thumbnails))
         .route("/{uuid}/thumbnail/{size}", get(get_thumbnail))
-        // 20MB limit: 10MB image + thumbnails + overhead
-        .layer(DefaultBodyLimit::max(20 * 1024 * 1024))
+        .layer(DefaultBodyLimit::max(body_limit))
         .with_state(state)
 }
 
-// --- Response types ---
+// --- Request/Response types ---
+
+#[derive(Deserialize)]
+struct ListAttachmentsQuery {
+    #[serde(default = "default_list_limit")]
+    limit: i64,
+    #[serde(default)]
+    offset: i64,
+}
+
+fn default_list_limit() -> i64 {
+    50
+}
 
 #[derive(Serialize)]
 struct UploadResponse {
     uuid: String,
+    /// True if the user already had this unencrypted image and its
+    /// attachment was returned instead of storing a copy
+    reused: bool,
+}
+
+impl UploadResponse {
+    /// 201 for a new attachment, 200 for a reused one.
+    fn status(&self) -> StatusCode {
+        if self.reused {
+            StatusCode::OK
+        } else {
+            StatusCode::CREATED
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct AttachmentInfoResponse {
+    uuid: String,
+    kind: AttachmentKind,
+    /// Original filename of a file attachment, encrypted if `filename_iv` is set
+    filename: Option<String>,
+    filename_iv: Option<String>,
+    mime_type: Option<String>,
+    has_thumbnails: bool,
+    encryption_version: i32,
+    created_at: String,
+}
+
+#[derive(Serialize)]
+struct PostRefResponse {
+    uuid: String,
+    /// Encrypted if `title_encrypted` is set
+    title: Option<String>,
+    title_encrypted: bool,
+    title_iv: Option<String>,
+}
+
+impl From<PostRef> for PostRefResponse {
+    fn from(post: PostRef) -> Self {
+        Self {
+            uuid: post.uuid,
+            title: post.title,
+            title_encrypted: post.title_encrypted,
+            title_iv: post.title_iv,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct AttachmentSummaryResponse {
+    uuid: String,
+    kind: AttachmentKind,
+    filename: Option<String>,
+    filename_iv: Option<String>,
+    mime_type: Option<String>,
+    /// Bytes of the data and thumbnails, as counted against the quota
+    size: u64,
+    has_thumbnails: bool,
+    encryption_version: i32,
+    reference_count: i32,
+    created_at: String,
+    posts: Vec<PostRefResponse>,
+}
+
+impl From<AttachmentSummary> for AttachmentSummaryResponse {
+    fn from(summary: AttachmentSummary) -> Self {
+        Self {
+            uuid: summary.uuid,
+            kind: summary.kind,
+            filename: summary.filename,
+            filename_iv: summary.filename_iv,
+            mime_type: summary.mime_type,
+            size: summary.size,
+            has_thumbnails: summary.has_thumbnails,
+            encryption_version: summary.encryption_version,
+            reference_count: summary.reference_count,
+            created_at: summary.created_at,
+            posts: summary.posts.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ListAttachmentsResponse {
+    attachments: Vec<AttachmentSummaryResponse>,
+    /// Attachments of the user across all pages
+    total: i64,
+    has_more: bool,
 }
 
 // --- Handlers ---
 
+/// Upload fields collected from the multipart body.
+#[derive(Default)]
+struct UploadFields {
+    image: Option<Vec<u8>>,
+    image_iv: Option<String>,
+    file: Option<Vec<u8>>,
+    file_iv: Option<String>,
+    filename: Option<String>,
+    filename_iv: Option<String>,
+    mime_type: Option<String>,
+    thumb_sm: Option<Vec<u8>>,
+    thumb_sm_iv: Option<String>,
+    thumb_md: Option<Vec<u8>>,
+    thumb_md_iv: Option<String>,
+    thumb_lg: Option<Vec<u8>>,
+    thumb_lg_iv: Option<String>,
+    encryption_version: Option<i32>,
+    /// `image` or `file`: what a finalized resumable upload contains
+    kind: Option<String>,
+}
+
+async fn read_bytes(field: Field<'_>, name: &str) -> Result<Vec<u8>, ApiError> {
+    let data = field
+        .bytes()
+        .await
+        .map_err(|_| ApiError::bad_request(format!("Failed to read {name} data")))?;
+    Ok(data.to_vec())
+}
+
+async fn read_text(field: Field<'_>, name: &str) -> Result<String, ApiError> {
+    field
+        .text()
+        .await
+        .map_err(|_| ApiError::bad_request(format!("Failed to read {name}")))
+}
+
+async fn read_upload_fields(mut multipart: Multipart) -> Result<UploadFields, ApiError> {
+    let mut fields = UploadFields::default();
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|_| ApiError::bad_request("Invalid multipart data"))?
+    {
+        let name = field.name().unwrap_or("").to_string();
+        match name.as_str() {
+            "image" => fields.image = Some(read_bytes(field, "image").await?),
+            "image_iv" => fields.image_iv = Some(read_text(field, "image_iv").await?),
+            "file" => fields.file = Some(read_bytes(field, "file").await?),
+            "file_iv" => fields.file_iv = Some(read_text(field, "file_iv").await?),
+            "filename" => fields.filename = Some(read_text(field, "filename").await?),
+            "filename_iv" => fields.filename_iv = Some(read_text(field, "filename_iv").await?),
+            "mime_type" => fields.mime_type = Some(read_text(field, "mime_type").await?),
+            "thumb_sm" => fields.thumb_sm = Some(read_bytes(field, "thumb_sm").await?),
+            "thumb_sm_iv" => fields.thumb_sm_iv = Some(read_text(field, "thumb_sm_iv").await?),
+            "thumb_md" => fields.thumb_md = Some(read_bytes(field, "thumb_md").await?),
+            "thumb_md_iv" => fields.thumb_md_iv = Some(read_text(field, "thumb_md_iv").await?),
+            "thumb_lg" => fields.thumb_lg = Some(read_bytes(field, "thumb_lg").await?),
+            "thumb_lg_iv" => fields.thumb_lg_iv = Some(read_text(field, "thumb_lg_iv").await?),
+            "encryption_version" => {
+                let text = read_text(field, "encryption_version").await?;
+                fields.encryption_version = Some(
+                    text.parse()
+                        .map_err(|_| ApiError::bad_request("Invalid encryption_version"))?,
+                );
+            }
+            "kind" => fields.kind = Some(read_text(field, "kind").await?),
+            _ => {
+                // Ignore unknown fields
+            }
+        }
+    }
+    Ok(fields)
+}
+
+/// Convert an empty IV (unencrypted) to None.
+fn non_empty(iv: &str) -> Option<&str> {
+    if iv.is_empty() { None } else { Some(iv) }
+}
+
+/// Pair thumbnail data with its IV (empty IVs mean unencrypted).
+fn thumbnail<'a>(
+    data: &'a Option<Vec<u8>>,
+    iv: &'a Option<String>,
+) -> Option<(&'a [u8], Option<&'a str>)> {
+    data.as_deref()
+        .map(|data| (data, iv.as_deref().and_then(non_empty)))
+}
+
+/// Check that every IV field is unpadded base64url of bounded length. IVs are
+/// sent back in response headers, so anything else could break or inject
+/// headers there.
+fn check_ivs(fields: &UploadFields) -> Result<(), ApiError> {
+    for (iv, name) in [
+        (&fields.image_iv, "image_iv"),
+        (&fields.file_iv, "file_iv"),
+        (&fields.filename_iv, "filename_iv"),
+        (&fields.thumb_sm_iv, "thumb_sm_iv"),
+        (&fields.thumb_md_iv, "thumb_md_iv"),
+        (&fields.thumb_lg_iv, "thumb_lg_iv"),
+    ] {
+        let Some(iv) = iv else {
+            continue;
+        };
+        let valid = iv.len() <= MAX_IV_LENGTH
+            && iv
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        if !valid {
+            return Err(ApiError::bad_request(format!("Invalid {name}")));
+        }
+    }
+    Ok(())
+}
+
+/// Check thumbnail sizes against the configured limits, and that every
+/// encrypted thumbnail comes with its IV.
+fn check_thumbnails(
+    fields: &UploadFields,
+    limits: &AttachmentLimits,
+    encrypted: bool,
+) -> Result<(), ApiError> {
+    for (data, iv, limit, label, iv_name) in [
+        (
+            &fields.thumb_sm,
+            &fields.thumb_sm_iv,
+            limits.thumb_sm,
+            "Small",
+            "thumb_sm_iv",
+        ),
+        (
+            &fields.thumb_md,
+            &fields.thumb_md_iv,
+            limits.thumb_md,
+            "Medium",
+            "thumb_md_iv",
+        ),
+        (
+            &fields.thumb_lg,
+            &fields.thumb_lg_iv,
+            limits.thumb_lg,
+            "Large",
+            "thumb_lg_iv",
+        ),
+    ] {
+        let Some(data) = data else {
+            continue;
+        };
+        if data.len() > limit {
+            return Err(ApiError::bad_request(format!(
+                "{label} thumbnail too large (max {})",
+                format_size(limit)
+            )));
+        }
+        if encrypted && iv.as_deref().and_then(non_empty).is_none() {
+            return Err(ApiError::bad_request(format!("Missing {iv_name} field")));
+        }
+    }
+    Ok(())
+}
+
+/// Check an unencrypted image, strip its metadata and render the small
+/// thumbnail. Thumbnails sent by the client are dropped, since they may
+/// carry the same metadata; the other sizes are rendered when they are first
+/// requested.
+async fn prepare_image(
+    fields: &mut UploadFields,
+    limits: &AttachmentLimits,
+) -> Result<(), ApiError> {
+    let image = fields.image.take().unwrap_or_default();
+    // Checked again on the result, but no need to decode anything too large
+    if image.len() > limits.image {
+        return Err(ApiError::bad_request(format!(
+            "Image too large (max {})",
+            format_size(limits.image)
+        )));
+    }
+    let (image, thumb_sm) = tokio::task::spawn_blocking(move || {
+        let image = images::sanitize(&image)?;
+        let thumb_sm = images::thumbnail(&image, ThumbnailSize::Sm)?;
+        Ok::<_, ImageError>((image, thumb_sm))
+    })
+    .await
+    .map_err(|e| {
+        error!(error = %e, "Image processing task failed");
+        ApiError::internal("Failed to process image")
+    })?
+    .map_err(|e| ApiError::bad_request(e.to_string()))?;
+
+    fields.image = Some(image);
+    fields.thumb_sm = Some(thumb_sm);
+    fields.thumb_sm_iv = None;
+    fields.thumb_md = None;
+    fields.thumb_md_iv = None;
+    fields.thumb_lg = None;
+    fields.thumb_lg_iv = None;
+    Ok(())
+}
+
+/// Validate and normalize a MIME type such as `application/pdf`.
+fn parse_mime_type(value: &str) -> Result<String, ApiError> {
+    let value = value.trim().to_ascii_lowercase();
+    let is_token = |s: &str| {
+        !s.is_empty()
+            && s.bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"!#$&^_.+-".contains(&b))
+    };
+    let valid = value.len() <= MAX_MIME_TYPE_LENGTH
+        && value
+            .split_once('/')
+            .is_some_and(|(kind, subtype)| is_token(kind) && is_token(subtype));
+    if !valid {
+        return Err(ApiError::bad_request("Invalid mime_type"));
+    }
+    Ok(value)
+}
+
 /// Upload an attachment using multipart form data.
 /// Supports both encrypted (encryption_version > 0) and unencrypted (encryption_version = 0) uploads.
 ///
-/// Expected fields:
+/// Image attachments send:
 /// - `image`: Binary image data (encrypted or raw)
 /// - `image_iv`: IV for image (base64url string, empty for unencrypted)
 /// - `thumb_sm`: Binary small thumbnail (200px)
@@ -67,110 +502,71 @@ struct UploadResponse {
 /// - `thumb_lg_iv`: IV for large thumbnail - optional
 /// - `encryption_version`: Version number (0 = unencrypted, >0 = encrypted)
 ///
+/// File attachments (PDFs, audio, archives, ...) send `file` and `file_iv`
+/// instead of `image` and `image_iv`, plus:
+/// - `filename`: Original filename (encrypted like the data)
+/// - `filename_iv`: IV for the filename (empty for unencrypted)
+/// - `mime_type`: MIME type, never encrypted - optional, defaults to
+///   `application/octet-stream`
+///
+/// and may send the thumbnail fields as a preview.
+///
+/// Unencrypted images may leave out the IV fields and the thumbnails, and
+/// thumbnails sent with them are ignored: the server renders `thumb_sm` at
+/// upload and the other sizes on first request. They must be JPEG, PNG, WebP
+/// or GIF, and their metadata is removed.
+///
+/// An unencrypted image the user already has (after metadata stripping) is
+/// not stored again: the response carries the existing attachment's UUID
+/// with `reused: true` and status 200 instead of 201.
+///
 /// If user has encryption enabled, encryption_version must be > 0.
 /// If user does not have encryption enabled, encryption_version must be 0.
 async fn upload_attachment(
     State(state): State<AttachmentsState>,
     auth: Auth<AnyRole>,
-    mut multipart: Multipart,
+    multipart: Multipart,
 ) -> Result<impl IntoResponse, ApiError> {
-    let mut image_data: Option<Vec<u8>> = None;
-    let mut image_iv: Option<String> = None;
-    let mut thumb_sm: Option<Vec<u8>> = None;
-    let mut thumb_sm_iv: Option<String> = None;
-    let mut thumb_md: Option<Vec<u8>> = None;
-    let mut thumb_md_iv: Option<String> = None;
-    let mut thumb_lg: Option<Vec<u8>> = None;
-    let mut thumb_lg_iv: Option<String> = None;
-    let mut encryption_version: Option<i32> = None;
+    let fields = read_upload_fields(multipart).await?;
+    let response = create_attachment(&state, &auth, fields, 0).await?;
+    Ok((response.status(), axum::Json(response)))
+}
 
-    while let Some(field) = multipart
-        .next_field()
-        .await
-        .map_err(|_| ApiError::bad_request("Invalid multipart data"))?
-    {
-        let name = field.name().unwrap_or("").to_string();
-        match name.as_str() {
-            "image" => {
-                let data = field
-                    .bytes()
-                    .await
-                    .map_err(|_| ApiError::bad_request("Failed to read image data"))?;
-                image_data = Some(data.to_vec());
-            }
-            "image_iv" => {
-                let text = field
-                    .text()
-                    .await
-                    .map_err(|_| ApiError::bad_request("Failed to read image_iv"))?;
-                image_iv = Some(text);
-            }
-            "thumb_sm" => {
-                let data = field
-                    .bytes()
-                    .await
-                    .map_err(|_| ApiError::bad_request("Failed to read thumb_sm data"))?;
-                thumb_sm = Some(data.to_vec());
-            }
-            "thumb_sm_iv" => {
-                let text = field
-                    .text()
-                    .await
-                    .map_err(|_| ApiError::bad_request("Failed to read thumb_sm_iv"))?;
-                thumb_sm_iv = Some(text);
-            }
-            "thumb_md" => {
-                let data = field
-                    .bytes()
-                    .await
-                    .map_err(|_| ApiError::bad_request("Failed to read thumb_md data"))?;
-                thumb_md = Some(data.to_vec());
-            }
-            "thumb_md_iv" => {
-                let text = field
-                    .text()
-                    .await
-                    .map_err(|_| ApiError::bad_request("Failed to read thumb_md_iv"))?;
-                thumb_md_iv = Some(text);
-            }
-            "thumb_lg" => {
-                let data = field
-                    .bytes()
-                    .await
-                    .map_err(|_| ApiError::bad_request("Failed to read thumb_lg data"))?;
-                thumb_lg = Some(data.to_vec());
-            }
-            "thumb_lg_iv" => {
-                let text = field
-                    .text()
-                    .await
-                    .map_err(|_| ApiError::bad_request("Failed to read thumb_lg_iv"))?;
-                thumb_lg_iv = Some(text);
-            }
-            "encryption_version" => {
-                let text = field
-                    .text()
-                    .await
-                    .map_err(|_| ApiError::bad_request("Failed to read encryption_version"))?;
-                encryption_version = Some(
-                    text.parse()
-                        .map_err(|_| ApiError::bad_request("Invalid encryption_version"))?,
-                );
-            }
-            _ => {
-                // Ignore unknown fields
+/// Validate upload fields against the user's encryption settings, the size
+/// limits and the storage quota, run the upload hooks and store the
+/// attachment. `reserved` bytes of the user's usage are already held for
+/// this upload. Unencrypted images the user already has are reused.
+async fn create_attachment(
+    state: &AttachmentsState,
+    auth: &Auth<AnyRole>,
+    mut fields: UploadFields,
+    reserved: u64,
+) -> Result<UploadResponse, ApiError> {
+    if fields.image.is_some() && fields.file.is_some() {
+        return Err(ApiError::bad_request(
+            "Send either an image or a file, not both",
+        ));
+    }
+    let is_file = fields.file.is_some();
+    let encryption_version = fields
+        .encryption_version
+        .ok_or_else(|| ApiError::bad_request("Missing encryption_version field"))?;
+    let encrypted = encryption_version != UNENCRYPTED_VERSION;
+    check_ivs(&fields)?;
+    if !is_file {
+        // The server renders thumbnails of unencrypted images itself
+        for (missing, name) in [
+            (fields.image.is_none(), "image"),
+            (encrypted && fields.image_iv.is_none(), "image_iv"),
+            (encrypted && fields.thumb_sm.is_none(), "thumb_sm"),
+            (encrypted && fields.thumb_sm_iv.is_none(), "thumb_sm_iv"),
+        ] {
+            if missing {
+                return Err(ApiError::bad_request(format!("Missing {name} field")));
             }
         }
     }
 
-    let image_data = image_data.ok_or_else(|| ApiError::bad_request("Missing image field"))?;
-    let image_iv = image_iv.ok_or_else(|| ApiError::bad_request("Missing image_iv field"))?;
-    let thumb_sm = thumb_sm.ok_or_else(|| ApiError::bad_request("Missing thumb_sm field"))?;
-    let thumb_sm_iv =
-        thumb_sm_iv.ok_or_else(|| ApiError::bad_request("Missing thumb_sm_iv field"))?;
-    let encryption_version = encryption_version
-        .ok_or_else(|| ApiError::bad_request("Missing encryption_version field"))?;
-
     // Check user's encryption settings and validate encryption_version
     let encryption_settings = state
         .db
@@ -183,173 +579,484 @@ async fn upload_attachment(
         .map(|s| s.encryption_enabled)
         .unwrap_or(false);
 
-    if user_has_encryption && encryption_version == UNENCRYPTED_VERSION {
+    if user_has_encryption && !encrypted {
         return Err(ApiError::bad_request(
             "Encryption is enabled but unencrypted data was submitted",
         ));
     }
 
-    if !user_has_encryption && encryption_version != UNENCRYPTED_VERSION {
+    if !user_has_encryption && encrypted {
         return Err(ApiError::bad_request(
             "Encryption is not enabled but encrypted data was submitted",
         ));
     }
 
-    // Limit image size to 10MB
-    if image_data.len() > 10 * 1024 * 1024 {
-        return Err(ApiError::bad_request("Image too large (max 10MB)"));
+    let limits = &*state.limits;
+    if !is_file && !encrypted {
+        prepare_image(&mut fields, limits).await?;
     }
-
-    // Limit thumbnail sizes (frontend targets: sm=100KB, md=200KB, lg=400KB + encryption overhead)
-    if thumb_sm.len() > 150 * 1024 {
-        return Err(ApiError::bad_request(
-            "Small thumbnail too large (max 150KB)",
-        ));
-    }
-    if let Some(ref data) = thumb_md {
-        if data.len() > 250 * 1024 {
+    let mut mime_type = None;
+    let data = if is_file {
+        let data = fields.file.as_deref().unwrap_or_default();
+        let filename = fields.filename.as_deref().unwrap_or_default();
+        if filename.is_empty() {
+            return Err(ApiError::bad_request("Missing filename field"));
+        }
+        if filename.len() > MAX_FILENAME_LENGTH || filename.chars().any(char::is_control) {
+            return Err(ApiError::bad_request("Invalid filename"));
+        }
+        let has_filename_iv = fields
+            .filename_iv
+            .as_deref()
+            .is_some_and(|iv| !iv.is_empty());
+        if encrypted != has_filename_iv {
             return Err(ApiError::bad_request(
-                "Medium thumbnail too large (max 250KB)",
+                "filename_iv must be set exactly when the file is encrypted",
             ));
         }
-    }
-    if let Some(ref data) = thumb_lg {
-        if data.len() > 500 * 1024 {
+        let has_file_iv = fields.file_iv.as_deref().is_some_and(|iv| !iv.is_empty());
+        if encrypted != has_file_iv {
             return Err(ApiError::bad_request(
-                "Large thumbnail too large (max 500KB)",
+                "file_iv must be set exactly when the file is encrypted",
             ));
         }
-    }
-
-    // Convert empty IVs to None for unencrypted uploads
-    let image_iv_opt = if image_iv.is_empty() {
-        None
+        if fields.thumb_sm.is_none() && (fields.thumb_md.is_some() || fields.thumb_lg.is_some()) {
+            return Err(ApiError::bad_request(
+                "A file preview needs a small thumbnail",
+            ));
+        }
+        let mime = match fields.mime_type.as_deref() {
+            Some(value) if !value.is_empty() => parse_mime_type(value)?,
+            _ => DEFAULT_MIME_TYPE.to_string(),
+        };
+        let limit = limits.for_file(&mime);
+        if data.len() > limit {
+            return Err(ApiError::bad_request(format!(
+                "File too large (max {})",
+                format_size(limit)
+            )));
+        }
+        mime_type = Some(mime);
+        data
     } else {
-        Some(image_iv)
+        let data = fields.image.as_deref().unwrap_or_default();
+        if data.len() > limits.image {
+            return Err(ApiError::bad_request(format!(
+                "Image too large (max {})",
+                format_size(limits.image)
+            )));
+        }
+        data
     };
-    let thumb_sm_iv_opt = if thumb_sm_iv.is_empty() {
-        None
+    check_thumbnails(&fields, limits, encrypted)?;
+
+    let kind = if is_file {
+        AttachmentKind::File
     } else {
-        Some(thumb_sm_iv)
+        AttachmentKind::Image
+    };
+    let hook_values = || {
+        let mut values = vec![
+            ("user_uuid".into(), auth.claims.sub.clone()),
+            ("size".into(), data.len().to_string()),
+            ("encrypted".into(), encrypted.to_string()),
+            ("kind".into(), kind.as_str().to_string()),
+        ];
+        if let Some(mime) = &mime_type {
+            values.push(("mime_type".into(), mime.clone()));
+        }
+        values
     };
 
-    let input = CreateAttachmentInput {
-        user_id: auth.user_id,
-        image_data: &image_data,
-        image_iv: image_iv_opt.as_deref(),
-        thumb_sm: &thumb_sm,
-        thumb_sm_iv: thumb_sm_iv_opt.as_deref(),
-        thumb_md: thumb_md.as_ref().zip(thumb_md_iv.as_ref()).map(|(d, iv)| {
-            (
-                d.as_slice(),
-                if iv.is_empty() {
-                    None
-                } else {
-                    Some(iv.as_str())
-                },
-            )
-        }),
-        thumb_lg: thumb_lg.as_ref().zip(thumb_lg_iv.as_ref()).map(|(d, iv)| {
-            (
-                d.as_slice(),
-                if iv.is_empty() {
-                    None
-                } else {
-                    Some(iv.as_str())
-                },
-            )
-        }),
-        encryption_version,
+    check_server_pre_hook(
+        state.settings.plugin_manager.as_ref(),
+        ServerPreHook::PreUpload,
+        hook_values,
+    )
+    .await
+    .map_err(ApiError::forbidden)?;
+
+    let uploaded = |uuid: &str, reused: bool| {
+        spawn_server_hook(
+            state.settings.plugin_manager.as_ref(),
+            ServerHook::AttachmentUploaded,
+            || {
+                let mut values = hook_values();
+                values.insert(1, ("attachment_uuid".into(), uuid.to_string()));
+                values.push(("reused".into(), reused.to_string()));
+                values
+            },
+        );
+    };
+
+    // Blobs are stored once anyway, but a new row would count against the
+    // quota again and show up twice in the library.
+    if !is_file && !encrypted {
+        let existing = state
+            .db
+            .attachments()
+            .reuse_image(auth.user_id, data)
+            .await
+            .db_err("Failed to look up attachment")?;
+        if let Some(uuid) = existing {
+            uploaded(&uuid, true);
+            return Ok(UploadResponse { uuid, reused: true });
+        }
+    }
+
+    let size = [&fields.thumb_sm, &fields.thumb_md, &fields.thumb_lg]
+        .into_iter()
+        .flatten()
+        .map(Vec::len)
+        .sum::<usize>()
+        + data.len();
+    let quota = state.db.quotas().lock(auth.user_id).await;
+    check_quota(&state.db, &quota, size as u64, reserved).await?;
+
+    let attachments = state.db.attachments();
+    let uuid = match &mime_type {
+        Some(mime_type) => attachments
+            .create_file(CreateFileAttachmentInput {
+                user_id: auth.user_id,
+                data,
+                data_iv: fields.file_iv.as_deref().and_then(non_empty),
+                filename: fields.filename.as_deref().unwrap_or_default(),
+                filename_iv: fields.filename_iv.as_deref().and_then(non_empty),
+                mime_type,
+                thumb_sm: thumbnail(&fields.thumb_sm, &fields.thumb_sm_iv),
+                thumb_md: thumbnail(&fields.thumb_md, &fields.thumb_md_iv),
+                thumb_lg: thumbnail(&fields.thumb_lg, &fields.thumb_lg_iv),
+                encryption_version,
+            })
+            .await
+            .db_err("Failed to create attachment")?,
+        None => attachments
+            .create(CreateAttachmentInput {
+                user_id: auth.user_id,
+                image_data: data,
+                image_iv: fields.image_iv.as_deref().and_then(non_empty),
+                thumb_sm: fields.thumb_sm.as_deref().unwrap_or_default(),
+                thumb_sm_iv: fields.thumb_sm_iv.as_deref().and_then(non_empty),
+                thumb_md: thumbnail(&fields.thumb_md, &fields.thumb_md_iv),
+                thumb_lg: thumbnail(&fields.thumb_lg, &fields.thumb_lg_iv),
+                encryption_version,
+            })
+            .await
+            .db_err("Failed to create attachment")?,
     };
 
-    let uuid = state
+    uploaded(&uuid, false);
+
+    Ok(UploadResponse {
+        uuid,
+        reused: false,
+    })
+}
+
+/// List the user's attachments, newest first, with the posts using each.
+async fn list_attachments(
+    State(state): State<AttachmentsState>,
+    auth: Auth<AnyRole>,
+    Query(query): Query<ListAttachmentsQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    if query.offset < 0 {
+        return Err(ApiError::bad_request("offset must not be negative"));
+    }
+    let limit = query.limit.clamp(1, MAX_LIST_LIMIT);
+    let attachments = state.db.attachments();
+
+    // Fetch one extra row to know whether another page exists
+    let mut page = attachments
+        .list(auth.user_id, limit + 1, query.offset)
+        .await
+        .db_err("Failed to list attachments")?;
+    let has_more = page.len() as i64 > limit;
+    page.truncate(limit as usize);
+
+    let total = attachments
+        .count(auth.user_id)
+        .await
+        .db_err("Failed to count attachments")?;
+
+    Ok(axum::Json(ListAttachmentsResponse {
+        attachments: page.into_iter().map(Into::into).collect(),
+        total,
+        has_more,
+    }))
+}
+
+/// Find the posts that use an attachment.
+async fn get_attachment_posts(
+    State(state): State<AttachmentsState>,
+    auth: Auth<AnyRole>,
+    Path(uuid): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let posts = state
         .db
         .attachments()
-        .create(input)
+        .get_referencing_posts(&uuid, auth.user_id)
         .await
-        .db_err("Failed to create attachment")?;
+        .db_err("Failed to get attachment posts")?
+        .ok_or_else(|| ApiError::not_found("Attachment not found"))?;
 
-    Ok((StatusCode::CREATED, axum::Json(UploadResponse { uuid })))
+    Ok(axum::Json(
+        posts
+            .into_iter()
+            .map(PostRefResponse::from)
+            .collect::<Vec<_>>(),
+    ))
+}
+
+/// Delete an attachment no post uses, such as an upload whose post was never
+/// saved. Attachments still in use are refused; they are deleted once the
+/// last post stops referencing them.
+async fn delete_attachment(
+    State(state): State<AttachmentsState>,
+    auth: Auth<AnyRole>,
+    Path(uuid): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let result = state
+        .db
+        .attachments()
+        .delete_unreferenced(&uuid, auth.user_id)
+        .await
+        .db_err("Failed to delete attachment")?;
+
+    match result {
+        DeleteAttachmentResult::Deleted => {
+            spawn_server_hook(
+                state.settings.plugin_manager.as_ref(),
+                ServerHook::AttachmentDeleted,
+                || {
+                    vec![
+                        ("user_uuid".into(), auth.claims.sub.clone()),
+                        ("attachment_uuid".into(), uuid),
+                    ]
+                },
+            );
+            Ok(StatusCode::NO_CONTENT)
+        }
+        DeleteAttachmentResult::NotFound => Err(ApiError::not_found("Attachment not found")),
+        DeleteAttachmentResult::Referenced(count) => Err(ApiError::conflict(format!(
+            "Attachment is used by {count} post(s)"
+        ))),
+    }
 }
 
 /// Get an attachment as binary stream.
 /// IV is returned in the `X-Encryption-IV` header (empty string if unencrypted).
+/// Supports `Range`, `If-Range` and `If-None-Match` (the ETag is the content hash).
 async fn get_attachment(
     State(state): State<AttachmentsState>,
     auth: Auth<AnyRole>,
     Path(uuid): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let attachments = state.db.attachments();
+    let blob = attachments
+        .get_blob_ref(&uuid, auth.user_id, "image")
+        .await
+        .db_err("Failed to get attachment")?
+        .ok_or_else(|| ApiError::not_found("Attachment not found"))?;
+
+    blob_response(
+        &headers,
+        &attachments,
+        blob,
+        HeaderValue::from_static(DEFAULT_MIME_TYPE),
+        attachment_disposition(None),
+    )
+    .await
+}
+
+/// Get an attachment's kind, filename, MIME type and encryption details.
+/// Clients decrypt the filename with `filename_iv` before showing it.
+async fn get_attachment_info(
+    State(state): State<AttachmentsState>,
+    auth: Auth<AnyRole>,
+    Path(uuid): Path<String>,
 ) -> Result<impl IntoResponse, ApiError> {
-    let attachment = state
+    let info = state
         .db
         .attachments()
-        .get_by_uuid(&uuid, auth.user_id)
+        .get_info(&uuid, auth.user_id)
         .await
         .db_err("Failed to get attachment")?
         .ok_or_else(|| ApiError::not_found("Attachment not found"))?;
 
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        header::CONTENT_TYPE,
-        "application/octet-stream".parse().unwrap(),
-    );
-    headers.insert(header::CONTENT_DISPOSITION, "attachment".parse().unwrap());
+    Ok(axum::Json(AttachmentInfoResponse {
+        uuid: info.uuid,
+        kind: info.kind,
+        filename: info.filename,
+        filename_iv: info.filename_iv,
+        mime_type: info.mime_type,
+        has_thumbnails: info.has_thumbnails,
+        encryption_version: info.encryption_version,
+        created_at: info.created_at,
+    }))
+}
 
-    // Empty string for unencrypted attachments
-    let iv = attachment.image_iv.unwrap_or_default();
-    headers.insert("X-Encryption-IV", iv.parse().unwrap());
+/// Download an attachment as a file. Unencrypted files are sent with their
+/// MIME type and original filename in `Content-Disposition`; encrypted ones
+/// as `application/octet-stream` for the client to decrypt. Supports the
+/// same caching and range headers as `GET /{uuid}`.
+async fn download_attachment(
+    State(state): State<AttachmentsState>,
+    auth: Auth<AnyRole>,
+    Path(uuid): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let attachments = state.db.attachments();
+    let info = attachments
+        .get_info(&uuid, auth.user_id)
+        .await
+        .db_err("Failed to get attachment")?
+        .ok_or_else(|| ApiError::not_found("Attachment not found"))?;
 
-    Ok((headers, Body::from(attachment.image_data)))
+    let encrypted = info.encryption_version != UNENCRYPTED_VERSION;
+    let (content_type, filename) = match (&info.mime_type, encrypted) {
+        (Some(mime_type), false) => (mime_type.as_str(), info.filename.as_deref()),
+        _ => (DEFAULT_MIME_TYPE, None),
+    };
+    let content_type = HeaderValue::from_str(content_type)
+        .unwrap_or_else(|_| HeaderValue::from_static(DEFAULT_MIME_TYPE));
+
+    blob_response(
+        &headers,
+        &attachments,
+        info.data,
+        content_type,
+        attachment_disposition(filename),
+    )
+    .await
 }
 
 /// Get a single thumbnail by size as binary stream.
 /// IV is returned in the `X-Encryption-IV` header (empty string if unencrypted).
-/// Size must be "sm", "md", or "lg".
+/// Size must be "sm", "md", or "lg". Supports the same caching and range
+/// headers as the full attachment. Sizes an unencrypted image lacks are
+/// rendered and stored on first request.
 async fn get_thumbnail(
     State(state): State<AttachmentsState>,
     auth: Auth<AnyRole>,
     Path((uuid, size)): Path<(String, String)>,
-) -> Result<impl IntoResponse, ApiError> {
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
     // Validate size parameter
     if !matches!(size.as_str(), "sm" | "md" | "lg") {
         return Err(ApiError::bad_request("Invalid size. Must be sm, md, or lg"));
     }
 
-    let thumbnail = state
-        .db
-        .attachments()
-        .get_thumbnail_by_size(&uuid, auth.user_id, &size)
+    let attachments = state.db.attachments();
+    let blob = match attachments
+        .get_blob_ref(&uuid, auth.user_id, &size)
         .await
         .db_err("Failed to get thumbnail")?
-        .ok_or_else(|| ApiError::not_found("Thumbnail not found"))?;
+    {
+        Some(blob) => blob,
+        None => render_thumbnail(&attachments, &uuid, auth.user_id, &size)
+            .await?
+            .ok_or_else(|| ApiError::not_found("Thumbnail not found"))?,
+    };
 
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        header::CONTENT_TYPE,
-        "application/octet-stream".parse().unwrap(),
-    );
-    headers.insert(header::CONTENT_DISPOSITION, "attachment".parse().unwrap());
+    blob_response(
+        &headers,
+        &attachments,
+        blob,
+        HeaderValue::from_static(DEFAULT_MIME_TYPE),
+        attachment_disposition(None),
+    )
+    .await
+}
+
+/// Render and store a missing thumbnail size of an unencrypted image.
+/// Returns None for attachments the server can't render thumbnails of,
+/// including images that failed to decode before.
+async fn render_thumbnail(
+    attachments: &AttachmentStore,
+    uuid: &str,
+    user_id: i64,
+    size: &str,
+) -> Result<Option<BlobRef>, ApiError> {
+    let Some(thumbnail_size) = ThumbnailSize::from_str(size) else {
+        return Ok(None);
+    };
+    let Some(info) = attachments
+        .get_info(uuid, user_id)
+        .await
+        .db_err("Failed to get attachment")?
+    else {
+        return Ok(None);
+    };
+    if info.kind != AttachmentKind::Image
+        || info.encryption_version != UNENCRYPTED_VERSION
+        || info.thumbnail_failed
+    {
+        return Ok(None);
+    }
 
-    // Empty string for unencrypted attachments
-    let iv = thumbnail.iv.unwrap_or_default();
-    headers.insert("X-Encryption-IV", iv.parse().unwrap());
+    let image = attachments
+        .read_blob(&info.data.key)
+        .await
+        .db_err("Failed to read attachment")?;
+    let rendered =
+        tokio::task::spawn_blocking(move || images::thumbnail(&image, thumbnail_size)).await;
+    let data = match rendered {
+        Ok(Ok(data)) => data,
+        // Images stored before the server checked them may not decode;
+        // don't try again on every request.
+        Ok(Err(e)) => {
+            warn!(attachment = %uuid, size, error = %e, "Cannot render thumbnail");
+            attachments
+                .mark_thumbnail_failed(uuid, user_id)
+                .await
+                .db_err("Failed to update attachment")?;
+            return Ok(None);
+        }
+        Err(e) => {
+            error!(error = %e, "Thumbnail task failed");
+            return Err(ApiError::internal("Failed to render thumbnail"));
+        }
+    };
 
-    Ok((headers, Body::from(thumbnail.data)))
+    attachments
+        .add_thumbnail(uuid, user_id, size, &data)
+        .await
+        .db_err("Failed to store thumbnail")
 }
 
 /// Get all thumbnails as a multipart response.
 /// Each part has `X-Thumbnail-Size` header (sm, md, lg) and `X-Encryption-IV` header (empty if unencrypted).
+/// Sizes an unencrypted image lacks are rendered and stored first.
 async fn get_thumbnails(
     State(state): State<AttachmentsState>,
     auth: Auth<AnyRole>,
     Path(uuid): Path<String>,
 ) -> Result<impl IntoResponse, ApiError> {
-    let thumbnails = state
-        .db
-        .attachments()
-        .get_thumbnails(&uuid, auth.user_id)
-        .await
-        .db_err("Failed to get thumbnails")?
-        .ok_or_else(|| ApiError::not_found("Attachment not found"))?;
+    let attachments = state.db.attachments();
+    let fetch = || async {
+        attachments
+            .get_thumbnails(&uuid, auth.user_id)
+            .await
+            .db_err("Failed to get thumbnails")?
+            .ok_or_else(|| ApiError::not_found("Attachment not found"))
+    };
+    let mut thumbnails = fetch().await?;
+
+    let mut rendered = false;
+    for (size, missing) in [
+        ("md", thumbnails.md.is_none()),
+        ("lg", thumbnails.lg.is_none()),
+    ] {
+        if missing {
+            rendered |= render_thumbnail(&attachments, &uuid, auth.user_id, size)
+                .await?
+                .is_some();
+        }
+    }
+    if rendered {
+        thumbnails = fetch().await?;
+    }
 
     // Build multipart response
     let boundary = "----ThumbnailBoundary";
@@ -0,0 +1,289 @@
+//! Resumable uploads for large attachments, modelled on the tus protocol:
+//!
+//! 1. `POST /uploads` with an `Upload-Length` header starts an upload and
+//!    returns its URL in `Location`.
+//! 2. `PATCH /uploads/{id}` with `Upload-Offset` and an
+//!    `application/offset+octet-stream` body appends a chunk.
+//! 3. `HEAD /uploads/{id}` reports `Upload-Offset`, where an interrupted
+//!    client resumes.
+//! 4. `POST /uploads/{id}/finalize` sends the multipart fields of a regular
+//!    upload without the data, plus `kind` (`image` or `file`), and creates
+//!    the attachment.
+//!
+//! `DELETE /uploads/{id}` abandons an upload. Chunks are staged on disk as
+//! they arrive. Encrypted uploads send the ciphertext in chunks and the IVs
+//! at finalize; the server never needs to tell the two apart.
+
+use axum::{
+    Json,
+    body::Body,
+    extract::{Multipart, OriginalUri, Path, State},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use futures::StreamExt;
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use tracing::error;
+
+use super::{AttachmentsState, create_attachment, format_size, read_upload_fields};
+use crate::api::error::{ApiError, ResultExt, validate_uuid};
+use crate::api::quota::check_quota;
+use crate::auth::{AnyRole, Auth};
+use crate::db::{UploadLock, UploadSession, UploadStore};
+
+const UPLOAD_LENGTH: HeaderName = HeaderName::from_static("upload-length");
+const UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+
+/// Content type of `PATCH` bodies.
+const CHUNK_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+/// Unfinished uploads a user may have at once.
+const MAX_PENDING_UPLOADS: i64 = 10;
+
+#[derive(Serialize)]
+struct UploadSessionResponse {
+    upload_id: String,
+    offset: u64,
+    length: u64,
+}
+
+/// Parse a required numeric header such as `Upload-Offset`.
+fn number_header(headers: &HeaderMap, name: &HeaderName) -> Result<u64, ApiError> {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+        .ok_or_else(|| ApiError::bad_request(format!("Missing or invalid {name} header")))
+}
+
+/// `Upload-Offset` and `Upload-Length` of an upload; never cached.
+fn progress_headers(session: &UploadSession) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(UPLOAD_OFFSET, session.received.into());
+    headers.insert(UPLOAD_LENGTH, session.length.into());
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    headers
+}
+
+/// Get an upload of the authenticated user.
+async fn find_session(
+    uploads: &UploadStore,
+    auth: &Auth<AnyRole>,
+    id: &str,
+) -> Result<UploadSession, ApiError> {
+    validate_uuid(id)?;
+    uploads
+        .get(id, auth.user_id)
+        .await
+        .db_err("Failed to get upload")?
+        .ok_or_else(|| ApiError::not_found("Upload not found"))
+}
+
+/// Lock an upload against concurrent chunks, finalizing and deletion.
+fn lock_upload(uploads: &UploadStore, id: &str) -> Result<UploadLock, ApiError> {
+    uploads
+        .try_lock(id)
+        .ok_or_else(|| ApiError::conflict("Upload is busy with another request"))
+}
+
+/// Start a resumable upload of `Upload-Length` bytes.
+pub(super) async fn create_upload(
+    State(state): State<AttachmentsState>,
+    auth: Auth<AnyRole>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let length = number_header(&headers, &UPLOAD_LENGTH)?;
+    let max = state.limits.max_data();
+    if length > max as u64 {
+        return Err(ApiError::bad_request(format!(
+            "Upload too large (max {})",
+            format_size(max)
+        )));
+    }
+
+    let uploads = state.db.uploads();
+    let pending = uploads
+        .count_by_user(auth.user_id)
+        .await
+        .db_err("Failed to count uploads")?;
+    if pending >= MAX_PENDING_UPLOADS {
+        return Err(ApiError::conflict(format!(
+            "Too many unfinished uploads (max {MAX_PENDING_UPLOADS})"
+        )));
+    }
+
+    // The full length is held against the quota until the upload is
+    // finalized or abandoned.
+    let quota = state.db.quotas().lock(auth.user_id).await;
+    check_quota(&state.db, &quota, length, 0).await?;
+
+    let session = uploads
+        .create(auth.user_id, length)
+        .await
+        .db_err("Failed to create upload")?;
+
+    let mut response_headers = progress_headers(&session);
+    let location = format!("{}/{}", uri.path().trim_end_matches('/'), session.uuid);
+    response_headers.insert(header::LOCATION, location.parse().unwrap());
+
+    Ok((
+        StatusCode::CREATED,
+        response_headers,
+        Json(UploadSessionResponse {
+            upload_id: session.uuid,
+            offset: session.received,
+            length: session.length,
+        }),
+    )
+        .into_response())
+}
+
+/// Report how many bytes of an upload have been received.
+pub(super) async fn upload_status(
+    State(state): State<AttachmentsState>,
+    auth: Auth<AnyRole>,
+    Path(id): Path<String>,
+) -> Result<impl IntoResponse, ApiError> {
+    let session = find_session(&state.db.uploads(), &auth, &id).await?;
+    Ok(progress_headers(&session))
+}
+
+/// Append a chunk at `Upload-Offset`, which must equal the bytes received
+/// so far. If the connection drops mid-chunk, the bytes that arrived are
+/// kept and `HEAD` reports where to resume.
+pub(super) async fn upload_chunk(
+    State(state): State<AttachmentsState>,
+    auth: Auth<AnyRole>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, ApiError> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    if content_type != Some(CHUNK_CONTENT_TYPE) {
+        return Err(ApiError::bad_request(format!(
+            "Content-Type must be {CHUNK_CONTENT_TYPE}"
+        )));
+    }
+    let offset = number_header(&headers, &UPLOAD_OFFSET)?;
+
+    let uploads = state.db.uploads();
+    let _lock = lock_upload(&uploads, &id)?;
+    let session = find_session(&uploads, &auth, &id).await?;
+    if offset != session.received {
+        return Err(ApiError::conflict(format!(
+            "Upload-Offset {offset} does not match the {} bytes received",
+            session.received
+        )));
+    }
+
+    let mut file = uploads
+        .open_staged(&session)
+        .await
+        .db_err("Failed to open staged upload")?;
+    let mut received = session.received;
+    let mut failure = None;
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(_) => {
+                failure = Some(ApiError::bad_request("Failed to read upload chunk"));
+                break;
+            }
+        };
+        if received + chunk.len() as u64 > session.length {
+            failure = Some(ApiError::bad_request("Chunk extends past Upload-Length"));
+            break;
+        }
+        if let Err(e) = file.write_all(&chunk).await {
+            failure = Some(ApiError::db_error("Failed to write upload chunk", e));
+            break;
+        }
+        received += chunk.len() as u64;
+    }
+
+    // Record what reached the disk, even if the chunk was cut short.
+    file.flush().await.db_err("Failed to write upload chunk")?;
+    uploads
+        .set_received(&session.uuid, received)
+        .await
+        .db_err("Failed to update upload")?;
+    if let Some(failure) = failure {
+        return Err(failure);
+    }
+
+    let session = UploadSession {
+        received,
+        ..session
+    };
+    Ok((StatusCode::NO_CONTENT, progress_headers(&session)).into_response())
+}
+
+/// Turn a complete upload into an attachment. Takes the same multipart
+/// fields as `POST /` except `image`/`file`, plus `kind`, and runs the same
+/// validation and hooks.
+pub(super) async fn finalize_upload(
+    State(state): State<AttachmentsState>,
+    auth: Auth<AnyRole>,
+    Path(id): Path<String>,
+    multipart: Multipart,
+) -> Result<impl IntoResponse, ApiError> {
+    let uploads = state.db.uploads();
+    let _lock = lock_upload(&uploads, &id)?;
+    let session = find_session(&uploads, &auth, &id).await?;
+    if !session.is_complete() {
+        return Err(ApiError::conflict(format!(
+            "Upload is incomplete ({} of {} bytes received)",
+            session.received, session.length
+        )));
+    }
+
+    let mut fields = read_upload_fields(multipart).await?;
+    if fields.image.is_some() || fields.file.is_some() {
+        return Err(ApiError::bad_request(
+            "The data of a resumable upload is sent in chunks, not at finalize",
+        ));
+    }
+    let data = uploads
+        .read_staged(&session)
+        .await
+        .db_err("Failed to read staged upload")?;
+    match fields.kind.as_deref() {
+        Some("image") => fields.image = Some(data),
+        Some("file") => fields.file = Some(data),
+        _ => {
+            return Err(ApiError::bad_request(
+                "Missing or invalid kind field (image or file)",
+            ));
+        }
+    }
+
+    let response = create_attachment(&state, &auth, fields, session.length).await?;
+
+    // The attachment exists now; a leftover session is removed by cleanup.
+    if let Err(e) = uploads.delete(&session.uuid).await {
+        error!(upload = %session.uuid, error = %e, "Failed to delete finalized upload");
+    }
+
+    Ok((response.status(), Json(response)))
+}
+
+/// Abandon an upload and delete its staged data.
+pub(super) async fn abort_upload(
+    State(state): State<AttachmentsState>,
+    auth: Auth<AnyRole>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let uploads = state.db.uploads();
+    let _lock = lock_upload(&uploads, &id)?;
+    let session = find_session(&uploads, &auth, &id).await?;
+    uploads
+        .delete(&session.uuid)
+        .await
+        .db_err("Failed to delete upload")?;
+    Ok(StatusCode::NO_CONTENT)
+}
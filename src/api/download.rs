@@ -0,0 +1,185 @@
+//! Streamed blob downloads with `Range`, strong ETags and `304 Not Modified`.
+//!
+//! A blob key is the SHA-256 of its content, so it doubles as a strong ETag
+//! and a URL serving a blob never changes content.
+
+use std::ops::Range;
+
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use tracing::error;
+
+use super::error::{ApiError, ResultExt};
+use crate::db::attachments::{AttachmentStore, BlobRef};
+
+/// Cacheable for a year by the browser only; URLs need authentication.
+const CACHE_CONTROL: &str = "private, max-age=31536000, immutable";
+
+/// Stream `blob` with the given content type and disposition, honoring the
+/// conditional and range headers of the request. The IV is returned in the
+/// `X-Encryption-IV` header (empty string if unencrypted).
+pub(super) async fn blob_response(
+    request: &HeaderMap,
+    store: &AttachmentStore,
+    blob: BlobRef,
+    content_type: HeaderValue,
+    disposition: HeaderValue,
+) -> Result<Response, ApiError> {
+    let etag = format!("\"{}\"", blob.key);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(header::ETAG, etag.parse().unwrap());
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static(CACHE_CONTROL),
+    );
+    // Empty string for unencrypted attachments. Uploads only accept
+    // base64url IVs, but rows stored before that was checked may hold anything.
+    let iv = HeaderValue::try_from(blob.iv.unwrap_or_default()).map_err(|_| {
+        error!(key = %blob.key, "Stored encryption IV is not a valid header value");
+        ApiError::internal("Invalid encryption IV")
+    })?;
+    headers.insert("X-Encryption-IV", iv);
+
+    if if_none_match(request, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, headers).into_response());
+    }
+
+    let open = store
+        .open_blob(&blob.key)
+        .await
+        .db_err("Failed to open attachment blob")?;
+    let size = open.size;
+
+    headers.insert(header::CONTENT_TYPE, content_type);
+    headers.insert(header::CONTENT_DISPOSITION, disposition);
+    // Never render a user-supplied type as something else.
+    headers.insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+    // A Range only applies while the client's copy is still current.
+    let range_header = request
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .filter(|_| if_range(request, &etag));
+    let (status, range) = match range_header.map(|v| parse_range(v, size)) {
+        None | Some(RangeRequest::Ignore) => (StatusCode::OK, 0..size),
+        Some(RangeRequest::Satisfiable(range)) => {
+            let content_range = format!("bytes {}-{}/{size}", range.start, range.end - 1);
+            headers.insert(header::CONTENT_RANGE, content_range.parse().unwrap());
+            (StatusCode::PARTIAL_CONTENT, range)
+        }
+        Some(RangeRequest::Unsatisfiable) => {
+            let content_range = format!("bytes */{size}");
+            headers.insert(header::CONTENT_RANGE, content_range.parse().unwrap());
+            return Ok((StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response());
+        }
+    };
+    headers.insert(header::CONTENT_LENGTH, (range.end - range.start).into());
+
+    let stream = open
+        .read_range(range)
+        .await
+        .db_err("Failed to read attachment blob")?;
+    Ok((status, headers, Body::from_stream(stream)).into_response())
+}
+
+/// `Content-Disposition: attachment`, with the filename if one is given:
+/// a sanitized ASCII `filename` for old clients and the exact UTF-8 name
+/// as `filename*`.
+pub(super) fn attachment_disposition(filename: Option<&str>) -> HeaderValue {
+    let Some(filename) = filename else {
+        return HeaderValue::from_static("attachment");
+    };
+    let ascii: String = filename
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect();
+    let encoded: String = filename
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect();
+    format!("attachment; filename=\"{ascii}\"; filename*=UTF-8''{encoded}")
+        .parse()
+        .unwrap_or_else(|_| HeaderValue::from_static("attachment"))
+}
+
+/// Whether `If-None-Match` lists `etag` (or `*`).
+fn if_none_match(request: &HeaderMap, etag: &str) -> bool {
+    request
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+/// Whether a `Range` may be honored: there is no `If-Range`, or it names
+/// the current (strong) ETag. Dates never match since blobs carry none.
+fn if_range(request: &HeaderMap, etag: &str) -> bool {
+    match request.get(header::IF_RANGE) {
+        Some(value) => value.to_str().is_ok_and(|v| v.trim() == etag),
+        None => true,
+    }
+}
+
+enum RangeRequest {
+    /// Malformed, multi-range or not in bytes: send the whole blob.
+    Ignore,
+    Satisfiable(Range<u64>),
+    Unsatisfiable,
+}
+
+/// Parse a single `bytes=` range against a blob of `size` bytes.
+fn parse_range(value: &str, size: u64) -> RangeRequest {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Ignore;
+    };
+    if spec.contains(',') {
+        return RangeRequest::Ignore;
+    }
+    let Some((first, last)) = spec.trim().split_once('-') else {
+        return RangeRequest::Ignore;
+    };
+
+    if first.is_empty() {
+        // Suffix range: the last N bytes.
+        return match last.parse::<u64>() {
+            Ok(0) => RangeRequest::Unsatisfiable,
+            Ok(_) if size == 0 => RangeRequest::Unsatisfiable,
+            Ok(n) => RangeRequest::Satisfiable(size.saturating_sub(n)..size),
+            Err(_) => RangeRequest::Ignore,
+        };
+    }
+
+    let Ok(start) = first.parse::<u64>() else {
+        return RangeRequest::Ignore;
+    };
+    let end = if last.is_empty() {
+        size
+    } else {
+        match last.parse::<u64>() {
+            Ok(last) if last >= start => last.saturating_add(1).min(size),
+            _ => return RangeRequest::Ignore,
+        }
+    };
+    if start >= size {
+        return RangeRequest::Unsatisfiable;
+    }
+    RangeRequest::Satisfiable(start..end)
+}
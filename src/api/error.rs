@@ -31,6 +31,9 @@ pub enum ApiError {
     Unauthorized(String),
     Conflict(String),
     Internal(String),
+    ServiceUnavailable(String),
+    /// The write would take the user over their storage quota.
+    QuotaExceeded(String),
 }
 
 impl ApiError {
@@ -58,6 +61,28 @@ impl ApiError {
         Self::Internal(msg.into())
     }
 
+    pub fn service_unavailable(msg: impl Into<String>) -> Self {
+        Self::ServiceUnavailable(msg.into())
+    }
+
+    pub fn quota_exceeded(msg: impl Into<String>) -> Self {
+        Self::QuotaExceeded(msg.into())
+    }
+
+    /// The client-facing error message.
+    pub fn message(&self) -> &str {
+        match self {
+            ApiError::BadRequest(msg)
+            | ApiError::Forbidden(msg)
+            | ApiError::NotFound(msg)
+            | ApiError::Unauthorized(msg)
+            | ApiError::Conflict(msg)
+            | ApiError::Internal(msg)
+            | ApiError::ServiceUnavailable(msg)
+            | ApiError::QuotaExceeded(msg) => msg,
+        }
+    }
+
     pub fn db_error(context: &str, e: impl std::fmt::Display) -> Self {
         error!("{}: {}", context, e);
         Self::Internal("Database error".into())
@@ -83,6 +108,8 @@ impl IntoResponse for ApiError {
             ApiError::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, msg),
             ApiError::Conflict(msg) => (StatusCode::CONFLICT, msg),
             ApiError::Internal(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
+            ApiError::ServiceUnavailable(msg) => (StatusCode::SERVICE_UNAVAILABLE, msg),
+            ApiError::QuotaExceeded(msg) => (StatusCode::INSUFFICIENT_STORAGE, msg),
         };
         (status, Json(ErrorResponse { error: message })).into_response()
     }
@@ -1,10 +1,14 @@
 mod admin;
 mod attachments;
 mod config;
+mod download;
 mod encryption;
 mod error;
 mod passkeys;
+mod plugins;
 mod posts;
+mod quota;
+mod sync;
 #[cfg(feature = "test-mode")]
 mod test;
 mod tokens;
@@ -56,6 +60,19 @@ pub fn create_api_router(
         db: db.clone(),
         jwt: jwt.clone(),
         settings: settings.clone(),
+        limits: Arc::new(attachments::AttachmentLimits::default()),
+    };
+
+    let sync_state = sync::SyncState {
+        db: db.clone(),
+        jwt: jwt.clone(),
+        settings: settings.clone(),
+    };
+
+    let plugins_state = plugins::PluginsState {
+        db: db.clone(),
+        jwt: jwt.clone(),
+        settings: settings.clone(),
     };
 
     let tokens_state = tokens::TokensState {
@@ -105,10 +122,12 @@ pub fn create_api_router(
             passkeys::router(passkeys_state, rate_limit_config),
         )
         .nest("/posts", posts::router(posts_state))
+        .nest("/sync", sync::router(sync_state))
         .nest("/encryption", encryption::router(encryption_state))
         .nest("/config", config::router(config_state))
         .nest("/attachments", attachments::router(attachments_state))
         .nest("/tokens", tokens::router(tokens_state))
+        .nest("/plugins", plugins::router(plugins_state))
         .nest("/admin", admin::router(admin_state))
         .nest("/user", user_settings::router(user_settings_state));
 
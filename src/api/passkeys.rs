@@ -20,6 +20,7 @@ use super::error::{ApiError, ResultExt, validate_uuid};
 use crate::auth::{REFRESH_COOKIE_NAME, ServerSettings, extract_client_ip, get_cookie};
 use crate::db::{AuthChallenge, Database, User};
 use crate::jwt::JwtConfig;
+use crate::plugin::{ServerHook, ServerPreHook, check_server_pre_hook, spawn_server_hook};
 use crate::rate_limit::{RateLimitConfig, rate_limit_login_finish, rate_limit_login_start};
 
 #[derive(Clone)]
@@ -89,6 +90,76 @@ impl PasskeysState {
             })?;
         Ok(())
     }
+
+    /// Ask plugins whether this user may log in. Called after the passkey was
+    /// verified and before any session state is changed.
+    async fn check_pre_login(&self, user: &User, ip: Option<&str>) -> Result<(), ApiError> {
+        check_server_pre_hook(
+            self.settings.plugin_manager.as_ref(),
+            ServerPreHook::PreLogin,
+            || {
+                let mut values = vec![
+                    ("user_uuid".into(), user.uuid.clone()),
+                    ("username".into(), user.username.clone()),
+                ];
+                if let Some(ip) = ip {
+                    values.push(("ip".into(), ip.to_string()));
+                }
+                values
+            },
+        )
+        .await
+        .map_err(ApiError::forbidden)
+    }
+
+    /// Notify plugins that an account was activated (`method` is "register" or "claim").
+    fn fire_user_activated(&self, user: &User, method: &str) {
+        spawn_server_hook(
+            self.settings.plugin_manager.as_ref(),
+            ServerHook::UserActivated,
+            || {
+                vec![
+                    ("user_uuid".into(), user.uuid.clone()),
+                    ("username".into(), user.username.clone()),
+                    ("method".into(), method.to_string()),
+                ]
+            },
+        );
+    }
+
+    /// Notify plugins that a login was rejected.
+    fn fire_login_failed(&self, error: &ApiError, ip: Option<&str>) {
+        spawn_server_hook(
+            self.settings.plugin_manager.as_ref(),
+            ServerHook::LoginFailed,
+            || {
+                let mut values = vec![("reason".into(), error.message().to_string())];
+                if let Some(ip) = ip {
+                    values.push(("ip".into(), ip.to_string()));
+                }
+                values
+            },
+        );
+    }
+
+    /// Notify plugins that a login completed and a session was issued.
+    fn fire_login_succeeded(&self, user: &User, passkey_id: i64, ip: Option<String>) {
+        spawn_server_hook(
+            self.settings.plugin_manager.as_ref(),
+            ServerHook::LoginSucceeded,
+            || {
+                let mut values = vec![
+                    ("user_uuid".into(), user.uuid.clone()),
+                    ("username".into(), user.username.clone()),
+                    ("passkey_id".into(), passkey_id.to_string()),
+                ];
+                if let Some(ip) = ip {
+                    values.push(("ip".into(), ip));
+                }
+                values
+            },
+        );
+    }
 }
 
 pub fn router(state: PasskeysState, rate_limit_config: Arc<RateLimitConfig>) -> Router {
@@ -284,6 +355,8 @@ async fn register_finish(
         .await
         .db_err("Failed to activate user")?;
 
+    state.fire_user_activated(&user, "register");
+
     let refresh_token = state.make_refresh_token(&user)?;
 
     // Store refresh token for tracking
@@ -371,14 +444,17 @@ async fn login_finish(
         .db_err("Failed to get challenge")?
         .ok_or_else(|| ApiError::bad_request("No pending authentication or challenge expired"))?;
 
+    let ip = extract_client_ip(&parts, state.settings.ip_extractor.as_ref()).ok();
+
     let result = match challenge {
         AuthChallenge::Passkey(auth_state) => {
-            finish_passkey_auth(&state, &payload.credential, &auth_state).await?
+            finish_passkey_auth(&state, &payload.credential, &auth_state).await
         }
         AuthChallenge::Discoverable(auth_state) => {
-            finish_discoverable_auth(&state, &payload.credential, auth_state).await?
+            finish_discoverable_auth(&state, &payload.credential, auth_state).await
         }
     };
+    let result = result.inspect_err(|e| state.fire_login_failed(e, ip.as_deref()))?;
 
     if result.auth_result.needs_update() {
         if let Err(e) = update_passkey_counter(&state.db, &result.auth_result).await {
@@ -405,7 +481,10 @@ async fn login_finish(
     }
 
     // Only generate JWT if user is activated
-    let ip = extract_client_ip(&parts, state.settings.ip_extractor.as_ref()).ok();
+    state
+        .check_pre_login(&result.user, ip.as_deref())
+        .await
+        .inspect_err(|e| state.fire_login_failed(e, ip.as_deref()))?;
 
     // Revoke existing refresh token if present, then always issue a new one.
     // This invalidates any stolen copies of the old token.
@@ -425,6 +504,9 @@ async fn login_finish(
             refresh_token.refresh_expires_at,
         )
         .await?;
+
+    state.fire_login_succeeded(&result.user, result.passkey_id, ip);
+
     Ok((
         StatusCode::OK,
         [(SET_COOKIE, refresh_token.refresh_cookie)],
@@ -497,6 +579,9 @@ async fn claim_finish(
 
     let result = finish_discoverable_auth(&state, &payload.credential, auth_state).await?;
 
+    let ip = extract_client_ip(&parts, state.settings.ip_extractor.as_ref()).ok();
+    state.check_pre_login(&result.user, ip.as_deref()).await?;
+
     // Activate the user if not already activated
     if !result.user.activated {
         state
@@ -505,6 +590,8 @@ async fn claim_finish(
             .activate(result.user.id)
             .await
             .db_err("Failed to activate user")?;
+
+        state.fire_user_activated(&result.user, "claim");
     }
 
     if result.auth_result.needs_update() {
@@ -526,7 +613,6 @@ async fn claim_finish(
     let refresh_token = state.make_refresh_token(&result.user)?;
 
     // Store refresh token for tracking
-    let ip = extract_client_ip(&parts, state.settings.ip_extractor.as_ref()).ok();
     state
         .store_refresh_token(
             &refresh_token.refresh_jti,
@@ -537,6 +623,8 @@ async fn claim_finish(
         )
         .await?;
 
+    state.fire_login_succeeded(&result.user, result.passkey_id, ip);
+
     Ok((
         StatusCode::OK,
         [(SET_COOKIE, refresh_token.refresh_cookie)],
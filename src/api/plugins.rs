@@ -0,0 +1,192 @@
+//! HTTP routes served by WASM plugins.
+//!
+//! Requests to `/api/plugins/{name}/{path}` are forwarded to the plugin's
+//! `http-handler` export. The plugin declares in its config whether the routes
+//! are public, require a logged-in user, or require an admin; the caller's UUID
+//! is passed along for authenticated routes. Session cookies and authorization
+//! headers are never forwarded. Plugins may only set a fixed set of response
+//! headers, and every response is sandboxed so plugin content can't run
+//! scripts on the app's origin.
+
+use axum::{
+    Router,
+    body::Body,
+    extract::{FromRequestParts, Path, State},
+    http::{HeaderName, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::any,
+};
+use std::sync::Arc;
+
+use super::error::ApiError;
+use crate::auth::{AdminOnly, AnyRole, Auth, ServerSettings};
+use crate::db::Database;
+use crate::impl_has_auth_backend;
+use crate::jwt::JwtConfig;
+use crate::plugin::{HttpAccess, HttpRequest, PluginError};
+
+/// Maximum request and response body size for plugin routes (1 MiB).
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// Request headers withheld from plugins so they never see session credentials.
+const WITHHELD_REQUEST_HEADERS: [HeaderName; 2] = [header::COOKIE, header::AUTHORIZATION];
+
+/// Response headers plugins may set. Anything else (cookies, CSP, CORS,
+/// framing headers, ...) is dropped.
+const ALLOWED_RESPONSE_HEADERS: [HeaderName; 9] = [
+    header::CONTENT_TYPE,
+    header::CONTENT_DISPOSITION,
+    header::CONTENT_LANGUAGE,
+    header::CACHE_CONTROL,
+    header::ETAG,
+    header::LAST_MODIFIED,
+    header::EXPIRES,
+    header::VARY,
+    header::LOCATION,
+];
+
+/// Prefix for plugin-specific response headers, which are always allowed.
+const PLUGIN_HEADER_PREFIX: &str = "x-plugin-";
+
+/// State for plugin route endpoints.
+#[derive(Clone)]
+pub struct PluginsState {
+    pub db: Database,
+    pub jwt: Arc<JwtConfig>,
+    pub settings: ServerSettings,
+}
+
+impl_has_auth_backend!(PluginsState);
+
+pub fn router(state: PluginsState) -> Router {
+    Router::new()
+        .route("/{name}", any(handle_root))
+        .route("/{name}/", any(handle_root))
+        .route("/{name}/{*path}", any(handle_path))
+        .with_state(state)
+}
+
+// --- Handlers ---
+
+async fn handle_root(
+    State(state): State<PluginsState>,
+    Path(name): Path<String>,
+    request: axum::extract::Request,
+) -> Result<Response, Response> {
+    forward(state, name, String::new(), request).await
+}
+
+async fn handle_path(
+    State(state): State<PluginsState>,
+    Path((name, path)): Path<(String, String)>,
+    request: axum::extract::Request,
+) -> Result<Response, Response> {
+    forward(state, name, path, request).await
+}
+
+/// Authenticate the caller as the plugin requires and forward the request.
+async fn forward(
+    state: PluginsState,
+    name: String,
+    path: String,
+    request: axum::extract::Request,
+) -> Result<Response, Response> {
+    let not_found = || ApiError::not_found("Plugin not found").into_response();
+    let plugin = state
+        .settings
+        .plugin_manager
+        .as_ref()
+        .and_then(|manager| manager.get(&name))
+        .ok_or_else(not_found)?;
+    let access = plugin.http_access().ok_or_else(not_found)?;
+
+    let (mut parts, body) = request.into_parts();
+
+    let user_uuid = match access {
+        HttpAccess::Public => None,
+        HttpAccess::User => {
+            let auth = Auth::<AnyRole>::from_request_parts(&mut parts, &state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            Some(auth.claims.sub.clone())
+        }
+        HttpAccess::Admin => {
+            let auth = Auth::<AdminOnly>::from_request_parts(&mut parts, &state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            Some(auth.claims.sub.clone())
+        }
+    };
+
+    let body = axum::body::to_bytes(body, MAX_BODY_BYTES)
+        .await
+        .map_err(|_| ApiError::bad_request("Request body too large").into_response())?;
+
+    let headers = parts
+        .headers
+        .iter()
+        .filter(|(name, _)| !WITHHELD_REQUEST_HEADERS.contains(*name))
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect();
+
+    let plugin_request = HttpRequest {
+        method: parts.method.to_string(),
+        path: format!("/{path}"),
+        query: parts.uri.query().map(str::to_string),
+        headers,
+        body: body.to_vec(),
+        user_uuid,
+    };
+
+    let plugin_response = match plugin.call_http(&plugin_request).await {
+        Ok(Ok(response)) => response,
+        Ok(Err(reason)) => {
+            tracing::warn!(plugin = %name, reason = %reason, "Plugin HTTP handler returned an error");
+            return Err(ApiError::internal("Plugin failed to handle the request").into_response());
+        }
+        Err(e @ PluginError::Unavailable(_)) => {
+            tracing::debug!(plugin = %name, error = %e, "Plugin HTTP handler skipped");
+            return Err(
+                ApiError::service_unavailable("Plugin is temporarily unavailable").into_response(),
+            );
+        }
+        Err(e) => {
+            tracing::warn!(plugin = %name, error = %e, "Plugin HTTP handler failed");
+            return Err(ApiError::internal("Plugin failed to handle the request").into_response());
+        }
+    };
+
+    if plugin_response.body.len() > MAX_BODY_BYTES {
+        tracing::warn!(plugin = %name, size = plugin_response.body.len(), "Plugin HTTP response too large");
+        return Err(ApiError::internal("Plugin response too large").into_response());
+    }
+    let status = StatusCode::from_u16(plugin_response.status).map_err(|_| {
+        tracing::warn!(plugin = %name, status = plugin_response.status, "Plugin returned invalid HTTP status");
+        ApiError::internal("Plugin returned an invalid response").into_response()
+    })?;
+
+    let mut response = Response::new(Body::from(plugin_response.body));
+    *response.status_mut() = status;
+    for (key, value) in plugin_response.headers {
+        let (Ok(key), Ok(value)) = (HeaderName::try_from(key), HeaderValue::try_from(value)) else {
+            continue;
+        };
+        if !ALLOWED_RESPONSE_HEADERS.contains(&key)
+            && !key.as_str().starts_with(PLUGIN_HEADER_PREFIX)
+        {
+            continue;
+        }
+        response.headers_mut().append(key, value);
+    }
+    // Treat plugin content as an opaque, unique origin and never sniff it
+    // into something executable.
+    response.headers_mut().insert(
+        header::CONTENT_SECURITY_POLICY,
+        HeaderValue::from_static("sandbox"),
+    );
+    response.headers_mut().insert(
+        header::X_CONTENT_TYPE_OPTIONS,
+        HeaderValue::from_static("nosniff"),
+    );
+    Ok(response)
+}
@@ -5,8 +5,8 @@
 use axum::{
     Json, Router,
     extract::{Path, Query, State},
-    http::StatusCode,
-    response::IntoResponse,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
     routing::{delete, get, post, put},
 };
 use serde::{Deserialize, Serialize};
@@ -14,10 +14,12 @@ use sqlx;
 use std::sync::Arc;
 
 use super::error::{ApiError, ResultExt};
+use super::quota::check_quota;
 use crate::auth::{AnyRole, Auth, ServerSettings};
-use crate::db::{Database, PostNode, UpdatePostParams};
+use crate::db::{Database, Post, PostNode, UpdatePostParams, UpdatePostResult};
 use crate::impl_has_auth_backend;
 use crate::jwt::JwtConfig;
+use crate::plugin::{ServerHook, spawn_server_hook};
 
 /// State for posts endpoints.
 #[derive(Clone)]
@@ -82,10 +84,31 @@ struct PostResponse {
     encryption_version: Option<i32>,
     position: Option<i32>,
     parent_id: Option<String>,
+    revision: i64,
     created_at: String,
     updated_at: String,
 }
 
+impl From<Post> for PostResponse {
+    fn from(post: Post) -> Self {
+        Self {
+            uuid: post.uuid,
+            title: post.title,
+            title_encrypted: post.title_encrypted,
+            title_iv: post.title_iv,
+            content: post.content,
+            content_encrypted: post.content_encrypted,
+            iv: post.iv,
+            encryption_version: post.encryption_version,
+            position: post.position,
+            parent_id: post.parent_id,
+            revision: post.revision,
+            created_at: post.created_at,
+            updated_at: post.updated_at,
+        }
+    }
+}
+
 #[derive(Serialize)]
 struct PostNodeResponse {
     uuid: String,
@@ -142,6 +165,15 @@ struct UpdatePostRequest {
     encryption_version: Option<i32>,
     /// Optional attachment UUIDs to update refs (used with sendBeacon on page unload)
     attachment_uuids: Option<Vec<String>>,
+    /// Revision this edit is based on. Takes precedence over the `If-Match` header,
+    /// since sendBeacon cannot set custom headers.
+    expected_revision: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct ConflictResponse {
+    error: &'static str,
+    current: PostResponse,
 }
 
 #[derive(Deserialize)]
@@ -194,6 +226,46 @@ async fn validate_encryption(
     Ok(())
 }
 
+/// Strong ETag for a post revision.
+fn revision_etag(revision: i64) -> HeaderValue {
+    HeaderValue::from_str(&format!("\"{revision}\"")).expect("ETag is valid ASCII")
+}
+
+/// Parse an `If-Match` header into an expected revision.
+/// Returns None if the header is absent or `*` (matches any revision).
+fn parse_if_match(headers: &HeaderMap) -> Result<Option<i64>, ApiError> {
+    let Some(value) = headers.get(header::IF_MATCH) else {
+        return Ok(None);
+    };
+    let value = value
+        .to_str()
+        .map_err(|_| ApiError::bad_request("Invalid If-Match header"))?
+        .trim();
+    if value == "*" {
+        return Ok(None);
+    }
+    let tag = value.strip_prefix("W/").unwrap_or(value).trim_matches('"');
+    tag.parse()
+        .map(Some)
+        .map_err(|_| ApiError::bad_request("Invalid If-Match header"))
+}
+
+/// Fire `attachment-deleted` for attachments dropped by a post update or delete.
+fn fire_attachments_deleted(settings: &ServerSettings, user_uuid: &str, uuids: Vec<String>) {
+    for uuid in uuids {
+        spawn_server_hook(
+            settings.plugin_manager.as_ref(),
+            ServerHook::AttachmentDeleted,
+            || {
+                vec![
+                    ("user_uuid".into(), user_uuid.to_string()),
+                    ("attachment_uuid".into(), uuid),
+                ]
+            },
+        );
+    }
+}
+
 // --- Handlers ---
 
 async fn list_posts(
@@ -247,6 +319,15 @@ async fn create_post(
     )
     .await?;
 
+    let quota = state.db.quotas().lock(auth.user_id).await;
+    check_quota(
+        &state.db,
+        &quota,
+        post_size(payload.title.as_deref(), &payload.content),
+        0,
+    )
+    .await?;
+
     let uuid = state
         .db
         .posts()
@@ -275,25 +356,34 @@ async fn create_post(
         .db_err("Failed to get created post")?
         .ok_or_else(|| ApiError::internal("Created post not found"))?;
 
+    spawn_server_hook(
+        state.settings.plugin_manager.as_ref(),
+        ServerHook::PostCreated,
+        || {
+            let mut values = vec![
+                ("user_uuid".into(), auth.claims.sub.clone()),
+                ("post_uuid".into(), post.uuid.clone()),
+                ("encrypted".into(), post.content_encrypted.to_string()),
+            ];
+            if let Some(parent) = &post.parent_id {
+                values.push(("parent_uuid".into(), parent.clone()));
+            }
+            values
+        },
+    );
+
     Ok((
         StatusCode::CREATED,
-        Json(PostResponse {
-            uuid: post.uuid,
-            title: post.title,
-            title_encrypted: post.title_encrypted,
-            title_iv: post.title_iv,
-            content: post.content,
-            content_encrypted: post.content_encrypted,
-            iv: post.iv,
-            encryption_version: post.encryption_version,
-            position: post.position,
-            parent_id: post.parent_id,
-            created_at: post.created_at,
-            updated_at: post.updated_at,
-        }),
+        [(header::ETAG, revision_etag(post.revision))],
+        Json(PostResponse::from(post)),
     ))
 }
 
+/// Bytes a post's title and content count against the storage quota.
+fn post_size(title: Option<&str>, content: &str) -> u64 {
+    (title.map_or(0, str::len) + content.len()) as u64
+}
+
 async fn get_post(
     State(state): State<PostsState>,
     auth: Auth<AnyRole>,
@@ -307,28 +397,24 @@ async fn get_post(
         .db_err("Failed to get post")?
         .ok_or_else(|| ApiError::not_found("Post not found"))?;
 
-    Ok(Json(PostResponse {
-        uuid: post.uuid,
-        title: post.title,
-        title_encrypted: post.title_encrypted,
-        title_iv: post.title_iv,
-        content: post.content,
-        content_encrypted: post.content_encrypted,
-        iv: post.iv,
-        encryption_version: post.encryption_version,
-        position: post.position,
-        parent_id: post.parent_id,
-        created_at: post.created_at,
-        updated_at: post.updated_at,
-    }))
+    Ok((
+        [(header::ETAG, revision_etag(post.revision))],
+        Json(PostResponse::from(post)),
+    ))
 }
 
+/// Update a post's content.
+///
+/// If the request carries an expected revision (`expected_revision` in the body or an
+/// `If-Match` ETag) that no longer matches, responds with 409 and the current post so
+/// the client can merge instead of silently overwriting another device's edit.
 async fn update_post(
     State(state): State<PostsState>,
     auth: Auth<AnyRole>,
     Path(uuid): Path<String>,
+    headers: HeaderMap,
     Json(payload): Json<UpdatePostRequest>,
-) -> Result<impl IntoResponse, ApiError> {
+) -> Result<Response, ApiError> {
     // Validate encryption matches user settings
     validate_encryption(
         &state.db,
@@ -338,7 +424,29 @@ async fn update_post(
     )
     .await?;
 
-    let found = state
+    // Only the growth of the post counts; a missing post is a 404 below.
+    let quotas = state.db.quotas();
+    let quota = quotas.lock(auth.user_id).await;
+    let current_size = quotas
+        .post_size(&uuid, auth.user_id)
+        .await
+        .db_err("Failed to get post size")?;
+    if let Some(current_size) = current_size {
+        check_quota(
+            &state.db,
+            &quota,
+            post_size(payload.title.as_deref(), &payload.content),
+            current_size,
+        )
+        .await?;
+    }
+
+    let expected_revision = match payload.expected_revision {
+        Some(revision) => Some(revision),
+        None => parse_if_match(&headers)?,
+    };
+
+    let result = state
         .db
         .update_post_with_attachments(UpdatePostParams {
             uuid: &uuid,
@@ -351,15 +459,46 @@ async fn update_post(
             iv: payload.iv.as_deref(),
             encryption_version: payload.encryption_version,
             attachment_uuids: payload.attachment_uuids.as_deref(),
+            expected_revision,
         })
         .await
         .db_err("Failed to update post")?;
 
-    if !found {
-        return Err(ApiError::not_found("Post not found"));
+    match result {
+        UpdatePostResult::Updated {
+            revision,
+            deleted_attachments,
+        } => {
+            spawn_server_hook(
+                state.settings.plugin_manager.as_ref(),
+                ServerHook::PostUpdated,
+                || {
+                    vec![
+                        ("user_uuid".into(), auth.claims.sub.clone()),
+                        ("post_uuid".into(), uuid.clone()),
+                        ("revision".into(), revision.to_string()),
+                    ]
+                },
+            );
+            fire_attachments_deleted(&state.settings, &auth.claims.sub, deleted_attachments);
+
+            Ok((
+                StatusCode::NO_CONTENT,
+                [(header::ETAG, revision_etag(revision))],
+            )
+                .into_response())
+        }
+        UpdatePostResult::NotFound => Err(ApiError::not_found("Post not found")),
+        UpdatePostResult::Conflict(current) => Ok((
+            StatusCode::CONFLICT,
+            [(header::ETAG, revision_etag(current.revision))],
+            Json(ConflictResponse {
+                error: "Post was modified by another client",
+                current: PostResponse::from(current),
+            }),
+        )
+            .into_response()),
     }
-
-    Ok(StatusCode::NO_CONTENT)
 }
 
 async fn delete_post(
@@ -377,6 +516,26 @@ async fn delete_post(
         return Err(ApiError::not_found("Post not found"));
     }
 
+    spawn_server_hook(
+        state.settings.plugin_manager.as_ref(),
+        ServerHook::PostDeleted,
+        || {
+            vec![
+                ("user_uuid".into(), auth.claims.sub.clone()),
+                ("post_uuid".into(), uuid.clone()),
+                (
+                    "children_deleted".into(),
+                    result.children_deleted.to_string(),
+                ),
+            ]
+        },
+    );
+    fire_attachments_deleted(
+        &state.settings,
+        &auth.claims.sub,
+        result.deleted_attachments,
+    );
+
     Ok(Json(DeleteResponse {
         deleted: true,
         children_deleted: result.children_deleted,
@@ -426,5 +585,21 @@ async fn move_post(
         return Err(ApiError::not_found("Post not found"));
     }
 
+    spawn_server_hook(
+        state.settings.plugin_manager.as_ref(),
+        ServerHook::PostMoved,
+        || {
+            let mut values = vec![
+                ("user_uuid".into(), auth.claims.sub.clone()),
+                ("post_uuid".into(), uuid.clone()),
+                ("position".into(), payload.position.to_string()),
+            ];
+            if let Some(parent) = &payload.parent_id {
+                values.push(("parent_uuid".into(), parent.clone()));
+            }
+            values
+        },
+    );
+
     Ok(StatusCode::NO_CONTENT)
 }
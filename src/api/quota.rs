@@ -0,0 +1,65 @@
+//! Storage quota checks shared by the endpoints that store user data, and
+//! the usage report of `/api/user/usage` and the admin API.
+
+use serde::Serialize;
+
+use super::error::{ApiError, ResultExt};
+use crate::db::{Database, QuotaLock, StorageUsage};
+
+#[derive(Serialize)]
+pub(super) struct UsageResponse {
+    /// Total bytes stored
+    used: u64,
+    /// Quota in bytes, null if unlimited
+    quota: Option<u64>,
+    #[serde(flatten)]
+    usage: StorageUsage,
+}
+
+/// Usage and quota of a user.
+pub(super) async fn usage_response(db: &Database, user_id: i64) -> Result<UsageResponse, ApiError> {
+    let quotas = db.quotas();
+    let usage = quotas
+        .usage(user_id)
+        .await
+        .db_err("Failed to get storage usage")?;
+    let quota = quotas.quota(user_id).await.db_err("Failed to get quota")?;
+    Ok(UsageResponse {
+        used: usage.total(),
+        quota,
+        usage,
+    })
+}
+
+/// Check that a write storing `added` bytes in place of `replaced` bytes
+/// keeps the user within their quota. Writes that do not grow usage always
+/// pass, so a user over quota can still shrink or delete data.
+///
+/// Keep `lock` held until the write is done, so concurrent writes can't all
+/// pass against the same usage.
+pub(super) async fn check_quota(
+    db: &Database,
+    lock: &QuotaLock,
+    added: u64,
+    replaced: u64,
+) -> Result<(), ApiError> {
+    if added <= replaced {
+        return Ok(());
+    }
+    let user_id = lock.user_id();
+    let quotas = db.quotas();
+    let Some(quota) = quotas.quota(user_id).await.db_err("Failed to get quota")? else {
+        return Ok(());
+    };
+    let used = quotas
+        .usage(user_id)
+        .await
+        .db_err("Failed to get storage usage")?
+        .total();
+    if used.saturating_sub(replaced) + added > quota {
+        return Err(ApiError::quota_exceeded(format!(
+            "Storage quota exceeded ({used} of {quota} bytes used)"
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,151 @@
+//! Delta sync API for clients that keep a local cache of the post tree.
+//!
+//! Clients pass the cursor from their previous sync and receive only posts
+//! that changed since then, plus the UUIDs of deleted posts.
+//! All endpoints require JWT authentication.
+
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    response::IntoResponse,
+    routing::get,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+use super::error::{ApiError, ResultExt};
+use crate::auth::{AnyRole, Auth, ServerSettings};
+use crate::db::{ChangedPost, Database, PostChange};
+use crate::impl_has_auth_backend;
+use crate::jwt::JwtConfig;
+
+/// Maximum number of changes returned in a single response.
+const MAX_LIMIT: i64 = 1000;
+
+/// State for sync endpoints.
+#[derive(Clone)]
+pub struct SyncState {
+    pub db: Database,
+    pub jwt: Arc<JwtConfig>,
+    pub settings: ServerSettings,
+}
+
+impl_has_auth_backend!(SyncState);
+
+pub fn router(state: SyncState) -> Router {
+    Router::new().route("/", get(sync)).with_state(state)
+}
+
+// --- Request/Response types ---
+
+#[derive(Deserialize)]
+struct SyncQuery {
+    #[serde(default)]
+    since: i64,
+    #[serde(default = "default_limit")]
+    limit: i64,
+}
+
+fn default_limit() -> i64 {
+    500
+}
+
+#[derive(Serialize)]
+struct ChangedPostResponse {
+    uuid: String,
+    title: Option<String>,
+    title_encrypted: bool,
+    title_iv: Option<String>,
+    content_encrypted: bool,
+    encryption_version: Option<i32>,
+    position: Option<i32>,
+    parent_id: Option<String>,
+    revision: i64,
+    created_at: String,
+    updated_at: String,
+}
+
+impl From<ChangedPost> for ChangedPostResponse {
+    fn from(post: ChangedPost) -> Self {
+        Self {
+            uuid: post.uuid,
+            title: post.title,
+            title_encrypted: post.title_encrypted,
+            title_iv: post.title_iv,
+            content_encrypted: post.content_encrypted,
+            encryption_version: post.encryption_version,
+            position: post.position,
+            parent_id: post.parent_id,
+            revision: post.revision,
+            created_at: post.created_at,
+            updated_at: post.updated_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct SyncResponse {
+    /// Pass as `since` on the next request.
+    cursor: i64,
+    /// More changes are available; request again with the new cursor.
+    has_more: bool,
+    /// The client's cursor is unknown to the server (e.g. database restored).
+    /// The client should discard its cache; `changes` starts from the beginning.
+    reset: bool,
+    changes: Vec<ChangedPostResponse>,
+    deleted: Vec<String>,
+}
+
+// --- Handlers ---
+
+async fn sync(
+    State(state): State<SyncState>,
+    auth: Auth<AnyRole>,
+    Query(query): Query<SyncQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    if query.since < 0 {
+        return Err(ApiError::bad_request("since must not be negative"));
+    }
+    let limit = query.limit.clamp(1, MAX_LIMIT);
+
+    let current = state
+        .db
+        .sync()
+        .current_seq(auth.user_id)
+        .await
+        .db_err("Failed to get sync cursor")?;
+
+    // A cursor ahead of the server can't be trusted, so start over
+    let reset = query.since > current;
+    let since = if reset { 0 } else { query.since };
+
+    // Fetch one extra row to know whether another page exists
+    let mut entries = state
+        .db
+        .sync()
+        .changes_since(auth.user_id, since, limit + 1)
+        .await
+        .db_err("Failed to list changes")?;
+
+    let has_more = entries.len() as i64 > limit;
+    entries.truncate(limit as usize);
+
+    let cursor = entries.last().map(PostChange::seq).unwrap_or(since);
+
+    let mut changes = Vec::new();
+    let mut deleted = Vec::new();
+    for entry in entries {
+        match entry {
+            PostChange::Upsert { post, .. } => changes.push(post.into()),
+            PostChange::Deleted { uuid, .. } => deleted.push(uuid),
+        }
+    }
+
+    Ok(Json(SyncResponse {
+        cursor,
+        has_more,
+        reset,
+        changes,
+        deleted,
+    }))
+}
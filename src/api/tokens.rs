@@ -24,6 +24,7 @@ use crate::auth::{
 use crate::db::{Database, UserRole};
 use crate::impl_has_auth_backend;
 use crate::jwt::JwtConfig;
+use crate::plugin::{ServerHook, spawn_server_hook};
 
 #[derive(Clone)]
 pub struct TokensState {
@@ -108,6 +109,17 @@ async fn logout(
         if let Ok(claims) = state.jwt.validate_refresh_token(refresh_token) {
             // Delete the refresh token from database
             let _ = state.db.tokens().delete_by_jti(&claims.jti).await;
+
+            spawn_server_hook(
+                state.settings.plugin_manager.as_ref(),
+                ServerHook::Logout,
+                || {
+                    vec![
+                        ("user_uuid".into(), claims.sub.clone()),
+                        ("jti".into(), claims.jti.clone()),
+                    ]
+                },
+            );
         }
     }
 
@@ -152,6 +164,18 @@ async fn revoke_all_tokens(
         .await
         .db_err("Failed to revoke tokens")?;
 
+    spawn_server_hook(
+        state.settings.plugin_manager.as_ref(),
+        ServerHook::TokenRevoked,
+        || {
+            vec![
+                ("user_uuid".into(), auth.claims.sub.clone()),
+                ("scope".into(), "all".into()),
+                ("count".into(), revoked_count.to_string()),
+            ]
+        },
+    );
+
     use axum::response::AppendHeaders;
     let secure = if state.settings.secure_cookies {
         "; Secure"
@@ -207,6 +231,21 @@ async fn revoke_token(
             .await
             .db_err("Failed to revoke token")?;
 
+        if revoked {
+            spawn_server_hook(
+                state.settings.plugin_manager.as_ref(),
+                ServerHook::TokenRevoked,
+                || {
+                    vec![
+                        ("user_uuid".into(), auth.claims.sub.clone()),
+                        ("scope".into(), "single".into()),
+                        ("count".into(), "1".into()),
+                        ("jti".into(), jti.clone()),
+                    ]
+                },
+            );
+        }
+
         Ok((StatusCode::OK, Json(RevokeResponse { revoked })))
     } else {
         // Token not found - already revoked or never existed
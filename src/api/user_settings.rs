@@ -1,12 +1,14 @@
 //! User settings API.
 //!
-//! Combines encryption settings with user-specific info (admin status, dashboard path).
+//! Combines encryption settings with user-specific info (admin status, dashboard path),
+//! and reports the user's storage usage.
 
 use axum::{Json, Router, extract::State, response::IntoResponse, routing::get};
 use serde::Serialize;
 use std::sync::Arc;
 
 use super::error::{ApiError, ResultExt};
+use super::quota::usage_response;
 use crate::auth::{AnyRole, Auth, ServerSettings};
 use crate::db::{Database, UserRole};
 use crate::impl_has_auth_backend;
@@ -26,6 +28,7 @@ impl_has_auth_backend!(UserSettingsState);
 pub fn router(state: UserSettingsState) -> Router {
     Router::new()
         .route("/settings", get(get_settings))
+        .route("/usage", get(get_usage))
         .with_state(state)
 }
 
@@ -76,6 +79,14 @@ async fn get_settings(
     }))
 }
 
+/// Get the bytes the user stores (total and by kind) and their quota.
+async fn get_usage(
+    State(state): State<UserSettingsState>,
+    auth: Auth<AnyRole>,
+) -> Result<impl IntoResponse, ApiError> {
+    Ok(Json(usage_response(&state.db, auth.user_id).await?))
+}
+
 fn base64_encode(data: &[u8]) -> String {
     use base64::Engine;
     base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(data)
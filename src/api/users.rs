@@ -10,10 +10,11 @@ use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
 use super::error::{ApiError, ResultExt, validate_uuid};
-use crate::auth::{OptionalAuth, ServerSettings};
+use crate::auth::{OptionalAuth, ServerSettings, extract_client_ip};
 use crate::db::{Database, UserRole};
 use crate::impl_has_auth_backend;
 use crate::jwt::JwtConfig;
+use crate::plugin::{ServerHook, ServerPreHook, check_server_pre_hook, spawn_server_hook};
 use crate::rate_limit::{RateLimitConfig, rate_limit_user_create};
 
 #[derive(Clone)]
@@ -60,8 +61,16 @@ struct CreateUserResponse {
 
 async fn create_user(
     State(state): State<UsersState>,
-    Json(payload): Json<CreateUserRequest>,
+    request: axum::extract::Request,
 ) -> Result<impl IntoResponse, ApiError> {
+    let (parts, body) = request.into_parts();
+    let Json(payload): Json<CreateUserRequest> = Json::from_bytes(
+        &axum::body::to_bytes(body, 1024 * 1024)
+            .await
+            .map_err(|_| ApiError::bad_request("Invalid request body"))?,
+    )
+    .map_err(|_| ApiError::bad_request("Invalid JSON"))?;
+
     let username = payload.username.trim();
 
     if username.is_empty() {
@@ -98,6 +107,20 @@ async fn create_user(
         return Err(ApiError::conflict("Username is already taken"));
     }
 
+    check_server_pre_hook(
+        state.settings.plugin_manager.as_ref(),
+        ServerPreHook::PreRegister,
+        || {
+            let mut values = vec![("username".into(), username.to_string())];
+            if let Ok(ip) = extract_client_ip(&parts, state.settings.ip_extractor.as_ref()) {
+                values.push(("ip".into(), ip));
+            }
+            values
+        },
+    )
+    .await
+    .map_err(ApiError::forbidden)?;
+
     state
         .db
         .users()
@@ -105,6 +128,17 @@ async fn create_user(
         .await
         .db_err("Failed to create user")?;
 
+    spawn_server_hook(
+        state.settings.plugin_manager.as_ref(),
+        ServerHook::UserRegistered,
+        || {
+            vec![
+                ("user_uuid".into(), uuid.clone()),
+                ("username".into(), username.to_string()),
+            ]
+        },
+    );
+
     Ok((
         StatusCode::CREATED,
         Json(CreateUserResponse {
@@ -132,7 +166,8 @@ async fn delete_user(
 
     // For activated users, require authentication
     if user.activated {
-        let claims = auth_user
+        let claims = &auth_user
+            .as_ref()
             .ok_or_else(|| ApiError::unauthorized("Authentication required"))?
             .claims;
 
@@ -156,5 +191,20 @@ async fn delete_user(
         return Err(ApiError::not_found("User not found"));
     }
 
+    spawn_server_hook(
+        state.settings.plugin_manager.as_ref(),
+        ServerHook::UserDeleted,
+        || {
+            let mut values = vec![
+                ("user_uuid".into(), user.uuid.clone()),
+                ("username".into(), user.username.clone()),
+            ];
+            if let Some(actor) = &auth_user {
+                values.push(("deleted_by".into(), actor.claims.sub.clone()));
+            }
+            values
+        },
+    );
+
     Ok(StatusCode::NO_CONTENT)
 }
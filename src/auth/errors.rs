@@ -17,6 +17,8 @@ pub enum AuthErrorKind {
     AccountNotActivated,
     InsufficientRole,
     DatabaseError,
+    /// A plugin pre-hook denied the request. Contains the reason shown to the client.
+    Denied(String),
 }
 
 /// API authentication errors (returns JSON and clears cookies).
@@ -41,15 +43,15 @@ impl ApiAuthError {
             | AuthErrorKind::InvalidToken
             | AuthErrorKind::TokenRevoked
             | AuthErrorKind::UserNotFound => StatusCode::UNAUTHORIZED,
-            AuthErrorKind::AccountNotActivated | AuthErrorKind::InsufficientRole => {
-                StatusCode::FORBIDDEN
-            }
+            AuthErrorKind::AccountNotActivated
+            | AuthErrorKind::InsufficientRole
+            | AuthErrorKind::Denied(_) => StatusCode::FORBIDDEN,
             AuthErrorKind::DatabaseError => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 
-    fn message(&self) -> &'static str {
-        match self.kind {
+    fn message(&self) -> &str {
+        match &self.kind {
             AuthErrorKind::NotAuthenticated => "Not authenticated",
             AuthErrorKind::InvalidToken => "Invalid or expired token",
             AuthErrorKind::TokenRevoked => "Token has been revoked",
@@ -57,6 +59,7 @@ impl ApiAuthError {
             AuthErrorKind::AccountNotActivated => "Account not activated",
             AuthErrorKind::InsufficientRole => "Insufficient permissions",
             AuthErrorKind::DatabaseError => "Database error",
+            AuthErrorKind::Denied(reason) => reason,
         }
     }
 }
@@ -68,8 +71,8 @@ impl IntoResponse for ApiAuthError {
         use serde::Serialize;
 
         #[derive(Serialize)]
-        struct ErrorResponse {
-            error: &'static str,
+        struct ErrorResponse<'a> {
+            error: &'a str,
         }
 
         // Clear both cookies on auth errors
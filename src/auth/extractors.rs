@@ -32,7 +32,7 @@ use super::ip::extract_client_ip;
 use super::state::{HasAssetAuthBackend, HasAuthBackend};
 use super::types::{ActivatedAuthenticatedUser, AuthenticatedUser, AuthenticatedUserWithSession};
 use crate::db::UserRole;
-use crate::plugin::{Hook, ServerHook};
+use crate::plugin::{ServerHook, ServerPreHook, check_server_pre_hook, spawn_server_hook};
 
 tokio::task_local! {
     pub static NEW_ACCESS_TOKEN_COOKIE: RefCell<Option<String>>;
@@ -141,6 +141,25 @@ where
         return Err(AuthErrorKind::AccountNotActivated);
     }
 
+    // Let plugins veto the refresh before any state changes.
+    check_server_pre_hook(
+        state.plugin_manager(),
+        ServerPreHook::PreTokenRefresh,
+        || {
+            let mut values = vec![
+                ("user_uuid".into(), user.uuid.clone()),
+                ("username".into(), user.username.clone()),
+                ("ip".into(), client_ip.clone()),
+            ];
+            if let Some(old_ip) = &active_token.last_ip {
+                values.push(("old_ip".into(), old_ip.clone()));
+            }
+            values
+        },
+    )
+    .await
+    .map_err(AuthErrorKind::Denied)?;
+
     if active_token.last_ip.as_ref() != Some(&client_ip) {
         if let Err(e) = state
             .db()
@@ -153,27 +172,16 @@ where
     }
 
     // Fire ip-change hook to notify plugins.
-    let ip_change_hook = Hook::Server(ServerHook::IpChange);
-    if let Some(pm) = state
-        .plugin_manager()
-        .filter(|pm| pm.has_hook(&ip_change_hook))
-    {
-        let old_ip = active_token.last_ip.clone().unwrap_or_default();
-        let new_ip = client_ip.clone();
-        let user_uuid = refresh_claims.sub.clone();
-        let pm = pm.clone();
-        tokio::spawn(async move {
-            pm.fire_hook(
-                ip_change_hook,
-                vec![
-                    ("old_ip".into(), old_ip),
-                    ("new_ip".into(), new_ip),
-                    ("user_uuid".into(), user_uuid),
-                ],
-            )
-            .await;
-        });
-    }
+    spawn_server_hook(state.plugin_manager(), ServerHook::IpChange, || {
+        vec![
+            (
+                "old_ip".into(),
+                active_token.last_ip.clone().unwrap_or_default(),
+            ),
+            ("new_ip".into(), client_ip.clone()),
+            ("user_uuid".into(), refresh_claims.sub.clone()),
+        ]
+    });
 
     let access_result = state
         .jwt()
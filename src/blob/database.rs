@@ -0,0 +1,105 @@
+//! Blobs in the `blobs` table of the main database.
+//!
+//! The default store, and where blobs from before pluggable storage live
+//! until `crowchiper storage migrate` moves them out.
+
+use std::io;
+use std::ops::Range;
+
+use axum::body::Bytes;
+use futures::future::BoxFuture;
+use futures::stream;
+use sqlx::SqlitePool;
+
+use super::{BlobStore, BlobStream};
+
+pub struct DatabaseBlobStore {
+    pool: SqlitePool,
+}
+
+impl DatabaseBlobStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Keys of every blob in the table.
+    pub async fn keys(&self) -> Result<Vec<String>, sqlx::Error> {
+        let rows: Vec<(String,)> = sqlx::query_as("SELECT key FROM blobs ORDER BY key")
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.into_iter().map(|r| r.0).collect())
+    }
+}
+
+fn io_error(e: sqlx::Error) -> io::Error {
+    io::Error::other(e)
+}
+
+impl BlobStore for DatabaseBlobStore {
+    fn put<'a>(&'a self, key: &'a str, data: &'a [u8]) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async move {
+            sqlx::query("INSERT OR IGNORE INTO blobs (key, data) VALUES (?, ?)")
+                .bind(key)
+                .bind(data)
+                .execute(&self.pool)
+                .await
+                .map_err(io_error)?;
+            Ok(())
+        })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<Option<Vec<u8>>>> {
+        Box::pin(async move {
+            let row: Option<(Vec<u8>,)> = sqlx::query_as("SELECT data FROM blobs WHERE key = ?")
+                .bind(key)
+                .fetch_optional(&self.pool)
+                .await
+                .map_err(io_error)?;
+            Ok(row.map(|r| r.0))
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async move {
+            sqlx::query("DELETE FROM blobs WHERE key = ?")
+                .bind(key)
+                .execute(&self.pool)
+                .await
+                .map_err(io_error)?;
+            Ok(())
+        })
+    }
+
+    fn size<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<Option<u64>>> {
+        Box::pin(async move {
+            let row: Option<(i64,)> =
+                sqlx::query_as("SELECT length(data) FROM blobs WHERE key = ?")
+                    .bind(key)
+                    .fetch_optional(&self.pool)
+                    .await
+                    .map_err(io_error)?;
+            Ok(row.map(|r| r.0 as u64))
+        })
+    }
+
+    fn read_range<'a>(
+        &'a self,
+        key: &'a str,
+        range: Range<u64>,
+    ) -> BoxFuture<'a, io::Result<Option<BlobStream>>> {
+        Box::pin(async move {
+            // substr() counts from 1 and works on bytes for BLOBs.
+            let row: Option<(Vec<u8>,)> =
+                sqlx::query_as("SELECT substr(data, ?, ?) FROM blobs WHERE key = ?")
+                    .bind(range.start as i64 + 1)
+                    .bind((range.end - range.start) as i64)
+                    .bind(key)
+                    .fetch_optional(&self.pool)
+                    .await
+                    .map_err(io_error)?;
+            Ok(row.map(|(chunk,)| {
+                Box::pin(stream::once(async move { Ok(Bytes::from(chunk)) })) as BlobStream
+            }))
+        })
+    }
+}
@@ -0,0 +1,153 @@
+//! Blobs as files under a local directory, fanned out by key prefix
+//! (`<dir>/ab/abcdef...`) to keep directories small.
+
+use std::io;
+use std::io::SeekFrom;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use axum::body::Bytes;
+use futures::future::BoxFuture;
+use futures::stream;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+use super::{BlobStore, BlobStream, check_key};
+
+/// Bytes read from disk per streamed chunk.
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+pub struct FsBlobStore {
+    dir: PathBuf,
+}
+
+impl FsBlobStore {
+    /// Use `dir`, creating it if missing.
+    pub fn new(dir: &Path) -> io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    fn path(&self, key: &str) -> io::Result<PathBuf> {
+        check_key(key)?;
+        Ok(self.dir.join(&key[..2]).join(key))
+    }
+}
+
+impl BlobStore for FsBlobStore {
+    fn put<'a>(&'a self, key: &'a str, data: &'a [u8]) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async move {
+            let path = self.path(key)?;
+            if tokio::fs::try_exists(&path).await? {
+                return Ok(());
+            }
+            tokio::fs::create_dir_all(path.parent().unwrap()).await?;
+            // Write via a temporary file and rename, so a reader never sees
+            // a partial blob.
+            let tmp = path.with_extension(format!("tmp{}", uuid::Uuid::new_v4().simple()));
+            tokio::fs::write(&tmp, data).await?;
+            if let Err(e) = tokio::fs::rename(&tmp, &path).await {
+                tokio::fs::remove_file(&tmp).await.ok();
+                return Err(e);
+            }
+            Ok(())
+        })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<Option<Vec<u8>>>> {
+        Box::pin(async move {
+            match tokio::fs::read(self.path(key)?).await {
+                Ok(data) => Ok(Some(data)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            }
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async move {
+            match tokio::fs::remove_file(self.path(key)?).await {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(()),
+            }
+        })
+    }
+
+    fn size<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<Option<u64>>> {
+        Box::pin(async move {
+            match tokio::fs::metadata(self.path(key)?).await {
+                Ok(metadata) => Ok(Some(metadata.len())),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e),
+            }
+        })
+    }
+
+    fn read_range<'a>(
+        &'a self,
+        key: &'a str,
+        range: Range<u64>,
+    ) -> BoxFuture<'a, io::Result<Option<BlobStream>>> {
+        Box::pin(async move {
+            let mut file = match tokio::fs::File::open(self.path(key)?).await {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(e) => return Err(e),
+            };
+            file.seek(SeekFrom::Start(range.start)).await?;
+            let remaining = range.end - range.start;
+            let chunks =
+                stream::try_unfold((file, remaining), |(mut file, remaining)| async move {
+                    if remaining == 0 {
+                        return Ok::<_, io::Error>(None);
+                    }
+                    let mut chunk = vec![0; remaining.min(CHUNK_SIZE) as usize];
+                    file.read_exact(&mut chunk).await?;
+                    let remaining = remaining - chunk.len() as u64;
+                    Ok(Some((Bytes::from(chunk), (file, remaining))))
+                });
+            Ok(Some(Box::pin(chunks) as BlobStream))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::blob::content_key;
+    use futures::TryStreamExt;
+
+    #[tokio::test]
+    async fn put_get_delete() {
+        let dir = std::env::temp_dir().join(format!("crowchiper-fs-blobs-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        let store = FsBlobStore::new(&dir).unwrap();
+        let key = content_key(b"hello");
+
+        assert_eq!(store.get(&key).await.unwrap(), None);
+        store.put(&key, b"hello").await.unwrap();
+        // Putting the same content again is a no-op.
+        store.put(&key, b"hello").await.unwrap();
+        assert_eq!(store.get(&key).await.unwrap(), Some(b"hello".to_vec()));
+        assert!(dir.join(&key[..2]).join(&key).is_file());
+
+        assert_eq!(store.size(&key).await.unwrap(), Some(5));
+        let chunks: Vec<_> = store
+            .read_range(&key, 1..4)
+            .await
+            .unwrap()
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(chunks.concat(), b"ell");
+
+        store.delete(&key).await.unwrap();
+        store.delete(&key).await.unwrap();
+        assert_eq!(store.get(&key).await.unwrap(), None);
+        assert!(store.get("../../etc/passwd").await.is_err());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
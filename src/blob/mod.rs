@@ -0,0 +1,199 @@
+//! Pluggable storage for attachment blobs.
+//!
+//! Attachment rows only hold blob keys; the bytes live in a [`BlobStore`].
+//! Keys are the SHA-256 of the content ([`content_key`]), so identical blobs
+//! are stored once and a blob is only deleted once no row refers to it.
+
+mod database;
+mod fs;
+mod s3;
+
+use std::fmt;
+use std::io;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::body::Bytes;
+use futures::future::BoxFuture;
+use futures::stream::{self, BoxStream};
+use sqlx::SqlitePool;
+
+pub use database::DatabaseBlobStore;
+pub use fs::FsBlobStore;
+pub use s3::{S3BlobStore, S3Config};
+
+/// Chunks of a blob, as returned by [`BlobStore::read_range`].
+pub type BlobStream = BoxStream<'static, io::Result<Bytes>>;
+
+/// A key-value store for immutable blobs.
+///
+/// Putting a key that already exists is not an error; since keys are content
+/// hashes the stored bytes are the same. Getting a missing key returns `None`
+/// and deleting one succeeds.
+pub trait BlobStore: Send + Sync {
+    fn put<'a>(&'a self, key: &'a str, data: &'a [u8]) -> BoxFuture<'a, io::Result<()>>;
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<Option<Vec<u8>>>>;
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<()>>;
+
+    /// Size of a blob in bytes. The default reads the whole blob.
+    fn size<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<Option<u64>>> {
+        Box::pin(async move { Ok(self.get(key).await?.map(|data| data.len() as u64)) })
+    }
+
+    /// Stream the bytes of `range`, which must lie within the blob. The
+    /// default reads the whole blob and sends the range as one chunk.
+    fn read_range<'a>(
+        &'a self,
+        key: &'a str,
+        range: Range<u64>,
+    ) -> BoxFuture<'a, io::Result<Option<BlobStream>>> {
+        Box::pin(async move {
+            let Some(data) = self.get(key).await? else {
+                return Ok(None);
+            };
+            let chunk = data
+                .get(range.start as usize..range.end as usize)
+                .ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidInput, "range out of bounds")
+                })?;
+            let chunk = Bytes::copy_from_slice(chunk);
+            Ok(Some(
+                Box::pin(stream::once(async move { Ok(chunk) })) as BlobStream
+            ))
+        })
+    }
+}
+
+/// Content-addressed key for `data`: its SHA-256 as lowercase hex.
+pub fn content_key(data: &[u8]) -> String {
+    openssl::sha::sha256(data)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Keys are generated by [`content_key`]; refuse anything else so a key
+/// can never escape a directory or bucket prefix.
+fn check_key(key: &str) -> io::Result<()> {
+    if key.len() >= 4 && key.bytes().all(|b| b.is_ascii_alphanumeric()) {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid blob key '{key}'"),
+        ))
+    }
+}
+
+/// Where attachment blobs are stored, as given on the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlobStoreSpec {
+    /// The `blobs` table of the main database.
+    Database,
+    /// Files under a local directory.
+    Fs(PathBuf),
+    /// Objects in an S3-compatible bucket, under an optional key prefix.
+    S3 { bucket: String, prefix: String },
+}
+
+impl fmt::Display for BlobStoreSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlobStoreSpec::Database => write!(f, "database"),
+            BlobStoreSpec::Fs(dir) => write!(f, "fs:{}", dir.display()),
+            BlobStoreSpec::S3 { bucket, prefix } if prefix.is_empty() => write!(f, "s3:{bucket}"),
+            BlobStoreSpec::S3 { bucket, prefix } => write!(f, "s3:{bucket}/{prefix}"),
+        }
+    }
+}
+
+/// Parse `database`, `fs:<dir>` or `s3:<bucket>[/<prefix>]`.
+pub fn parse_blob_store(value: &str) -> Result<BlobStoreSpec, String> {
+    if value == "database" {
+        return Ok(BlobStoreSpec::Database);
+    }
+    if let Some(dir) = value.strip_prefix("fs:") {
+        if dir.is_empty() {
+            return Err("fs blob store needs a directory, e.g. fs:/var/lib/crowchiper".into());
+        }
+        return Ok(BlobStoreSpec::Fs(PathBuf::from(dir)));
+    }
+    if let Some(rest) = value.strip_prefix("s3:") {
+        let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+        let valid_bucket = !bucket.is_empty()
+            && bucket
+                .bytes()
+                .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'.');
+        if !valid_bucket {
+            return Err(format!("invalid S3 bucket name '{bucket}'"));
+        }
+        return Ok(BlobStoreSpec::S3 {
+            bucket: bucket.to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+        });
+    }
+    Err(format!(
+        "invalid blob store '{value}': expected database, fs:<dir> or s3:<bucket>[/<prefix>]"
+    ))
+}
+
+/// Open the store described by `spec`. `s3` supplies the endpoint and
+/// credentials for `s3:` specs; `pool` backs the database store.
+pub fn open_blob_store(
+    spec: &BlobStoreSpec,
+    s3: Option<&S3Config>,
+    pool: &SqlitePool,
+) -> Result<Arc<dyn BlobStore>, String> {
+    match spec {
+        BlobStoreSpec::Database => Ok(Arc::new(DatabaseBlobStore::new(pool.clone()))),
+        BlobStoreSpec::Fs(dir) => FsBlobStore::new(dir)
+            .map(|store| Arc::new(store) as Arc<dyn BlobStore>)
+            .map_err(|e| format!("failed to open blob directory {}: {e}", dir.display())),
+        BlobStoreSpec::S3 { bucket, prefix } => {
+            let config =
+                s3.ok_or("s3 blob store needs AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY")?;
+            S3BlobStore::new(config.clone(), bucket, prefix)
+                .map(|store| Arc::new(store) as Arc<dyn BlobStore>)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_key_is_sha256_hex() {
+        assert_eq!(
+            content_key(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        check_key(&content_key(b"abc")).unwrap();
+        assert!(check_key("../etc/passwd").is_err());
+        assert!(check_key("").is_err());
+    }
+
+    #[test]
+    fn parse_specs() {
+        assert_eq!(parse_blob_store("database"), Ok(BlobStoreSpec::Database));
+        assert_eq!(
+            parse_blob_store("fs:/var/blobs"),
+            Ok(BlobStoreSpec::Fs(PathBuf::from("/var/blobs")))
+        );
+        assert_eq!(
+            parse_blob_store("s3:notes/prod/attachments/"),
+            Ok(BlobStoreSpec::S3 {
+                bucket: "notes".into(),
+                prefix: "prod/attachments".into()
+            })
+        );
+        assert_eq!(
+            parse_blob_store("s3:notes").unwrap().to_string(),
+            "s3:notes"
+        );
+        assert!(parse_blob_store("fs:").is_err());
+        assert!(parse_blob_store("s3:Bad_Bucket").is_err());
+        assert!(parse_blob_store("ftp:x").is_err());
+    }
+}
@@ -8,6 +8,10 @@ use tracing::{error, info};
 /// Attachments with reference_count = 0 older than this will be deleted.
 const ORPHANED_ATTACHMENT_AGE_MINUTES: i64 = 60;
 
+/// Age threshold for abandoned resumable uploads (in hours).
+/// Uploads that received no chunk for this long are deleted with their staged data.
+const ABANDONED_UPLOAD_AGE_HOURS: i64 = 24;
+
 /// Interval between cleanup runs.
 const CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60); // 1 hour
 
@@ -51,6 +55,24 @@ pub async fn run_cleanup(db: &Database) {
         Ok(_) => {}
         Err(e) => error!("Failed to clean up orphaned attachments: {}", e),
     }
+
+    // Clean up abandoned resumable uploads
+    match db
+        .uploads()
+        .cleanup_abandoned(ABANDONED_UPLOAD_AGE_HOURS)
+        .await
+    {
+        Ok(count) if count > 0 => info!("Cleaned up {} abandoned uploads", count),
+        Ok(_) => {}
+        Err(e) => error!("Failed to clean up abandoned uploads: {}", e),
+    }
+
+    // Delete blobs of deleted attachments from the blob store
+    match db.attachments().purge_deleted_blobs().await {
+        Ok(count) if count > 0 => info!("Deleted {} unreferenced attachment blobs", count),
+        Ok(_) => {}
+        Err(e) => error!("Failed to delete attachment blobs: {}", e),
+    }
 }
 
 /// Spawn a background task that runs cleanup periodically.
@@ -1,16 +1,27 @@
 //! CLI argument parsing, validation, and startup helpers.
 
+mod plugin_command;
+mod storage_command;
+
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use crate::ServerConfig;
+use crate::blob::{BlobStore, BlobStoreSpec, S3Config, parse_blob_store};
 use crate::db::Database;
 use crate::names::generate_name;
-use crate::plugin::{PluginManager, PluginRuntime, PluginSpec, parse_plugin_spec};
-use clap::Parser;
+use crate::plugin::{
+    LoadOptions, PluginManager, PluginRuntime, PluginSpec, SignaturePolicy, TrustedKey,
+    parse_plugin_spec,
+};
+use clap::{Parser, Subcommand};
 use tracing::{error, info};
 use url::Url;
 use uuid::Uuid;
 
+pub use plugin_command::{PluginCommand, run_plugin_command};
+pub use storage_command::{StorageCommand, run_storage_command};
+
 const MIN_JWT_SECRET_LENGTH: usize = 32;
 
 #[derive(clap::ValueEnum, Clone, Debug, Default)]
@@ -194,14 +205,80 @@ pub struct Args {
     #[arg(short, long, value_enum)]
     pub ip_header: Option<ClientIpHeader>,
 
-    /// WASM plugin. Format: path.wasm[:perm,timeout=5|500ms,var-k=v]
-    /// Permissions: net, env-VAR, fs-read=/p, fs-write=/p. Timeout default: 5s, min: 10ms.
+    /// WASM plugin. Format: path.wasm[:perm,timeout=5|500ms,var-k=v,fail-closed=pre-hook,kv-quota=64k,fuel=N,memory=32m,stack=1m]
+    /// Permissions: net, net=cidr:ports, net-dns, http=host, env-VAR, fs-read=/p, fs-write=/p.
+    /// Timeout default: 5s, min: 10ms.
+    /// Pre-hooks fail open on plugin timeout unless listed with fail-closed.
+    /// Key-value storage quota default: 1m.
+    /// Limits default: fuel=10000000, memory=10m, stack=512k (max 1000000000, 512m, 8m).
+    /// Circuit breaker default: breaker=5/60 (failures/cooldown secs), or breaker=off.
     #[arg(long, value_parser = parse_plugin_spec)]
     pub plugin: Vec<PluginSpec>,
 
+    /// TOML file listing plugins with their permissions, vars, limits and
+    /// enabled flag. Vars can read secrets from files or environment variables.
+    /// Loaded after any --plugin specs.
+    #[arg(long)]
+    pub plugin_config: Option<PathBuf>,
+
     /// Behavior when a plugin fails to load: abort (default) or warn
     #[arg(long, default_value = "abort", value_enum)]
     pub plugin_error: PluginErrorMode,
+
+    /// Directory admins may load additional plugins from at runtime via
+    /// POST /api/admin/plugins. Without it, only --plugin plugins can be managed.
+    #[arg(long)]
+    pub plugin_dir: Option<PathBuf>,
+
+    /// Directory to cache compiled plugins in, so restarts skip recompiling
+    /// unchanged .wasm files. Created if missing; must only be writable by
+    /// the server.
+    #[arg(long)]
+    pub plugin_cache_dir: Option<PathBuf>,
+
+    /// PEM public key of a trusted plugin publisher. Can be repeated. With at
+    /// least one key, a plugin's plugin.wasm.sig must verify under one of
+    /// them before it is loaded.
+    #[arg(long)]
+    pub plugin_trusted_key: Vec<PathBuf>,
+
+    /// Whether unsigned plugins load (with a warning) or are refused.
+    /// `required` needs at least one --plugin-trusted-key.
+    #[arg(long, default_value = "optional", value_enum)]
+    pub plugin_signatures: PluginSignatureMode,
+
+    /// Where attachment data is stored: database, fs:<dir> or
+    /// s3:<bucket>[/<prefix>]. S3 credentials are read from the
+    /// AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY env vars.
+    #[arg(long, default_value = "database", value_parser = parse_blob_store)]
+    pub blob_store: BlobStoreSpec,
+
+    /// Endpoint of the S3-compatible service for s3: blob stores
+    #[arg(long, default_value = "https://s3.amazonaws.com")]
+    pub s3_endpoint: Url,
+
+    /// Region S3 requests are signed for
+    #[arg(long, default_value = "us-east-1")]
+    pub s3_region: String,
+
+    /// Directory resumable uploads are staged in until they are finalized
+    /// (default: <database>.uploads next to the database file)
+    #[arg(long)]
+    pub upload_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Offline tools. Without a subcommand, the server starts.
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Inspect, validate and test WASM plugins without starting the server
+    #[command(subcommand)]
+    Plugin(PluginCommand),
+    /// Manage attachment storage
+    #[command(subcommand)]
+    Storage(StorageCommand),
 }
 
 #[derive(clap::ValueEnum, Clone, Debug, Default)]
@@ -213,6 +290,15 @@ pub enum PluginErrorMode {
     Warn,
 }
 
+#[derive(clap::ValueEnum, Clone, Debug, Default)]
+pub enum PluginSignatureMode {
+    /// Verify signatures that exist; load unsigned plugins with a warning
+    #[default]
+    Optional,
+    /// Refuse plugins without a signature
+    Required,
+}
+
 fn validate_base_path(s: &str) -> Result<String, String> {
     if s.is_empty() {
         return Ok(String::new());
@@ -242,6 +328,66 @@ pub fn init_logging(format: &LogFormat) {
     }
 }
 
+/// Load the trusted plugin publisher keys and build the signature policy.
+/// Returns None and logs an error if a key cannot be loaded or signatures are
+/// required without any key.
+pub fn load_signature_policy(
+    key_paths: &[PathBuf],
+    mode: &PluginSignatureMode,
+) -> Option<SignaturePolicy> {
+    let require = matches!(mode, PluginSignatureMode::Required);
+    if require && key_paths.is_empty() {
+        error!("--plugin-signatures required needs at least one --plugin-trusted-key");
+        return None;
+    }
+
+    let mut trusted_keys = Vec::new();
+    for path in key_paths {
+        match TrustedKey::load(path) {
+            Ok(key) => trusted_keys.push(key),
+            Err(e) => {
+                error!(path = %path.display(), error = %e, "Failed to load trusted plugin key");
+                return None;
+            }
+        }
+    }
+    Some(SignaturePolicy {
+        trusted_keys,
+        require,
+    })
+}
+
+/// S3 endpoint and credentials, if AWS_ACCESS_KEY_ID and
+/// AWS_SECRET_ACCESS_KEY are set.
+pub fn load_s3_config(endpoint: &Url, region: &str) -> Option<S3Config> {
+    let access_key = std::env::var("AWS_ACCESS_KEY_ID").ok()?;
+    let secret_key = std::env::var("AWS_SECRET_ACCESS_KEY").ok()?;
+    Some(S3Config {
+        endpoint: endpoint.clone(),
+        region: region.to_string(),
+        access_key,
+        secret_key,
+    })
+}
+
+/// Open the blob store for `spec`, logging errors if it fails.
+pub fn load_blob_store(
+    db: &Database,
+    spec: &BlobStoreSpec,
+    s3: Option<&S3Config>,
+) -> Option<Arc<dyn BlobStore>> {
+    match crate::blob::open_blob_store(spec, s3, db.pool()) {
+        Ok(store) => {
+            info!(store = %spec, "Blob store opened");
+            Some(store)
+        }
+        Err(e) => {
+            error!(store = %spec, error = %e, "Failed to open blob store");
+            None
+        }
+    }
+}
+
 /// Load JWT secret from environment variable or file.
 /// Returns None and logs an error if the secret cannot be loaded.
 pub fn load_jwt_secret(jwt_secret_file: Option<&str>) -> Option<String> {
@@ -343,7 +489,8 @@ pub async fn handle_create_admin(db: &Database, rp_origin: &str, base: Option<&s
     }
 }
 
-/// Build ServerConfig from validated arguments.
+/// Build ServerConfig from validated arguments. Returns None if the plugins
+/// can't be managed together; the reason has already been logged.
 pub fn build_config(
     base: Option<String>,
     db: Database,
@@ -354,16 +501,28 @@ pub fn build_config(
     csp_nonce: bool,
     ip_header: Option<ClientIpHeader>,
     plugins: Vec<PluginRuntime>,
-) -> ServerConfig {
+    plugin_dir: Option<PathBuf>,
+    load_options: LoadOptions,
+) -> Option<ServerConfig> {
     let secure_cookies = rp_origin.scheme() == "https";
 
-    let plugin_manager = if plugins.is_empty() {
+    let plugin_manager = if plugin_dir.is_none() && plugins.is_empty() {
         None
     } else {
-        Some(Arc::new(PluginManager::new(plugins)))
+        let manager = match PluginManager::new(plugins) {
+            Ok(manager) => manager.with_load_options(load_options),
+            Err(e) => {
+                error!(error = %e, "Failed to start plugins");
+                return None;
+            }
+        };
+        Some(Arc::new(match plugin_dir {
+            Some(dir) => manager.with_plugin_dir(dir),
+            None => manager,
+        }))
     };
 
-    ServerConfig {
+    Some(ServerConfig {
         base,
         db,
         rp_id,
@@ -374,7 +533,7 @@ pub fn build_config(
         csp_nonce,
         ip_extractor: ip_header.map(IpExtractor::from),
         plugin_manager,
-    }
+    })
 }
 
 /// Open the database, logging errors if it fails.
@@ -0,0 +1,181 @@
+//! `crowchiper plugin ...`: load a plugin exactly like the server does and
+//! report on it, so authors can test a component without starting the server.
+
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use clap::Subcommand;
+use tracing::error;
+
+use crate::db::Database;
+use crate::plugin::{
+    Hook, HookEvent, LoadOptions, PluginError, PluginRuntime, PluginSpec, hook_target,
+    parse_plugin_spec,
+};
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum PluginCommand {
+    /// Load a plugin and print its config(), hooks, limits and imports
+    Inspect {
+        /// Plugin to load, in the same format as --plugin
+        #[arg(value_parser = parse_plugin_spec)]
+        spec: PluginSpec,
+    },
+    /// Check a --plugin spec and print it in normalized form
+    Validate {
+        /// Spec to check; the .wasm file is not read
+        spec: String,
+    },
+    /// Load a plugin, fire one hook event at it and print the result
+    Fire {
+        /// Plugin to load, in the same format as --plugin
+        #[arg(value_parser = parse_plugin_spec)]
+        spec: PluginSpec,
+        /// Hook to fire, e.g. ip-change or pre-login. `schedule` fires the
+        /// plugin's first schedule hook.
+        #[arg(long)]
+        hook: String,
+        /// Event value as key=value. Can be repeated.
+        #[arg(long = "value", value_parser = parse_event_value)]
+        values: Vec<(String, String)>,
+    },
+}
+
+fn parse_event_value(s: &str) -> Result<(String, String), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid value '{s}': expected key=value"))?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Run a plugin subcommand. Returns false if it failed; the reason has
+/// already been printed or logged.
+pub async fn run_plugin_command(command: &PluginCommand, options: &LoadOptions) -> bool {
+    match command {
+        PluginCommand::Inspect { spec } => match load(spec, options).await {
+            Ok(plugin) => {
+                print_plugin(&plugin);
+                true
+            }
+            Err(e) => {
+                error!(path = %spec.path.display(), error = %e, "Failed to load plugin");
+                false
+            }
+        },
+        PluginCommand::Validate { spec } => match parse_plugin_spec(spec) {
+            Ok(spec) => {
+                println!("{spec}");
+                true
+            }
+            Err(e) => {
+                error!(error = %e, "Invalid plugin spec");
+                false
+            }
+        },
+        PluginCommand::Fire { spec, hook, values } => {
+            let plugin = match load(spec, options).await {
+                Ok(plugin) => plugin,
+                Err(e) => {
+                    error!(path = %spec.path.display(), error = %e, "Failed to load plugin");
+                    return false;
+                }
+            };
+            // Give kv plugins a scratch store, so nothing they write persists.
+            let plugin = match Database::open(":memory:").await {
+                Ok(db) => plugin.with_kv(db, spec.kv_quota),
+                Err(e) => {
+                    error!(error = %e, "Failed to open scratch database");
+                    return false;
+                }
+            };
+            fire(&plugin, hook, values.clone()).await
+        }
+    }
+}
+
+/// Load a plugin with every setting from its spec.
+async fn load(spec: &PluginSpec, options: &LoadOptions) -> Result<PluginRuntime, PluginError> {
+    let plugin = PluginRuntime::load_with_limits(
+        &spec.path,
+        &spec.permissions,
+        &spec.config,
+        spec.hook_timeout,
+        spec.limits,
+        options,
+    )
+    .await?;
+    Ok(plugin
+        .with_fail_closed(&spec.fail_closed)
+        .with_breaker(spec.breaker))
+}
+
+fn print_plugin(plugin: &PluginRuntime) {
+    let limits = plugin.limits();
+    println!("name:    {}", plugin.name());
+    println!("version: {}", plugin.version());
+    println!("target:  {:?}", plugin.target());
+    match plugin.http_access() {
+        Some(access) => println!("http:    {access:?}"),
+        None => println!("http:    none"),
+    }
+    println!(
+        "limits:  fuel={} memory={} stack={}",
+        limits.fuel, limits.memory, limits.stack
+    );
+    println!("hooks:");
+    for hook in plugin.hooks() {
+        println!("  {hook:?}");
+    }
+    println!("imports:");
+    for import in plugin.imports() {
+        println!("  {import}");
+    }
+}
+
+/// Fire `hook_name` at the plugin and print the outcome. Like the server,
+/// only hooks the plugin registered for are delivered.
+async fn fire(plugin: &PluginRuntime, hook_name: &str, values: Vec<(String, String)>) -> bool {
+    let Some(hook) = plugin.registered_hook(hook_name).cloned() else {
+        error!(plugin = %plugin.name(), hook = %hook_name, "Plugin does not register this hook");
+        return false;
+    };
+
+    let event = HookEvent {
+        target: hook_target(&hook),
+        hook,
+        time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        values,
+    };
+
+    let started = Instant::now();
+    let result = plugin.invoke_hook(&event).await;
+    let elapsed = started.elapsed();
+
+    let ok = match &result {
+        Ok(Ok(())) if matches!(event.hook, Hook::ServerPre(_)) => {
+            println!("result:  allowed");
+            true
+        }
+        Ok(Ok(())) => {
+            println!("result:  ok");
+            true
+        }
+        Ok(Err(reason)) if matches!(event.hook, Hook::ServerPre(_)) => {
+            println!("result:  denied: {reason}");
+            false
+        }
+        Ok(Err(reason)) => {
+            println!("result:  error: {reason}");
+            false
+        }
+        Err(e) => {
+            println!("result:  failed: {e}");
+            false
+        }
+    };
+    println!("time:    {}ms", elapsed.as_millis());
+    println!("fuel:    {}", plugin.stats().fuel_consumed());
+    ok
+}
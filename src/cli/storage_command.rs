@@ -0,0 +1,147 @@
+//! `crowchiper storage ...`: move attachment blobs between stores while the
+//! server is stopped.
+
+use clap::Subcommand;
+use tracing::error;
+
+use crate::blob::{BlobStoreSpec, S3Config, content_key, open_blob_store, parse_blob_store};
+use crate::db::Database;
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum StorageCommand {
+    /// Copy every attachment blob to another store, verify it and delete it
+    /// from the source. Start the server with the new --blob-store afterwards.
+    Migrate {
+        /// Store to move blobs to: database, fs:<dir> or s3:<bucket>[/<prefix>]
+        #[arg(long, value_parser = parse_blob_store)]
+        to: BlobStoreSpec,
+        /// Store to move blobs from
+        #[arg(long, default_value = "database", value_parser = parse_blob_store)]
+        from: BlobStoreSpec,
+    },
+}
+
+/// Run a storage subcommand against the database at `path`. Returns false if
+/// it failed; the reason has already been logged.
+pub async fn run_storage_command(
+    command: &StorageCommand,
+    path: &str,
+    s3: Option<&S3Config>,
+) -> bool {
+    match command {
+        StorageCommand::Migrate { to, from } => {
+            if to == from {
+                error!(store = %to, "Source and target blob store are the same");
+                return false;
+            }
+            let db = match Database::open(path).await {
+                Ok(db) => db,
+                Err(e) => {
+                    error!(path = %path, error = %e, "Failed to open database");
+                    return false;
+                }
+            };
+            migrate(&db, from, to, s3).await
+        }
+    }
+}
+
+async fn migrate(
+    db: &Database,
+    from: &BlobStoreSpec,
+    to: &BlobStoreSpec,
+    s3: Option<&S3Config>,
+) -> bool {
+    let (source, target) = match (
+        open_blob_store(from, s3, db.pool()),
+        open_blob_store(to, s3, db.pool()),
+    ) {
+        (Ok(source), Ok(target)) => (source, target),
+        (Err(e), _) | (_, Err(e)) => {
+            error!(error = %e, "Failed to open blob store");
+            return false;
+        }
+    };
+    let keys = match db.attachments().blob_keys().await {
+        Ok(keys) => keys,
+        Err(e) => {
+            error!(error = %e, "Failed to list attachment blobs");
+            return false;
+        }
+    };
+
+    let mut moved = 0;
+    let mut bytes = 0;
+    let mut skipped = 0;
+    let mut lost = Vec::new();
+    for key in &keys {
+        let data = match source.get(key).await {
+            Ok(Some(data)) => data,
+            // Already moved by an earlier, interrupted run, or in neither store.
+            Ok(None) => match target.size(key).await {
+                Ok(Some(_)) => {
+                    skipped += 1;
+                    continue;
+                }
+                Ok(None) => {
+                    error!(key = %key, "Blob is in neither store");
+                    lost.push(key);
+                    continue;
+                }
+                Err(e) => {
+                    error!(key = %key, error = %e, "Failed to check blob in target");
+                    return false;
+                }
+            },
+            Err(e) => {
+                error!(key = %key, error = %e, "Failed to read blob");
+                return false;
+            }
+        };
+        if let Err(e) = target.put(key, &data).await {
+            error!(key = %key, error = %e, "Failed to write blob");
+            return false;
+        }
+        match target.get(key).await {
+            Ok(Some(copy)) if content_key(&copy) == *key => {}
+            Ok(_) => {
+                error!(key = %key, "Blob read back from the target does not match");
+                return false;
+            }
+            Err(e) => {
+                error!(key = %key, error = %e, "Failed to read back blob");
+                return false;
+            }
+        }
+        if let Err(e) = source.delete(key).await {
+            error!(key = %key, error = %e, "Failed to delete migrated blob");
+            return false;
+        }
+        moved += 1;
+        bytes += data.len();
+    }
+
+    // Give the space the blobs took back to the filesystem.
+    if *from == BlobStoreSpec::Database {
+        let vacuum = sqlx::query("VACUUM").execute(db.pool()).await;
+        if let Err(e) = vacuum {
+            error!(error = %e, "Failed to vacuum database");
+        }
+    }
+
+    println!("moved {moved} blobs ({bytes} bytes) from {from} to {to}");
+    if skipped > 0 {
+        println!("{skipped} blobs were already in {to} and were skipped");
+    }
+    if !lost.is_empty() {
+        error!(
+            count = lost.len(),
+            "Attachments refer to blobs missing from both stores"
+        );
+        for key in lost {
+            println!("missing: {key}");
+        }
+        return false;
+    }
+    true
+}
@@ -1,10 +1,23 @@
 //! Attachment storage for encrypted images with reference counting.
+//!
+//! Rows hold content keys and IVs; the image and thumbnail bytes live in the
+//! configured [`BlobStore`].
+
+use std::collections::HashMap;
+use std::io;
+use std::ops::Range;
+use std::sync::Arc;
 
 use sqlx::sqlite::SqlitePool;
+use tokio::sync::RwLock;
+
+use crate::blob::{BlobStore, BlobStream, DatabaseBlobStore, content_key};
 
 #[derive(Clone)]
 pub struct AttachmentStore {
     pool: SqlitePool,
+    blobs: Arc<dyn BlobStore>,
+    blob_gc: Arc<RwLock<()>>,
 }
 
 /// Thumbnail data for a single size.
@@ -15,6 +28,65 @@ pub struct ThumbnailData {
     pub iv: Option<String>,
 }
 
+/// Where the image or one thumbnail of an attachment is stored.
+#[derive(Debug, Clone)]
+pub struct BlobRef {
+    /// Content key (SHA-256 of the stored bytes)
+    pub key: String,
+    /// IV for decryption, None if unencrypted
+    pub iv: Option<String>,
+}
+
+/// A blob located in its store, ready to be streamed.
+pub struct OpenBlob {
+    store: Arc<dyn BlobStore>,
+    key: String,
+    pub size: u64,
+}
+
+impl OpenBlob {
+    /// Stream `range` of the blob, which must lie within `0..size`.
+    pub async fn read_range(&self, range: Range<u64>) -> Result<BlobStream, sqlx::Error> {
+        self.store
+            .read_range(&self.key, range)
+            .await?
+            .ok_or_else(|| missing_blob(&self.key).into())
+    }
+}
+
+fn missing_blob(key: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("attachment blob {key} is missing from the blob store"),
+    )
+}
+
+/// What an attachment holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AttachmentKind {
+    /// An image with at least a small thumbnail
+    Image,
+    /// Any other file, with its original filename and MIME type
+    File,
+}
+
+impl AttachmentKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AttachmentKind::Image => "image",
+            AttachmentKind::File => "file",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Self {
+        match s {
+            "file" => AttachmentKind::File,
+            _ => AttachmentKind::Image,
+        }
+    }
+}
+
 /// All thumbnail sizes for an attachment.
 #[derive(Debug, Clone)]
 pub struct Thumbnails {
@@ -29,10 +101,19 @@ pub struct Attachment {
     pub id: i64,
     pub uuid: String,
     pub user_id: i64,
+    pub kind: AttachmentKind,
+    /// Original filename of a file attachment, encrypted if the data is
+    pub filename: Option<String>,
+    /// IV for decrypting the filename, None if unencrypted
+    pub filename_iv: Option<String>,
+    /// MIME type of a file attachment (never encrypted)
+    pub mime_type: Option<String>,
+    /// The image, or the file contents for file attachments
     pub image_data: Vec<u8>,
     /// IV for decryption, None if unencrypted (encryption_version = 0)
     pub image_iv: Option<String>,
-    pub thumbnails: Thumbnails,
+    /// None for files uploaded without a preview
+    pub thumbnails: Option<Thumbnails>,
     pub encryption_version: i32,
     pub reference_count: i32,
     pub created_at: String,
@@ -43,48 +124,24 @@ struct AttachmentRow {
     id: i64,
     uuid: String,
     user_id: i64,
-    image_data: Vec<u8>,
+    kind: String,
+    filename: Option<String>,
+    filename_iv: Option<String>,
+    mime_type: Option<String>,
+    image_key: String,
     image_iv: Option<String>,
-    thumb_sm: Vec<u8>,
+    thumb_sm_key: Option<String>,
     thumb_sm_iv: Option<String>,
-    thumb_md: Option<Vec<u8>>,
+    thumb_md_key: Option<String>,
     thumb_md_iv: Option<String>,
-    thumb_lg: Option<Vec<u8>>,
+    thumb_lg_key: Option<String>,
     thumb_lg_iv: Option<String>,
     encryption_version: i32,
     reference_count: i32,
+    thumbnail_failed: bool,
     created_at: String,
 }
 
-impl From<AttachmentRow> for Attachment {
-    fn from(row: AttachmentRow) -> Self {
-        Self {
-            id: row.id,
-            uuid: row.uuid,
-            user_id: row.user_id,
-            image_data: row.image_data,
-            image_iv: row.image_iv,
-            thumbnails: Thumbnails {
-                sm: ThumbnailData {
-                    data: row.thumb_sm,
-                    iv: row.thumb_sm_iv,
-                },
-                md: row.thumb_md.map(|data| ThumbnailData {
-                    data,
-                    iv: row.thumb_md_iv,
-                }),
-                lg: row.thumb_lg.map(|data| ThumbnailData {
-                    data,
-                    iv: row.thumb_lg_iv,
-                }),
-            },
-            encryption_version: row.encryption_version,
-            reference_count: row.reference_count,
-            created_at: row.created_at,
-        }
-    }
-}
-
 /// Input for creating an attachment with multiple thumbnail sizes.
 pub struct CreateAttachmentInput<'a> {
     pub user_id: i64,
@@ -102,35 +159,280 @@ pub struct CreateAttachmentInput<'a> {
     pub encryption_version: i32,
 }
 
+/// Input for creating a file attachment. Thumbnails are an optional
+/// preview; a medium or large one needs the small one.
+pub struct CreateFileAttachmentInput<'a> {
+    pub user_id: i64,
+    pub data: &'a [u8],
+    /// IV for encryption, None if unencrypted
+    pub data_iv: Option<&'a str>,
+    /// Original filename, encrypted if the data is
+    pub filename: &'a str,
+    /// IV for the encrypted filename, None if unencrypted
+    pub filename_iv: Option<&'a str>,
+    pub mime_type: &'a str,
+    /// Small thumbnail data and optional IV
+    pub thumb_sm: Option<(&'a [u8], Option<&'a str>)>,
+    /// Medium thumbnail data and optional IV
+    pub thumb_md: Option<(&'a [u8], Option<&'a str>)>,
+    /// Large thumbnail data and optional IV
+    pub thumb_lg: Option<(&'a [u8], Option<&'a str>)>,
+    /// 0 = unencrypted, >0 = encrypted with that version
+    pub encryption_version: i32,
+}
+
+/// Metadata of an attachment, without reading any data.
+#[derive(Debug, Clone)]
+pub struct AttachmentInfo {
+    pub uuid: String,
+    pub kind: AttachmentKind,
+    pub filename: Option<String>,
+    pub filename_iv: Option<String>,
+    pub mime_type: Option<String>,
+    /// The image, or the file contents for file attachments
+    pub data: BlobRef,
+    pub has_thumbnails: bool,
+    /// The server could not render thumbnails of this image
+    pub thumbnail_failed: bool,
+    pub encryption_version: i32,
+    pub created_at: String,
+}
+
+/// An attachment as listed in the attachment library.
+#[derive(Debug, Clone)]
+pub struct AttachmentSummary {
+    pub uuid: String,
+    pub kind: AttachmentKind,
+    pub filename: Option<String>,
+    pub filename_iv: Option<String>,
+    pub mime_type: Option<String>,
+    /// Bytes of the data and thumbnails
+    pub size: u64,
+    pub has_thumbnails: bool,
+    pub encryption_version: i32,
+    pub reference_count: i32,
+    pub created_at: String,
+    /// Posts referencing the attachment
+    pub posts: Vec<PostRef>,
+}
+
+/// A post referencing an attachment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PostRef {
+    pub uuid: String,
+    pub title: Option<String>,
+    pub title_encrypted: bool,
+    /// IV for decrypting the title, None if unencrypted
+    pub title_iv: Option<String>,
+}
+
+/// Result of deleting an attachment from the library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeleteAttachmentResult {
+    Deleted,
+    NotFound,
+    /// Still used by this many posts; nothing was deleted.
+    Referenced(i32),
+}
+
+/// Columns of a new row, shared by images and files.
+struct NewAttachment<'a> {
+    user_id: i64,
+    kind: AttachmentKind,
+    filename: Option<&'a str>,
+    filename_iv: Option<&'a str>,
+    mime_type: Option<&'a str>,
+    data: &'a [u8],
+    data_iv: Option<&'a str>,
+    thumb_sm: Option<(&'a [u8], Option<&'a str>)>,
+    thumb_md: Option<(&'a [u8], Option<&'a str>)>,
+    thumb_lg: Option<(&'a [u8], Option<&'a str>)>,
+    encryption_version: i32,
+}
+
 impl AttachmentStore {
-    pub fn new(pool: SqlitePool) -> Self {
-        Self { pool }
+    pub fn new(pool: SqlitePool, blobs: Arc<dyn BlobStore>, blob_gc: Arc<RwLock<()>>) -> Self {
+        Self {
+            pool,
+            blobs,
+            blob_gc,
+        }
+    }
+
+    /// Read a blob, falling back to the database for blobs that have not
+    /// been moved to the configured store yet.
+    pub async fn read_blob(&self, key: &str) -> Result<Vec<u8>, sqlx::Error> {
+        if let Some(data) = self.blobs.get(key).await? {
+            return Ok(data);
+        }
+        if let Some(data) = DatabaseBlobStore::new(self.pool.clone()).get(key).await? {
+            return Ok(data);
+        }
+        Err(missing_blob(key).into())
+    }
+
+    /// Find a blob for streaming, with the same fallback as `read_blob`.
+    pub async fn open_blob(&self, key: &str) -> Result<OpenBlob, sqlx::Error> {
+        let database: Arc<dyn BlobStore> = Arc::new(DatabaseBlobStore::new(self.pool.clone()));
+        for store in [self.blobs.clone(), database] {
+            if let Some(size) = store.size(key).await? {
+                return Ok(OpenBlob {
+                    store,
+                    key: key.to_string(),
+                    size,
+                });
+            }
+        }
+        Err(missing_blob(key).into())
+    }
+
+    async fn read_thumbnail(
+        &self,
+        key: Option<String>,
+        iv: Option<String>,
+    ) -> Result<Option<ThumbnailData>, sqlx::Error> {
+        match key {
+            Some(key) => Ok(Some(ThumbnailData {
+                data: self.read_blob(&key).await?,
+                iv,
+            })),
+            None => Ok(None),
+        }
     }
 
     /// Create a new attachment with multiple thumbnail sizes. Returns the attachment UUID.
     /// Reference count starts at 0 (incremented when added to a post).
     pub async fn create(&self, input: CreateAttachmentInput<'_>) -> Result<String, sqlx::Error> {
+        self.insert(NewAttachment {
+            user_id: input.user_id,
+            kind: AttachmentKind::Image,
+            filename: None,
+            filename_iv: None,
+            mime_type: None,
+            data: input.image_data,
+            data_iv: input.image_iv,
+            thumb_sm: Some((input.thumb_sm, input.thumb_sm_iv)),
+            thumb_md: input.thumb_md,
+            thumb_lg: input.thumb_lg,
+            encryption_version: input.encryption_version,
+        })
+        .await
+    }
+
+    /// Find an unencrypted image of the user with exactly this content, to
+    /// hand out again instead of storing a copy. Returns its UUID.
+    ///
+    /// An image no post uses yet gets its age reset, so the orphan cleanup
+    /// gives the new upload the same grace period as a fresh one.
+    pub async fn reuse_image(
+        &self,
+        user_id: i64,
+        image_data: &[u8],
+    ) -> Result<Option<String>, sqlx::Error> {
+        let row: Option<(String, i32)> = sqlx::query_as(
+            "SELECT uuid, reference_count FROM attachments
+             WHERE user_id = ? AND image_key = ? AND kind = 'image' AND encryption_version = 0
+             ORDER BY reference_count DESC, id LIMIT 1",
+        )
+        .bind(user_id)
+        .bind(content_key(image_data))
+        .fetch_optional(&self.pool)
+        .await?;
+        let Some((uuid, reference_count)) = row else {
+            return Ok(None);
+        };
+        if reference_count > 0 {
+            return Ok(Some(uuid));
+        }
+
+        // Fails if the cleanup or the user deleted it in the meantime
+        let result = sqlx::query(
+            "UPDATE attachments SET created_at = CURRENT_TIMESTAMP
+             WHERE uuid = ? AND reference_count = 0",
+        )
+        .bind(&uuid)
+        .execute(&self.pool)
+        .await?;
+        Ok((result.rows_affected() > 0).then_some(uuid))
+    }
+
+    /// Create a new file attachment. Returns the attachment UUID.
+    /// Reference count starts at 0 (incremented when added to a post).
+    pub async fn create_file(
+        &self,
+        input: CreateFileAttachmentInput<'_>,
+    ) -> Result<String, sqlx::Error> {
+        self.insert(NewAttachment {
+            user_id: input.user_id,
+            kind: AttachmentKind::File,
+            filename: Some(input.filename),
+            filename_iv: input.filename_iv,
+            mime_type: Some(input.mime_type),
+            data: input.data,
+            data_iv: input.data_iv,
+            thumb_sm: input.thumb_sm,
+            thumb_md: input.thumb_md,
+            thumb_lg: input.thumb_lg,
+            encryption_version: input.encryption_version,
+        })
+        .await
+    }
+
+    async fn insert(&self, input: NewAttachment<'_>) -> Result<String, sqlx::Error> {
         let uuid = uuid::Uuid::new_v4().to_string();
 
+        let image_key = content_key(input.data);
+        let thumb_sm_key = input.thumb_sm.map(|(d, _)| content_key(d));
+        let thumb_md_key = input.thumb_md.map(|(d, _)| content_key(d));
+        let thumb_lg_key = input.thumb_lg.map(|(d, _)| content_key(d));
+        let blobs = [
+            Some((&image_key, input.data)),
+            thumb_sm_key.as_ref().zip(input.thumb_sm.map(|(d, _)| d)),
+            thumb_md_key.as_ref().zip(input.thumb_md.map(|(d, _)| d)),
+            thumb_lg_key.as_ref().zip(input.thumb_lg.map(|(d, _)| d)),
+        ];
+        // Counted against the user's quota
+        let size: usize = blobs.iter().flatten().map(|(_, data)| data.len()).sum();
+
+        let _gc = self.blob_gc.read().await;
+        // Queue the keys for deletion first: if storing the blobs or the row
+        // fails, the next purge removes whatever was stored. Once the row
+        // exists, the purge sees the keys are in use and dequeues them.
+        for (key, _) in blobs.iter().flatten() {
+            sqlx::query("INSERT OR IGNORE INTO blob_deletions (key) VALUES (?)")
+                .bind(key)
+                .execute(&self.pool)
+                .await?;
+        }
+        for (key, data) in blobs.iter().flatten() {
+            self.blobs.put(key, data).await?;
+        }
+
         sqlx::query(
-            "INSERT INTO attachments (uuid, user_id, image_data, image_iv,
-             thumb_sm, thumb_sm_iv,
-             thumb_md, thumb_md_iv,
-             thumb_lg, thumb_lg_iv,
-             encryption_version, reference_count)
-             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 0)",
+            "INSERT INTO attachments (uuid, user_id, kind, filename, filename_iv, mime_type,
+             image_key, image_iv,
+             thumb_sm_key, thumb_sm_iv,
+             thumb_md_key, thumb_md_iv,
+             thumb_lg_key, thumb_lg_iv,
+             encryption_version, size, reference_count)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 0)",
         )
         .bind(&uuid)
         .bind(input.user_id)
-        .bind(input.image_data)
-        .bind(input.image_iv)
-        .bind(input.thumb_sm)
-        .bind(input.thumb_sm_iv)
-        .bind(input.thumb_md.map(|(d, _)| d))
+        .bind(input.kind.as_str())
+        .bind(input.filename)
+        .bind(input.filename_iv)
+        .bind(input.mime_type)
+        .bind(&image_key)
+        .bind(input.data_iv)
+        .bind(&thumb_sm_key)
+        .bind(input.thumb_sm.and_then(|(_, iv)| iv))
+        .bind(&thumb_md_key)
         .bind(input.thumb_md.and_then(|(_, iv)| iv))
-        .bind(input.thumb_lg.map(|(d, _)| d))
+        .bind(&thumb_lg_key)
         .bind(input.thumb_lg.and_then(|(_, iv)| iv))
         .bind(input.encryption_version)
+        .bind(size as i64)
         .execute(&self.pool)
         .await?;
 
@@ -144,58 +446,94 @@ impl AttachmentStore {
         user_id: i64,
     ) -> Result<Option<Attachment>, sqlx::Error> {
         let row: Option<AttachmentRow> = sqlx::query_as(
-            "SELECT id, uuid, user_id, image_data, image_iv,
-             thumb_sm, thumb_sm_iv,
-             thumb_md, thumb_md_iv,
-             thumb_lg, thumb_lg_iv,
-             encryption_version, reference_count, created_at
+            "SELECT id, uuid, user_id, kind, filename, filename_iv, mime_type, image_key, image_iv,
+             thumb_sm_key, thumb_sm_iv,
+             thumb_md_key, thumb_md_iv,
+             thumb_lg_key, thumb_lg_iv,
+             encryption_version, reference_count, thumbnail_failed, created_at
              FROM attachments WHERE uuid = ? AND user_id = ?",
         )
         .bind(uuid)
         .bind(user_id)
         .fetch_optional(&self.pool)
         .await?;
-        Ok(row.map(Attachment::from))
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let thumbnails = match self
+            .read_thumbnail(row.thumb_sm_key, row.thumb_sm_iv)
+            .await?
+        {
+            Some(sm) => Some(Thumbnails {
+                sm,
+                md: self
+                    .read_thumbnail(row.thumb_md_key, row.thumb_md_iv)
+                    .await?,
+                lg: self
+                    .read_thumbnail(row.thumb_lg_key, row.thumb_lg_iv)
+                    .await?,
+            }),
+            None => None,
+        };
+
+        Ok(Some(Attachment {
+            image_data: self.read_blob(&row.image_key).await?,
+            thumbnails,
+            kind: AttachmentKind::from_str(&row.kind),
+            filename: row.filename,
+            filename_iv: row.filename_iv,
+            mime_type: row.mime_type,
+            id: row.id,
+            uuid: row.uuid,
+            user_id: row.user_id,
+            image_iv: row.image_iv,
+            encryption_version: row.encryption_version,
+            reference_count: row.reference_count,
+            created_at: row.created_at,
+        }))
     }
 
     /// Get all thumbnails for an attachment.
+    /// Returns None if it doesn't exist or is a file without a preview.
     pub async fn get_thumbnails(
         &self,
         uuid: &str,
         user_id: i64,
     ) -> Result<Option<Thumbnails>, sqlx::Error> {
         let row: Option<(
-            Vec<u8>,
             Option<String>,
-            Option<Vec<u8>>,
             Option<String>,
-            Option<Vec<u8>>,
+            Option<String>,
+            Option<String>,
+            Option<String>,
             Option<String>,
         )> = sqlx::query_as(
-            "SELECT thumb_sm, thumb_sm_iv,
-             thumb_md, thumb_md_iv,
-             thumb_lg, thumb_lg_iv
+            "SELECT thumb_sm_key, thumb_sm_iv,
+             thumb_md_key, thumb_md_iv,
+             thumb_lg_key, thumb_lg_iv
              FROM attachments WHERE uuid = ? AND user_id = ?",
         )
         .bind(uuid)
         .bind(user_id)
         .fetch_optional(&self.pool)
         .await?;
+        let Some((sm_key, sm_iv, md_key, md_iv, lg_key, lg_iv)) = row else {
+            return Ok(None);
+        };
+        let Some(sm) = self.read_thumbnail(sm_key, sm_iv).await? else {
+            return Ok(None);
+        };
 
-        Ok(row.map(
-            |(sm_data, sm_iv, md_data, md_iv, lg_data, lg_iv)| Thumbnails {
-                sm: ThumbnailData {
-                    data: sm_data,
-                    iv: sm_iv,
-                },
-                md: md_data.map(|data| ThumbnailData { data, iv: md_iv }),
-                lg: lg_data.map(|data| ThumbnailData { data, iv: lg_iv }),
-            },
-        ))
+        Ok(Some(Thumbnails {
+            sm,
+            md: self.read_thumbnail(md_key, md_iv).await?,
+            lg: self.read_thumbnail(lg_key, lg_iv).await?,
+        }))
     }
 
     /// Get a single thumbnail by size for an attachment.
-    /// Only fetches the requested size column from the database.
+    /// Only reads the requested size from the blob store.
     /// Returns None if attachment doesn't exist or requested size is not available.
     pub async fn get_thumbnail_by_size(
         &self,
@@ -203,46 +541,241 @@ impl AttachmentStore {
         user_id: i64,
         size: &str,
     ) -> Result<Option<ThumbnailData>, sqlx::Error> {
-        let row: Option<(Option<Vec<u8>>, Option<String>)> = match size {
+        let query = match size {
             "sm" => {
-                sqlx::query_as(
-                    "SELECT thumb_sm, thumb_sm_iv
-                     FROM attachments WHERE uuid = ? AND user_id = ?",
-                )
-                .bind(uuid)
-                .bind(user_id)
-                .fetch_optional(&self.pool)
-                .await?
+                "SELECT thumb_sm_key, thumb_sm_iv FROM attachments WHERE uuid = ? AND user_id = ?"
             }
             "md" => {
-                sqlx::query_as(
-                    "SELECT thumb_md, thumb_md_iv
-                     FROM attachments WHERE uuid = ? AND user_id = ?",
-                )
-                .bind(uuid)
-                .bind(user_id)
-                .fetch_optional(&self.pool)
-                .await?
+                "SELECT thumb_md_key, thumb_md_iv FROM attachments WHERE uuid = ? AND user_id = ?"
             }
             "lg" => {
-                sqlx::query_as(
-                    "SELECT thumb_lg, thumb_lg_iv
-                     FROM attachments WHERE uuid = ? AND user_id = ?",
-                )
-                .bind(uuid)
-                .bind(user_id)
-                .fetch_optional(&self.pool)
-                .await?
+                "SELECT thumb_lg_key, thumb_lg_iv FROM attachments WHERE uuid = ? AND user_id = ?"
             }
             _ => return Ok(None),
         };
+        let row: Option<(Option<String>, Option<String>)> = sqlx::query_as(query)
+            .bind(uuid)
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
 
-        let Some((data, iv)) = row else {
+        let Some((key, iv)) = row else {
             return Ok(None);
         };
 
-        // Return thumbnail if data is present (IV may be None for unencrypted)
-        Ok(data.map(|data| ThumbnailData { data, iv }))
+        // Return thumbnail if this size exists (IV may be None for unencrypted)
+        self.read_thumbnail(key, iv).await
+    }
+
+    /// Get the blob key and IV of an attachment's image (`part` = "image")
+    /// or of one thumbnail size ("sm", "md", "lg") without reading its data.
+    /// Returns None if the attachment doesn't exist or lacks that size.
+    pub async fn get_blob_ref(
+        &self,
+        uuid: &str,
+        user_id: i64,
+        part: &str,
+    ) -> Result<Option<BlobRef>, sqlx::Error> {
+        let query = match part {
+            "image" => "SELECT image_key, image_iv FROM attachments WHERE uuid = ? AND user_id = ?",
+            "sm" => {
+                "SELECT thumb_sm_key, thumb_sm_iv FROM attachments WHERE uuid = ? AND user_id = ?"
+            }
+            "md" => {
+                "SELECT thumb_md_key, thumb_md_iv FROM attachments WHERE uuid = ? AND user_id = ?"
+            }
+            "lg" => {
+                "SELECT thumb_lg_key, thumb_lg_iv FROM attachments WHERE uuid = ? AND user_id = ?"
+            }
+            _ => return Ok(None),
+        };
+        let row: Option<(Option<String>, Option<String>)> = sqlx::query_as(query)
+            .bind(uuid)
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.and_then(|(key, iv)| key.map(|key| BlobRef { key, iv })))
+    }
+
+    /// Store a thumbnail size an attachment lacks, such as one the server
+    /// rendered for an unencrypted image. Returns the thumbnail's blob, or
+    /// None if the attachment doesn't exist. If the size was stored in the
+    /// meantime, that thumbnail is kept and returned.
+    pub async fn add_thumbnail(
+        &self,
+        uuid: &str,
+        user_id: i64,
+        size: &str,
+        data: &[u8],
+    ) -> Result<Option<BlobRef>, sqlx::Error> {
+        let query = match size {
+            "sm" => {
+                "UPDATE attachments SET thumb_sm_key = ?, size = size + ?
+                 WHERE uuid = ? AND user_id = ? AND thumb_sm_key IS NULL"
+            }
+            "md" => {
+                "UPDATE attachments SET thumb_md_key = ?, size = size + ?
+                 WHERE uuid = ? AND user_id = ? AND thumb_md_key IS NULL"
+            }
+            "lg" => {
+                "UPDATE attachments SET thumb_lg_key = ?, size = size + ?
+                 WHERE uuid = ? AND user_id = ? AND thumb_lg_key IS NULL"
+            }
+            _ => return Ok(None),
+        };
+        let key = content_key(data);
+
+        let _gc = self.blob_gc.read().await;
+        // Queued like in `insert`, in case the row is gone or already has
+        // this size.
+        sqlx::query("INSERT OR IGNORE INTO blob_deletions (key) VALUES (?)")
+            .bind(&key)
+            .execute(&self.pool)
+            .await?;
+        self.blobs.put(&key, data).await?;
+        sqlx::query(query)
+            .bind(&key)
+            .bind(data.len() as i64)
+            .bind(uuid)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        self.get_blob_ref(uuid, user_id, size).await
+    }
+
+    /// Record that the server could not render thumbnails of an image.
+    pub async fn mark_thumbnail_failed(&self, uuid: &str, user_id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE attachments SET thumbnail_failed = 1 WHERE uuid = ? AND user_id = ?")
+            .bind(uuid)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Get the metadata of an attachment. Only returns if it belongs to the given user.
+    pub async fn get_info(
+        &self,
+        uuid: &str,
+        user_id: i64,
+    ) -> Result<Option<AttachmentInfo>, sqlx::Error> {
+        let row: Option<AttachmentRow> = sqlx::query_as(
+            "SELECT id, uuid, user_id, kind, filename, filename_iv, mime_type, image_key, image_iv,
+             thumb_sm_key, thumb_sm_iv,
+             thumb_md_key, thumb_md_iv,
+             thumb_lg_key, thumb_lg_iv,
+             encryption_version, reference_count, thumbnail_failed, created_at
+             FROM attachments WHERE uuid = ? AND user_id = ?",
+        )
+        .bind(uuid)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| AttachmentInfo {
+            uuid: row.uuid,
+            kind: AttachmentKind::from_str(&row.kind),
+            filename: row.filename,
+            filename_iv: row.filename_iv,
+            mime_type: row.mime_type,
+            data: BlobRef {
+                key: row.image_key,
+                iv: row.image_iv,
+            },
+            has_thumbnails: row.thumb_sm_key.is_some(),
+            thumbnail_failed: row.thumbnail_failed,
+            encryption_version: row.encryption_version,
+            created_at: row.created_at,
+        }))
+    }
+
+    /// Delete blobs of deleted attachments that no remaining row refers to.
+    ///
+    /// Deleting a row (directly, via reference counting or by cascade from
+    /// its user) only queues its keys; this removes the blobs themselves.
+    /// Returns the number of blobs deleted.
+    pub async fn purge_deleted_blobs(&self) -> Result<u64, sqlx::Error> {
+        let _gc = self.blob_gc.write().await;
+        // Keys a row still (or again) uses are only dequeued.
+        sqlx::query(
+            "DELETE FROM blob_deletions WHERE EXISTS (SELECT 1 FROM attachments a
+             WHERE a.image_key = blob_deletions.key OR a.thumb_sm_key = blob_deletions.key
+             OR a.thumb_md_key = blob_deletions.key OR a.thumb_lg_key = blob_deletions.key)",
+        )
+        .execute(&self.pool)
+        .await?;
+        let keys: Vec<(String,)> = sqlx::query_as("SELECT key FROM blob_deletions")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let database = DatabaseBlobStore::new(self.pool.clone());
+        let mut deleted = 0;
+        for (key,) in keys {
+            self.blobs.delete(&key).await?;
+            database.delete(&key).await?;
+            sqlx::query("DELETE FROM blob_deletions WHERE key = ?")
+                .bind(&key)
+                .execute(&self.pool)
+                .await?;
+            deleted += 1;
+        }
+        Ok(deleted)
+    }
+
+    /// Distinct blob keys of all attachments.
+    pub async fn blob_keys(&self) -> Result<Vec<String>, sqlx::Error> {
+        let rows: Vec<(String,)> = sqlx::query_as(
+            "SELECT image_key FROM attachments
+             UNION SELECT thumb_sm_key FROM attachments WHERE thumb_sm_key IS NOT NULL
+             UNION SELECT thumb_md_key FROM attachments WHERE thumb_md_key IS NOT NULL
+             UNION SELECT thumb_lg_key FROM attachments WHERE thumb_lg_key IS NOT NULL",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(|r| r.0).collect())
+    }
+
+    /// Fill in the size of attachments that have none recorded.
+    ///
+    /// The v8 migration could only measure blobs still in the database, so
+    /// attachments whose blobs had already been moved to another store start
+    /// out at zero. Blobs missing from every store count as empty. Returns the
+    /// number of attachments updated.
+    pub async fn backfill_sizes(&self) -> Result<u64, sqlx::Error> {
+        let rows: Vec<(i64, String, Option<String>, Option<String>, Option<String>)> =
+            sqlx::query_as(
+                "SELECT id, image_key, thumb_sm_key, thumb_md_key, thumb_lg_key
+                 FROM attachments WHERE size = 0",
+            )
+            .fetch_all(&self.pool)
+            .await?;
+
+        let database = DatabaseBlobStore::new(self.pool.clone());
+        let mut updated = 0;
+        for (id, image_key, thumb_sm_key, thumb_md_key, thumb_lg_key) in rows {
+            let mut size = 0;
+            for key in [Some(image_key), thumb_sm_key, thumb_md_key, thumb_lg_key]
+                .into_iter()
+                .flatten()
+            {
+                size += match self.blobs.size(&key).await? {
+                    Some(size) => size,
+                    None => database.size(&key).await?.unwrap_or(0),
+                };
+            }
+            if size == 0 {
+                continue;
+            }
+            let result = sqlx::query("UPDATE attachments SET size = ? WHERE id = ? AND size = 0")
+                .bind(size as i64)
+                .bind(id)
+                .execute(&self.pool)
+                .await?;
+            updated += result.rows_affected();
+        }
+        Ok(updated)
     }
 
     /// Increment reference count for an attachment.
@@ -305,12 +838,13 @@ impl AttachmentStore {
 
     /// Update attachment references for a post within an existing transaction.
     /// Computes the diff between current and new attachments, updates ref counts accordingly.
+    /// Returns the UUIDs of attachments deleted because their ref count reached 0.
     pub async fn update_post_attachments_tx(
         tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
         post_id: i64,
         user_id: i64,
         new_uuids: &[String],
-    ) -> Result<(), sqlx::Error> {
+    ) -> Result<Vec<String>, sqlx::Error> {
         // Get current attachments for this post
         let current_rows: Vec<(String,)> =
             sqlx::query_as("SELECT attachment_uuid FROM post_attachments WHERE post_id = ?")
@@ -321,6 +855,7 @@ impl AttachmentStore {
             current_rows.into_iter().map(|r| r.0).collect();
 
         let new_set: std::collections::HashSet<String> = new_uuids.iter().cloned().collect();
+        let mut deleted = Vec::new();
 
         // Find removed attachments (in current but not in new)
         for uuid in current.difference(&new_set) {
@@ -335,13 +870,16 @@ impl AttachmentStore {
             .await?;
 
             // Delete if reference count is now 0
-            sqlx::query(
+            let result = sqlx::query(
                 "DELETE FROM attachments WHERE uuid = ? AND user_id = ? AND reference_count = 0",
             )
             .bind(uuid)
             .bind(user_id)
             .execute(&mut **tx)
             .await?;
+            if result.rows_affected() > 0 {
+                deleted.push(uuid.clone());
+            }
 
             // Remove from post_attachments
             sqlx::query("DELETE FROM post_attachments WHERE post_id = ? AND attachment_uuid = ?")
@@ -373,7 +911,7 @@ impl AttachmentStore {
             .await?;
         }
 
-        Ok(())
+        Ok(deleted)
     }
 
     /// Update attachment references for a post.
@@ -392,11 +930,12 @@ impl AttachmentStore {
 
     /// Remove all attachment references for a post within an existing transaction.
     /// Decrements ref counts and deletes attachments with 0 refs.
+    /// Returns the UUIDs of attachments that were deleted.
     pub async fn remove_post_attachments_tx(
         tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
         post_id: i64,
         user_id: i64,
-    ) -> Result<(), sqlx::Error> {
+    ) -> Result<Vec<String>, sqlx::Error> {
         // Get current attachments for this post
         let current_rows: Vec<(String,)> =
             sqlx::query_as("SELECT attachment_uuid FROM post_attachments WHERE post_id = ?")
@@ -404,6 +943,8 @@ impl AttachmentStore {
                 .fetch_all(&mut **tx)
                 .await?;
 
+        let mut deleted = Vec::new();
+
         // Decrement ref count for each and delete if 0
         for (uuid,) in current_rows {
             sqlx::query(
@@ -415,13 +956,16 @@ impl AttachmentStore {
             .execute(&mut **tx)
             .await?;
 
-            sqlx::query(
+            let result = sqlx::query(
                 "DELETE FROM attachments WHERE uuid = ? AND user_id = ? AND reference_count = 0",
             )
             .bind(&uuid)
             .bind(user_id)
             .execute(&mut **tx)
             .await?;
+            if result.rows_affected() > 0 {
+                deleted.push(uuid);
+            }
         }
 
         // Remove all post_attachments entries for this post
@@ -430,7 +974,7 @@ impl AttachmentStore {
             .execute(&mut **tx)
             .await?;
 
-        Ok(())
+        Ok(deleted)
     }
 
     /// Remove all attachment references for a post (called on post delete).
@@ -457,6 +1001,162 @@ impl AttachmentStore {
         Ok(result.rows_affected() > 0)
     }
 
+    /// Number of attachments a user has.
+    pub async fn count(&self, user_id: i64) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar("SELECT COUNT(*) FROM attachments WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    /// List a user's attachments, newest first, with the posts referencing
+    /// each of them.
+    pub async fn list(
+        &self,
+        user_id: i64,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<AttachmentSummary>, sqlx::Error> {
+        #[allow(clippy::type_complexity)]
+        let rows: Vec<(
+            String,
+            String,
+            Option<String>,
+            Option<String>,
+            Option<String>,
+            i64,
+            bool,
+            i32,
+            i32,
+            String,
+        )> = sqlx::query_as(
+            "SELECT uuid, kind, filename, filename_iv, mime_type, size,
+             thumb_sm_key IS NOT NULL, encryption_version, reference_count, created_at
+             FROM attachments WHERE user_id = ?
+             ORDER BY created_at DESC, id DESC LIMIT ? OFFSET ?",
+        )
+        .bind(user_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        // References of the same page, in one query
+        let references: Vec<(String, String, Option<String>, bool, Option<String>)> =
+            sqlx::query_as(
+                "SELECT pa.attachment_uuid, p.uuid, p.title, p.title_encrypted, p.title_iv
+                 FROM post_attachments pa JOIN posts p ON p.id = pa.post_id
+                 WHERE p.user_id = ? AND pa.attachment_uuid IN (
+                     SELECT uuid FROM attachments WHERE user_id = ?
+                     ORDER BY created_at DESC, id DESC LIMIT ? OFFSET ?
+                 )
+                 ORDER BY p.created_at, p.id",
+            )
+            .bind(user_id)
+            .bind(user_id)
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&self.pool)
+            .await?;
+        let mut posts: HashMap<String, Vec<PostRef>> = HashMap::new();
+        for (attachment_uuid, uuid, title, title_encrypted, title_iv) in references {
+            posts.entry(attachment_uuid).or_default().push(PostRef {
+                uuid,
+                title,
+                title_encrypted,
+                title_iv,
+            });
+        }
+
+        Ok(rows
+            .into_iter()
+            .map(|row| AttachmentSummary {
+                posts: posts.remove(&row.0).unwrap_or_default(),
+                uuid: row.0,
+                kind: AttachmentKind::from_str(&row.1),
+                filename: row.2,
+                filename_iv: row.3,
+                mime_type: row.4,
+                size: row.5 as u64,
+                has_thumbnails: row.6,
+                encryption_version: row.7,
+                reference_count: row.8,
+                created_at: row.9,
+            })
+            .collect())
+    }
+
+    /// Posts referencing an attachment, oldest first. None if the user has
+    /// no such attachment.
+    pub async fn get_referencing_posts(
+        &self,
+        uuid: &str,
+        user_id: i64,
+    ) -> Result<Option<Vec<PostRef>>, sqlx::Error> {
+        let exists: bool = sqlx::query_scalar(
+            "SELECT EXISTS(SELECT 1 FROM attachments WHERE uuid = ? AND user_id = ?)",
+        )
+        .bind(uuid)
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+        if !exists {
+            return Ok(None);
+        }
+
+        let rows: Vec<(String, Option<String>, bool, Option<String>)> = sqlx::query_as(
+            "SELECT p.uuid, p.title, p.title_encrypted, p.title_iv
+             FROM post_attachments pa JOIN posts p ON p.id = pa.post_id
+             WHERE pa.attachment_uuid = ? AND p.user_id = ?
+             ORDER BY p.created_at, p.id",
+        )
+        .bind(uuid)
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(Some(
+            rows.into_iter()
+                .map(|(uuid, title, title_encrypted, title_iv)| PostRef {
+                    uuid,
+                    title,
+                    title_encrypted,
+                    title_iv,
+                })
+                .collect(),
+        ))
+    }
+
+    /// Delete an attachment no post references. Referenced attachments are
+    /// left alone; they go once the posts stop using them.
+    pub async fn delete_unreferenced(
+        &self,
+        uuid: &str,
+        user_id: i64,
+    ) -> Result<DeleteAttachmentResult, sqlx::Error> {
+        let result = sqlx::query(
+            "DELETE FROM attachments WHERE uuid = ? AND user_id = ? AND reference_count = 0",
+        )
+        .bind(uuid)
+        .bind(user_id)
+        .execute(&self.pool)
+        .await?;
+        if result.rows_affected() > 0 {
+            return Ok(DeleteAttachmentResult::Deleted);
+        }
+
+        let reference_count: Option<i32> = sqlx::query_scalar(
+            "SELECT reference_count FROM attachments WHERE uuid = ? AND user_id = ?",
+        )
+        .bind(uuid)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(match reference_count {
+            Some(count) => DeleteAttachmentResult::Referenced(count),
+            None => DeleteAttachmentResult::NotFound,
+        })
+    }
+
     /// Delete orphaned attachments (reference_count = 0) older than the given age.
     /// Returns the number of deleted attachments.
     pub async fn cleanup_orphaned(&self, older_than_minutes: i64) -> Result<u64, sqlx::Error> {
@@ -508,18 +1208,113 @@ mod tests {
         assert_eq!(attachment.user_id, user_id);
         assert_eq!(attachment.image_data, b"image_data_bytes");
         assert_eq!(attachment.image_iv, Some("image_iv_123".to_string()));
-        assert_eq!(attachment.thumbnails.sm.data, b"thumb_sm_data");
-        assert_eq!(attachment.thumbnails.sm.iv, Some("thumb_sm_iv".to_string()));
+        assert_eq!(attachment.kind, AttachmentKind::Image);
+        let thumbnails = attachment.thumbnails.unwrap();
+        assert_eq!(thumbnails.sm.data, b"thumb_sm_data");
+        assert_eq!(thumbnails.sm.iv, Some("thumb_sm_iv".to_string()));
+        assert_eq!(thumbnails.md.as_ref().unwrap().data, b"thumb_md_data");
+        assert_eq!(thumbnails.lg.as_ref().unwrap().data, b"thumb_lg_data");
+        assert_eq!(attachment.encryption_version, 1);
+        assert_eq!(attachment.reference_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_purge_deleted_blobs_keeps_shared_content() {
+        let db = Database::open(":memory:").await.unwrap();
+        let user_id = db.users().create("uuid-1", "alice").await.unwrap();
+
+        let first = db
+            .attachments()
+            .create(create_test_input(user_id))
+            .await
+            .unwrap();
+        // Same thumbnails, different image.
+        let second = db
+            .attachments()
+            .create(CreateAttachmentInput {
+                image_data: b"other_image",
+                ..create_test_input(user_id)
+            })
+            .await
+            .unwrap();
+        assert_eq!(db.attachments().purge_deleted_blobs().await.unwrap(), 0);
+
+        assert!(db.attachments().delete(&first, user_id).await.unwrap());
+        assert_eq!(db.attachments().purge_deleted_blobs().await.unwrap(), 1);
         assert_eq!(
-            attachment.thumbnails.md.as_ref().unwrap().data,
-            b"thumb_md_data"
+            db.blobs()
+                .get(&content_key(b"image_data_bytes"))
+                .await
+                .unwrap(),
+            None
         );
-        assert_eq!(
-            attachment.thumbnails.lg.as_ref().unwrap().data,
-            b"thumb_lg_data"
+
+        let attachment = db
+            .attachments()
+            .get_by_uuid(&second, user_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(attachment.image_data, b"other_image");
+        assert_eq!(attachment.thumbnails.unwrap().sm.data, b"thumb_sm_data");
+
+        // Deleting the user cascades to the row and its blobs.
+        db.users().delete(user_id).await.unwrap();
+        assert_eq!(db.attachments().purge_deleted_blobs().await.unwrap(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_create_file_attachment_without_preview() {
+        let db = Database::open(":memory:").await.unwrap();
+        let user_id = db.users().create("uuid-1", "alice").await.unwrap();
+
+        let uuid = db
+            .attachments()
+            .create_file(CreateFileAttachmentInput {
+                user_id,
+                data: b"%PDF-1.7",
+                data_iv: None,
+                filename: "report.pdf",
+                filename_iv: None,
+                mime_type: "application/pdf",
+                thumb_sm: None,
+                thumb_md: None,
+                thumb_lg: None,
+                encryption_version: 0,
+            })
+            .await
+            .unwrap();
+
+        let attachment = db
+            .attachments()
+            .get_by_uuid(&uuid, user_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(attachment.kind, AttachmentKind::File);
+        assert_eq!(attachment.filename.as_deref(), Some("report.pdf"));
+        assert_eq!(attachment.mime_type.as_deref(), Some("application/pdf"));
+        assert_eq!(attachment.image_data, b"%PDF-1.7");
+        assert!(attachment.thumbnails.is_none());
+
+        let info = db
+            .attachments()
+            .get_info(&uuid, user_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!info.has_thumbnails);
+        assert_eq!(info.data.key, content_key(b"%PDF-1.7"));
+        assert!(
+            db.attachments()
+                .get_thumbnails(&uuid, user_id)
+                .await
+                .unwrap()
+                .is_none()
         );
-        assert_eq!(attachment.encryption_version, 1);
-        assert_eq!(attachment.reference_count, 0);
+
+        assert!(db.attachments().delete(&uuid, user_id).await.unwrap());
+        assert_eq!(db.attachments().purge_deleted_blobs().await.unwrap(), 1);
     }
 
     #[tokio::test]
@@ -543,6 +1338,134 @@ mod tests {
         assert_eq!(thumbs.lg.as_ref().unwrap().data, b"thumb_lg_data");
     }
 
+    #[tokio::test]
+    async fn test_add_missing_thumbnail() {
+        let db = Database::open(":memory:").await.unwrap();
+        let user_id = db.users().create("uuid-1", "alice").await.unwrap();
+        let attachments = db.attachments();
+
+        let uuid = attachments
+            .create(CreateAttachmentInput {
+                user_id,
+                image_data: b"image",
+                image_iv: None,
+                thumb_sm: b"sm",
+                thumb_sm_iv: None,
+                thumb_md: None,
+                thumb_lg: None,
+                encryption_version: 0,
+            })
+            .await
+            .unwrap();
+
+        let blob = attachments
+            .add_thumbnail(&uuid, user_id, "md", b"rendered_md")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            attachments.read_blob(&blob.key).await.unwrap(),
+            b"rendered_md"
+        );
+        assert!(blob.iv.is_none());
+
+        // A size that exists is kept
+        let blob = attachments
+            .add_thumbnail(&uuid, user_id, "md", b"other_md")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            attachments.read_blob(&blob.key).await.unwrap(),
+            b"rendered_md"
+        );
+
+        let summary = attachments.list(user_id, 1, 0).await.unwrap();
+        assert_eq!(summary[0].size, 5 + 2 + 11);
+        assert!(
+            attachments
+                .add_thumbnail(&uuid, user_id + 1, "lg", b"lg")
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reuse_unencrypted_image() {
+        let db = Database::open(":memory:").await.unwrap();
+        let user_id = db.users().create("uuid-1", "alice").await.unwrap();
+        let other = db.users().create("uuid-2", "bob").await.unwrap();
+        let attachments = db.attachments();
+        let unencrypted = |user_id| CreateAttachmentInput {
+            image_iv: None,
+            thumb_sm_iv: None,
+            thumb_md: None,
+            thumb_lg: None,
+            encryption_version: 0,
+            ..create_test_input(user_id)
+        };
+
+        assert!(
+            attachments
+                .reuse_image(user_id, b"image_data_bytes")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        let uuid = attachments.create(unencrypted(user_id)).await.unwrap();
+        assert_eq!(
+            attachments
+                .reuse_image(user_id, b"image_data_bytes")
+                .await
+                .unwrap(),
+            Some(uuid.clone())
+        );
+
+        // Only the user's own unencrypted images with the same content
+        assert!(
+            attachments
+                .reuse_image(user_id, b"other_bytes")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            attachments
+                .reuse_image(other, b"image_data_bytes")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        attachments.create(create_test_input(other)).await.unwrap();
+        assert!(
+            attachments
+                .reuse_image(other, b"image_data_bytes")
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        // A reused image no post uses is safe from the orphan cleanup
+        sqlx::query("UPDATE attachments SET created_at = datetime('now', '-2 hours')")
+            .execute(db.pool())
+            .await
+            .unwrap();
+        attachments
+            .reuse_image(user_id, b"image_data_bytes")
+            .await
+            .unwrap()
+            .unwrap();
+        attachments.cleanup_orphaned(60).await.unwrap();
+        assert!(
+            attachments
+                .get_by_uuid(&uuid, user_id)
+                .await
+                .unwrap()
+                .is_some()
+        );
+    }
+
     #[tokio::test]
     async fn test_reference_counting() {
         let db = Database::open(":memory:").await.unwrap();
@@ -814,4 +1737,156 @@ mod tests {
             .unwrap();
         assert!(att.is_none());
     }
+
+    #[tokio::test]
+    async fn test_delete_post_reports_deleted_attachments() {
+        let db = Database::open(":memory:").await.unwrap();
+        let user_id = db.users().create("uuid-1", "alice").await.unwrap();
+
+        let post_uuid = db
+            .posts()
+            .create(user_id, None, false, None, "", false, None, None, None)
+            .await
+            .unwrap();
+        let post = db
+            .posts()
+            .get_by_uuid(&post_uuid, user_id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let att_uuid = db
+            .attachments()
+            .create(create_test_input(user_id))
+            .await
+            .unwrap();
+        db.attachments()
+            .update_post_attachments(post.id, user_id, &[att_uuid.clone()])
+            .await
+            .unwrap();
+
+        let result = db
+            .delete_post_with_attachments(&post_uuid, user_id)
+            .await
+            .unwrap();
+        assert!(result.deleted);
+        assert_eq!(result.deleted_attachments, vec![att_uuid]);
+    }
+
+    #[tokio::test]
+    async fn test_library_listing_and_deletion() {
+        let db = Database::open(":memory:").await.unwrap();
+        let user_id = db.users().create("uuid-1", "alice").await.unwrap();
+        let other = db.users().create("uuid-2", "bob").await.unwrap();
+        let attachments = db.attachments();
+
+        let post_uuid = db
+            .posts()
+            .create(
+                user_id,
+                Some("Holiday"),
+                false,
+                None,
+                "content",
+                false,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        let post = db
+            .posts()
+            .get_by_uuid(&post_uuid, user_id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let used = attachments
+            .create(create_test_input(user_id))
+            .await
+            .unwrap();
+        let unused = attachments
+            .create(CreateAttachmentInput {
+                image_data: b"other_image",
+                ..create_test_input(user_id)
+            })
+            .await
+            .unwrap();
+        attachments.create(create_test_input(other)).await.unwrap();
+        attachments
+            .update_post_attachments(post.id, user_id, &[used.clone()])
+            .await
+            .unwrap();
+
+        assert_eq!(attachments.count(user_id).await.unwrap(), 2);
+
+        // Newest first, one page at a time
+        let page = attachments.list(user_id, 1, 0).await.unwrap();
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].uuid, unused);
+        assert!(page[0].posts.is_empty());
+        assert_eq!(page[0].size, 11 + 13 + 13 + 13);
+        let page = attachments.list(user_id, 1, 1).await.unwrap();
+        assert_eq!(page[0].uuid, used);
+        assert_eq!(page[0].reference_count, 1);
+        assert_eq!(page[0].encryption_version, 1);
+        assert!(page[0].has_thumbnails);
+        assert_eq!(
+            page[0].posts,
+            vec![PostRef {
+                uuid: post_uuid.clone(),
+                title: Some("Holiday".to_string()),
+                title_encrypted: false,
+                title_iv: None,
+            }]
+        );
+        assert!(attachments.list(user_id, 1, 2).await.unwrap().is_empty());
+
+        let posts = attachments
+            .get_referencing_posts(&used, user_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(posts.len(), 1);
+        assert_eq!(posts[0].uuid, post_uuid);
+        assert!(
+            attachments
+                .get_referencing_posts(&used, other)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        // Only unreferenced attachments of the user can be deleted
+        assert_eq!(
+            attachments
+                .delete_unreferenced(&used, user_id)
+                .await
+                .unwrap(),
+            DeleteAttachmentResult::Referenced(1)
+        );
+        assert_eq!(
+            attachments
+                .delete_unreferenced(&unused, other)
+                .await
+                .unwrap(),
+            DeleteAttachmentResult::NotFound
+        );
+        assert_eq!(
+            attachments
+                .delete_unreferenced(&unused, user_id)
+                .await
+                .unwrap(),
+            DeleteAttachmentResult::Deleted
+        );
+        assert!(
+            attachments
+                .get_by_uuid(&used, user_id)
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert_eq!(attachments.count(user_id).await.unwrap(), 1);
+    }
 }
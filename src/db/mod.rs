@@ -3,24 +3,54 @@ mod challenge;
 mod encryption;
 mod login_challenge;
 mod passkey;
+mod plugin_kv;
 mod posts;
+mod quota;
+mod sync;
 mod token;
+mod uploads;
 mod user;
 
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
 use sqlx::sqlite::{SqlitePool, SqlitePoolOptions};
+use tokio::sync::RwLock;
+
+use crate::blob::{BlobStore, DatabaseBlobStore, content_key};
 
-pub use attachments::{Attachment, AttachmentStore};
+pub use attachments::{
+    Attachment, AttachmentInfo, AttachmentKind, AttachmentStore, AttachmentSummary,
+    DeleteAttachmentResult, PostRef,
+};
 pub use challenge::ChallengeStore;
 pub use encryption::{EncryptionSettings, EncryptionSettingsStore};
 pub use login_challenge::{AuthChallenge, LoginChallengeStore};
 pub use passkey::{PasskeyStore, StoredPasskey};
-pub use posts::{DeleteResult, Post, PostNode, PostStore, PostSummary, UpdatePostParams};
+pub use plugin_kv::PluginKvStore;
+pub use posts::{
+    DeleteResult, Post, PostNode, PostStore, PostSummary, UpdatePostParams, UpdatePostResult,
+};
+pub use quota::{QuotaLock, QuotaStore, StorageUsage};
+pub use sync::{ChangedPost, PostChange, SyncStore};
 pub use token::{ActiveToken, TokenStore};
+pub use uploads::{UploadLock, UploadSession, UploadStore};
 pub use user::{User, UserRole, UserStore};
 
 #[derive(Clone)]
 pub struct Database {
     pool: SqlitePool,
+    blobs: Arc<dyn BlobStore>,
+    /// Held shared while storing new blobs and exclusively while deleting
+    /// unreferenced ones, so a blob is never deleted under a new upload.
+    blob_gc: Arc<RwLock<()>>,
+    /// Where resumable uploads are staged until they are finalized.
+    upload_dir: Arc<PathBuf>,
+    /// Uploads a request is currently writing to or finalizing.
+    active_uploads: Arc<Mutex<HashSet<String>>>,
+    /// Users whose quota a request is currently checking and writing against.
+    quota_locks: quota::QuotaLocks,
 }
 
 impl Database {
@@ -38,11 +68,49 @@ impl Database {
             .connect(&url)
             .await?;
 
-        let db = Self { pool };
+        let db = Self::from_pool(pool, Self::default_upload_dir(path));
         db.migrate().await?;
         Ok(db)
     }
 
+    /// Wrap a pool with the default blob store, without running migrations.
+    fn from_pool(pool: SqlitePool, upload_dir: PathBuf) -> Self {
+        Self {
+            blobs: Arc::new(DatabaseBlobStore::new(pool.clone())),
+            pool,
+            blob_gc: Arc::new(RwLock::new(())),
+            upload_dir: Arc::new(upload_dir),
+            active_uploads: Arc::new(Mutex::new(HashSet::new())),
+            quota_locks: Default::default(),
+        }
+    }
+
+    /// Where uploads are staged unless configured otherwise: next to the
+    /// database file, so upload sessions and their data persist together and
+    /// databases never share staging files. In-memory databases use a
+    /// directory of their own under the system temp directory.
+    fn default_upload_dir(path: &str) -> PathBuf {
+        if path == ":memory:" {
+            let name = format!("crowchiper-uploads-{}", uuid::Uuid::new_v4().simple());
+            std::env::temp_dir().join(name)
+        } else {
+            PathBuf::from(format!("{path}.uploads"))
+        }
+    }
+
+    /// Keep attachment data in `blobs` instead of the database's own
+    /// `blobs` table.
+    pub fn with_blob_store(mut self, blobs: Arc<dyn BlobStore>) -> Self {
+        self.blobs = blobs;
+        self
+    }
+
+    /// Stage resumable uploads in `dir` instead of next to the database.
+    pub fn with_upload_dir(mut self, dir: PathBuf) -> Self {
+        self.upload_dir = Arc::new(dir);
+        self
+    }
+
     /// Get the current schema version.
     async fn get_version(&self) -> Result<i32, sqlx::Error> {
         let result: Option<(i32,)> = sqlx::query_as("SELECT version FROM schema_version LIMIT 1")
@@ -77,6 +145,30 @@ impl Database {
         if version < 1 {
             self.migrate_v1().await?;
         }
+        if version < 2 {
+            self.migrate_v2().await?;
+        }
+        if version < 3 {
+            self.migrate_v3().await?;
+        }
+        if version < 4 {
+            self.migrate_v4().await?;
+        }
+        if version < 5 {
+            self.migrate_v5().await?;
+        }
+        if version < 6 {
+            self.migrate_v6().await?;
+        }
+        if version < 7 {
+            self.migrate_v7().await?;
+        }
+        if version < 8 {
+            self.migrate_v8().await?;
+        }
+        if version < 9 {
+            self.migrate_v9().await?;
+        }
         Ok(())
     }
 
@@ -215,6 +307,290 @@ impl Database {
         .await
     }
 
+    /// Add a revision counter to posts for optimistic concurrency control.
+    async fn migrate_v2(&self) -> Result<(), sqlx::Error> {
+        self.run_migration(
+            2,
+            &["ALTER TABLE posts ADD COLUMN revision INTEGER NOT NULL DEFAULT 1"],
+        )
+        .await
+    }
+
+    /// Add the per-user post change feed used by incremental sync, seeded with
+    /// one change per existing post.
+    async fn migrate_v3(&self) -> Result<(), sqlx::Error> {
+        self.run_migration(
+            3,
+            &[
+                // Latest change sequence number per user
+                "CREATE TABLE sync_sequences (
+                    user_id INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+                    seq INTEGER NOT NULL
+                )",
+                // Latest change per post, including tombstones for deleted posts
+                "CREATE TABLE post_changes (
+                    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                    post_uuid TEXT NOT NULL,
+                    seq INTEGER NOT NULL,
+                    deleted INTEGER NOT NULL DEFAULT 0,
+                    PRIMARY KEY (user_id, post_uuid)
+                )",
+                "CREATE INDEX idx_post_changes_seq ON post_changes(user_id, seq)",
+                // Existing posts enter the feed once, so a full sync returns them
+                "INSERT INTO post_changes (user_id, post_uuid, seq)
+                 SELECT user_id, uuid, ROW_NUMBER() OVER (PARTITION BY user_id ORDER BY id)
+                 FROM posts",
+                "INSERT INTO sync_sequences (user_id, seq)
+                 SELECT user_id, MAX(seq) FROM post_changes GROUP BY user_id",
+            ],
+        )
+        .await
+    }
+
+    /// Add per-plugin key-value storage.
+    async fn migrate_v4(&self) -> Result<(), sqlx::Error> {
+        self.run_migration(
+            4,
+            &["CREATE TABLE plugin_kv (
+                plugin TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value BLOB NOT NULL,
+                updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+                PRIMARY KEY (plugin, key)
+            )"],
+        )
+        .await
+    }
+
+    /// Move attachment data out of the `attachments` table.
+    ///
+    /// Rows keep only content keys and IVs; the bytes move to the `blobs`
+    /// table (the database blob store), from where `crowchiper storage migrate`
+    /// can move them to another store. Deleted rows queue their keys in
+    /// `blob_deletions` for [`AttachmentStore::purge_deleted_blobs`].
+    async fn migrate_v5(&self) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        for query in [
+            "CREATE TABLE blobs (
+                key TEXT PRIMARY KEY,
+                data BLOB NOT NULL
+            )",
+            "CREATE TABLE blob_deletions (
+                key TEXT PRIMARY KEY,
+                created_at TEXT NOT NULL DEFAULT (datetime('now'))
+            )",
+            "CREATE TABLE attachments_v5 (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                uuid TEXT UNIQUE NOT NULL,
+                user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                image_key TEXT NOT NULL,
+                image_iv TEXT,
+                thumb_sm_key TEXT NOT NULL,
+                thumb_sm_iv TEXT,
+                thumb_md_key TEXT,
+                thumb_md_iv TEXT,
+                thumb_lg_key TEXT,
+                thumb_lg_iv TEXT,
+                encryption_version INTEGER NOT NULL,
+                reference_count INTEGER NOT NULL DEFAULT 0,
+                created_at TEXT NOT NULL DEFAULT (datetime('now'))
+            )",
+        ] {
+            sqlx::query(query).execute(&mut *tx).await?;
+        }
+
+        // Copy one row at a time so only a single attachment is in memory.
+        let ids: Vec<(i64,)> = sqlx::query_as("SELECT id FROM attachments ORDER BY id")
+            .fetch_all(&mut *tx)
+            .await?;
+        for (id,) in ids {
+            let row: (Vec<u8>, Vec<u8>, Option<Vec<u8>>, Option<Vec<u8>>) = sqlx::query_as(
+                "SELECT image_data, thumb_sm, thumb_md, thumb_lg FROM attachments WHERE id = ?",
+            )
+            .bind(id)
+            .fetch_one(&mut *tx)
+            .await?;
+            let (image, thumb_sm, thumb_md, thumb_lg) = row;
+
+            let mut keys: Vec<Option<String>> = Vec::new();
+            for data in [
+                Some(&image),
+                Some(&thumb_sm),
+                thumb_md.as_ref(),
+                thumb_lg.as_ref(),
+            ] {
+                let Some(data) = data else {
+                    keys.push(None);
+                    continue;
+                };
+                let key = content_key(data);
+                sqlx::query("INSERT OR IGNORE INTO blobs (key, data) VALUES (?, ?)")
+                    .bind(&key)
+                    .bind(data)
+                    .execute(&mut *tx)
+                    .await?;
+                keys.push(Some(key));
+            }
+
+            sqlx::query(
+                "INSERT INTO attachments_v5 (id, uuid, user_id, image_key, image_iv,
+                 thumb_sm_key, thumb_sm_iv, thumb_md_key, thumb_md_iv, thumb_lg_key, thumb_lg_iv,
+                 encryption_version, reference_count, created_at)
+                 SELECT id, uuid, user_id, ?, image_iv, ?, thumb_sm_iv, ?, thumb_md_iv, ?, thumb_lg_iv,
+                 encryption_version, reference_count, created_at
+                 FROM attachments WHERE id = ?",
+            )
+            .bind(&keys[0])
+            .bind(&keys[1])
+            .bind(&keys[2])
+            .bind(&keys[3])
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for query in [
+            "DROP TABLE attachments",
+            "ALTER TABLE attachments_v5 RENAME TO attachments",
+            "CREATE INDEX idx_attachments_uuid ON attachments(uuid)",
+            "CREATE INDEX idx_attachments_user_id ON attachments(user_id)",
+            "CREATE INDEX idx_attachments_ref_count ON attachments(reference_count)",
+            "CREATE INDEX idx_attachments_image_key ON attachments(image_key)",
+            "CREATE INDEX idx_attachments_thumb_sm_key ON attachments(thumb_sm_key)",
+            "CREATE INDEX idx_attachments_thumb_md_key ON attachments(thumb_md_key)",
+            "CREATE INDEX idx_attachments_thumb_lg_key ON attachments(thumb_lg_key)",
+            // Also fires for rows removed by ON DELETE CASCADE.
+            "CREATE TRIGGER attachments_queue_blob_deletions AFTER DELETE ON attachments
+             BEGIN
+                INSERT OR IGNORE INTO blob_deletions (key)
+                SELECT key FROM (
+                    SELECT OLD.image_key AS key
+                    UNION SELECT OLD.thumb_sm_key
+                    UNION SELECT OLD.thumb_md_key
+                    UNION SELECT OLD.thumb_lg_key
+                ) WHERE key IS NOT NULL;
+             END",
+        ] {
+            sqlx::query(query).execute(&mut *tx).await?;
+        }
+
+        Self::set_version(&mut tx, 5).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Add file attachments: a kind, the original filename (encrypted along
+    /// with the data when the attachment is) and MIME type, and optional
+    /// thumbnails. Making `thumb_sm_key` nullable needs a table rebuild.
+    async fn migrate_v6(&self) -> Result<(), sqlx::Error> {
+        self.run_migration(
+            6,
+            &[
+                "CREATE TABLE attachments_v6 (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    uuid TEXT UNIQUE NOT NULL,
+                    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                    kind TEXT NOT NULL DEFAULT 'image',
+                    filename TEXT,
+                    filename_iv TEXT,
+                    mime_type TEXT,
+                    image_key TEXT NOT NULL,
+                    image_iv TEXT,
+                    thumb_sm_key TEXT,
+                    thumb_sm_iv TEXT,
+                    thumb_md_key TEXT,
+                    thumb_md_iv TEXT,
+                    thumb_lg_key TEXT,
+                    thumb_lg_iv TEXT,
+                    encryption_version INTEGER NOT NULL,
+                    reference_count INTEGER NOT NULL DEFAULT 0,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+                )",
+                "INSERT INTO attachments_v6 (id, uuid, user_id, image_key, image_iv,
+                 thumb_sm_key, thumb_sm_iv, thumb_md_key, thumb_md_iv, thumb_lg_key, thumb_lg_iv,
+                 encryption_version, reference_count, created_at)
+                 SELECT id, uuid, user_id, image_key, image_iv,
+                 thumb_sm_key, thumb_sm_iv, thumb_md_key, thumb_md_iv, thumb_lg_key, thumb_lg_iv,
+                 encryption_version, reference_count, created_at
+                 FROM attachments",
+                "DROP TABLE attachments",
+                "ALTER TABLE attachments_v6 RENAME TO attachments",
+                "CREATE INDEX idx_attachments_uuid ON attachments(uuid)",
+                "CREATE INDEX idx_attachments_user_id ON attachments(user_id)",
+                "CREATE INDEX idx_attachments_ref_count ON attachments(reference_count)",
+                "CREATE INDEX idx_attachments_image_key ON attachments(image_key)",
+                "CREATE INDEX idx_attachments_thumb_sm_key ON attachments(thumb_sm_key)",
+                "CREATE INDEX idx_attachments_thumb_md_key ON attachments(thumb_md_key)",
+                "CREATE INDEX idx_attachments_thumb_lg_key ON attachments(thumb_lg_key)",
+                "CREATE TRIGGER attachments_queue_blob_deletions AFTER DELETE ON attachments
+                 BEGIN
+                    INSERT OR IGNORE INTO blob_deletions (key)
+                    SELECT key FROM (
+                        SELECT OLD.image_key AS key
+                        UNION SELECT OLD.thumb_sm_key
+                        UNION SELECT OLD.thumb_md_key
+                        UNION SELECT OLD.thumb_lg_key
+                    ) WHERE key IS NOT NULL;
+                 END",
+            ],
+        )
+        .await
+    }
+
+    async fn migrate_v7(&self) -> Result<(), sqlx::Error> {
+        self.run_migration(
+            7,
+            &[
+                "CREATE TABLE upload_sessions (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    uuid TEXT UNIQUE NOT NULL,
+                    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+                    length INTEGER NOT NULL,
+                    received INTEGER NOT NULL DEFAULT 0,
+                    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+                )",
+                "CREATE INDEX idx_upload_sessions_user_id ON upload_sessions(user_id)",
+                "CREATE INDEX idx_upload_sessions_updated_at ON upload_sessions(updated_at)",
+            ],
+        )
+        .await
+    }
+
+    /// Add storage quotas: attachment sizes for usage accounting (backfilled
+    /// from blobs still in the database, the rest at startup by
+    /// [`AttachmentStore::backfill_sizes`]), per-user quota overrides and a
+    /// table for server-wide settings such as the default quota.
+    async fn migrate_v8(&self) -> Result<(), sqlx::Error> {
+        self.run_migration(
+            8,
+            &[
+                "ALTER TABLE attachments ADD COLUMN size INTEGER NOT NULL DEFAULT 0",
+                "UPDATE attachments SET size =
+                    COALESCE((SELECT length(data) FROM blobs WHERE key = image_key), 0)
+                    + COALESCE((SELECT length(data) FROM blobs WHERE key = thumb_sm_key), 0)
+                    + COALESCE((SELECT length(data) FROM blobs WHERE key = thumb_md_key), 0)
+                    + COALESCE((SELECT length(data) FROM blobs WHERE key = thumb_lg_key), 0)",
+                "ALTER TABLE users ADD COLUMN storage_quota INTEGER",
+                "CREATE TABLE server_settings (
+                    key TEXT PRIMARY KEY,
+                    value TEXT NOT NULL
+                )",
+            ],
+        )
+        .await
+    }
+
+    /// Remember unencrypted images the server could not render thumbnails
+    /// of, so they are not decoded again on every thumbnail request.
+    async fn migrate_v9(&self) -> Result<(), sqlx::Error> {
+        self.run_migration(
+            9,
+            &["ALTER TABLE attachments ADD COLUMN thumbnail_failed INTEGER NOT NULL DEFAULT 0"],
+        )
+        .await
+    }
+
     /// Get the user store.
     pub fn users(&self) -> UserStore {
         UserStore::new(self.pool.clone())
@@ -252,7 +628,26 @@ impl Database {
 
     /// Get the attachments store.
     pub fn attachments(&self) -> AttachmentStore {
-        AttachmentStore::new(self.pool.clone())
+        AttachmentStore::new(self.pool.clone(), self.blobs.clone(), self.blob_gc.clone())
+    }
+
+    /// Get the blob store attachment data is kept in.
+    pub fn blobs(&self) -> &Arc<dyn BlobStore> {
+        &self.blobs
+    }
+
+    /// Get the resumable upload store.
+    pub fn uploads(&self) -> UploadStore {
+        UploadStore::new(
+            self.pool.clone(),
+            self.upload_dir.clone(),
+            self.active_uploads.clone(),
+        )
+    }
+
+    /// Get the storage quota store.
+    pub fn quotas(&self) -> QuotaStore {
+        QuotaStore::new(self.pool.clone(), self.quota_locks.clone())
     }
 
     /// Get the tokens store.
@@ -260,46 +655,89 @@ impl Database {
         TokenStore::new(self.pool.clone())
     }
 
+    /// Get the sync (change feed) store.
+    pub fn sync(&self) -> SyncStore {
+        SyncStore::new(self.pool.clone())
+    }
+
+    /// Get the plugin key-value store.
+    pub fn plugin_kv(&self) -> PluginKvStore {
+        PluginKvStore::new(self.pool.clone())
+    }
+
     /// Begin a new transaction.
     pub async fn begin(&self) -> Result<sqlx::Transaction<'_, sqlx::Sqlite>, sqlx::Error> {
         self.pool.begin().await
     }
 
     /// Update a post and optionally its attachment references atomically.
-    /// Returns Ok(true) if the post was found and updated, Ok(false) if not found.
+    ///
+    /// If `expected_revision` is set and does not match the stored revision, nothing
+    /// is written and the current post is returned as a conflict. The revision check
+    /// and the content update happen in a single conditional `UPDATE`, and attachment
+    /// references are only touched once that update succeeds, so a retried request
+    /// can never apply its attachment diff on top of a rejected write.
     pub async fn update_post_with_attachments(
         &self,
         params: UpdatePostParams<'_>,
-    ) -> Result<bool, sqlx::Error> {
+    ) -> Result<UpdatePostResult, sqlx::Error> {
         let mut tx = self.pool.begin().await?;
 
-        let post_id =
-            match PostStore::get_id_by_uuid_tx(&mut tx, params.uuid, params.user_id).await? {
-                Some(id) => id,
-                None => return Ok(false),
+        let (post_id, revision) =
+            match PostStore::get_revision_tx(&mut tx, params.uuid, params.user_id).await? {
+                Some(row) => row,
+                None => return Ok(UpdatePostResult::NotFound),
             };
 
-        PostStore::update_tx(
-            &mut tx,
-            params.uuid,
-            params.user_id,
-            params.title,
-            params.title_encrypted,
-            params.title_iv,
-            params.content,
-            params.content_encrypted,
-            params.iv,
-            params.encryption_version,
-        )
-        .await?;
+        let is_stale = params
+            .expected_revision
+            .is_some_and(|expected| expected != revision);
+
+        let updated = !is_stale
+            && PostStore::update_tx(
+                &mut tx,
+                params.uuid,
+                params.user_id,
+                params.title,
+                params.title_encrypted,
+                params.title_iv,
+                params.content,
+                params.content_encrypted,
+                params.iv,
+                params.encryption_version,
+                revision,
+            )
+            .await?;
 
-        if let Some(uuids) = params.attachment_uuids {
-            AttachmentStore::update_post_attachments_tx(&mut tx, post_id, params.user_id, uuids)
-                .await?;
+        if !updated {
+            // Either the client was stale, or another writer bumped the revision
+            // between our read and the conditional update.
+            tx.rollback().await?;
+            return match self
+                .posts()
+                .get_by_uuid(params.uuid, params.user_id)
+                .await?
+            {
+                Some(current) => Ok(UpdatePostResult::Conflict(current)),
+                None => Ok(UpdatePostResult::NotFound),
+            };
         }
 
+        let deleted_attachments = match params.attachment_uuids {
+            Some(uuids) => {
+                AttachmentStore::update_post_attachments_tx(&mut tx, post_id, params.user_id, uuids)
+                    .await?
+            }
+            None => Vec::new(),
+        };
+
+        SyncStore::record_tx(&mut tx, params.user_id, params.uuid, false).await?;
+
         tx.commit().await?;
-        Ok(true)
+        Ok(UpdatePostResult::Updated {
+            revision: revision + 1,
+            deleted_attachments,
+        })
     }
 
     /// Delete a post and all its descendants, cleaning up attachment references atomically.
@@ -317,22 +755,30 @@ impl Database {
             return Ok(DeleteResult {
                 deleted: false,
                 children_deleted: 0,
+                deleted_attachments: Vec::new(),
             });
         }
 
-        let descendant_ids = PostStore::get_descendant_ids_tx(&mut tx, uuid, user_id).await?;
+        let descendants = PostStore::get_descendants_tx(&mut tx, uuid, user_id).await?;
 
-        for id in descendant_ids {
-            AttachmentStore::remove_post_attachments_tx(&mut tx, id, user_id).await?;
+        let mut deleted_attachments = Vec::new();
+        for (id, _) in &descendants {
+            deleted_attachments
+                .extend(AttachmentStore::remove_post_attachments_tx(&mut tx, *id, user_id).await?);
         }
 
         PostStore::delete_tx(&mut tx, uuid, user_id).await?;
 
+        for (_, descendant_uuid) in &descendants {
+            SyncStore::record_tx(&mut tx, user_id, descendant_uuid, true).await?;
+        }
+
         tx.commit().await?;
 
         Ok(DeleteResult {
             deleted: true,
             children_deleted: children_count,
+            deleted_attachments,
         })
     }
 }
@@ -391,6 +837,129 @@ mod tests {
         assert!(!db.users().is_username_available("alice").await.unwrap());
     }
 
+    #[tokio::test]
+    async fn test_migrate_v3_backfills_post_changes() {
+        // One connection, so every query sees the same in-memory database.
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let db = Database::from_pool(pool, Database::default_upload_dir(":memory:"));
+        sqlx::query("CREATE TABLE schema_version (version INTEGER NOT NULL)")
+            .execute(db.pool())
+            .await
+            .unwrap();
+        db.migrate_v1().await.unwrap();
+        db.migrate_v2().await.unwrap();
+
+        let alice = db.users().create("uuid-1", "alice").await.unwrap();
+        let bob = db.users().create("uuid-2", "bob").await.unwrap();
+        for (uuid, user_id) in [("post-1", alice), ("post-2", alice), ("post-3", bob)] {
+            sqlx::query("INSERT INTO posts (uuid, user_id, title) VALUES (?, ?, 'title')")
+                .bind(uuid)
+                .bind(user_id)
+                .execute(db.pool())
+                .await
+                .unwrap();
+        }
+
+        db.migrate().await.unwrap();
+
+        let changes = db.sync().changes_since(alice, 0, 100).await.unwrap();
+        let uuids: Vec<_> = changes
+            .iter()
+            .map(|change| match change {
+                PostChange::Upsert { post, .. } => post.uuid.as_str(),
+                PostChange::Deleted { uuid, .. } => panic!("unexpected tombstone for {uuid}"),
+            })
+            .collect();
+        assert_eq!(uuids, ["post-1", "post-2"]);
+        assert_eq!(db.sync().current_seq(alice).await.unwrap(), 2);
+        assert_eq!(db.sync().changes_since(bob, 0, 100).await.unwrap().len(), 1);
+        assert_eq!(db.sync().current_seq(bob).await.unwrap(), 1);
+
+        // New changes continue after the backfilled sequence
+        let uuid = db
+            .posts()
+            .create(alice, Some("new"), false, None, "", false, None, None, None)
+            .await
+            .unwrap();
+        let changes = db.sync().changes_since(alice, 2, 100).await.unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].seq(), 3);
+        assert!(matches!(&changes[0], PostChange::Upsert { post, .. } if post.uuid == uuid));
+    }
+
+    #[tokio::test]
+    async fn test_migrate_v5_moves_attachment_data_to_blobs() {
+        // One connection, so every query sees the same in-memory database.
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        let db = Database::from_pool(pool, Database::default_upload_dir(":memory:"));
+        sqlx::query("CREATE TABLE schema_version (version INTEGER NOT NULL)")
+            .execute(db.pool())
+            .await
+            .unwrap();
+        db.migrate_v1().await.unwrap();
+        db.migrate_v2().await.unwrap();
+        db.migrate_v3().await.unwrap();
+        db.migrate_v4().await.unwrap();
+
+        let user_id = db.users().create("uuid-1", "alice").await.unwrap();
+        sqlx::query(
+            "INSERT INTO attachments (uuid, user_id, image_data, image_iv, thumb_sm, thumb_sm_iv,
+             encryption_version, reference_count) VALUES ('att-1', ?, ?, 'iv', ?, NULL, 1, 1)",
+        )
+        .bind(user_id)
+        .bind(b"image".as_slice())
+        .bind(b"thumb".as_slice())
+        .execute(db.pool())
+        .await
+        .unwrap();
+
+        db.migrate_v5().await.unwrap();
+        assert_eq!(db.get_version().await.unwrap(), 5);
+        db.migrate_v6().await.unwrap();
+        db.migrate_v7().await.unwrap();
+        db.migrate_v8().await.unwrap();
+        db.migrate_v9().await.unwrap();
+
+        let attachment = db
+            .attachments()
+            .get_by_uuid("att-1", user_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(attachment.image_data, b"image");
+        assert_eq!(attachment.image_iv.as_deref(), Some("iv"));
+        assert_eq!(attachment.kind, AttachmentKind::Image);
+        let thumbnails = attachment.thumbnails.unwrap();
+        assert_eq!(thumbnails.sm.data, b"thumb");
+        assert!(thumbnails.md.is_none());
+        assert_eq!(attachment.reference_count, 1);
+        assert_eq!(
+            db.blobs().get(&content_key(b"image")).await.unwrap(),
+            Some(b"image".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_default_upload_dir() {
+        assert_eq!(
+            Database::default_upload_dir("/var/lib/crowchiper/notes.db"),
+            PathBuf::from("/var/lib/crowchiper/notes.db.uploads")
+        );
+        // In-memory databases never share a directory
+        assert_ne!(
+            Database::default_upload_dir(":memory:"),
+            Database::default_upload_dir(":memory:")
+        );
+    }
+
     #[tokio::test]
     async fn test_delete_user() {
         let db = Database::open(":memory:").await.unwrap();
@@ -0,0 +1,193 @@
+//! Durable key-value storage for WASM plugins.
+//!
+//! Every plugin gets its own namespace, keyed by the plugin name. Quotas are
+//! set by the caller (see `PluginState` in the plugin module) and enforced by
+//! [`PluginKvStore::set_within_quota`].
+
+use sqlx::sqlite::SqlitePool;
+
+#[derive(Clone)]
+pub struct PluginKvStore {
+    pool: SqlitePool,
+}
+
+impl PluginKvStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Get the value stored under `key` in the plugin's namespace.
+    pub async fn get(&self, plugin: &str, key: &str) -> Result<Option<Vec<u8>>, sqlx::Error> {
+        let row: Option<(Vec<u8>,)> =
+            sqlx::query_as("SELECT value FROM plugin_kv WHERE plugin = ? AND key = ?")
+                .bind(plugin)
+                .bind(key)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(row.map(|r| r.0))
+    }
+
+    /// Store `value` under `key`, replacing any existing value.
+    pub async fn set(&self, plugin: &str, key: &str, value: &[u8]) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO plugin_kv (plugin, key, value, updated_at) VALUES (?, ?, ?, datetime('now'))
+             ON CONFLICT(plugin, key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at",
+        )
+        .bind(plugin)
+        .bind(key)
+        .bind(value)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Store `value` under `key` unless the plugin's keys plus values would
+    /// then exceed `quota` bytes. Returns false, storing nothing, if they would.
+    ///
+    /// The usage check and the write are a single statement, so concurrent
+    /// writes can't each pass the check against the same usage.
+    pub async fn set_within_quota(
+        &self,
+        plugin: &str,
+        key: &str,
+        value: &[u8],
+        quota: u64,
+    ) -> Result<bool, sqlx::Error> {
+        let size = (key.len() + value.len()) as i64;
+        let result = sqlx::query(
+            "INSERT INTO plugin_kv (plugin, key, value, updated_at)
+             SELECT ?1, ?2, ?3, datetime('now')
+             WHERE (SELECT COALESCE(SUM(length(CAST(key AS BLOB)) + length(value)), 0)
+                    FROM plugin_kv WHERE plugin = ?1 AND key != ?2) + ?4 <= ?5
+             ON CONFLICT(plugin, key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at",
+        )
+        .bind(plugin)
+        .bind(key)
+        .bind(value)
+        .bind(size)
+        .bind(quota.min(i64::MAX as u64) as i64)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Delete `key`. Returns true if it existed.
+    pub async fn delete(&self, plugin: &str, key: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM plugin_kv WHERE plugin = ? AND key = ?")
+            .bind(plugin)
+            .bind(key)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// List keys starting with `prefix` in ascending order, up to `limit`.
+    pub async fn list_prefix(
+        &self,
+        plugin: &str,
+        prefix: &str,
+        limit: i64,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        // instr() avoids LIKE, where `%` and `_` in the prefix would be wildcards
+        let rows: Vec<(String,)> = sqlx::query_as(
+            "SELECT key FROM plugin_kv WHERE plugin = ? AND instr(key, ?) = 1
+             ORDER BY key LIMIT ?",
+        )
+        .bind(plugin)
+        .bind(prefix)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(|r| r.0).collect())
+    }
+
+    /// Total bytes (keys plus values) stored by the plugin, not counting `key`.
+    ///
+    /// Used to check whether replacing `key` would exceed the plugin's quota.
+    pub async fn usage_excluding(&self, plugin: &str, key: &str) -> Result<u64, sqlx::Error> {
+        let (bytes,): (i64,) = sqlx::query_as(
+            "SELECT COALESCE(SUM(length(CAST(key AS BLOB)) + length(value)), 0)
+             FROM plugin_kv WHERE plugin = ? AND key != ?",
+        )
+        .bind(plugin)
+        .bind(key)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(bytes as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Database;
+
+    #[tokio::test]
+    async fn test_set_get_delete() {
+        let db = Database::open(":memory:").await.unwrap();
+        let kv = db.plugin_kv();
+
+        assert_eq!(kv.get("a", "k").await.unwrap(), None);
+
+        kv.set("a", "k", b"one").await.unwrap();
+        kv.set("a", "k", b"two").await.unwrap();
+        assert_eq!(kv.get("a", "k").await.unwrap(), Some(b"two".to_vec()));
+
+        assert!(kv.delete("a", "k").await.unwrap());
+        assert!(!kv.delete("a", "k").await.unwrap());
+        assert_eq!(kv.get("a", "k").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_namespaces_are_isolated() {
+        let db = Database::open(":memory:").await.unwrap();
+        let kv = db.plugin_kv();
+
+        kv.set("a", "k", b"from a").await.unwrap();
+        kv.set("b", "k", b"from b").await.unwrap();
+
+        assert_eq!(kv.get("a", "k").await.unwrap(), Some(b"from a".to_vec()));
+        assert_eq!(kv.list_prefix("b", "", 10).await.unwrap(), vec!["k"]);
+        assert_eq!(kv.usage_excluding("a", "").await.unwrap(), 7);
+    }
+
+    #[tokio::test]
+    async fn test_set_within_quota() {
+        let db = Database::open(":memory:").await.unwrap();
+        let kv = db.plugin_kv();
+
+        assert!(kv.set_within_quota("a", "k", b"12345", 10).await.unwrap());
+        assert!(!kv.set_within_quota("a", "j", b"123456", 10).await.unwrap());
+        assert_eq!(kv.get("a", "j").await.unwrap(), None);
+        // Replacing a value only counts its new size
+        assert!(
+            kv.set_within_quota("a", "k", b"123456789", 10)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !kv.set_within_quota("a", "k", b"1234567890", 10)
+                .await
+                .unwrap()
+        );
+        assert_eq!(kv.get("a", "k").await.unwrap(), Some(b"123456789".to_vec()));
+        // Other namespaces don't count
+        assert!(kv.set_within_quota("b", "k", b"12345", 10).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_list_prefix_treats_wildcards_literally() {
+        let db = Database::open(":memory:").await.unwrap();
+        let kv = db.plugin_kv();
+
+        for key in ["ip:alice", "ip:bob", "ip_x", "other"] {
+            kv.set("a", key, b"").await.unwrap();
+        }
+
+        assert_eq!(
+            kv.list_prefix("a", "ip:", 10).await.unwrap(),
+            vec!["ip:alice", "ip:bob"]
+        );
+        assert_eq!(kv.list_prefix("a", "ip_", 10).await.unwrap(), vec!["ip_x"]);
+        assert_eq!(kv.list_prefix("a", "", 2).await.unwrap().len(), 2);
+    }
+}
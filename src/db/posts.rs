@@ -3,6 +3,8 @@
 use sqlx::sqlite::SqlitePool;
 use std::collections::HashMap;
 
+use super::sync::SyncStore;
+
 #[derive(Clone)]
 pub struct PostStore {
     pool: SqlitePool,
@@ -23,6 +25,8 @@ pub struct Post {
     pub encryption_version: Option<i32>,
     pub position: Option<i32>,
     pub parent_id: Option<String>,
+    /// Incremented on every content update, used for optimistic concurrency control.
+    pub revision: i64,
     pub created_at: String,
     pub updated_at: String,
 }
@@ -64,6 +68,8 @@ pub struct PostNode {
 pub struct DeleteResult {
     pub deleted: bool,
     pub children_deleted: i64,
+    /// Attachments removed because the deleted posts held their last reference.
+    pub deleted_attachments: Vec<String>,
 }
 
 /// Parameters for updating a post with optional attachment references.
@@ -78,6 +84,23 @@ pub struct UpdatePostParams<'a> {
     pub iv: Option<&'a str>,
     pub encryption_version: Option<i32>,
     pub attachment_uuids: Option<&'a [String]>,
+    /// Revision the client based its edit on. `None` skips the check (last writer wins).
+    pub expected_revision: Option<i64>,
+}
+
+/// Outcome of a conditional post update.
+#[derive(Debug, Clone)]
+pub enum UpdatePostResult {
+    /// The post was updated and now has this revision. `deleted_attachments` lists
+    /// attachments dropped from the post that are no longer referenced anywhere.
+    Updated {
+        revision: i64,
+        deleted_attachments: Vec<String>,
+    },
+    /// The post does not exist or doesn't belong to the user.
+    NotFound,
+    /// The expected revision did not match. Contains the current server version.
+    Conflict(Post),
 }
 
 #[derive(sqlx::FromRow)]
@@ -94,6 +117,7 @@ struct PostRow {
     encryption_version: Option<i32>,
     position: Option<i32>,
     parent_id: Option<String>,
+    revision: i64,
     created_at: String,
     updated_at: String,
 }
@@ -113,6 +137,7 @@ impl From<PostRow> for Post {
             encryption_version: row.encryption_version,
             position: row.position,
             parent_id: row.parent_id,
+            revision: row.revision,
             created_at: row.created_at,
             updated_at: row.updated_at,
         }
@@ -244,6 +269,9 @@ impl PostStore {
         .execute(&mut *tx)
         .await?;
 
+        // Every sibling's position shifted, so all of them are changes
+        SyncStore::record_children_tx(&mut tx, user_id, parent_id).await?;
+
         tx.commit().await?;
         Ok(uuid)
     }
@@ -251,7 +279,7 @@ impl PostStore {
     /// Get a post by UUID. Only returns the post if it belongs to the given user.
     pub async fn get_by_uuid(&self, uuid: &str, user_id: i64) -> Result<Option<Post>, sqlx::Error> {
         let row: Option<PostRow> = sqlx::query_as(
-            "SELECT id, uuid, user_id, title, title_encrypted, title_iv, content, content_encrypted, iv, encryption_version, position, parent_id, created_at, updated_at
+            "SELECT id, uuid, user_id, title, title_encrypted, title_iv, content, content_encrypted, iv, encryption_version, position, parent_id, revision, created_at, updated_at
              FROM posts WHERE uuid = ? AND user_id = ?",
         )
         .bind(uuid)
@@ -441,7 +469,8 @@ impl PostStore {
         Ok(row.map(|r| r.0))
     }
 
-    /// Update a post within an existing transaction.
+    /// Update a post within an existing transaction, bumping its revision.
+    /// Only applies if the stored revision still equals `current_revision`.
     /// Returns true if the post was updated.
     #[allow(clippy::too_many_arguments)]
     pub async fn update_tx(
@@ -455,10 +484,11 @@ impl PostStore {
         content_encrypted: bool,
         iv: Option<&str>,
         encryption_version: Option<i32>,
+        current_revision: i64,
     ) -> Result<bool, sqlx::Error> {
         let result = sqlx::query(
-            "UPDATE posts SET title = ?, title_encrypted = ?, title_iv = ?, content = ?, content_encrypted = ?, iv = ?, encryption_version = ?, updated_at = datetime('now')
-             WHERE uuid = ? AND user_id = ?",
+            "UPDATE posts SET title = ?, title_encrypted = ?, title_iv = ?, content = ?, content_encrypted = ?, iv = ?, encryption_version = ?, revision = revision + 1, updated_at = datetime('now')
+             WHERE uuid = ? AND user_id = ? AND revision = ?",
         )
         .bind(title)
         .bind(title_encrypted)
@@ -469,33 +499,47 @@ impl PostStore {
         .bind(encryption_version)
         .bind(uuid)
         .bind(user_id)
+        .bind(current_revision)
         .execute(&mut **tx)
         .await?;
         Ok(result.rows_affected() > 0)
     }
 
-    /// Get all descendant post IDs (including the post itself) within a transaction.
-    pub async fn get_descendant_ids_tx(
+    /// Get the internal post ID and current revision by UUID within an existing transaction.
+    /// Returns None if post doesn't exist or doesn't belong to user.
+    pub async fn get_revision_tx(
         tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
         uuid: &str,
         user_id: i64,
-    ) -> Result<Vec<i64>, sqlx::Error> {
-        let rows: Vec<(i64,)> = sqlx::query_as(
+    ) -> Result<Option<(i64, i64)>, sqlx::Error> {
+        sqlx::query_as("SELECT id, revision FROM posts WHERE uuid = ? AND user_id = ?")
+            .bind(uuid)
+            .bind(user_id)
+            .fetch_optional(&mut **tx)
+            .await
+    }
+
+    /// Get the IDs and UUIDs of all descendants (including the post itself) within a transaction.
+    pub async fn get_descendants_tx(
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        uuid: &str,
+        user_id: i64,
+    ) -> Result<Vec<(i64, String)>, sqlx::Error> {
+        sqlx::query_as(
             "WITH RECURSIVE descendants AS (
-                SELECT id FROM posts WHERE uuid = ? AND user_id = ?
+                SELECT id, uuid FROM posts WHERE uuid = ? AND user_id = ?
                 UNION ALL
-                SELECT p.id FROM posts p
-                INNER JOIN descendants d ON p.parent_id = (SELECT uuid FROM posts WHERE id = d.id)
+                SELECT p.id, p.uuid FROM posts p
+                INNER JOIN descendants d ON p.parent_id = d.uuid
                 WHERE p.user_id = ?
             )
-            SELECT id FROM descendants",
+            SELECT id, uuid FROM descendants",
         )
         .bind(uuid)
         .bind(user_id)
         .bind(user_id)
         .fetch_all(&mut **tx)
-        .await?;
-        Ok(rows.into_iter().map(|r| r.0).collect())
+        .await
     }
 
     /// Delete a post within an existing transaction.
@@ -528,8 +572,10 @@ impl PostStore {
         iv: Option<&str>,
         encryption_version: Option<i32>,
     ) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
         let result = sqlx::query(
-            "UPDATE posts SET title = ?, title_encrypted = ?, title_iv = ?, content = ?, content_encrypted = ?, iv = ?, encryption_version = ?, updated_at = datetime('now')
+            "UPDATE posts SET title = ?, title_encrypted = ?, title_iv = ?, content = ?, content_encrypted = ?, iv = ?, encryption_version = ?, revision = revision + 1, updated_at = datetime('now')
              WHERE uuid = ? AND user_id = ?",
         )
         .bind(title)
@@ -541,9 +587,16 @@ impl PostStore {
         .bind(encryption_version)
         .bind(uuid)
         .bind(user_id)
-        .execute(&self.pool)
+        .execute(&mut *tx)
         .await?;
-        Ok(result.rows_affected() > 0)
+
+        let updated = result.rows_affected() > 0;
+        if updated {
+            SyncStore::record_tx(&mut tx, user_id, uuid, false).await?;
+        }
+
+        tx.commit().await?;
+        Ok(updated)
     }
 
     /// Count all descendants of a post (for delete warning).
@@ -574,38 +627,34 @@ impl PostStore {
         // Count descendants first
         let children_count = self.count_descendants(uuid, user_id).await?;
 
-        // Delete all descendants first using recursive CTE
+        let mut tx = self.pool.begin().await?;
+
+        // Collect the post and all descendants so they can be tombstoned
+        let descendants = Self::get_descendants_tx(&mut tx, uuid, user_id).await?;
+        if descendants.is_empty() {
+            return Ok(DeleteResult {
+                deleted: false,
+                children_deleted: 0,
+                deleted_attachments: Vec::new(),
+            });
+        }
+
+        // Delete all descendants and the post itself.
         // This handles the case where parent_id doesn't have ON DELETE CASCADE
-        sqlx::query(
-            "WITH RECURSIVE descendants AS (
-                SELECT uuid FROM posts WHERE parent_id = ? AND user_id = ?
-                UNION ALL
-                SELECT p.uuid FROM posts p
-                INNER JOIN descendants d ON p.parent_id = d.uuid
-                WHERE p.user_id = ?
-            )
-            DELETE FROM posts WHERE uuid IN (SELECT uuid FROM descendants)",
-        )
-        .bind(uuid)
-        .bind(user_id)
-        .bind(user_id)
-        .execute(&self.pool)
-        .await?;
+        for (id, descendant_uuid) in &descendants {
+            sqlx::query("DELETE FROM posts WHERE id = ?")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            SyncStore::record_tx(&mut tx, user_id, descendant_uuid, true).await?;
+        }
 
-        // Now delete the parent post itself
-        let result = sqlx::query("DELETE FROM posts WHERE uuid = ? AND user_id = ?")
-            .bind(uuid)
-            .bind(user_id)
-            .execute(&self.pool)
-            .await?;
+        tx.commit().await?;
 
         Ok(DeleteResult {
-            deleted: result.rows_affected() > 0,
-            children_deleted: if result.rows_affected() > 0 {
-                children_count
-            } else {
-                0
-            },
+            deleted: true,
+            children_deleted: children_count,
+            deleted_attachments: Vec::new(),
         })
     }
 
@@ -720,6 +769,12 @@ impl PostStore {
         .execute(&mut *tx)
         .await?;
 
+        // Positions shifted in both the old and new parent
+        SyncStore::record_children_tx(&mut tx, user_id, post.parent_id.as_deref()).await?;
+        if new_parent_id != post.parent_id.as_deref() {
+            SyncStore::record_children_tx(&mut tx, user_id, new_parent_id).await?;
+        }
+
         tx.commit().await?;
         Ok(result.rows_affected() > 0)
     }
@@ -765,6 +820,8 @@ impl PostStore {
             updated += result.rows_affected() as usize;
         }
 
+        SyncStore::record_children_tx(&mut tx, user_id, parent_id).await?;
+
         tx.commit().await?;
         Ok(updated)
     }
@@ -1452,4 +1509,83 @@ mod tests {
             .await;
         assert!(result.is_err());
     }
+
+    #[tokio::test]
+    async fn test_update_with_stale_revision_conflicts() {
+        use crate::db::{UpdatePostParams, UpdatePostResult};
+
+        let db = Database::open(":memory:").await.unwrap();
+        let user_id = db.users().create("uuid-1", "alice").await.unwrap();
+
+        let post_uuid = db
+            .posts()
+            .create(
+                user_id,
+                Some("Original"),
+                false,
+                None,
+                "Original content",
+                false,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+
+        let post = db
+            .posts()
+            .get_by_uuid(&post_uuid, user_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(post.revision, 1);
+
+        let uuid = post_uuid.as_str();
+        let params = move |content, expected_revision| UpdatePostParams {
+            uuid,
+            user_id,
+            title: None,
+            title_encrypted: false,
+            title_iv: None,
+            content,
+            content_encrypted: false,
+            iv: None,
+            encryption_version: None,
+            attachment_uuids: None,
+            expected_revision,
+        };
+
+        let result = db
+            .update_post_with_attachments(params("First edit", Some(1)))
+            .await
+            .unwrap();
+        assert!(matches!(
+            result,
+            UpdatePostResult::Updated { revision: 2, .. }
+        ));
+
+        // A second writer still based on revision 1 must not overwrite the first edit
+        let result = db
+            .update_post_with_attachments(params("Stale edit", Some(1)))
+            .await
+            .unwrap();
+        match result {
+            UpdatePostResult::Conflict(current) => {
+                assert_eq!(current.revision, 2);
+                assert_eq!(current.content, "First edit");
+            }
+            other => panic!("expected conflict, got {other:?}"),
+        }
+
+        // Without an expected revision the update always applies
+        let result = db
+            .update_post_with_attachments(params("Forced edit", None))
+            .await
+            .unwrap();
+        assert!(matches!(
+            result,
+            UpdatePostResult::Updated { revision: 3, .. }
+        ));
+    }
 }
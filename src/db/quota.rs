@@ -0,0 +1,303 @@
+//! Per-user storage accounting and quotas.
+//!
+//! Usage is summed from the stored rows on demand: post titles and content,
+//! attachment sizes (recorded at upload) and the announced length of
+//! unfinished resumable uploads. A user's quota is their own override, or the
+//! server default if they have none; no quota means unlimited.
+//!
+//! Checking the quota and storing the data are separate statements, so writes
+//! that grow usage hold the user's [`QuotaLock`] across both. Otherwise
+//! concurrent uploads could each pass the check against the same usage.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use sqlx::sqlite::SqlitePool;
+use tokio::sync::OwnedMutexGuard;
+
+/// Per-user locks serializing quota checks with the writes they allow.
+pub type QuotaLocks = Arc<Mutex<HashMap<i64, Arc<tokio::sync::Mutex<()>>>>>;
+
+/// `server_settings` key of the default quota.
+const DEFAULT_QUOTA_KEY: &str = "default_storage_quota";
+
+/// Bytes a user stores, by kind.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct StorageUsage {
+    /// Post titles and content
+    pub posts: u64,
+    /// Attachment data and thumbnails
+    pub attachments: u64,
+    /// Unfinished resumable uploads, at their full announced length
+    pub uploads: u64,
+}
+
+impl StorageUsage {
+    pub fn total(&self) -> u64 {
+        self.posts + self.attachments + self.uploads
+    }
+}
+
+/// Held while checking a user's quota and storing the data it allows.
+pub struct QuotaLock {
+    locks: QuotaLocks,
+    user_id: i64,
+    guard: Option<OwnedMutexGuard<()>>,
+}
+
+impl QuotaLock {
+    pub fn user_id(&self) -> i64 {
+        self.user_id
+    }
+}
+
+impl Drop for QuotaLock {
+    fn drop(&mut self) {
+        self.guard.take();
+        let mut locks = self.locks.lock().unwrap();
+        // Nobody else holds or waits for the lock once only the map has it.
+        if locks
+            .get(&self.user_id)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            locks.remove(&self.user_id);
+        }
+    }
+}
+
+pub struct QuotaStore {
+    pool: SqlitePool,
+    locks: QuotaLocks,
+}
+
+impl QuotaStore {
+    pub fn new(pool: SqlitePool, locks: QuotaLocks) -> Self {
+        Self { pool, locks }
+    }
+
+    /// Wait for exclusive access to a user's quota.
+    pub async fn lock(&self, user_id: i64) -> QuotaLock {
+        let lock = self
+            .locks
+            .lock()
+            .unwrap()
+            .entry(user_id)
+            .or_default()
+            .clone();
+        QuotaLock {
+            locks: self.locks.clone(),
+            user_id,
+            guard: Some(lock.lock_owned().await),
+        }
+    }
+
+    /// Bytes stored by a user.
+    pub async fn usage(&self, user_id: i64) -> Result<StorageUsage, sqlx::Error> {
+        let (posts, attachments, uploads): (i64, i64, i64) = sqlx::query_as(
+            "SELECT
+                (SELECT COALESCE(SUM(length(CAST(content AS BLOB))
+                    + COALESCE(length(CAST(title AS BLOB)), 0)), 0)
+                 FROM posts WHERE user_id = ?1),
+                (SELECT COALESCE(SUM(size), 0) FROM attachments WHERE user_id = ?1),
+                (SELECT COALESCE(SUM(length), 0) FROM upload_sessions WHERE user_id = ?1)",
+        )
+        .bind(user_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(StorageUsage {
+            posts: posts as u64,
+            attachments: attachments as u64,
+            uploads: uploads as u64,
+        })
+    }
+
+    /// Bytes a post's title and content take, None if it does not exist.
+    pub async fn post_size(&self, uuid: &str, user_id: i64) -> Result<Option<u64>, sqlx::Error> {
+        let size: Option<i64> = sqlx::query_scalar(
+            "SELECT length(CAST(content AS BLOB)) + COALESCE(length(CAST(title AS BLOB)), 0)
+             FROM posts WHERE uuid = ? AND user_id = ?",
+        )
+        .bind(uuid)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(size.map(|s| s as u64))
+    }
+
+    /// Quota applying to a user: their own, else the default. None if
+    /// unlimited.
+    pub async fn quota(&self, user_id: i64) -> Result<Option<u64>, sqlx::Error> {
+        let quota: Option<Option<i64>> = sqlx::query_scalar(
+            "SELECT COALESCE(storage_quota,
+                (SELECT CAST(value AS INTEGER) FROM server_settings WHERE key = ?))
+             FROM users WHERE id = ?",
+        )
+        .bind(DEFAULT_QUOTA_KEY)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(quota.flatten().map(|q| q as u64))
+    }
+
+    /// A user's own quota, None if the default applies.
+    pub async fn user_quota(&self, user_id: i64) -> Result<Option<u64>, sqlx::Error> {
+        let quota: Option<Option<i64>> =
+            sqlx::query_scalar("SELECT storage_quota FROM users WHERE id = ?")
+                .bind(user_id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(quota.flatten().map(|q| q as u64))
+    }
+
+    /// Set a user's own quota; None makes the default apply again.
+    /// Returns false if the user does not exist.
+    pub async fn set_user_quota(
+        &self,
+        user_id: i64,
+        quota: Option<u64>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("UPDATE users SET storage_quota = ? WHERE id = ?")
+            .bind(quota.map(|q| q as i64))
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Quota of users without their own. None if unlimited.
+    pub async fn default_quota(&self) -> Result<Option<u64>, sqlx::Error> {
+        let quota: Option<i64> =
+            sqlx::query_scalar("SELECT CAST(value AS INTEGER) FROM server_settings WHERE key = ?")
+                .bind(DEFAULT_QUOTA_KEY)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(quota.map(|q| q as u64))
+    }
+
+    /// Set the default quota; None makes it unlimited.
+    pub async fn set_default_quota(&self, quota: Option<u64>) -> Result<(), sqlx::Error> {
+        match quota {
+            Some(quota) => {
+                sqlx::query(
+                    "INSERT INTO server_settings (key, value) VALUES (?, ?)
+                     ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+                )
+                .bind(DEFAULT_QUOTA_KEY)
+                .bind(quota.to_string())
+                .execute(&self.pool)
+                .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM server_settings WHERE key = ?")
+                    .bind(DEFAULT_QUOTA_KEY)
+                    .execute(&self.pool)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::Database;
+    use crate::db::attachments::CreateAttachmentInput;
+
+    #[tokio::test]
+    async fn test_usage_counts_posts_attachments_and_uploads() {
+        let db = Database::open(":memory:").await.unwrap();
+        let user_id = db.users().create("uuid-1", "alice").await.unwrap();
+        let other = db.users().create("uuid-2", "bob").await.unwrap();
+
+        db.posts()
+            .create(
+                user_id,
+                Some("Tïtle"),
+                false,
+                None,
+                "content",
+                false,
+                None,
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        db.attachments()
+            .create(CreateAttachmentInput {
+                user_id,
+                image_data: b"image",
+                image_iv: None,
+                thumb_sm: b"sm",
+                thumb_sm_iv: None,
+                thumb_md: Some((b"md", None)),
+                thumb_lg: None,
+                encryption_version: 0,
+            })
+            .await
+            .unwrap();
+        let dir = std::env::temp_dir().join(format!(
+            "crowchiper-quota-test-{}",
+            uuid::Uuid::new_v4().simple()
+        ));
+        let db = db.with_upload_dir(dir.clone());
+        db.uploads().create(user_id, 100).await.unwrap();
+
+        let usage = db.quotas().usage(user_id).await.unwrap();
+        // Sizes are in bytes, not characters
+        assert_eq!(usage.posts, 6 + 7);
+        assert_eq!(usage.attachments, 5 + 2 + 2);
+        assert_eq!(usage.uploads, 100);
+        assert_eq!(usage.total(), 122);
+        assert_eq!(db.quotas().usage(other).await.unwrap().total(), 0);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_quota_lock_serializes_per_user() {
+        let db = Database::open(":memory:").await.unwrap();
+        let quotas = db.quotas();
+
+        let first = quotas.lock(1).await;
+        assert_eq!(first.user_id(), 1);
+        // Other users are not blocked.
+        let _other = quotas.lock(2).await;
+
+        let waiting = tokio::spawn({
+            let quotas = db.quotas();
+            async move { quotas.lock(1).await.user_id() }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+
+        drop(first);
+        assert_eq!(waiting.await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_user_quota_overrides_default() {
+        let db = Database::open(":memory:").await.unwrap();
+        let user_id = db.users().create("uuid-1", "alice").await.unwrap();
+        let quotas = db.quotas();
+
+        assert_eq!(quotas.quota(user_id).await.unwrap(), None);
+
+        quotas.set_default_quota(Some(1000)).await.unwrap();
+        assert_eq!(quotas.default_quota().await.unwrap(), Some(1000));
+        assert_eq!(quotas.quota(user_id).await.unwrap(), Some(1000));
+
+        assert!(quotas.set_user_quota(user_id, Some(50)).await.unwrap());
+        assert_eq!(quotas.user_quota(user_id).await.unwrap(), Some(50));
+        assert_eq!(quotas.quota(user_id).await.unwrap(), Some(50));
+
+        assert!(quotas.set_user_quota(user_id, None).await.unwrap());
+        assert_eq!(quotas.quota(user_id).await.unwrap(), Some(1000));
+
+        quotas.set_default_quota(None).await.unwrap();
+        assert_eq!(quotas.quota(user_id).await.unwrap(), None);
+
+        assert!(!quotas.set_user_quota(9999, Some(1)).await.unwrap());
+    }
+}
@@ -0,0 +1,272 @@
+//! Per-user change feed for incremental post sync.
+//!
+//! Every write to a post (create, update, move, reorder, delete) records the
+//! affected post UUIDs in `post_changes` with a per-user, monotonically
+//! increasing sequence number. Only the latest change per post is kept, so the
+//! feed stays proportional to the number of posts rather than the number of
+//! edits. Deleted posts leave a tombstone row so clients can evict them.
+
+use sqlx::sqlite::SqlitePool;
+
+#[derive(Clone)]
+pub struct SyncStore {
+    pool: SqlitePool,
+}
+
+/// Post metadata included in a change (no content).
+#[derive(Debug, Clone)]
+pub struct ChangedPost {
+    pub uuid: String,
+    pub title: Option<String>,
+    pub title_encrypted: bool,
+    pub title_iv: Option<String>,
+    pub content_encrypted: bool,
+    pub encryption_version: Option<i32>,
+    pub position: Option<i32>,
+    pub parent_id: Option<String>,
+    pub revision: i64,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// A single entry in the change feed.
+#[derive(Debug, Clone)]
+pub enum PostChange {
+    /// The post was created or modified. Contains its current metadata.
+    Upsert { seq: i64, post: ChangedPost },
+    /// The post was deleted.
+    Deleted { seq: i64, uuid: String },
+}
+
+impl PostChange {
+    pub fn seq(&self) -> i64 {
+        match self {
+            PostChange::Upsert { seq, .. } | PostChange::Deleted { seq, .. } => *seq,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct PostChangeRow {
+    seq: i64,
+    post_uuid: String,
+    deleted: bool,
+    // Post columns come from a LEFT JOIN, so they are nullable here even where
+    // the posts table declares them NOT NULL.
+    title: Option<String>,
+    title_encrypted: Option<bool>,
+    title_iv: Option<String>,
+    content_encrypted: Option<bool>,
+    encryption_version: Option<i32>,
+    position: Option<i32>,
+    parent_id: Option<String>,
+    revision: Option<i64>,
+    created_at: Option<String>,
+    updated_at: Option<String>,
+}
+
+impl From<PostChangeRow> for PostChange {
+    fn from(row: PostChangeRow) -> Self {
+        // A missing post row means it was deleted after the change was recorded.
+        match (row.deleted, row.revision, row.created_at, row.updated_at) {
+            (false, Some(revision), Some(created_at), Some(updated_at)) => PostChange::Upsert {
+                seq: row.seq,
+                post: ChangedPost {
+                    uuid: row.post_uuid,
+                    title: row.title,
+                    title_encrypted: row.title_encrypted.unwrap_or(false),
+                    title_iv: row.title_iv,
+                    content_encrypted: row.content_encrypted.unwrap_or(false),
+                    encryption_version: row.encryption_version,
+                    position: row.position,
+                    parent_id: row.parent_id,
+                    revision,
+                    created_at,
+                    updated_at,
+                },
+            },
+            _ => PostChange::Deleted {
+                seq: row.seq,
+                uuid: row.post_uuid,
+            },
+        }
+    }
+}
+
+impl SyncStore {
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+
+    /// Get the latest change sequence number for a user (0 if nothing has changed yet).
+    pub async fn current_seq(&self, user_id: i64) -> Result<i64, sqlx::Error> {
+        let row: Option<(i64,)> =
+            sqlx::query_as("SELECT seq FROM sync_sequences WHERE user_id = ?")
+                .bind(user_id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(row.map(|r| r.0).unwrap_or(0))
+    }
+
+    /// List changes with a sequence number greater than `since`, oldest first.
+    pub async fn changes_since(
+        &self,
+        user_id: i64,
+        since: i64,
+        limit: i64,
+    ) -> Result<Vec<PostChange>, sqlx::Error> {
+        let rows: Vec<PostChangeRow> = sqlx::query_as(
+            "SELECT c.seq, c.post_uuid, c.deleted,
+             p.title, p.title_encrypted, p.title_iv, p.content_encrypted, p.encryption_version,
+             p.position, p.parent_id, p.revision, p.created_at, p.updated_at
+             FROM post_changes c
+             LEFT JOIN posts p ON p.uuid = c.post_uuid AND p.user_id = c.user_id
+             WHERE c.user_id = ? AND c.seq > ?
+             ORDER BY c.seq ASC
+             LIMIT ?",
+        )
+        .bind(user_id)
+        .bind(since)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(PostChange::from).collect())
+    }
+
+    /// Allocate the next sequence number for a user within an existing transaction.
+    async fn next_seq_tx(
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        user_id: i64,
+    ) -> Result<i64, sqlx::Error> {
+        let (seq,): (i64,) = sqlx::query_as(
+            "INSERT INTO sync_sequences (user_id, seq) VALUES (?, 1)
+             ON CONFLICT(user_id) DO UPDATE SET seq = seq + 1
+             RETURNING seq",
+        )
+        .bind(user_id)
+        .fetch_one(&mut **tx)
+        .await?;
+        Ok(seq)
+    }
+
+    /// Record a change to a single post within an existing transaction.
+    /// Replaces any earlier change recorded for the same post.
+    pub async fn record_tx(
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        user_id: i64,
+        post_uuid: &str,
+        deleted: bool,
+    ) -> Result<i64, sqlx::Error> {
+        let seq = Self::next_seq_tx(tx, user_id).await?;
+        sqlx::query(
+            "INSERT INTO post_changes (user_id, post_uuid, seq, deleted) VALUES (?, ?, ?, ?)
+             ON CONFLICT(user_id, post_uuid) DO UPDATE SET seq = excluded.seq, deleted = excluded.deleted",
+        )
+        .bind(user_id)
+        .bind(post_uuid)
+        .bind(seq)
+        .bind(deleted)
+        .execute(&mut **tx)
+        .await?;
+        Ok(seq)
+    }
+
+    /// Record a change for every child of `parent_id` (root level if None).
+    /// Used after operations that shift sibling positions.
+    pub async fn record_children_tx(
+        tx: &mut sqlx::Transaction<'_, sqlx::Sqlite>,
+        user_id: i64,
+        parent_id: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        let children: Vec<(String,)> =
+            sqlx::query_as("SELECT uuid FROM posts WHERE user_id = ? AND parent_id IS ?")
+                .bind(user_id)
+                .bind(parent_id)
+                .fetch_all(&mut **tx)
+                .await?;
+        for (uuid,) in children {
+            Self::record_tx(tx, user_id, &uuid, false).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+
+    async fn create_post(db: &Database, user_id: i64, parent: Option<&str>) -> String {
+        db.posts()
+            .create(user_id, None, false, None, "", false, None, None, parent)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_changes_since_returns_only_newer_changes() {
+        let db = Database::open(":memory:").await.unwrap();
+        let user_id = db.users().create("uuid-1", "alice").await.unwrap();
+
+        let first = create_post(&db, user_id, None).await;
+        let cursor = db.sync().current_seq(user_id).await.unwrap();
+
+        let second = create_post(&db, user_id, Some(&first)).await;
+
+        let changes = db.sync().changes_since(user_id, cursor, 100).await.unwrap();
+        assert_eq!(changes.len(), 1);
+        match &changes[0] {
+            PostChange::Upsert { post, .. } => {
+                assert_eq!(post.uuid, second);
+                assert_eq!(post.parent_id.as_deref(), Some(first.as_str()));
+            }
+            other => panic!("expected upsert, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_delete_records_tombstones_for_descendants() {
+        let db = Database::open(":memory:").await.unwrap();
+        let user_id = db.users().create("uuid-1", "alice").await.unwrap();
+
+        let parent = create_post(&db, user_id, None).await;
+        let child = create_post(&db, user_id, Some(&parent)).await;
+        let cursor = db.sync().current_seq(user_id).await.unwrap();
+
+        db.delete_post_with_attachments(&parent, user_id)
+            .await
+            .unwrap();
+
+        let changes = db.sync().changes_since(user_id, cursor, 100).await.unwrap();
+        let mut deleted: Vec<_> = changes
+            .iter()
+            .filter_map(|c| match c {
+                PostChange::Deleted { uuid, .. } => Some(uuid.clone()),
+                _ => None,
+            })
+            .collect();
+        deleted.sort();
+        let mut expected = vec![parent, child];
+        expected.sort();
+        assert_eq!(deleted, expected);
+    }
+
+    #[tokio::test]
+    async fn test_sequences_are_per_user() {
+        let db = Database::open(":memory:").await.unwrap();
+        let alice = db.users().create("uuid-1", "alice").await.unwrap();
+        let bob = db.users().create("uuid-2", "bob").await.unwrap();
+
+        create_post(&db, alice, None).await;
+        create_post(&db, alice, None).await;
+
+        assert_eq!(db.sync().current_seq(bob).await.unwrap(), 0);
+        assert!(
+            db.sync()
+                .changes_since(bob, 0, 100)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
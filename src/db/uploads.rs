@@ -0,0 +1,357 @@
+//! Resumable upload sessions.
+//!
+//! A session row tracks how many bytes of an upload have arrived; the bytes
+//! themselves are staged in `<dir>/<uuid>.part` until the upload is
+//! finalized into an attachment or abandoned.
+
+use std::collections::HashSet;
+use std::io::{self, SeekFrom};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use sqlx::sqlite::SqlitePool;
+use tokio::io::AsyncSeekExt;
+
+/// An in-progress upload.
+#[derive(Debug, Clone)]
+pub struct UploadSession {
+    pub uuid: String,
+    pub user_id: i64,
+    /// Total size announced when the upload was created
+    pub length: u64,
+    /// Bytes staged so far; the offset the next chunk must start at
+    pub received: u64,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl UploadSession {
+    pub fn is_complete(&self) -> bool {
+        self.received == self.length
+    }
+}
+
+/// Exclusive access to one upload while a chunk is written or the upload is
+/// finalized. Released on drop.
+pub struct UploadLock {
+    active: Arc<Mutex<HashSet<String>>>,
+    uuid: String,
+}
+
+impl Drop for UploadLock {
+    fn drop(&mut self) {
+        self.active.lock().unwrap().remove(&self.uuid);
+    }
+}
+
+#[derive(Clone)]
+pub struct UploadStore {
+    pool: SqlitePool,
+    dir: Arc<PathBuf>,
+    active: Arc<Mutex<HashSet<String>>>,
+}
+
+type SessionRow = (String, i64, i64, i64, String, String);
+
+fn session_from_row(row: SessionRow) -> UploadSession {
+    let (uuid, user_id, length, received, created_at, updated_at) = row;
+    UploadSession {
+        uuid,
+        user_id,
+        length: length as u64,
+        received: received as u64,
+        created_at,
+        updated_at,
+    }
+}
+
+impl UploadStore {
+    pub fn new(pool: SqlitePool, dir: Arc<PathBuf>, active: Arc<Mutex<HashSet<String>>>) -> Self {
+        Self { pool, dir, active }
+    }
+
+    /// Staging file of an upload.
+    pub fn path(&self, uuid: &str) -> PathBuf {
+        self.dir.join(format!("{uuid}.part"))
+    }
+
+    /// Start an upload of `length` bytes with an empty staging file.
+    pub async fn create(&self, user_id: i64, length: u64) -> Result<UploadSession, sqlx::Error> {
+        let uuid = uuid::Uuid::new_v4().to_string();
+        tokio::fs::create_dir_all(&*self.dir).await?;
+        tokio::fs::File::create(self.path(&uuid)).await?;
+
+        let row: SessionRow = sqlx::query_as(
+            "INSERT INTO upload_sessions (uuid, user_id, length) VALUES (?, ?, ?)
+             RETURNING uuid, user_id, length, received, created_at, updated_at",
+        )
+        .bind(&uuid)
+        .bind(user_id)
+        .bind(length as i64)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(session_from_row(row))
+    }
+
+    /// Get an upload owned by `user_id`.
+    pub async fn get(
+        &self,
+        uuid: &str,
+        user_id: i64,
+    ) -> Result<Option<UploadSession>, sqlx::Error> {
+        let row: Option<SessionRow> = sqlx::query_as(
+            "SELECT uuid, user_id, length, received, created_at, updated_at
+             FROM upload_sessions WHERE uuid = ? AND user_id = ?",
+        )
+        .bind(uuid)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.map(session_from_row))
+    }
+
+    /// Number of unfinished uploads a user has.
+    pub async fn count_by_user(&self, user_id: i64) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar("SELECT COUNT(*) FROM upload_sessions WHERE user_id = ?")
+            .bind(user_id)
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    /// Take the upload's lock, or None if another request holds it.
+    pub fn try_lock(&self, uuid: &str) -> Option<UploadLock> {
+        if !self.active.lock().unwrap().insert(uuid.to_string()) {
+            return None;
+        }
+        Some(UploadLock {
+            active: self.active.clone(),
+            uuid: uuid.to_string(),
+        })
+    }
+
+    /// Open the staging file for appending at `session.received`, dropping
+    /// any bytes written past it by an interrupted request.
+    pub async fn open_staged(
+        &self,
+        session: &UploadSession,
+    ) -> Result<tokio::fs::File, sqlx::Error> {
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(self.path(&session.uuid))
+            .await?;
+        file.set_len(session.received).await?;
+        file.seek(SeekFrom::Start(session.received)).await?;
+        Ok(file)
+    }
+
+    /// Read a complete upload back from its staging file.
+    pub async fn read_staged(&self, session: &UploadSession) -> Result<Vec<u8>, sqlx::Error> {
+        let mut data = tokio::fs::read(self.path(&session.uuid)).await?;
+        if (data.len() as u64) < session.length {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("staged upload {} is truncated", session.uuid),
+            )
+            .into());
+        }
+        data.truncate(session.length as usize);
+        Ok(data)
+    }
+
+    /// Record that `received` bytes are staged.
+    pub async fn set_received(&self, uuid: &str, received: u64) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE upload_sessions SET received = ?, updated_at = datetime('now') WHERE uuid = ?",
+        )
+        .bind(received as i64)
+        .bind(uuid)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Delete an upload and its staging file.
+    pub async fn delete(&self, uuid: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM upload_sessions WHERE uuid = ?")
+            .bind(uuid)
+            .execute(&self.pool)
+            .await?;
+        remove_file(&self.path(uuid)).await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Delete uploads that received nothing for `older_than_hours`, and
+    /// staging files left without a session (e.g. by a deleted user).
+    pub async fn cleanup_abandoned(&self, older_than_hours: i64) -> Result<u64, sqlx::Error> {
+        let modifier = format!("-{} hours", older_than_hours);
+        let uuids: Vec<String> = sqlx::query_scalar(
+            "DELETE FROM upload_sessions WHERE updated_at < datetime('now', ?) RETURNING uuid",
+        )
+        .bind(&modifier)
+        .fetch_all(&self.pool)
+        .await?;
+        for uuid in &uuids {
+            remove_file(&self.path(uuid)).await?;
+        }
+
+        let mut entries = match tokio::fs::read_dir(&*self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(uuids.len() as u64),
+            Err(e) => return Err(e.into()),
+        };
+        let cutoff = SystemTime::now() - Duration::from_secs(older_than_hours as u64 * 3600);
+        let mut orphans = 0;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            let Some(uuid) = name.to_str().and_then(|n| n.strip_suffix(".part")) else {
+                continue;
+            };
+            let modified = entry.metadata().await?.modified()?;
+            if modified >= cutoff {
+                continue;
+            }
+            let exists: bool =
+                sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM upload_sessions WHERE uuid = ?)")
+                    .bind(uuid)
+                    .fetch_one(&self.pool)
+                    .await?;
+            if !exists {
+                remove_file(&entry.path()).await?;
+                orphans += 1;
+            }
+        }
+        Ok(uuids.len() as u64 + orphans)
+    }
+}
+
+/// Remove a file, treating an already missing one as removed.
+async fn remove_file(path: &std::path::Path) -> io::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+    use tokio::io::AsyncWriteExt;
+
+    async fn setup() -> (Database, i64, PathBuf) {
+        let dir = std::env::temp_dir().join(format!(
+            "crowchiper-uploads-test-{}",
+            uuid::Uuid::new_v4().simple()
+        ));
+        let db = Database::open(":memory:")
+            .await
+            .unwrap()
+            .with_upload_dir(dir.clone());
+        let user_id = db.users().create("uuid-1", "alice").await.unwrap();
+        (db, user_id, dir)
+    }
+
+    #[tokio::test]
+    async fn test_append_resumes_at_received() {
+        let (db, user_id, dir) = setup().await;
+        let uploads = db.uploads();
+
+        let session = uploads.create(user_id, 6).await.unwrap();
+        assert_eq!(session.received, 0);
+        assert!(uploads.path(&session.uuid).is_file());
+
+        // Bytes written past the recorded offset are dropped on reopen.
+        let mut file = uploads.open_staged(&session).await.unwrap();
+        file.write_all(b"abcxx").await.unwrap();
+        file.flush().await.unwrap();
+        uploads.set_received(&session.uuid, 3).await.unwrap();
+
+        let session = uploads.get(&session.uuid, user_id).await.unwrap().unwrap();
+        assert_eq!(session.received, 3);
+        let mut file = uploads.open_staged(&session).await.unwrap();
+        file.write_all(b"def").await.unwrap();
+        file.flush().await.unwrap();
+        uploads.set_received(&session.uuid, 6).await.unwrap();
+
+        let session = uploads.get(&session.uuid, user_id).await.unwrap().unwrap();
+        assert!(session.is_complete());
+        assert_eq!(uploads.read_staged(&session).await.unwrap(), b"abcdef");
+
+        assert!(uploads.delete(&session.uuid).await.unwrap());
+        assert!(!uploads.path(&session.uuid).exists());
+        assert!(uploads.get(&session.uuid, user_id).await.unwrap().is_none());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_get_checks_owner() {
+        let (db, user_id, dir) = setup().await;
+        let other = db.users().create("uuid-2", "bob").await.unwrap();
+
+        let session = db.uploads().create(user_id, 10).await.unwrap();
+        assert!(
+            db.uploads()
+                .get(&session.uuid, other)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(db.uploads().count_by_user(user_id).await.unwrap(), 1);
+        assert_eq!(db.uploads().count_by_user(other).await.unwrap(), 0);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_lock_is_exclusive() {
+        let (db, user_id, dir) = setup().await;
+        let session = db.uploads().create(user_id, 10).await.unwrap();
+
+        let lock = db.uploads().try_lock(&session.uuid).unwrap();
+        assert!(db.uploads().try_lock(&session.uuid).is_none());
+        drop(lock);
+        assert!(db.uploads().try_lock(&session.uuid).is_some());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_cleanup_abandoned() {
+        let (db, user_id, dir) = setup().await;
+        let uploads = db.uploads();
+        let stale = uploads.create(user_id, 10).await.unwrap();
+        let fresh = uploads.create(user_id, 10).await.unwrap();
+        sqlx::query(
+            "UPDATE upload_sessions SET updated_at = datetime('now', '-2 days') WHERE uuid = ?",
+        )
+        .bind(&stale.uuid)
+        .execute(db.pool())
+        .await
+        .unwrap();
+
+        assert_eq!(uploads.cleanup_abandoned(24).await.unwrap(), 1);
+        assert!(uploads.get(&stale.uuid, user_id).await.unwrap().is_none());
+        assert!(!uploads.path(&stale.uuid).exists());
+        assert!(uploads.get(&fresh.uuid, user_id).await.unwrap().is_some());
+        assert!(uploads.path(&fresh.uuid).is_file());
+
+        // Staging files without a session go once they are as old.
+        let orphan = uploads.path("orphan");
+        std::fs::write(&orphan, b"x").unwrap();
+        assert_eq!(uploads.cleanup_abandoned(24).await.unwrap(), 0);
+        assert!(orphan.is_file());
+        std::fs::File::options()
+            .write(true)
+            .open(&orphan)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(2 * 24 * 3600))
+            .unwrap();
+        assert_eq!(uploads.cleanup_abandoned(24).await.unwrap(), 1);
+        assert!(!orphan.exists());
+        assert!(uploads.path(&fresh.uuid).is_file());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
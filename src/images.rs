@@ -0,0 +1,497 @@
+//! Server-side processing of unencrypted images.
+//!
+//! Encrypted attachments are opaque to the server, so clients compress them
+//! and render their thumbnails. Unencrypted images are checked here instead:
+//! the format must be one the server can decode, metadata (EXIF, XMP, IPTC,
+//! text chunks, GIF comments) is stripped, and missing thumbnail sizes are
+//! rendered.
+//!
+//! Metadata is removed from the container without re-encoding the pixels.
+//! The exception is an EXIF orientation other than the default: it is
+//! applied to the pixels first, since stripping it would turn the image.
+//!
+//! Decoding is bounded by [`MAX_DIMENSION`] and [`MAX_DECODED_BYTES`] so a
+//! small file cannot expand into gigabytes of pixels. All functions here are
+//! CPU-bound; call them from a blocking task.
+
+use std::fmt;
+use std::io::Cursor;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::PngEncoder;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageFormat, ImageReader, Limits};
+
+/// Largest width or height the server decodes.
+pub const MAX_DIMENSION: u32 = 16384;
+
+/// Largest decoded (in-memory) image the server accepts, in bytes.
+pub const MAX_DECODED_BYTES: u64 = 256 * 1024 * 1024;
+
+/// JPEG quality of re-encoded images.
+const IMAGE_QUALITY: u8 = 90;
+
+/// JPEG quality of thumbnails.
+const THUMBNAIL_QUALITY: u8 = 75;
+
+/// Thumbnail sizes and their largest width or height, matching the sizes the
+/// web client renders.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThumbnailSize {
+    Sm,
+    Md,
+    Lg,
+}
+
+impl ThumbnailSize {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "sm" => Some(ThumbnailSize::Sm),
+            "md" => Some(ThumbnailSize::Md),
+            "lg" => Some(ThumbnailSize::Lg),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ThumbnailSize::Sm => "sm",
+            ThumbnailSize::Md => "md",
+            ThumbnailSize::Lg => "lg",
+        }
+    }
+
+    pub fn max_dimension(&self) -> u32 {
+        match self {
+            ThumbnailSize::Sm => 200,
+            ThumbnailSize::Md => 400,
+            ThumbnailSize::Lg => 800,
+        }
+    }
+}
+
+/// Why an image was rejected.
+#[derive(Debug)]
+pub enum ImageError {
+    /// Not JPEG, PNG, WebP or GIF
+    UnsupportedFormat,
+    /// HEIC/HEIF, which clients convert before uploading
+    Heic,
+    /// Larger than [`MAX_DIMENSION`] or [`MAX_DECODED_BYTES`]
+    TooLarge,
+    /// Corrupt or truncated
+    Invalid(String),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::UnsupportedFormat => {
+                write!(f, "Unsupported image format (use JPEG, PNG, WebP or GIF)")
+            }
+            ImageError::Heic => write!(f, "HEIC images must be converted before uploading"),
+            ImageError::TooLarge => write!(
+                f,
+                "Image dimensions too large (max {MAX_DIMENSION}x{MAX_DIMENSION} pixels)"
+            ),
+            ImageError::Invalid(msg) => write!(f, "Invalid image: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {}
+
+impl From<image::ImageError> for ImageError {
+    fn from(e: image::ImageError) -> Self {
+        match e {
+            image::ImageError::Limits(_) => ImageError::TooLarge,
+            image::ImageError::Unsupported(_) => ImageError::UnsupportedFormat,
+            e => ImageError::Invalid(e.to_string()),
+        }
+    }
+}
+
+/// Check an image and strip its metadata. Returns the cleaned image.
+pub fn sanitize(data: &[u8]) -> Result<Vec<u8>, ImageError> {
+    let (format, mut decoder) = open(data)?;
+    let orientation = decoder.orientation()?;
+    if orientation != Orientation::NoTransforms {
+        let mut image = decode(decoder)?;
+        image.apply_orientation(orientation);
+        return encode(&image, IMAGE_QUALITY);
+    }
+    // Decode the whole image once so corrupt data is caught at upload
+    decode(decoder)?;
+
+    match format {
+        ImageFormat::Jpeg => strip_jpeg(data),
+        ImageFormat::Png => strip_png(data),
+        ImageFormat::WebP => strip_webp(data),
+        ImageFormat::Gif => strip_gif(data),
+        _ => Ok(data.to_vec()),
+    }
+}
+
+/// Render a thumbnail that fits within `size`. Images already that small are
+/// re-encoded at their size.
+pub fn thumbnail(data: &[u8], size: ThumbnailSize) -> Result<Vec<u8>, ImageError> {
+    let (_, mut decoder) = open(data)?;
+    let orientation = decoder.orientation()?;
+    let mut image = decode(decoder)?;
+    image.apply_orientation(orientation);
+
+    let max = size.max_dimension();
+    if image.width() > max || image.height() > max {
+        image = image.thumbnail(max, max);
+    }
+    encode(&image, THUMBNAIL_QUALITY)
+}
+
+/// Detect the format and open a decoder within the limits.
+fn open(data: &[u8]) -> Result<(ImageFormat, impl ImageDecoder + '_), ImageError> {
+    if is_heic(data) {
+        return Err(ImageError::Heic);
+    }
+    let mut reader = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .map_err(|e| ImageError::Invalid(e.to_string()))?;
+    let format = match reader.format() {
+        Some(
+            format @ (ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP | ImageFormat::Gif),
+        ) => format,
+        _ => return Err(ImageError::UnsupportedFormat),
+    };
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    limits.max_alloc = Some(MAX_DECODED_BYTES);
+    reader.limits(limits);
+    Ok((format, reader.into_decoder()?))
+}
+
+fn decode(decoder: impl ImageDecoder) -> Result<DynamicImage, ImageError> {
+    // Decoders only check the dimensions; the pixel buffer is checked here.
+    if decoder.total_bytes() > MAX_DECODED_BYTES {
+        return Err(ImageError::TooLarge);
+    }
+    Ok(DynamicImage::from_decoder(decoder)?)
+}
+
+/// Encode as JPEG, or as PNG if the image has transparency.
+fn encode(image: &DynamicImage, quality: u8) -> Result<Vec<u8>, ImageError> {
+    let mut out = Vec::new();
+    if image.color().has_alpha() {
+        image.write_with_encoder(PngEncoder::new(&mut out))?;
+    } else {
+        // JPEG has no alpha channel and at most 8 bits per channel
+        let rgb = DynamicImage::ImageRgb8(image.to_rgb8());
+        rgb.write_with_encoder(JpegEncoder::new_with_quality(&mut out, quality))?;
+    }
+    Ok(out)
+}
+
+/// HEIC/HEIF files are ISO media files with one of these brands.
+fn is_heic(data: &[u8]) -> bool {
+    const BRANDS: [&[u8]; 8] = [
+        b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"mif1", b"msf1",
+    ];
+    data.len() >= 12 && &data[4..8] == b"ftyp" && BRANDS.contains(&&data[8..12])
+}
+
+fn truncated() -> ImageError {
+    ImageError::Invalid("truncated image".to_string())
+}
+
+/// Drop JPEG segments that carry metadata: APP1 (EXIF, XMP), APP3 to APP13
+/// (including IPTC), APP15 and comments. JFIF (APP0), ICC profiles (APP2)
+/// and Adobe color information (APP14) are needed to show the image and
+/// are kept.
+fn strip_jpeg(data: &[u8]) -> Result<Vec<u8>, ImageError> {
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(&data[..2]);
+    let mut pos = 2;
+    loop {
+        // Markers may be preceded by any number of fill bytes
+        while data.get(pos) == Some(&0xFF) && data.get(pos + 1) == Some(&0xFF) {
+            pos += 1;
+        }
+        let (Some(&0xFF), Some(&marker)) = (data.get(pos), data.get(pos + 1)) else {
+            return Err(truncated());
+        };
+        // Start of scan: the rest is entropy-coded data and the trailer
+        if marker == 0xDA {
+            out.extend_from_slice(&data[pos..]);
+            return Ok(out);
+        }
+        let length = data
+            .get(pos + 2..pos + 4)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]) as usize)
+            .ok_or_else(truncated)?;
+        let end = pos + 2 + length;
+        if length < 2 || end > data.len() {
+            return Err(truncated());
+        }
+        let metadata = matches!(marker, 0xE1 | 0xE3..=0xED | 0xEF | 0xFE);
+        if !metadata {
+            out.extend_from_slice(&data[pos..end]);
+        }
+        pos = end;
+    }
+}
+
+/// Drop PNG chunks that carry metadata: EXIF, text and modification time.
+fn strip_png(data: &[u8]) -> Result<Vec<u8>, ImageError> {
+    const SIGNATURE_LEN: usize = 8;
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(&data[..SIGNATURE_LEN]);
+    let mut pos = SIGNATURE_LEN;
+    while pos < data.len() {
+        let header = data.get(pos..pos + 8).ok_or_else(truncated)?;
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let kind = &header[4..8];
+        // Length, type, data and CRC
+        let end = pos + 12 + length;
+        if end > data.len() {
+            return Err(truncated());
+        }
+        if !matches!(kind, b"eXIf" | b"tEXt" | b"zTXt" | b"iTXt" | b"tIME") {
+            out.extend_from_slice(&data[pos..end]);
+        }
+        pos = end;
+    }
+    Ok(out)
+}
+
+/// Drop the EXIF and XMP chunks of a WebP file and clear their flags in the
+/// extended header.
+fn strip_webp(data: &[u8]) -> Result<Vec<u8>, ImageError> {
+    const HEADER_LEN: usize = 12;
+    const EXIF_FLAG: u8 = 0x08;
+    const XMP_FLAG: u8 = 0x04;
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(&data[..HEADER_LEN]);
+    let mut pos = HEADER_LEN;
+    while pos < data.len() {
+        let header = data.get(pos..pos + 8).ok_or_else(truncated)?;
+        let kind = &header[..4];
+        let length = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as usize;
+        if pos + 8 + length > data.len() {
+            return Err(truncated());
+        }
+        // Chunks are padded to an even length; encoders may omit the last pad
+        let end = (pos + 8 + length + (length & 1)).min(data.len());
+        match kind {
+            b"EXIF" | b"XMP " => {}
+            b"VP8X" => {
+                let start = out.len();
+                out.extend_from_slice(&data[pos..end]);
+                if let Some(flags) = out.get_mut(start + 8) {
+                    *flags &= !(EXIF_FLAG | XMP_FLAG);
+                }
+            }
+            _ => out.extend_from_slice(&data[pos..end]),
+        }
+        pos = end;
+    }
+    // The RIFF size covers everything after its own field
+    let riff_size = (out.len() - 8) as u32;
+    out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    Ok(out)
+}
+
+/// Drop GIF comment extensions and application extensions other than the
+/// NETSCAPE looping extension (XMP and other metadata ride in those).
+fn strip_gif(data: &[u8]) -> Result<Vec<u8>, ImageError> {
+    const HEADER_LEN: usize = 13;
+    const IMAGE_DESCRIPTOR_LEN: usize = 10;
+    const LOOP_EXTENSION: &[u8] = b"\x0bNETSCAPE2.0";
+    /// Size of the color table a packed field announces, if any.
+    fn color_table_len(packed: u8) -> usize {
+        if packed & 0x80 == 0 {
+            return 0;
+        }
+        3 << ((packed & 0x07) + 1)
+    }
+    /// End of the data sub-blocks starting at `pos`, after the terminator.
+    fn sub_blocks_end(data: &[u8], mut pos: usize) -> Result<usize, ImageError> {
+        loop {
+            let size = *data.get(pos).ok_or_else(truncated)? as usize;
+            pos += 1 + size;
+            if size == 0 {
+                return Ok(pos);
+            }
+        }
+    }
+
+    let packed = *data.get(10).ok_or_else(truncated)?;
+    let mut pos = HEADER_LEN + color_table_len(packed);
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(data.get(..pos).ok_or_else(truncated)?);
+    loop {
+        match data.get(pos) {
+            // Trailer; anything after it is not part of the image
+            Some(0x3B) => {
+                out.push(0x3B);
+                return Ok(out);
+            }
+            Some(0x21) => {
+                let label = *data.get(pos + 1).ok_or_else(truncated)?;
+                let end = sub_blocks_end(data, pos + 2)?;
+                let block = data.get(pos..end).ok_or_else(truncated)?;
+                let metadata = match label {
+                    0xFE => true,
+                    0xFF => !block[2..].starts_with(LOOP_EXTENSION),
+                    _ => false,
+                };
+                if !metadata {
+                    out.extend_from_slice(block);
+                }
+                pos = end;
+            }
+            Some(0x2C) => {
+                let packed = *data.get(pos + 9).ok_or_else(truncated)?;
+                // Descriptor, local color table and LZW minimum code size
+                let start = pos + IMAGE_DESCRIPTOR_LEN + color_table_len(packed) + 1;
+                let end = sub_blocks_end(data, start)?;
+                out.extend_from_slice(data.get(pos..end).ok_or_else(truncated)?);
+                pos = end;
+            }
+            _ => return Err(truncated()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage, Rgba, RgbaImage};
+
+    fn jpeg(width: u32, height: u32) -> Vec<u8> {
+        let image = RgbImage::from_pixel(width, height, Rgb([200, 100, 50]));
+        let mut out = Vec::new();
+        DynamicImage::ImageRgb8(image)
+            .write_with_encoder(JpegEncoder::new_with_quality(&mut out, 90))
+            .unwrap();
+        out
+    }
+
+    /// Insert an APP1 segment after the SOI marker.
+    fn with_app1(jpeg: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut out = jpeg[..2].to_vec();
+        out.extend_from_slice(&[0xFF, 0xE1]);
+        out.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+        out.extend_from_slice(payload);
+        out.extend_from_slice(&jpeg[2..]);
+        out
+    }
+
+    /// A minimal EXIF block holding only an orientation tag.
+    fn exif_orientation(orientation: u16) -> Vec<u8> {
+        let mut exif = b"Exif\0\0MM\0\x2a\0\0\0\x08\0\x01".to_vec();
+        exif.extend_from_slice(&[0x01, 0x12, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01]);
+        exif.extend_from_slice(&orientation.to_be_bytes());
+        exif.extend_from_slice(&[0, 0, 0, 0, 0, 0]);
+        exif
+    }
+
+    #[test]
+    fn test_sanitize_strips_jpeg_metadata() {
+        let original = jpeg(16, 8);
+        let tagged = with_app1(&original, b"Exif\0\0GPS position");
+
+        let clean = sanitize(&tagged).unwrap();
+        assert_eq!(clean, original);
+        // Clean images are left alone
+        assert_eq!(sanitize(&original).unwrap(), original);
+    }
+
+    #[test]
+    fn test_sanitize_applies_orientation() {
+        // Rotated 90 degrees clockwise
+        let tagged = with_app1(&jpeg(16, 8), &exif_orientation(6));
+
+        let clean = sanitize(&tagged).unwrap();
+        let image = image::load_from_memory(&clean).unwrap();
+        assert_eq!((image.width(), image.height()), (8, 16));
+        assert!(!clean.windows(4).any(|w| w == b"Exif"));
+    }
+
+    #[test]
+    fn test_sanitize_strips_png_text() {
+        let mut original = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 128])))
+            .write_with_encoder(PngEncoder::new(&mut original))
+            .unwrap();
+        // Insert a tEXt chunk after IHDR (signature + 25 bytes)
+        let text = b"Commentsecret";
+        let mut chunk = (text.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(b"tEXt");
+        chunk.extend_from_slice(text);
+        chunk.extend_from_slice(&[0, 0, 0, 0]);
+        let mut tagged = original[..33].to_vec();
+        tagged.extend_from_slice(&chunk);
+        tagged.extend_from_slice(&original[33..]);
+
+        assert_eq!(strip_png(&tagged).unwrap(), original);
+    }
+
+    #[test]
+    fn test_sanitize_strips_gif_comments_and_xmp() {
+        let mut original = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 4, Rgba([200, 0, 0, 255])))
+            .write_to(&mut Cursor::new(&mut original), ImageFormat::Gif)
+            .unwrap();
+        // Insert the blocks after the header and global color table
+        let packed = original[10];
+        let start = if packed & 0x80 != 0 {
+            13 + (3 << ((packed & 0x07) + 1))
+        } else {
+            13
+        };
+        let looping = b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x00\x00\x00";
+        let mut tagged = original[..start].to_vec();
+        tagged.extend_from_slice(b"\x21\xfe\x06secret\x00");
+        tagged.extend_from_slice(looping);
+        tagged.extend_from_slice(b"\x21\xff\x0bXMP DataXMP\x05<xmp>\x00");
+        tagged.extend_from_slice(&original[start..]);
+        let mut expected = original[..start].to_vec();
+        expected.extend_from_slice(looping);
+        expected.extend_from_slice(&original[start..]);
+
+        assert_eq!(sanitize(&tagged).unwrap(), expected);
+        assert_eq!(strip_gif(&original).unwrap(), original);
+    }
+
+    #[test]
+    fn test_sanitize_rejects_unsupported_and_corrupt() {
+        assert!(matches!(
+            sanitize(b"not an image"),
+            Err(ImageError::UnsupportedFormat)
+        ));
+        assert!(matches!(
+            sanitize(b"\0\0\0\x18ftypheic\0\0\0\0mif1heic"),
+            Err(ImageError::Heic)
+        ));
+        let truncated = &jpeg(16, 16)[..40];
+        assert!(sanitize(truncated).is_err());
+    }
+
+    #[test]
+    fn test_thumbnail_fits_size() {
+        let data = jpeg(1000, 500);
+        for (size, expected) in [
+            (ThumbnailSize::Sm, (200, 100)),
+            (ThumbnailSize::Md, (400, 200)),
+            (ThumbnailSize::Lg, (800, 400)),
+        ] {
+            let thumb = image::load_from_memory(&thumbnail(&data, size).unwrap()).unwrap();
+            assert_eq!((thumb.width(), thumb.height()), expected);
+        }
+
+        // Small images are not enlarged
+        let thumb = thumbnail(&jpeg(50, 20), ThumbnailSize::Lg).unwrap();
+        let thumb = image::load_from_memory(&thumb).unwrap();
+        assert_eq!((thumb.width(), thumb.height()), (50, 20));
+    }
+}
@@ -1,9 +1,11 @@
 pub mod api;
 pub mod assets;
 pub mod auth;
+pub mod blob;
 pub mod cleanup;
 pub mod cli;
 pub mod db;
+pub mod images;
 pub mod jwt;
 pub mod names;
 pub mod plugin;
@@ -148,6 +150,14 @@ pub async fn init_cleanup(db: &Database) {
     cleanup::spawn_cleanup_scheduler(db.clone());
 }
 
+/// Spawn background tasks for plugins with `schedule` hooks.
+/// Call this before starting the server.
+pub fn init_plugin_scheduler(plugin_manager: Option<&Arc<PluginManager>>) {
+    if let Some(manager) = plugin_manager {
+        plugin::spawn_plugin_scheduler(manager);
+    }
+}
+
 /// Run the server on the given listener. This function blocks until the server exits.
 /// Call `init_cleanup` before this to run cleanup on startup.
 pub async fn run_server(config: ServerConfig, listener: TcpListener) -> Result<(), std::io::Error> {
@@ -165,6 +175,7 @@ pub async fn start_server(
 ) -> (tokio::task::JoinHandle<()>, SocketAddr) {
     // Run cleanup tasks on startup
     init_cleanup(&config.db).await;
+    init_plugin_scheduler(config.plugin_manager.as_ref());
 
     let addr = format!("127.0.0.1:{}", port);
     let listener = TcpListener::bind(&addr).await.expect("Failed to bind");
@@ -1,10 +1,13 @@
+use std::sync::Arc;
+
 use clap::Parser;
 use crowchiper::cli::{
-    Args, PluginErrorMode, build_config, handle_create_admin, init_logging, load_jwt_secret,
-    open_database, validate_rp_origin,
+    Args, Command, PluginErrorMode, build_config, handle_create_admin, init_logging,
+    load_blob_store, load_jwt_secret, load_s3_config, load_signature_policy, open_database,
+    run_plugin_command, run_storage_command, validate_rp_origin,
 };
-use crowchiper::plugin::PluginRuntime;
-use crowchiper::{init_cleanup, run_server};
+use crowchiper::plugin::{LoadOptions, PluginRuntime, load_plugin_config};
+use crowchiper::{init_cleanup, init_plugin_scheduler, run_server};
 use tracing::{error, info, warn};
 
 #[tokio::main]
@@ -13,6 +16,28 @@ async fn main() {
 
     init_logging(&args.log_format);
 
+    let Some(signature_policy) =
+        load_signature_policy(&args.plugin_trusted_key, &args.plugin_signatures)
+    else {
+        std::process::exit(1);
+    };
+    let load_options = LoadOptions {
+        signature_policy: Arc::new(signature_policy),
+        cache_dir: args.plugin_cache_dir.clone(),
+    };
+
+    if let Some(Command::Plugin(command)) = &args.command {
+        let ok = run_plugin_command(command, &load_options).await;
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
+    let s3_config = load_s3_config(&args.s3_endpoint, &args.s3_region);
+
+    if let Some(Command::Storage(command)) = &args.command {
+        let ok = run_storage_command(command, &args.database, s3_config.as_ref()).await;
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
     let Some(jwt_secret) = load_jwt_secret(args.jwt_secret_file.as_deref()) else {
         std::process::exit(1);
     };
@@ -20,23 +45,67 @@ async fn main() {
     let Some(db) = open_database(&args.database).await else {
         std::process::exit(1);
     };
+    let Some(blob_store) = load_blob_store(&db, &args.blob_store, s3_config.as_ref()) else {
+        std::process::exit(1);
+    };
+    let db = db.with_blob_store(blob_store);
+    let db = match args.upload_dir.clone() {
+        Some(dir) => db.with_upload_dir(dir),
+        None => db,
+    };
+
+    // Attachments whose blobs were moved out of the database before sizes
+    // were recorded would otherwise never count against quotas.
+    match db.attachments().backfill_sizes().await {
+        Ok(0) => {}
+        Ok(count) => info!(count, "Recorded sizes of attachments in the blob store"),
+        Err(e) => warn!(error = %e, "Failed to record attachment sizes"),
+    }
 
     let Some(rp_origin) = validate_rp_origin(&args.rp_origin) else {
         std::process::exit(1);
     };
 
+    if let Some(dir) = args.plugin_dir.as_ref().filter(|dir| !dir.is_dir()) {
+        error!(path = %dir.display(), "Plugin directory does not exist");
+        std::process::exit(1);
+    }
+
+    let mut plugin_specs = args.plugin.clone();
+    if let Some(path) = &args.plugin_config {
+        match load_plugin_config(path) {
+            Ok(specs) => plugin_specs.extend(specs),
+            Err(e) => {
+                error!(error = %e, "Failed to read plugin config");
+                std::process::exit(1);
+            }
+        }
+    }
+
     let mut plugins = Vec::new();
-    for plugin_spec in &args.plugin {
-        let result = PluginRuntime::load(
+    let mut disabled_plugins = Vec::new();
+    for plugin_spec in &plugin_specs {
+        let result = PluginRuntime::load_with_limits(
             &plugin_spec.path,
             &plugin_spec.permissions,
             &plugin_spec.config,
             plugin_spec.hook_timeout,
+            plugin_spec.limits,
+            &load_options,
         )
-        .await;
+        .await
+        .map(|plugin| {
+            plugin
+                .with_fail_closed(&plugin_spec.fail_closed)
+                .with_breaker(plugin_spec.breaker)
+                .with_kv(db.clone(), plugin_spec.kv_quota)
+        });
         match result {
             Ok(plugin) => {
-                info!(name = %plugin.name(), version = %plugin.version(), "Plugin loaded");
+                info!(name = %plugin.name(), version = %plugin.version(), enabled = plugin_spec.enabled, "Plugin loaded");
+                if !plugin_spec.enabled {
+                    disabled_plugins.push(plugin.name().to_string());
+                }
                 plugins.push(plugin);
             }
             Err(e) => match args.plugin_error {
@@ -68,7 +137,7 @@ async fn main() {
     #[cfg(feature = "test-mode")]
     let rp_origin = test_mode::maybe_update_rp_origin(rp_origin, args.port, local_addr.port());
 
-    let config = build_config(
+    let Some(config) = build_config(
         args.base,
         db,
         args.rp_id,
@@ -78,10 +147,25 @@ async fn main() {
         args.csp_nonce,
         args.ip_header,
         plugins,
-    );
+        args.plugin_dir,
+        load_options,
+    ) else {
+        std::process::exit(1);
+    };
+
+    if let Some(manager) = &config.plugin_manager {
+        for name in &disabled_plugins {
+            // Running a plugin the config disabled is worse than not starting.
+            if let Err(e) = manager.set_enabled(name, false).await {
+                error!(plugin = %name, error = %e, "Failed to disable plugin");
+                std::process::exit(1);
+            }
+        }
+    }
 
     // Run cleanup on startup and spawn hourly scheduler
     init_cleanup(&config.db).await;
+    init_plugin_scheduler(config.plugin_manager.as_ref());
 
     info!(address = %local_addr, "Listening");
 
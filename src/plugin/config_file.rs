@@ -0,0 +1,325 @@
+//! `--plugin-config` file: a TOML alternative to `--plugin` spec strings.
+//!
+//! ```toml
+//! [[plugin]]
+//! path = "notify.wasm"            # relative to this file
+//! enabled = true                  # default true
+//! permissions = ["http=hooks.example.com", "net-dns"]
+//! timeout = "500ms"               # or seconds as a number
+//! fuel = 50000000
+//! memory = "32m"
+//! stack = "1m"
+//! kv-quota = "64k"
+//! breaker = "5/60"               # failures/cooldown, or "off"
+//! fail-closed = ["pre-login"]
+//!
+//! [plugin.vars]
+//! channel = "#alerts"
+//! token = { env = "SLACK_TOKEN" }
+//! password = { file = "/run/secrets/notify" }
+//! ```
+//!
+//! Every entry becomes a [`PluginSpec`], so both sources load the same way.
+//! Secret vars are resolved once at startup and never appear on the command
+//! line.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use super::hook_names::parse_pre_hook;
+use super::permissions::{
+    CircuitBreaker, DEFAULT_HOOK_TIMEOUT, DEFAULT_KV_QUOTA, PluginLimits, PluginSpec,
+    parse_breaker, parse_single_permission, parse_size, parse_timeout,
+};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    #[serde(default)]
+    plugin: Vec<PluginEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct PluginEntry {
+    path: PathBuf,
+    #[serde(default = "enabled_by_default")]
+    enabled: bool,
+    #[serde(default)]
+    permissions: Vec<String>,
+    #[serde(default)]
+    vars: BTreeMap<String, VarValue>,
+    timeout: Option<NumberOrString>,
+    fuel: Option<u64>,
+    memory: Option<NumberOrString>,
+    stack: Option<NumberOrString>,
+    kv_quota: Option<NumberOrString>,
+    breaker: Option<String>,
+    #[serde(default)]
+    fail_closed: Vec<String>,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+/// A value written either as a number or with a unit suffix (`"500ms"`, `"64k"`).
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumberOrString {
+    Number(u64),
+    String(String),
+}
+
+impl NumberOrString {
+    fn as_string(&self) -> String {
+        match self {
+            NumberOrString::Number(n) => n.to_string(),
+            NumberOrString::String(s) => s.clone(),
+        }
+    }
+}
+
+/// A config var: a literal string, or a reference to a secret.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum VarValue {
+    Literal(String),
+    Secret(SecretSource),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SecretSource {
+    /// Contents of a file, without the trailing newline.
+    File(PathBuf),
+    /// Value of a server environment variable.
+    Env(String),
+}
+
+/// Read a `--plugin-config` file into plugin specs.
+///
+/// Relative plugin and secret file paths are resolved against the directory
+/// containing the config file.
+pub fn load_plugin_config(path: &Path) -> Result<Vec<PluginSpec>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+    parse_plugin_config(&text, base_dir, |name| std::env::var(name).ok())
+        .map_err(|e| format!("{}: {e}", path.display()))
+}
+
+fn parse_plugin_config(
+    text: &str,
+    base_dir: &Path,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<Vec<PluginSpec>, String> {
+    let file: ConfigFile = toml::from_str(text).map_err(|e| e.to_string())?;
+    file.plugin
+        .into_iter()
+        .map(|entry| {
+            let path = entry.path.display().to_string();
+            entry
+                .into_spec(base_dir, &env)
+                .map_err(|e| format!("plugin {path}: {e}"))
+        })
+        .collect()
+}
+
+impl PluginEntry {
+    fn into_spec(
+        self,
+        base_dir: &Path,
+        env: &impl Fn(&str) -> Option<String>,
+    ) -> Result<PluginSpec, String> {
+        let permissions = self
+            .permissions
+            .iter()
+            .map(|perm| parse_single_permission(perm))
+            .collect::<Result<_, _>>()?;
+
+        let mut config = Vec::with_capacity(self.vars.len());
+        for (key, value) in self.vars {
+            let value = match value {
+                VarValue::Literal(value) => value,
+                VarValue::Secret(SecretSource::Env(name)) => env(&name).ok_or_else(|| {
+                    format!("var '{key}': environment variable {name} is not set")
+                })?,
+                VarValue::Secret(SecretSource::File(file)) => {
+                    let file = base_dir.join(file);
+                    let contents = std::fs::read_to_string(&file).map_err(|e| {
+                        format!("var '{key}': failed to read {}: {e}", file.display())
+                    })?;
+                    contents.trim_end_matches(['\n', '\r']).to_string()
+                }
+            };
+            config.push((key, value));
+        }
+
+        let hook_timeout = match self.timeout {
+            Some(timeout) => parse_timeout(&timeout.as_string())?,
+            None => DEFAULT_HOOK_TIMEOUT,
+        };
+        let kv_quota = match self.kv_quota {
+            Some(quota) => parse_size(&quota.as_string())?,
+            None => DEFAULT_KV_QUOTA,
+        };
+
+        let mut limits = PluginLimits::default();
+        if let Some(fuel) = self.fuel {
+            limits.fuel = fuel;
+        }
+        if let Some(memory) = self.memory {
+            limits.memory = parse_size(&memory.as_string())?;
+        }
+        if let Some(stack) = self.stack {
+            limits.stack = parse_size(&stack.as_string())?;
+        }
+        limits.validate()?;
+        let breaker = match self.breaker {
+            Some(breaker) => parse_breaker(&breaker)?,
+            None => CircuitBreaker::default(),
+        };
+
+        let mut fail_closed = Vec::new();
+        for name in &self.fail_closed {
+            let hook = parse_pre_hook(name).ok_or_else(|| {
+                format!(
+                    "unknown pre-hook '{name}' in fail-closed. Valid: pre-login, pre-register, pre-upload, pre-token-refresh"
+                )
+            })?;
+            if !fail_closed.contains(&hook) {
+                fail_closed.push(hook);
+            }
+        }
+
+        Ok(PluginSpec {
+            path: base_dir.join(self.path),
+            permissions,
+            config,
+            hook_timeout,
+            fail_closed,
+            kv_quota,
+            limits,
+            breaker,
+            enabled: self.enabled,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::{PluginPermission, ServerPreHook};
+    use std::time::Duration;
+
+    fn parse(text: &str) -> Result<Vec<PluginSpec>, String> {
+        parse_plugin_config(text, Path::new("/etc/crowchiper"), |name| {
+            (name == "TOKEN").then(|| "from-env".to_string())
+        })
+    }
+
+    #[test]
+    fn parse_full_entry() {
+        let specs = parse(
+            r#"
+            [[plugin]]
+            path = "notify.wasm"
+            enabled = false
+            permissions = ["net-dns", "http=hooks.example.com"]
+            timeout = "500ms"
+            fuel = 50000000
+            memory = "32m"
+            stack = "1m"
+            kv-quota = 4096
+            breaker = "3/30"
+            fail-closed = ["pre-login"]
+
+            [plugin.vars]
+            channel = "alerts"
+            token = { env = "TOKEN" }
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(specs.len(), 1);
+        let spec = &specs[0];
+        assert_eq!(spec.path, PathBuf::from("/etc/crowchiper/notify.wasm"));
+        assert!(!spec.enabled);
+        assert_eq!(
+            spec.permissions,
+            vec![
+                PluginPermission::NetDns,
+                PluginPermission::Http("hooks.example.com".into())
+            ]
+        );
+        assert_eq!(spec.hook_timeout, Duration::from_millis(500));
+        assert_eq!(spec.limits.fuel, 50_000_000);
+        assert_eq!(spec.limits.memory, 32 * 1024 * 1024);
+        assert_eq!(spec.limits.stack, 1024 * 1024);
+        assert_eq!(spec.kv_quota, 4096);
+        assert_eq!(spec.breaker.failures, 3);
+        assert_eq!(spec.breaker.cooldown, Duration::from_secs(30));
+        assert_eq!(spec.fail_closed, vec![ServerPreHook::PreLogin]);
+        assert_eq!(
+            spec.config,
+            vec![
+                ("channel".to_string(), "alerts".to_string()),
+                ("token".to_string(), "from-env".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_defaults() {
+        let specs = parse("[[plugin]]\npath = \"/opt/a.wasm\"").unwrap();
+        let spec = &specs[0];
+        assert_eq!(spec.path, PathBuf::from("/opt/a.wasm"));
+        assert!(spec.enabled);
+        assert_eq!(spec.hook_timeout, DEFAULT_HOOK_TIMEOUT);
+        assert_eq!(spec.kv_quota, DEFAULT_KV_QUOTA);
+        assert_eq!(spec.limits, PluginLimits::default());
+        assert_eq!(spec.breaker, CircuitBreaker::default());
+        assert!(parse("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_secret_file() {
+        let dir = std::env::temp_dir().join(format!("plugin-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("secret"), "hunter2\n").unwrap();
+
+        let text = "[[plugin]]\npath = \"a.wasm\"\nvars = { pw = { file = \"secret\" } }";
+        let specs = parse_plugin_config(text, &dir, |_| None).unwrap();
+        assert_eq!(specs[0].config, vec![("pw".into(), "hunter2".into())]);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn parse_errors_name_the_plugin() {
+        let err = parse("[[plugin]]\npath = \"a.wasm\"\nvars = { t = { env = \"MISSING\" } }")
+            .unwrap_err();
+        assert!(
+            err.contains("a.wasm") && err.contains("MISSING"),
+            "got: {err}"
+        );
+
+        let err = parse("[[plugin]]\npath = \"a.wasm\"\npermissions = [\"bogus\"]").unwrap_err();
+        assert!(err.contains("unknown permission"), "got: {err}");
+
+        let err = parse("[[plugin]]\npath = \"a.wasm\"\nfuel = 0").unwrap_err();
+        assert!(err.contains("fuel"), "got: {err}");
+
+        let err = parse("[[plugin]]\npath = \"a.wasm\"\nmemory = \"4096m\"").unwrap_err();
+        assert!(err.contains("memory"), "got: {err}");
+    }
+
+    #[test]
+    fn parse_rejects_unknown_keys() {
+        let err = parse("[[plugin]]\npath = \"a.wasm\"\ntimout = 5").unwrap_err();
+        assert!(err.contains("timout"), "got: {err}");
+    }
+}
@@ -0,0 +1,196 @@
+//! Shared wasmtime engines and the on-disk compiled component cache.
+//!
+//! Compiling a component with Cranelift is by far the slowest part of loading
+//! a plugin. All plugins share one [`Engine`] per WASM stack size (the stack
+//! limit is an engine setting, so plugins with a custom `stack=` get their
+//! own), and with a [`LoadOptions::cache_dir`] compiled components are kept
+//! on disk so restarts and reloads of an unchanged `.wasm` skip Cranelift.
+
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex};
+
+use openssl::sha::Sha256;
+use wasmtime::Engine;
+use wasmtime::component::Component;
+
+use super::PluginError;
+use super::permissions::PluginLimits;
+use super::signature::{self, SignaturePolicy};
+
+/// Host stack space reserved on top of a plugin's WASM stack for async calls.
+const ASYNC_STACK_HEADROOM: usize = 1024 * 1024;
+
+/// How plugin files are checked and compiled. Kept by each plugin, so
+/// reloads are handled the same way as the first load.
+#[derive(Clone, Default)]
+pub struct LoadOptions {
+    /// Which signatures a plugin needs; by default none are checked.
+    pub signature_policy: Arc<SignaturePolicy>,
+    /// Directory for compiled components (created if missing); `None`
+    /// compiles on every load.
+    ///
+    /// Cached files are loaded as native code without validation, so the
+    /// directory must only be writable by the server.
+    pub cache_dir: Option<PathBuf>,
+}
+
+/// Engines by `max_wasm_stack`, created on first use.
+static ENGINES: LazyLock<Mutex<HashMap<u64, Engine>>> = LazyLock::new(Default::default);
+
+/// The shared engine for plugins with the given limits.
+fn engine_for(limits: &PluginLimits) -> Result<Engine, PluginError> {
+    let mut engines = ENGINES.lock().unwrap();
+    if let Some(engine) = engines.get(&limits.stack) {
+        return Ok(engine.clone());
+    }
+
+    // Fuel metering caps CPU usage; memory is limited per store.
+    let mut config = wasmtime::Config::new();
+    config.consume_fuel(true);
+    config.async_support(true);
+    config.max_wasm_stack(limits.stack as usize);
+    // The host stack async calls run on must have room for the WASM stack.
+    config.async_stack_size(limits.stack as usize + ASYNC_STACK_HEADROOM);
+    let engine = Engine::new(&config)
+        .map_err(|e| PluginError::Load(format!("failed to create engine: {e}")))?;
+    engines.insert(limits.stack, engine.clone());
+    Ok(engine)
+}
+
+/// Read, verify and compile the component at `path`, using the on-disk cache
+/// if enabled.
+pub(super) fn compile(
+    path: &Path,
+    limits: &PluginLimits,
+    options: &LoadOptions,
+) -> Result<Component, PluginError> {
+    let wasm_bytes = std::fs::read(path)
+        .map_err(|e| PluginError::Load(format!("failed to read {}: {e}", path.display())))?;
+    signature::verify(&options.signature_policy, path, &wasm_bytes)?;
+    let engine = engine_for(limits)?;
+
+    let Some(cache_dir) = &options.cache_dir else {
+        return compile_bytes(&engine, path, &wasm_bytes);
+    };
+
+    let entry = cache_dir.join(cache_key(&engine, limits, &wasm_bytes));
+    if entry.is_file() {
+        // SAFETY: entries are only written by `write_cache_entry` from
+        // `Component::serialize`, and the cache directory is documented as
+        // server-writable only. Incompatible entries are rejected by wasmtime.
+        match unsafe { Component::deserialize_file(&engine, &entry) } {
+            Ok(component) => return Ok(component),
+            Err(e) => tracing::warn!(
+                path = %entry.display(),
+                error = %e,
+                "Ignoring unusable compiled plugin cache entry"
+            ),
+        }
+    }
+
+    let component = compile_bytes(&engine, path, &wasm_bytes)?;
+    if let Err(e) = write_cache_entry(cache_dir, &entry, &component) {
+        tracing::warn!(
+            path = %entry.display(),
+            error = %e,
+            "Failed to write compiled plugin cache entry"
+        );
+    }
+    Ok(component)
+}
+
+fn compile_bytes(
+    engine: &Engine,
+    path: &Path,
+    wasm_bytes: &[u8],
+) -> Result<Component, PluginError> {
+    Component::new(engine, wasm_bytes)
+        .map_err(|e| PluginError::Load(format!("failed to compile {}: {e}", path.display())))
+}
+
+/// File name for a component: SHA-256 of the wasm and every engine setting
+/// that affects compiled code, so a changed file, stack size or wasmtime
+/// version never picks up a stale entry.
+fn cache_key(engine: &Engine, limits: &PluginLimits, wasm_bytes: &[u8]) -> String {
+    let mut compatibility = Sha256Hasher(Sha256::new());
+    engine
+        .precompile_compatibility_hash()
+        .hash(&mut compatibility);
+
+    let mut sha = Sha256::new();
+    sha.update(hex(&compatibility.0.finish()).as_bytes());
+    sha.update(&limits.stack.to_le_bytes());
+    sha.update(wasm_bytes);
+    format!("{}.cwasm", hex(&sha.finish()))
+}
+
+/// Feeds hashed values into SHA-256, whose output (unlike `DefaultHasher`'s)
+/// is the same in every Rust release.
+struct Sha256Hasher(Sha256);
+
+impl Hasher for Sha256Hasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        let digest = self.0.clone().finish();
+        u64::from_le_bytes(digest[..8].try_into().unwrap())
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Write via a temporary file and rename, so a concurrent load never reads
+/// a half-written entry.
+fn write_cache_entry(cache_dir: &Path, entry: &Path, component: &Component) -> Result<(), String> {
+    std::fs::create_dir_all(cache_dir).map_err(|e| e.to_string())?;
+    let bytes = component.serialize().map_err(|e| e.to_string())?;
+    let tmp = entry.with_extension(format!("tmp{}", std::process::id()));
+    std::fs::write(&tmp, bytes).map_err(|e| e.to_string())?;
+    std::fs::rename(&tmp, entry).map_err(|e| {
+        std::fs::remove_file(&tmp).ok();
+        e.to_string()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn engines_are_shared_per_stack_size() {
+        let default = PluginLimits::default();
+        let bigger = PluginLimits {
+            stack: default.stack * 2,
+            ..default
+        };
+        let a = engine_for(&default).unwrap();
+        let b = engine_for(&default).unwrap();
+        let c = engine_for(&bigger).unwrap();
+        assert!(Engine::same(&a, &b));
+        assert!(!Engine::same(&a, &c));
+    }
+
+    #[test]
+    fn cache_key_depends_on_wasm_and_engine() {
+        let default = PluginLimits::default();
+        let bigger = PluginLimits {
+            stack: default.stack * 2,
+            ..default
+        };
+        let engine = engine_for(&default).unwrap();
+        let key = cache_key(&engine, &default, b"wasm");
+        assert_eq!(key, cache_key(&engine, &default, b"wasm"));
+        assert_ne!(key, cache_key(&engine, &default, b"other"));
+        let bigger_engine = engine_for(&bigger).unwrap();
+        assert_ne!(key, cache_key(&bigger_engine, &bigger, b"wasm"));
+        let hash = key.strip_suffix(".cwasm").unwrap();
+        assert_eq!(hash.len(), 64);
+        assert!(hash.bytes().all(|b| b.is_ascii_hexdigit()));
+    }
+}
@@ -6,6 +6,16 @@ pub enum PluginError {
     Runtime(String),
     InvalidConfig(String),
     Hook(String),
+    /// No loaded plugin has the given name.
+    NotFound(String),
+    /// A plugin with the given name is already loaded.
+    AlreadyLoaded(String),
+    /// The plugin's circuit breaker is open after repeated failures.
+    Unavailable(String),
+    /// Signatures are required and the plugin has no `.sig` file.
+    SignatureMissing(String),
+    /// The plugin's `.sig` file does not verify under any trusted key.
+    SignatureInvalid(String),
 }
 
 impl fmt::Display for PluginError {
@@ -15,6 +25,11 @@ impl fmt::Display for PluginError {
             PluginError::Runtime(msg) => write!(f, "plugin runtime error: {msg}"),
             PluginError::InvalidConfig(msg) => write!(f, "plugin config error: {msg}"),
             PluginError::Hook(msg) => write!(f, "plugin hook error: {msg}"),
+            PluginError::NotFound(name) => write!(f, "plugin not found: {name}"),
+            PluginError::AlreadyLoaded(name) => write!(f, "plugin already loaded: {name}"),
+            PluginError::Unavailable(msg) => write!(f, "plugin unavailable: {msg}"),
+            PluginError::SignatureMissing(msg) => write!(f, "plugin signature missing: {msg}"),
+            PluginError::SignatureInvalid(msg) => write!(f, "plugin signature invalid: {msg}"),
         }
     }
 }
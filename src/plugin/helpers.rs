@@ -1,30 +1,49 @@
+use std::net::SocketAddr;
 use std::path::Path;
 
 use wasmtime_wasi::WasiCtxBuilder;
+use wasmtime_wasi::sockets::SocketAddrUse;
 
-use super::permissions::PluginPermission;
+use super::hook_names::{parse_pre_hook, parse_server_hook};
+use super::permissions::{NetRule, PluginPermission};
 use super::{Hook, HookTarget, PluginError};
 
 /// Derive the target from a hook variant.
 pub(crate) fn hook_target(hook: &Hook) -> HookTarget {
     match hook {
-        Hook::Server(_) => HookTarget::Server,
+        Hook::Server(_) | Hook::ServerPre(_) => HookTarget::Server,
+        Hook::Schedule(_) => HookTarget::Schedule,
     }
 }
 
+/// Parse a server hook or pre-hook from its WIT name, e.g. `ip-change` or
+/// `pre-login`. Schedule hooks have no name and are not accepted.
+pub(crate) fn parse_hook_name(name: &str) -> Option<Hook> {
+    parse_server_hook(name)
+        .map(Hook::Server)
+        .or_else(|| parse_pre_hook(name).map(Hook::ServerPre))
+}
+
 /// Apply granted permissions to the WASI context builder.
 ///
 /// Each permission maps to a specific `WasiCtxBuilder` method:
 /// - `FsRead` → `preopened_dir` with read-only perms
 /// - `FsWrite` → `preopened_dir` with read+write perms
 /// - `Net` → `inherit_network`
+/// - `NetAddr` → `socket_addr_check` limited to the granted rules
+/// - `NetDns` → `allow_ip_name_lookup`
 /// - `Env` → `inherit_env`
+///
+/// `Net` takes precedence over any `NetAddr` rules.
+/// `Http` grants nothing at the WASI level; it is enforced by the host `http` import.
 pub(crate) fn apply_permissions(
     builder: &mut WasiCtxBuilder,
     permissions: &[PluginPermission],
 ) -> Result<(), PluginError> {
     use wasmtime_wasi::{DirPerms, FilePerms};
 
+    let mut net_rules = Vec::new();
+    let mut inherit_network = false;
     for perm in permissions {
         match perm {
             PluginPermission::FsRead(host_path) => {
@@ -67,8 +86,15 @@ pub(crate) fn apply_permissions(
                     })?;
             }
             PluginPermission::Net => {
-                builder.inherit_network();
+                inherit_network = true;
             }
+            PluginPermission::NetAddr(rule) => {
+                net_rules.push(rule.clone());
+            }
+            PluginPermission::NetDns => {
+                builder.allow_ip_name_lookup(true);
+            }
+            PluginPermission::Http(_) => {}
             PluginPermission::Env(var_name) => {
                 if let Ok(value) = std::env::var(var_name) {
                     builder.env(var_name, &value);
@@ -76,9 +102,31 @@ pub(crate) fn apply_permissions(
             }
         }
     }
+
+    if inherit_network {
+        builder.inherit_network();
+    } else if !net_rules.is_empty() {
+        builder.socket_addr_check(move |addr, addr_use| {
+            let allowed = socket_allowed(&net_rules, addr, addr_use);
+            Box::pin(async move { allowed })
+        });
+    }
     Ok(())
 }
 
+/// Decide whether a WASI socket operation is covered by the plugin's `net=` rules.
+///
+/// Binding a UDP socket to an ephemeral port on the unspecified address is
+/// always allowed: clients do this before sending, and every datagram is
+/// still checked against the rules as `UdpOutgoingDatagram`.
+fn socket_allowed(rules: &[NetRule], addr: SocketAddr, addr_use: SocketAddrUse) -> bool {
+    if matches!(addr_use, SocketAddrUse::UdpBind) && addr.ip().is_unspecified() && addr.port() == 0
+    {
+        return true;
+    }
+    rules.iter().any(|rule| rule.allows(&addr))
+}
+
 /// Canonicalize a filesystem path for plugin preopening.
 ///
 /// Resolves symlinks and `..` components so the WASI sandbox operates on the
@@ -0,0 +1,50 @@
+// WIT names of the server hooks, e.g. `ip-change` or `pre-login`.
+//
+// This is the only copy of the table: `plugin-sdk` `include!`s this file
+// for its guest-side `hook_name`, so it must only refer to `ServerHook` and
+// `ServerPreHook` through `super` (no `//!` docs, no crate paths).
+
+use super::{ServerHook, ServerPreHook};
+
+macro_rules! hook_names {
+    ($ty:ident, $name_fn:ident, $parse_fn:ident { $($variant:ident = $wit:literal,)* }) => {
+        /// WIT name of a hook.
+        pub(crate) fn $name_fn(hook: &$ty) -> &'static str {
+            match hook {
+                $($ty::$variant => $wit,)*
+            }
+        }
+
+        /// Parse a hook from its WIT name.
+        pub(crate) fn $parse_fn(name: &str) -> Option<$ty> {
+            match name {
+                $($wit => Some($ty::$variant),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+hook_names!(ServerHook, server_hook_name, parse_server_hook {
+    IpChange = "ip-change",
+    UserRegistered = "user-registered",
+    UserActivated = "user-activated",
+    LoginSucceeded = "login-succeeded",
+    LoginFailed = "login-failed",
+    Logout = "logout",
+    TokenRevoked = "token-revoked",
+    UserDeleted = "user-deleted",
+    PostCreated = "post-created",
+    PostUpdated = "post-updated",
+    PostMoved = "post-moved",
+    PostDeleted = "post-deleted",
+    AttachmentUploaded = "attachment-uploaded",
+    AttachmentDeleted = "attachment-deleted",
+});
+
+hook_names!(ServerPreHook, pre_hook_name, parse_pre_hook {
+    PreLogin = "pre-login",
+    PreRegister = "pre-register",
+    PreUpload = "pre-upload",
+    PreTokenRefresh = "pre-token-refresh",
+});
@@ -0,0 +1,179 @@
+//! Host implementation of the plugin `http` import.
+//!
+//! Plugins granted `http=<host-pattern>` can make HTTP requests through the
+//! host instead of getting raw sockets via `net`. Every request is checked
+//! against the plugin's allowlist before it is sent, redirects are never
+//! followed (a redirect could point at a host outside the allowlist), and the
+//! response body is read incrementally so an oversized response is rejected
+//! without buffering all of it.
+
+use std::time::Duration;
+
+use super::PluginError;
+use super::crowchiper::plugin::http::{self, Request, Response};
+use super::permissions::PluginPermission;
+use super::state::PluginState;
+
+/// Maximum response body size returned to a plugin (1 MiB).
+const MAX_RESPONSE_BYTES: usize = 1024 * 1024;
+
+/// HTTP client and allowlist for a single plugin instance.
+pub(crate) struct PluginHttp {
+    client: reqwest::Client,
+    /// Lowercased host patterns from `http=` permissions.
+    allowed_hosts: Vec<String>,
+}
+
+impl PluginHttp {
+    /// Build the client from the plugin's `http=` permissions.
+    ///
+    /// Returns `None` if no hosts are allowed, so plugins without the
+    /// permission don't carry an idle client around.
+    pub(crate) fn new(
+        permissions: &[PluginPermission],
+        timeout: Duration,
+    ) -> Result<Option<Self>, PluginError> {
+        let allowed_hosts: Vec<String> = permissions
+            .iter()
+            .filter_map(|perm| match perm {
+                PluginPermission::Http(pattern) => Some(pattern.clone()),
+                _ => None,
+            })
+            .collect();
+        if allowed_hosts.is_empty() {
+            return Ok(None);
+        }
+
+        let client = reqwest::Client::builder()
+            .timeout(timeout)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| PluginError::Load(format!("failed to create HTTP client: {e}")))?;
+
+        Ok(Some(Self {
+            client,
+            allowed_hosts,
+        }))
+    }
+
+    fn is_allowed(&self, host: &str) -> bool {
+        self.allowed_hosts
+            .iter()
+            .any(|pattern| host_matches(pattern, host))
+    }
+
+    async fn send(&self, req: Request) -> Result<Response, String> {
+        let url = url::Url::parse(&req.url).map_err(|e| format!("invalid url: {e}"))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(format!("unsupported url scheme '{}'", url.scheme()));
+        }
+        let host = url.host_str().ok_or("url has no host")?;
+        if !self.is_allowed(host) {
+            return Err(format!(
+                "host '{host}' is not in the plugin's http allowlist"
+            ));
+        }
+
+        let method = reqwest::Method::from_bytes(req.method.as_bytes())
+            .map_err(|_| format!("invalid method '{}'", req.method))?;
+
+        let mut builder = self.client.request(method, url);
+        for (name, value) in req.headers {
+            builder = builder.header(name, value);
+        }
+
+        let mut response = builder
+            .body(req.body)
+            .send()
+            .await
+            .map_err(|e| format!("request failed: {e}"))?;
+
+        if response
+            .content_length()
+            .is_some_and(|len| len > MAX_RESPONSE_BYTES as u64)
+        {
+            return Err(format!("response body exceeds {MAX_RESPONSE_BYTES} bytes"));
+        }
+
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| {
+                let value = value.to_str().ok()?;
+                Some((name.to_string(), value.to_string()))
+            })
+            .collect();
+
+        let mut body = Vec::new();
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| format!("failed to read response: {e}"))?
+        {
+            if body.len() + chunk.len() > MAX_RESPONSE_BYTES {
+                return Err(format!("response body exceeds {MAX_RESPONSE_BYTES} bytes"));
+            }
+            body.extend_from_slice(&chunk);
+        }
+
+        Ok(Response {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+/// Check a host against an allowlist pattern.
+///
+/// `*.example.com` matches any subdomain of `example.com` but not the bare
+/// domain. Other patterns must match exactly. Both sides are compared
+/// case-insensitively and a trailing root dot on the host is ignored.
+fn host_matches(pattern: &str, host: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host
+            .strip_suffix(domain)
+            .and_then(|sub| sub.strip_suffix('.'))
+            .is_some_and(|sub| !sub.is_empty()),
+        None => host == pattern,
+    }
+}
+
+/// Implements the host-provided `http` import.
+impl http::Host for PluginState {
+    async fn send(&mut self, req: Request) -> Result<Response, String> {
+        let Some(http) = self.http.as_ref() else {
+            return Err("http is not permitted (grant http=<host>)".to_string());
+        };
+        let result = http.send(req).await;
+        if let Err(e) = &result {
+            tracing::debug!(plugin = %self.plugin_name, error = %e, "Plugin HTTP request failed");
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::host_matches;
+
+    #[test]
+    fn exact_host() {
+        assert!(host_matches("hooks.example.com", "hooks.example.com"));
+        assert!(host_matches("hooks.example.com", "Hooks.Example.COM."));
+        assert!(!host_matches("hooks.example.com", "example.com"));
+        assert!(!host_matches("hooks.example.com", "evil.hooks.example.com"));
+        assert!(!host_matches("example.com", "evilexample.com"));
+    }
+
+    #[test]
+    fn wildcard_matches_subdomains_only() {
+        assert!(host_matches("*.example.com", "a.example.com"));
+        assert!(host_matches("*.example.com", "a.b.example.com"));
+        assert!(!host_matches("*.example.com", "example.com"));
+        assert!(!host_matches("*.example.com", "badexample.com"));
+        assert!(!host_matches("*.example.com", ".example.com"));
+    }
+}
@@ -1,37 +1,257 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use super::helpers::hook_target;
+use tokio::sync::{Mutex, watch};
+
+use super::engine::LoadOptions;
+use super::helpers::{hook_target, sanitize_plugin_output};
+use super::permissions::PluginSpec;
 use super::runtime::PluginRuntime;
-use super::{Hook, HookEvent};
+use super::{Hook, HookEvent, PluginError, ServerHook, ServerPreHook};
+use crate::db::Database;
 
-/// Manages all loaded plugins and dispatches hook events.
+/// Reason returned when a fail-closed plugin could not answer a pre-hook.
+/// Deliberately generic so plugin failures aren't leaked to clients.
+const FAIL_CLOSED_REASON: &str = "Request denied by server policy";
+
+/// An immutable snapshot of the loaded plugins.
 ///
 /// Maintains a pre-built index from hook to registered plugins so
 /// `fire_hook` and `has_hook` are O(1) lookups instead of iterating all plugins.
-pub struct PluginManager {
-    plugins: Vec<PluginRuntime>,
-    /// Maps each hook to the indices of plugins registered for it.
+pub(crate) struct PluginSet {
+    plugins: Vec<Arc<PluginRuntime>>,
+    /// Names of plugins that stay loaded but receive no hooks or requests.
+    disabled: HashSet<String>,
+    /// Maps each hook to the indices of enabled plugins registered for it.
     hook_index: HashMap<Hook, Vec<usize>>,
 }
 
-impl PluginManager {
-    pub fn new(plugins: Vec<PluginRuntime>) -> Self {
+impl PluginSet {
+    fn new(plugins: Vec<Arc<PluginRuntime>>, disabled: HashSet<String>) -> Self {
         let mut hook_index: HashMap<Hook, Vec<usize>> = HashMap::new();
         for (i, plugin) in plugins.iter().enumerate() {
+            if disabled.contains(plugin.name()) {
+                continue;
+            }
             for hook in plugin.hooks() {
                 hook_index.entry(hook.clone()).or_default().push(i);
             }
         }
         Self {
             plugins,
+            disabled,
             hook_index,
         }
     }
 
-    /// Returns true if any loaded plugin is registered for the given hook.
+    fn find(&self, name: &str) -> Option<&Arc<PluginRuntime>> {
+        self.plugins.iter().find(|plugin| plugin.name() == name)
+    }
+
+    /// All loaded plugins, enabled or not, in load order.
+    pub(crate) fn plugins(&self) -> &[Arc<PluginRuntime>] {
+        &self.plugins
+    }
+
+    /// Returns true if this exact runtime (not just one with the same name)
+    /// is part of the set.
+    pub(crate) fn contains(&self, plugin: &Arc<PluginRuntime>) -> bool {
+        self.plugins.iter().any(|p| Arc::ptr_eq(p, plugin))
+    }
+
+    /// Returns true if this exact runtime is part of the set and enabled.
+    pub(crate) fn is_enabled(&self, plugin: &Arc<PluginRuntime>) -> bool {
+        self.contains(plugin) && !self.disabled.contains(plugin.name())
+    }
+}
+
+/// A loaded plugin and whether it currently receives hooks and requests.
+pub struct PluginStatus {
+    pub plugin: Arc<PluginRuntime>,
+    pub enabled: bool,
+}
+
+/// Manages all loaded plugins and dispatches hook events.
+///
+/// The plugin set can be changed at runtime (enable, disable, reload, load).
+/// Every change builds a new [`PluginSet`] and swaps it in whole. Hook calls
+/// and HTTP requests run against the snapshot they started with, so a change
+/// never interrupts a call in flight: a replaced plugin is dropped once its
+/// last call finishes.
+pub struct PluginManager {
+    /// The current plugin set. A watch channel so the scheduler is told
+    /// about changes; readers only hold the lock long enough to clone the `Arc`.
+    current: watch::Sender<Arc<PluginSet>>,
+    /// Serializes changes so concurrent admin requests don't overwrite each other.
+    changes: Mutex<()>,
+    /// Directory that new plugins may be loaded from at runtime.
+    plugin_dir: Option<PathBuf>,
+    /// How plugins loaded at runtime are checked.
+    load_options: LoadOptions,
+}
+
+impl PluginManager {
+    /// Manage `plugins`. Plugins are looked up by their declared name, so two
+    /// plugins declaring the same name are rejected.
+    pub fn new(plugins: Vec<PluginRuntime>) -> Result<Self, PluginError> {
+        let mut names = HashSet::new();
+        for plugin in &plugins {
+            if !names.insert(plugin.name()) {
+                return Err(PluginError::AlreadyLoaded(plugin.name().to_string()));
+            }
+        }
+        let plugins = plugins.into_iter().map(Arc::new).collect();
+        Ok(Self {
+            current: watch::Sender::new(Arc::new(PluginSet::new(plugins, HashSet::new()))),
+            changes: Mutex::new(()),
+            plugin_dir: None,
+            load_options: LoadOptions::default(),
+        })
+    }
+
+    /// Allow [`load`](Self::load) to add plugins from `dir` at runtime.
+    pub fn with_plugin_dir(mut self, dir: PathBuf) -> Self {
+        self.plugin_dir = Some(dir);
+        self
+    }
+
+    /// Check plugins added by [`load`](Self::load) according to `options`.
+    pub fn with_load_options(mut self, options: LoadOptions) -> Self {
+        self.load_options = options;
+        self
+    }
+
+    pub fn plugin_dir(&self) -> Option<&Path> {
+        self.plugin_dir.as_deref()
+    }
+
+    /// The current plugin set.
+    fn snapshot(&self) -> Arc<PluginSet> {
+        self.current.borrow().clone()
+    }
+
+    /// Watch for changes to the plugin set.
+    pub(crate) fn subscribe(&self) -> watch::Receiver<Arc<PluginSet>> {
+        self.current.subscribe()
+    }
+
+    /// Look up an enabled plugin by its declared name.
+    pub fn get(&self, name: &str) -> Option<Arc<PluginRuntime>> {
+        let set = self.snapshot();
+        set.find(name)
+            .filter(|plugin| !set.disabled.contains(plugin.name()))
+            .cloned()
+    }
+
+    /// All loaded plugins, in load order.
+    pub fn list(&self) -> Vec<PluginStatus> {
+        let set = self.snapshot();
+        set.plugins
+            .iter()
+            .map(|plugin| PluginStatus {
+                plugin: plugin.clone(),
+                enabled: !set.disabled.contains(plugin.name()),
+            })
+            .collect()
+    }
+
+    /// Returns true if any enabled plugin is registered for the given hook.
     pub fn has_hook(&self, hook: &Hook) -> bool {
-        self.hook_index.contains_key(hook)
+        self.current.borrow().hook_index.contains_key(hook)
+    }
+
+    /// Stop or resume delivering hooks and HTTP requests to a plugin.
+    /// The plugin stays loaded, so enabling it again keeps its instance.
+    /// Enabling also closes its circuit breaker.
+    pub async fn set_enabled(&self, name: &str, enabled: bool) -> Result<(), PluginError> {
+        let _changes = self.changes.lock().await;
+        let set = self.snapshot();
+        let plugin = set
+            .find(name)
+            .ok_or_else(|| PluginError::NotFound(name.to_string()))?;
+        let mut disabled = set.disabled.clone();
+        if enabled {
+            plugin.stats().reset_breaker();
+            disabled.remove(name);
+        } else {
+            disabled.insert(name.to_string());
+        }
+        self.current
+            .send_replace(Arc::new(PluginSet::new(set.plugins.clone(), disabled)));
+        Ok(())
+    }
+
+    /// Load the plugin's `.wasm` file again and swap the new copy in.
+    ///
+    /// The new copy keeps the plugin's permissions, config and enabled state.
+    /// If loading fails, or the file now declares a different name, the
+    /// running plugin is kept.
+    pub async fn reload(&self, name: &str) -> Result<Arc<PluginRuntime>, PluginError> {
+        let _changes = self.changes.lock().await;
+        let set = self.snapshot();
+        let index = set
+            .plugins
+            .iter()
+            .position(|plugin| plugin.name() == name)
+            .ok_or_else(|| PluginError::NotFound(name.to_string()))?;
+
+        let plugin = Arc::new(set.plugins[index].load_again().await?);
+        if plugin.name() != name {
+            return Err(PluginError::InvalidConfig(format!(
+                "reloaded plugin declares name '{}', expected '{name}'",
+                plugin.name()
+            )));
+        }
+
+        let mut plugins = set.plugins.clone();
+        plugins[index] = plugin.clone();
+        self.current
+            .send_replace(Arc::new(PluginSet::new(plugins, set.disabled.clone())));
+        Ok(plugin)
+    }
+
+    /// Load a new plugin from the plugin directory and enable it.
+    ///
+    /// `spec.path` is resolved relative to the directory and must stay inside
+    /// it. Keys it stores go to `db` under the plugin's declared name.
+    pub async fn load(
+        &self,
+        spec: &PluginSpec,
+        db: Database,
+    ) -> Result<Arc<PluginRuntime>, PluginError> {
+        let dir = self
+            .plugin_dir
+            .as_deref()
+            .ok_or_else(|| PluginError::Load("no plugin directory configured".into()))?;
+        let path = resolve_in_dir(dir, &spec.path)?;
+
+        let _changes = self.changes.lock().await;
+        let plugin = PluginRuntime::load_with_limits(
+            &path,
+            &spec.permissions,
+            &spec.config,
+            spec.hook_timeout,
+            spec.limits,
+            &self.load_options,
+        )
+        .await?
+        .with_fail_closed(&spec.fail_closed)
+        .with_breaker(spec.breaker)
+        .with_kv(db, spec.kv_quota);
+
+        let set = self.snapshot();
+        if set.find(plugin.name()).is_some() {
+            return Err(PluginError::AlreadyLoaded(plugin.name().to_string()));
+        }
+
+        let plugin = Arc::new(plugin);
+        let mut plugins = set.plugins.clone();
+        plugins.push(plugin.clone());
+        self.current
+            .send_replace(Arc::new(PluginSet::new(plugins, set.disabled.clone())));
+        Ok(plugin)
     }
 
     /// Fire a hook asynchronously across all plugins registered for it.
@@ -39,9 +259,11 @@ impl PluginManager {
     /// Different plugins run concurrently via `tokio::join!`. A single plugin
     /// serializes its hook calls via `tokio::sync::Mutex` (WASM is single-threaded).
     /// Errors from individual plugins are logged but do not stop other plugins
-    /// from receiving the event.
+    /// from receiving the event. Plugins skipped by their circuit breaker are
+    /// only logged at debug level, since the breaker opening was already logged.
     pub async fn fire_hook(&self, hook: Hook, values: Vec<(String, String)>) {
-        let indices = match self.hook_index.get(&hook) {
+        let set = self.snapshot();
+        let indices = match set.hook_index.get(&hook) {
             Some(indices) => indices,
             None => return,
         };
@@ -61,17 +283,28 @@ impl PluginManager {
         let futures: Vec<_> = indices
             .iter()
             .map(|&i| {
-                let plugin = &self.plugins[i];
+                let plugin = &set.plugins[i];
                 let event = &event;
                 let hook = &hook;
                 async move {
-                    if let Err(e) = plugin.call_hook(event).await {
-                        tracing::warn!(
-                            plugin = %plugin.name(),
-                            hook = ?hook,
-                            error = %e,
-                            "Plugin hook failed"
-                        );
+                    match plugin.call_hook(event).await {
+                        Ok(()) => {}
+                        Err(e @ PluginError::Unavailable(_)) => {
+                            tracing::debug!(
+                                plugin = %plugin.name(),
+                                hook = ?hook,
+                                error = %e,
+                                "Plugin hook skipped"
+                            );
+                        }
+                        Err(e) => {
+                            tracing::warn!(
+                                plugin = %plugin.name(),
+                                hook = ?hook,
+                                error = %e,
+                                "Plugin hook failed"
+                            );
+                        }
                     }
                 }
             })
@@ -79,4 +312,158 @@ impl PluginManager {
 
         futures::future::join_all(futures).await;
     }
+
+    /// Run a blocking pre-hook and wait for every plugin registered for it.
+    ///
+    /// Returns `Err(reason)` if any plugin denies the action by returning an
+    /// error from `on-hook`. When several plugins deny, the first in load order
+    /// wins. A plugin that times out or traps is logged and allows the action,
+    /// unless it was loaded with `fail-closed` for this hook.
+    ///
+    /// The same applies while the plugin's circuit breaker is open: a
+    /// fail-closed pre-hook then denies every action until the cool-down
+    /// ends, e.g. locking everyone out for `pre-login`. Plugins guarding
+    /// such hooks can use `breaker=off` to be asked on every call instead.
+    pub async fn check_pre_hook(
+        &self,
+        pre_hook: ServerPreHook,
+        values: Vec<(String, String)>,
+    ) -> Result<(), String> {
+        let hook = Hook::ServerPre(pre_hook);
+        let set = self.snapshot();
+        let Some(indices) = set.hook_index.get(&hook) else {
+            return Ok(());
+        };
+
+        let event = HookEvent {
+            hook: hook.clone(),
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            target: hook_target(&hook),
+            values,
+        };
+
+        let futures: Vec<_> = indices
+            .iter()
+            .map(|&i| {
+                let plugin = &set.plugins[i];
+                let event = &event;
+                async move {
+                    match plugin.invoke_hook(event).await {
+                        Ok(Ok(())) => Ok(()),
+                        Ok(Err(reason)) => {
+                            let reason = sanitize_plugin_output(&reason);
+                            tracing::info!(
+                                plugin = %plugin.name(),
+                                hook = ?pre_hook,
+                                reason = %reason,
+                                "Plugin denied action"
+                            );
+                            Err(reason)
+                        }
+                        // Skipped by the circuit breaker: already logged when
+                        // it opened, so don't warn on every request.
+                        Err(e @ PluginError::Unavailable(_)) => {
+                            let fail_closed = plugin.fails_closed(&pre_hook);
+                            tracing::debug!(
+                                plugin = %plugin.name(),
+                                hook = ?pre_hook,
+                                error = %e,
+                                fail_closed,
+                                "Plugin pre-hook skipped"
+                            );
+                            if fail_closed {
+                                Err(FAIL_CLOSED_REASON.to_string())
+                            } else {
+                                Ok(())
+                            }
+                        }
+                        Err(e) if plugin.fails_closed(&pre_hook) => {
+                            tracing::warn!(
+                                plugin = %plugin.name(),
+                                hook = ?pre_hook,
+                                error = %e,
+                                "Plugin pre-hook failed, denying (fail-closed)"
+                            );
+                            Err(FAIL_CLOSED_REASON.to_string())
+                        }
+                        Err(e) => {
+                            tracing::warn!(
+                                plugin = %plugin.name(),
+                                hook = ?pre_hook,
+                                error = %e,
+                                "Plugin pre-hook failed, allowing (fail-open)"
+                            );
+                            Ok(())
+                        }
+                    }
+                }
+            })
+            .collect();
+
+        futures::future::join_all(futures)
+            .await
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Fire a server hook in the background if any plugin is registered for it.
+///
+/// `values` is only built when a plugin is listening, so handlers can call this
+/// unconditionally without allocating on the common (no plugin) path. The
+/// request never waits for plugins to finish.
+pub fn spawn_server_hook(
+    plugin_manager: Option<&Arc<PluginManager>>,
+    hook: ServerHook,
+    values: impl FnOnce() -> Vec<(String, String)>,
+) {
+    let hook = Hook::Server(hook);
+    let Some(pm) = plugin_manager.filter(|pm| pm.has_hook(&hook)) else {
+        return;
+    };
+    let values = values();
+    let pm = pm.clone();
+    tokio::spawn(async move {
+        pm.fire_hook(hook, values).await;
+    });
+}
+
+/// Run a blocking pre-hook if any plugin is registered for it.
+///
+/// Like [`spawn_server_hook`], `values` is only built when a plugin is listening.
+/// Returns the denial reason if a plugin vetoed the action.
+pub async fn check_server_pre_hook(
+    plugin_manager: Option<&Arc<PluginManager>>,
+    hook: ServerPreHook,
+    values: impl FnOnce() -> Vec<(String, String)>,
+) -> Result<(), String> {
+    match plugin_manager.filter(|pm| pm.has_hook(&Hook::ServerPre(hook))) {
+        Some(pm) => pm.check_pre_hook(hook, values()).await,
+        None => Ok(()),
+    }
+}
+
+/// Resolve `path` against `dir`, rejecting anything that ends up outside it
+/// (absolute paths, `..`, symlinks pointing elsewhere).
+fn resolve_in_dir(dir: &Path, path: &Path) -> Result<PathBuf, PluginError> {
+    let dir = dir.canonicalize().map_err(|e| {
+        PluginError::Load(format!(
+            "failed to read plugin directory {}: {e}",
+            dir.display()
+        ))
+    })?;
+    let resolved = dir
+        .join(path)
+        .canonicalize()
+        .map_err(|e| PluginError::Load(format!("failed to read {}: {e}", path.display())))?;
+    if !resolved.starts_with(&dir) {
+        return Err(PluginError::Load(format!(
+            "{} is outside the plugin directory",
+            path.display()
+        )));
+    }
+    Ok(resolved)
 }
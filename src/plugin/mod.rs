@@ -1,14 +1,33 @@
+mod config_file;
+mod engine;
 mod error;
 mod helpers;
+// The SDK uses the name direction for server hooks; the host only parses them.
+#[allow(dead_code)]
+mod hook_names;
+mod http_client;
 mod manager;
 mod permissions;
 mod runtime;
+mod scheduler;
+mod signature;
 mod state;
+mod stats;
 
+pub use config_file::load_plugin_config;
+pub use engine::LoadOptions;
 pub use error::PluginError;
-pub use manager::PluginManager;
-pub use permissions::{DEFAULT_HOOK_TIMEOUT, PluginPermission, PluginSpec, parse_plugin_spec};
+pub(crate) use helpers::hook_target;
+pub use manager::{PluginManager, PluginStatus, check_server_pre_hook, spawn_server_hook};
+pub use permissions::{
+    CircuitBreaker, DEFAULT_BREAKER_COOLDOWN, DEFAULT_BREAKER_FAILURES, DEFAULT_FUEL,
+    DEFAULT_HOOK_TIMEOUT, DEFAULT_KV_QUOTA, DEFAULT_MEMORY, DEFAULT_STACK, NetRule, PluginLimits,
+    PluginPermission, PluginSpec, parse_plugin_spec,
+};
 pub use runtime::PluginRuntime;
+pub use scheduler::spawn_plugin_scheduler;
+pub use signature::{SignaturePolicy, TrustedKey, signature_path};
+pub use stats::{CallStats, LATENCY_BUCKETS_MS, LatencyHistogram, PluginStats};
 
 wasmtime::component::bindgen!({
     world: "plugin",
@@ -16,6 +35,13 @@ wasmtime::component::bindgen!({
     with: {},
     additional_derives: [Clone, PartialEq, Eq, Hash],
     require_store_data_send: true,
+    imports: {
+        "crowchiper:plugin/kv.get": async,
+        "crowchiper:plugin/kv.set": async,
+        "crowchiper:plugin/kv.delete": async,
+        "crowchiper:plugin/kv.list-prefix": async,
+        "crowchiper:plugin/http.send": async,
+    },
     exports: {
         default: async,
     },
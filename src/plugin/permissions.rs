@@ -1,7 +1,12 @@
 use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::ops::RangeInclusive;
 use std::path::PathBuf;
 use std::time::Duration;
 
+use super::ServerPreHook;
+use super::hook_names::{parse_pre_hook, pre_hook_name};
+
 /// A single permission that can be granted to a plugin.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum PluginPermission {
@@ -11,6 +16,13 @@ pub enum PluginPermission {
     FsWrite(PathBuf),
     /// TCP and UDP network access.
     Net,
+    /// TCP and UDP access limited to an address block and port range.
+    NetAddr(NetRule),
+    /// DNS name resolution via WASI `ip-name-lookup`.
+    NetDns,
+    /// Outbound HTTP through the host `http` import, limited to hosts matching
+    /// the pattern: an exact host name or `*.domain` for any subdomain.
+    Http(String),
     /// Access to a specific host environment variable.
     Env(String),
 }
@@ -21,17 +33,221 @@ impl fmt::Display for PluginPermission {
             PluginPermission::FsRead(p) => write!(f, "fs-read={}", p.display()),
             PluginPermission::FsWrite(p) => write!(f, "fs-write={}", p.display()),
             PluginPermission::Net => write!(f, "net"),
+            PluginPermission::NetAddr(rule) => write!(f, "net={rule}"),
+            PluginPermission::NetDns => write!(f, "net-dns"),
+            PluginPermission::Http(pattern) => write!(f, "http={pattern}"),
             PluginPermission::Env(var) => write!(f, "env-{var}"),
         }
     }
 }
 
+/// A socket allowlist entry: an IP network in CIDR form plus a port range.
+///
+/// Written as `<cidr>:<ports>` where `<cidr>` is `10.0.0.0/8`, a single
+/// address like `127.0.0.1`, or an IPv6 network in brackets (`[fd00::/8]`),
+/// and `<ports>` is `514`, `1000-2000` or `*` for any port.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetRule {
+    network: IpAddr,
+    prefix_len: u8,
+    ports: RangeInclusive<u16>,
+}
+
+impl NetRule {
+    /// Returns true if `addr` lies in this rule's network and port range.
+    /// IPv4-mapped IPv6 addresses are matched as IPv4.
+    pub fn allows(&self, addr: &SocketAddr) -> bool {
+        let ip = match addr.ip() {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(IpAddr::V6(v6), IpAddr::V4),
+            v4 => v4,
+        };
+        self.ports.contains(&addr.port()) && mask(ip, self.prefix_len) == self.network
+    }
+}
+
+impl fmt::Display for NetRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let full_len = if self.network.is_ipv4() { 32 } else { 128 };
+        let cidr = if self.prefix_len == full_len {
+            self.network.to_string()
+        } else {
+            format!("{}/{}", self.network, self.prefix_len)
+        };
+        if self.network.is_ipv6() {
+            write!(f, "[{cidr}]:")?;
+        } else {
+            write!(f, "{cidr}:")?;
+        }
+        match (*self.ports.start(), *self.ports.end()) {
+            (0, u16::MAX) => write!(f, "*"),
+            (start, end) if start == end => write!(f, "{start}"),
+            (start, end) => write!(f, "{start}-{end}"),
+        }
+    }
+}
+
+/// Zero all bits of `ip` after the first `prefix_len`.
+fn mask(ip: IpAddr, prefix_len: u8) -> IpAddr {
+    match ip {
+        IpAddr::V4(v4) => {
+            // checked_shl yields None for a /0 prefix, which masks everything
+            let bits = u32::MAX
+                .checked_shl(32 - u32::from(prefix_len))
+                .unwrap_or(0);
+            IpAddr::V4((u32::from(v4) & bits).into())
+        }
+        IpAddr::V6(v6) => {
+            let bits = u128::MAX
+                .checked_shl(128 - u32::from(prefix_len))
+                .unwrap_or(0);
+            IpAddr::V6((u128::from(v6) & bits).into())
+        }
+    }
+}
+
 /// Default wall-clock timeout for plugin hook calls.
 pub const DEFAULT_HOOK_TIMEOUT: Duration = Duration::from_secs(5);
 
 /// Minimum allowed hook timeout (10ms).
 const MIN_HOOK_TIMEOUT: Duration = Duration::from_millis(10);
 
+/// Default key-value storage quota per plugin (1 MiB).
+pub const DEFAULT_KV_QUOTA: u64 = 1024 * 1024;
+
+/// Default fuel (roughly, WASM instructions) per call.
+pub const DEFAULT_FUEL: u64 = 10_000_000;
+
+/// Largest fuel budget a plugin can be given (100x the default).
+const MAX_FUEL: u64 = 1_000_000_000;
+
+/// Default cap on each linear memory of a plugin (10 MiB).
+pub const DEFAULT_MEMORY: u64 = 10 * 1024 * 1024;
+
+/// Largest memory cap a plugin can be given (512 MiB).
+const MAX_MEMORY: u64 = 512 * 1024 * 1024;
+
+/// Default WASM stack size of a plugin (512 KiB).
+pub const DEFAULT_STACK: u64 = 512 * 1024;
+
+/// Largest WASM stack a plugin can be given (8 MiB).
+const MAX_STACK: u64 = 8 * 1024 * 1024;
+
+/// Consecutive failures after which a plugin's circuit breaker opens.
+pub const DEFAULT_BREAKER_FAILURES: u32 = 5;
+
+/// How long an open circuit breaker skips a plugin.
+pub const DEFAULT_BREAKER_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// When to stop calling a plugin that keeps failing.
+///
+/// After `failures` consecutive calls that time out, trap or fail to reload,
+/// the plugin is skipped for `cooldown`. The first call after that is a trial:
+/// success closes the breaker, another failure opens it again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitBreaker {
+    /// Consecutive failures that open the breaker; 0 never opens it.
+    pub failures: u32,
+    pub cooldown: Duration,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self {
+            failures: DEFAULT_BREAKER_FAILURES,
+            cooldown: DEFAULT_BREAKER_COOLDOWN,
+        }
+    }
+}
+
+impl fmt::Display for CircuitBreaker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.failures == 0 {
+            return write!(f, "off");
+        }
+        write!(f, "{}/", self.failures)?;
+        fmt_duration(f, self.cooldown)
+    }
+}
+
+/// Parse a breaker setting like `"5/60"` (failures/cooldown seconds),
+/// `"3/500ms"` or `"off"`.
+pub(super) fn parse_breaker(value: &str) -> Result<CircuitBreaker, String> {
+    if value == "off" {
+        return Ok(CircuitBreaker {
+            failures: 0,
+            ..CircuitBreaker::default()
+        });
+    }
+    let invalid = || {
+        format!(
+            "invalid breaker '{value}': expected <failures>/<cooldown> or off (e.g., breaker=5/60)"
+        )
+    };
+    let (failures, cooldown) = value.split_once('/').ok_or_else(invalid)?;
+    let failures: u32 = failures.parse().map_err(|_| invalid())?;
+    if failures == 0 {
+        return Err(invalid());
+    }
+    let cooldown = parse_timeout(cooldown).map_err(|_| invalid())?;
+    Ok(CircuitBreaker { failures, cooldown })
+}
+
+/// Write a duration as whole seconds, or milliseconds with an `ms` suffix.
+fn fmt_duration(f: &mut fmt::Formatter<'_>, duration: Duration) -> fmt::Result {
+    let millis = duration.as_millis();
+    if millis % 1000 == 0 {
+        write!(f, "{}", millis / 1000)
+    } else {
+        write!(f, "{millis}ms")
+    }
+}
+
+/// Per-plugin CPU, memory and stack budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PluginLimits {
+    /// Fuel available to each `config()`, `on-hook()` or `http-handler()` call.
+    pub fuel: u64,
+    /// Maximum size in bytes of each linear memory.
+    pub memory: u64,
+    /// Maximum WASM stack size in bytes.
+    pub stack: u64,
+}
+
+impl Default for PluginLimits {
+    fn default() -> Self {
+        Self {
+            fuel: DEFAULT_FUEL,
+            memory: DEFAULT_MEMORY,
+            stack: DEFAULT_STACK,
+        }
+    }
+}
+
+impl PluginLimits {
+    /// Check that every limit is non-zero and below its upper bound.
+    pub(super) fn validate(&self) -> Result<(), String> {
+        if self.fuel == 0 || self.fuel > MAX_FUEL {
+            return Err(format!(
+                "fuel must be between 1 and {MAX_FUEL}, got {}",
+                self.fuel
+            ));
+        }
+        if self.memory == 0 || self.memory > MAX_MEMORY {
+            return Err(format!(
+                "memory must be between 1 and {MAX_MEMORY} bytes, got {}",
+                self.memory
+            ));
+        }
+        if self.stack == 0 || self.stack > MAX_STACK {
+            return Err(format!(
+                "stack must be between 1 and {MAX_STACK} bytes, got {}",
+                self.stack
+            ));
+        }
+        Ok(())
+    }
+}
+
 /// A plugin path bundled with its granted permissions and config variables.
 #[derive(Debug, Clone)]
 pub struct PluginSpec {
@@ -44,13 +260,45 @@ pub struct PluginSpec {
     /// Parsed from `timeout=<N>` (seconds) or `timeout=<N>ms` (milliseconds).
     /// Defaults to 5s. Minimum 10ms.
     pub hook_timeout: Duration,
+    /// Pre-hooks that deny the action when this plugin times out or traps.
+    /// Parsed from `fail-closed=<hook>` entries. Pre-hooks not listed fail open.
+    pub fail_closed: Vec<ServerPreHook>,
+    /// Maximum bytes of keys plus values the plugin may keep in the kv store.
+    /// Parsed from `kv-quota=<N>`, `<N>k` or `<N>m`. Defaults to 1 MiB.
+    pub kv_quota: u64,
+    /// Fuel, memory and stack budget. Parsed from `fuel=<N>`, `memory=<size>`
+    /// and `stack=<size>`. Defaults to 10M fuel, 10 MiB memory, 512 KiB stack.
+    pub limits: PluginLimits,
+    /// When to stop calling the plugin after repeated failures. Parsed from
+    /// `breaker=<failures>/<cooldown>` or `breaker=off`. Defaults to 5/60s.
+    pub breaker: CircuitBreaker,
+    /// Whether the plugin receives hooks and requests after startup. Disabled
+    /// plugins are still loaded and can be enabled via the admin API. Only
+    /// settable from a `--plugin-config` file; `--plugin` specs are enabled.
+    pub enabled: bool,
 }
 
 impl fmt::Display for PluginSpec {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.path.display())?;
         let has_timeout = self.hook_timeout != DEFAULT_HOOK_TIMEOUT;
-        if !self.permissions.is_empty() || !self.config.is_empty() || has_timeout {
+        let has_kv_quota = self.kv_quota != DEFAULT_KV_QUOTA;
+        let defaults = PluginLimits::default();
+        let limits = [
+            ("fuel", self.limits.fuel, defaults.fuel),
+            ("memory", self.limits.memory, defaults.memory),
+            ("stack", self.limits.stack, defaults.stack),
+        ];
+        let has_limits = limits.iter().any(|(_, value, default)| value != default);
+        let has_breaker = self.breaker != CircuitBreaker::default();
+        if !self.permissions.is_empty()
+            || !self.config.is_empty()
+            || has_timeout
+            || !self.fail_closed.is_empty()
+            || has_kv_quota
+            || has_limits
+            || has_breaker
+        {
             write!(f, ":")?;
             let mut need_comma = false;
             for perm in &self.permissions {
@@ -71,12 +319,39 @@ impl fmt::Display for PluginSpec {
                 if need_comma {
                     write!(f, ",")?;
                 }
-                let millis = self.hook_timeout.as_millis();
-                if millis % 1000 == 0 {
-                    write!(f, "timeout={}", millis / 1000)?;
-                } else {
-                    write!(f, "timeout={millis}ms")?;
+                write!(f, "timeout=")?;
+                fmt_duration(f, self.hook_timeout)?;
+                need_comma = true;
+            }
+            for hook in &self.fail_closed {
+                if need_comma {
+                    write!(f, ",")?;
                 }
+                write!(f, "fail-closed={}", pre_hook_name(hook))?;
+                need_comma = true;
+            }
+            if has_kv_quota {
+                if need_comma {
+                    write!(f, ",")?;
+                }
+                write!(f, "kv-quota={}", self.kv_quota)?;
+                need_comma = true;
+            }
+            for (name, value, default) in limits {
+                if value == default {
+                    continue;
+                }
+                if need_comma {
+                    write!(f, ",")?;
+                }
+                write!(f, "{name}={value}")?;
+                need_comma = true;
+            }
+            if has_breaker {
+                if need_comma {
+                    write!(f, ",")?;
+                }
+                write!(f, "breaker={}", self.breaker)?;
             }
         }
         Ok(())
@@ -97,6 +372,10 @@ pub fn parse_plugin_spec(value: &str) -> Result<PluginSpec, String> {
     let mut permissions = Vec::new();
     let mut config = Vec::new();
     let mut hook_timeout = DEFAULT_HOOK_TIMEOUT;
+    let mut fail_closed = Vec::new();
+    let mut kv_quota = DEFAULT_KV_QUOTA;
+    let mut limits = PluginLimits::default();
+    let mut breaker = CircuitBreaker::default();
     if let Some(perms) = perms_str {
         for entry in perms.split(',') {
             let entry = entry.trim();
@@ -115,17 +394,45 @@ pub fn parse_plugin_spec(value: &str) -> Result<PluginSpec, String> {
                 config.push((key.to_string(), value.to_string()));
             } else if let Some(timeout_val) = entry.strip_prefix("timeout=") {
                 hook_timeout = parse_timeout(timeout_val)?;
+            } else if let Some(hook_name) = entry.strip_prefix("fail-closed=") {
+                let hook = parse_pre_hook(hook_name).ok_or_else(|| {
+                    format!(
+                        "unknown pre-hook '{hook_name}' in fail-closed. Valid: pre-login, pre-register, pre-upload, pre-token-refresh"
+                    )
+                })?;
+                if !fail_closed.contains(&hook) {
+                    fail_closed.push(hook);
+                }
+            } else if let Some(quota_val) = entry.strip_prefix("kv-quota=") {
+                kv_quota = parse_size(quota_val)?;
+            } else if let Some(fuel_val) = entry.strip_prefix("fuel=") {
+                limits.fuel = fuel_val.parse().map_err(|_| {
+                    format!("invalid fuel '{fuel_val}': expected a number (e.g., fuel=50000000)")
+                })?;
+            } else if let Some(memory_val) = entry.strip_prefix("memory=") {
+                limits.memory = parse_size(memory_val)?;
+            } else if let Some(stack_val) = entry.strip_prefix("stack=") {
+                limits.stack = parse_size(stack_val)?;
+            } else if let Some(breaker_val) = entry.strip_prefix("breaker=") {
+                breaker = parse_breaker(breaker_val)?;
             } else {
                 permissions.push(parse_single_permission(entry)?);
             }
         }
     }
 
+    limits.validate()?;
+
     Ok(PluginSpec {
         path: PathBuf::from(path_str),
         permissions,
         config,
         hook_timeout,
+        fail_closed,
+        kv_quota,
+        limits,
+        breaker,
+        enabled: true,
     })
 }
 
@@ -151,7 +458,7 @@ fn validate_fs_path(path: &str, perm_name: &str) -> Result<(), String> {
 /// Parse a timeout value like `"5"` (seconds) or `"500ms"` (milliseconds).
 ///
 /// Returns an error if the value is not a valid number or below the minimum (10ms).
-fn parse_timeout(value: &str) -> Result<Duration, String> {
+pub(super) fn parse_timeout(value: &str) -> Result<Duration, String> {
     let duration = if let Some(ms_str) = value.strip_suffix("ms") {
         let ms: u64 = ms_str.parse().map_err(|_| {
             format!(
@@ -177,9 +484,109 @@ fn parse_timeout(value: &str) -> Result<Duration, String> {
     Ok(duration)
 }
 
-fn parse_single_permission(s: &str) -> Result<PluginPermission, String> {
+/// Parse a size like `"4096"` (bytes), `"64k"` (KiB) or `"2m"` (MiB).
+pub(super) fn parse_size(value: &str) -> Result<u64, String> {
+    let (digits, multiplier) = if let Some(n) = value.strip_suffix('k') {
+        (n, 1024)
+    } else if let Some(n) = value.strip_suffix('m') {
+        (n, 1024 * 1024)
+    } else {
+        (value, 1)
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| {
+            format!("invalid size '{value}': expected a number of bytes (e.g., 4096, 64k or 2m)")
+        })
+}
+
+/// Validate an `http=` host pattern and normalize it to lowercase.
+///
+/// Accepts an exact host (`hooks.example.com`, `127.0.0.1`) or a leading
+/// wildcard label (`*.example.com`). Schemes, ports and paths are rejected so
+/// the pattern can't be mistaken for a URL prefix.
+fn parse_host_pattern(pattern: &str) -> Result<String, String> {
+    let host = pattern.strip_prefix("*.").unwrap_or(pattern);
+    if host.is_empty() {
+        return Err("http requires a host (e.g., http=hooks.example.com)".to_string());
+    }
+    if host.contains(['*', '/', ':', '@', '?', '#']) || host.starts_with('.') {
+        return Err(format!(
+            "invalid http host pattern '{pattern}': expected a host like example.com or *.example.com"
+        ));
+    }
+    Ok(pattern.to_ascii_lowercase())
+}
+
+/// Parse a `net=` value like `10.0.0.0/8:25`, `127.0.0.1:1000-2000` or `[::1]:*`.
+fn parse_net_rule(value: &str) -> Result<NetRule, String> {
+    let usage = || {
+        format!(
+            "invalid net rule '{value}': expected <cidr>:<ports> (e.g., net=10.0.0.0/8:25, net=[::1]:514, net=127.0.0.1:*)"
+        )
+    };
+
+    // IPv6 networks are bracketed so their colons aren't taken as the port separator
+    let (cidr, ports) = match value.strip_prefix('[') {
+        Some(rest) => {
+            let (cidr, after) = rest.split_once(']').ok_or_else(usage)?;
+            (cidr, after.strip_prefix(':').ok_or_else(usage)?)
+        }
+        None => value.rsplit_once(':').ok_or_else(usage)?,
+    };
+
+    let (addr, prefix) = match cidr.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None => (cidr, None),
+    };
+    let ip: IpAddr = addr.parse().map_err(|_| usage())?;
+    if ip.is_ipv6() != value.starts_with('[') {
+        return Err(usage());
+    }
+    let full_len = if ip.is_ipv4() { 32 } else { 128 };
+    let prefix_len = match prefix {
+        Some(p) => p
+            .parse::<u8>()
+            .ok()
+            .filter(|len| *len <= full_len)
+            .ok_or_else(|| format!("invalid prefix length in net rule '{value}'"))?,
+        None => full_len,
+    };
+
+    let ports = match ports {
+        "*" => 0..=u16::MAX,
+        _ => {
+            let (start, end) = ports.split_once('-').unwrap_or((ports, ports));
+            let start: u16 = start.parse().map_err(|_| usage())?;
+            let end: u16 = end.parse().map_err(|_| usage())?;
+            if start > end {
+                return Err(format!("empty port range in net rule '{value}'"));
+            }
+            start..=end
+        }
+    };
+
+    Ok(NetRule {
+        network: mask(ip, prefix_len),
+        prefix_len,
+        ports,
+    })
+}
+
+pub(super) fn parse_single_permission(s: &str) -> Result<PluginPermission, String> {
     match s {
         "net" => Ok(PluginPermission::Net),
+        "net-dns" => Ok(PluginPermission::NetDns),
+        _ if s.starts_with("net=") => {
+            let rule = parse_net_rule(&s["net=".len()..])?;
+            Ok(PluginPermission::NetAddr(rule))
+        }
+        _ if s.starts_with("http=") => {
+            let pattern = parse_host_pattern(&s["http=".len()..])?;
+            Ok(PluginPermission::Http(pattern))
+        }
         _ if s.starts_with("env-") => {
             let var_name = &s["env-".len()..];
             if var_name.is_empty() {
@@ -198,7 +605,7 @@ fn parse_single_permission(s: &str) -> Result<PluginPermission, String> {
             Ok(PluginPermission::FsWrite(PathBuf::from(path)))
         }
         _ => Err(format!(
-            "unknown permission '{s}'. Valid: net, env-<VAR>, fs-read=<path>, fs-write=<path>, var-<key>=<value>, timeout=<secs|ms>"
+            "unknown permission '{s}'. Valid: net, net=<cidr>:<ports>, net-dns, http=<host>, env-<VAR>, fs-read=<path>, fs-write=<path>, var-<key>=<value>, timeout=<secs|ms>, fail-closed=<pre-hook>, kv-quota=<size>, fuel=<N>, memory=<size>, stack=<size>, breaker=<failures>/<cooldown>"
         )),
     }
 }
@@ -478,4 +885,272 @@ mod tests {
         let displayed = spec.to_string();
         assert!(!displayed.contains("timeout"), "got: {displayed}");
     }
+
+    #[test]
+    fn parse_breaker_settings() {
+        let spec = parse_plugin_spec("a.wasm").unwrap();
+        assert_eq!(spec.breaker, CircuitBreaker::default());
+
+        let spec = parse_plugin_spec("a.wasm:breaker=3/500ms").unwrap();
+        assert_eq!(spec.breaker.failures, 3);
+        assert_eq!(spec.breaker.cooldown, Duration::from_millis(500));
+
+        let spec = parse_plugin_spec("a.wasm:breaker=off").unwrap();
+        assert_eq!(spec.breaker.failures, 0);
+
+        for bad in ["breaker=5", "breaker=0/60", "breaker=x/60", "breaker=5/1ms"] {
+            let err = parse_plugin_spec(&format!("a.wasm:{bad}")).unwrap_err();
+            assert!(err.contains("invalid breaker"), "{bad}: got {err}");
+        }
+    }
+
+    #[test]
+    fn display_with_breaker() {
+        for value in ["3/500ms", "10/120", "off"] {
+            let spec = parse_plugin_spec(&format!("a.wasm:breaker={value}")).unwrap();
+            assert_eq!(spec.to_string(), format!("a.wasm:breaker={value}"));
+        }
+        let spec = parse_plugin_spec("a.wasm:breaker=5/60").unwrap();
+        assert_eq!(spec.to_string(), "a.wasm");
+    }
+
+    // ── Fail-closed pre-hooks ────────────────────────────────────────
+
+    #[test]
+    fn parse_default_fail_open() {
+        let spec = parse_plugin_spec("a.wasm:net").unwrap();
+        assert!(spec.fail_closed.is_empty());
+    }
+
+    #[test]
+    fn parse_fail_closed_hooks() {
+        let spec =
+            parse_plugin_spec("a.wasm:fail-closed=pre-login,net,fail-closed=pre-upload").unwrap();
+        assert_eq!(
+            spec.fail_closed,
+            vec![ServerPreHook::PreLogin, ServerPreHook::PreUpload]
+        );
+        assert_eq!(spec.permissions, vec![PluginPermission::Net]);
+    }
+
+    #[test]
+    fn parse_fail_closed_unknown_hook_rejected() {
+        let err = parse_plugin_spec("a.wasm:fail-closed=ip-change").unwrap_err();
+        assert!(err.contains("unknown pre-hook"), "got: {err}");
+    }
+
+    #[test]
+    fn display_with_fail_closed() {
+        let spec = parse_plugin_spec("a.wasm:timeout=2,fail-closed=pre-token-refresh").unwrap();
+        let displayed = spec.to_string();
+        assert_eq!(displayed, "a.wasm:timeout=2,fail-closed=pre-token-refresh");
+    }
+
+    // ── KV quota ─────────────────────────────────────────────────────
+
+    #[test]
+    fn parse_default_kv_quota() {
+        let spec = parse_plugin_spec("a.wasm").unwrap();
+        assert_eq!(spec.kv_quota, DEFAULT_KV_QUOTA);
+    }
+
+    #[test]
+    fn parse_kv_quota_units() {
+        assert_eq!(
+            parse_plugin_spec("a.wasm:kv-quota=4096").unwrap().kv_quota,
+            4096
+        );
+        assert_eq!(
+            parse_plugin_spec("a.wasm:kv-quota=64k").unwrap().kv_quota,
+            64 * 1024
+        );
+        assert_eq!(
+            parse_plugin_spec("a.wasm:kv-quota=2m").unwrap().kv_quota,
+            2 * 1024 * 1024
+        );
+    }
+
+    #[test]
+    fn parse_kv_quota_invalid() {
+        let err = parse_plugin_spec("a.wasm:kv-quota=lots").unwrap_err();
+        assert!(err.contains("invalid size"), "got: {err}");
+    }
+
+    #[test]
+    fn display_with_kv_quota() {
+        let spec = parse_plugin_spec("a.wasm:net,kv-quota=64k").unwrap();
+        let displayed = spec.to_string();
+        assert_eq!(displayed, "a.wasm:net,kv-quota=65536");
+        let reparsed = parse_plugin_spec(&displayed).unwrap();
+        assert_eq!(reparsed.kv_quota, spec.kv_quota);
+    }
+
+    #[test]
+    fn parse_default_limits() {
+        let spec = parse_plugin_spec("a.wasm").unwrap();
+        assert_eq!(spec.limits, PluginLimits::default());
+    }
+
+    #[test]
+    fn parse_limits() {
+        let spec = parse_plugin_spec("a.wasm:fuel=50000000,memory=32m,stack=1m").unwrap();
+        assert_eq!(spec.limits.fuel, 50_000_000);
+        assert_eq!(spec.limits.memory, 32 * 1024 * 1024);
+        assert_eq!(spec.limits.stack, 1024 * 1024);
+    }
+
+    #[test]
+    fn parse_limits_out_of_bounds() {
+        for (spec, name) in [
+            ("a.wasm:fuel=0", "fuel"),
+            ("a.wasm:fuel=1000000001", "fuel"),
+            ("a.wasm:memory=1024m", "memory"),
+            ("a.wasm:stack=0", "stack"),
+            ("a.wasm:stack=9m", "stack"),
+        ] {
+            let err = parse_plugin_spec(spec).unwrap_err();
+            assert!(err.contains(name), "{spec}: got {err}");
+        }
+        let err = parse_plugin_spec("a.wasm:fuel=lots").unwrap_err();
+        assert!(err.contains("invalid fuel"), "got: {err}");
+    }
+
+    #[test]
+    fn display_with_limits() {
+        let spec = parse_plugin_spec("a.wasm:stack=1m,fuel=20000000").unwrap();
+        let displayed = spec.to_string();
+        assert_eq!(displayed, "a.wasm:fuel=20000000,stack=1048576");
+        let reparsed = parse_plugin_spec(&displayed).unwrap();
+        assert_eq!(reparsed.limits, spec.limits);
+    }
+
+    // ── HTTP allowlist ───────────────────────────────────────────────
+
+    #[test]
+    fn parse_http_hosts() {
+        let spec = parse_plugin_spec("a.wasm:http=Hooks.Example.com,http=*.slack.com").unwrap();
+        assert_eq!(
+            spec.permissions,
+            vec![
+                PluginPermission::Http("hooks.example.com".to_string()),
+                PluginPermission::Http("*.slack.com".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_http_empty_host_rejected() {
+        let err = parse_plugin_spec("a.wasm:http=").unwrap_err();
+        assert!(err.contains("requires a host"), "got: {err}");
+        let err = parse_plugin_spec("a.wasm:http=*.").unwrap_err();
+        assert!(err.contains("requires a host"), "got: {err}");
+    }
+
+    #[test]
+    fn parse_http_url_rejected() {
+        for pattern in [
+            "https://example.com",
+            "example.com/hook",
+            "*.*.com",
+            "ex*.com",
+        ] {
+            let err = parse_plugin_spec(&format!("a.wasm:http={pattern}")).unwrap_err();
+            assert!(
+                err.contains("invalid http host pattern"),
+                "{pattern}: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn display_with_http() {
+        let spec = parse_plugin_spec("a.wasm:http=*.example.com").unwrap();
+        assert_eq!(spec.to_string(), "a.wasm:http=*.example.com");
+    }
+
+    // ── Socket allowlist ─────────────────────────────────────────────
+
+    fn net_rule(spec: &str) -> NetRule {
+        match parse_plugin_spec(&format!("a.wasm:net={spec}"))
+            .unwrap()
+            .permissions
+            .remove(0)
+        {
+            PluginPermission::NetAddr(rule) => rule,
+            other => panic!("expected net rule, got {other:?}"),
+        }
+    }
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_net_single_address() {
+        let rule = net_rule("127.0.0.1:514");
+        assert!(rule.allows(&addr("127.0.0.1:514")));
+        assert!(!rule.allows(&addr("127.0.0.1:515")));
+        assert!(!rule.allows(&addr("127.0.0.2:514")));
+    }
+
+    #[test]
+    fn parse_net_cidr_and_port_range() {
+        let rule = net_rule("10.1.2.3/8:1000-2000");
+        assert_eq!(rule.to_string(), "10.0.0.0/8:1000-2000");
+        assert!(rule.allows(&addr("10.200.0.1:1000")));
+        assert!(rule.allows(&addr("10.0.0.1:2000")));
+        assert!(!rule.allows(&addr("10.0.0.1:2001")));
+        assert!(!rule.allows(&addr("11.0.0.1:1500")));
+    }
+
+    #[test]
+    fn parse_net_ipv6() {
+        let rule = net_rule("[fd00::/8]:*");
+        assert!(rule.allows(&addr("[fd12::1]:25")));
+        assert!(!rule.allows(&addr("[fe80::1]:25")));
+
+        let rule = net_rule("[::1]:25");
+        assert_eq!(rule.to_string(), "[::1]:25");
+    }
+
+    #[test]
+    fn net_rule_matches_ipv4_mapped_addresses() {
+        let rule = net_rule("127.0.0.1:25");
+        assert!(rule.allows(&addr("[::ffff:127.0.0.1]:25")));
+    }
+
+    #[test]
+    fn parse_net_zero_prefix_allows_all() {
+        let rule = net_rule("0.0.0.0/0:53");
+        assert!(rule.allows(&addr("8.8.8.8:53")));
+        assert!(!rule.allows(&addr("[::1]:53")));
+    }
+
+    #[test]
+    fn parse_net_rule_invalid() {
+        for spec in [
+            "127.0.0.1",
+            "127.0.0.1:",
+            "localhost:25",
+            "::1:25",
+            "[127.0.0.1]:25",
+            "127.0.0.1:99999",
+            "127.0.0.1:x",
+        ] {
+            let err = parse_plugin_spec(&format!("a.wasm:net={spec}")).unwrap_err();
+            assert!(err.contains("invalid net rule"), "{spec}: {err}");
+        }
+
+        let err = parse_plugin_spec("a.wasm:net=10.0.0.0/33:25").unwrap_err();
+        assert!(err.contains("invalid prefix length"), "got: {err}");
+        let err = parse_plugin_spec("a.wasm:net=10.0.0.1:30-20").unwrap_err();
+        assert!(err.contains("empty port range"), "got: {err}");
+    }
+
+    #[test]
+    fn parse_net_dns() {
+        let spec = parse_plugin_spec("a.wasm:net-dns,net=127.0.0.1:25").unwrap();
+        assert_eq!(spec.permissions[0], PluginPermission::NetDns);
+        assert_eq!(spec.to_string(), "a.wasm:net-dns,net=127.0.0.1:25");
+    }
 }
@@ -1,16 +1,24 @@
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, MutexGuard};
 use wasmtime::component::{Component, HasSelf, Linker};
-use wasmtime::{Engine, Store, StoreLimitsBuilder};
+use wasmtime::{Store, StoreLimitsBuilder};
 use wasmtime_wasi::WasiCtxBuilder;
 use wasmtime_wasi::p2::pipe::MemoryOutputPipe;
 
-use super::helpers::{apply_permissions, extract_panic_message, hook_target};
-use super::permissions::PluginPermission;
-use super::state::PluginState;
-use super::{Hook, HookEvent, HookTarget, Plugin, PluginError};
+use super::engine::{self, LoadOptions};
+use super::helpers::{apply_permissions, extract_panic_message, hook_target, parse_hook_name};
+use super::http_client::PluginHttp;
+use super::permissions::{CircuitBreaker, PluginLimits, PluginPermission};
+use super::scheduler::ParsedSchedule;
+use super::state::{PluginKv, PluginState};
+use super::stats::{CallOutcome, PluginStats};
+use super::{
+    Hook, HookEvent, HookTarget, HttpAccess, HttpRequest, HttpResponse, Plugin, PluginError,
+    ServerPreHook,
+};
+use crate::db::Database;
 
 /// Stderr buffer capacity in bytes. Used only for capturing panic messages
 /// from the WASM guest. The buffer has a hard cap — once full, further writes
@@ -32,10 +40,11 @@ const FUEL_YIELD_INTERVAL: u64 = 10_000;
 /// # Lifecycle
 ///
 /// 1. **Read** — The `.wasm` file is read from disk.
-/// 2. **Compile** — The bytes are compiled into a wasmtime `Component`.
-/// 3. **Link** — WASI imports and the host `log()` function are wired up.
-///    Stderr is captured for panic extraction; filesystem, env, and network
-///    access is granted per-plugin via permissions.
+/// 2. **Compile** — The bytes are compiled into a wasmtime `Component` on an
+///    engine shared by all plugins, or loaded from the compiled component cache.
+/// 3. **Link** — WASI imports and the host `log()`, `kv` and `http` imports are
+///    wired up. Stderr is captured for panic extraction; filesystem, env, network
+///    and HTTP host access is granted per-plugin via permissions.
 /// 4. **Instantiate** — The component is instantiated inside a fresh `Store`.
 /// 5. **Configure** — The plugin's exported `config()` function is called. This
 ///    returns the plugin's name, version, and the set of hooks it wants to handle.
@@ -50,18 +59,34 @@ pub struct PluginRuntime {
     target: HookTarget,
     /// Hooks this plugin registered for. All must match `target`.
     hooks: Vec<Hook>,
+    /// Who may call the plugin's HTTP routes; `None` if it serves none.
+    http_access: Option<HttpAccess>,
     /// Live store and instance created at load time.
     /// Each plugin has its own Mutex so different plugins can run in parallel.
     /// A single plugin serializes its hook calls (WASM is single-threaded).
     /// On timeout, the old instance is dropped and a fresh one is created.
     instance: Mutex<Option<PluginInstance>>,
+    /// Compiled component, kept so timeout reloads skip reading and compiling.
+    component: Component,
     /// Wall-clock timeout for `config()` and `on-hook()` calls.
     /// Covers both WASM execution and time spent in async host calls.
     hook_timeout: Duration,
+    /// Fuel, memory and stack budget for each instance.
+    limits: PluginLimits,
+    /// Pre-hooks that deny the action if this plugin times out or traps.
+    fail_closed: Vec<ServerPreHook>,
+    /// Key-value store namespace, attached after load via [`with_kv`](Self::with_kv).
+    kv: Option<PluginKv>,
+    /// When to stop calling the plugin after repeated failures.
+    breaker: CircuitBreaker,
+    /// Call, error and latency counters and circuit breaker state.
+    stats: PluginStats,
     /// Retained for reloading after a timeout kills the instance.
     path: PathBuf,
     permissions: Vec<PluginPermission>,
     config_vars: Vec<(String, String)>,
+    /// Retained so [`load_again`](Self::load_again) checks the file the same way.
+    load_options: LoadOptions,
 }
 
 /// A live WASM plugin instance: store, bindings, and stderr pipe.
@@ -101,8 +126,38 @@ impl PluginRuntime {
         config_vars: &[(String, String)],
         hook_timeout: Duration,
     ) -> Result<Self, PluginError> {
-        let (inst, config) =
-            Self::create_instance(path, permissions, config_vars, hook_timeout).await?;
+        Self::load_with_limits(
+            path,
+            permissions,
+            config_vars,
+            hook_timeout,
+            PluginLimits::default(),
+            &LoadOptions::default(),
+        )
+        .await
+    }
+
+    /// Like [`load`](Self::load), with a custom fuel, memory and stack budget,
+    /// checking the file according to `options`.
+    pub async fn load_with_limits(
+        path: &Path,
+        permissions: &[PluginPermission],
+        config_vars: &[(String, String)],
+        hook_timeout: Duration,
+        limits: PluginLimits,
+        options: &LoadOptions,
+    ) -> Result<Self, PluginError> {
+        let component = engine::compile(path, &limits, options)?;
+        let (inst, config) = Self::create_instance(
+            &component,
+            path,
+            permissions,
+            config_vars,
+            hook_timeout,
+            limits,
+            None,
+        )
+        .await?;
 
         // Validate the config — a plugin must have a non-empty name.
         if config.name.is_empty() {
@@ -118,6 +173,9 @@ impl PluginRuntime {
                     config.target
                 )));
             }
+            if let Hook::Schedule(schedule) = hook {
+                ParsedSchedule::parse(schedule).map_err(PluginError::InvalidConfig)?;
+            }
         }
 
         Ok(Self {
@@ -125,42 +183,41 @@ impl PluginRuntime {
             plugin_version: config.version,
             target: config.target,
             hooks: config.hooks,
+            http_access: config.http,
             instance: Mutex::new(Some(inst)),
+            component,
             hook_timeout,
+            limits,
+            fail_closed: Vec::new(),
+            kv: None,
+            breaker: CircuitBreaker::default(),
+            stats: PluginStats::default(),
             path: path.to_path_buf(),
             permissions: permissions.to_vec(),
             config_vars: config_vars.to_vec(),
+            load_options: options.clone(),
         })
     }
 
-    /// Create a fresh WASM instance: compile, link, instantiate, call `config()`.
+    /// Create a fresh WASM instance: link, instantiate, call `config()`.
     ///
-    /// Used by both initial `load()` and automatic reload after a timeout.
+    /// Used by both initial `load()` and automatic reload after a timeout,
+    /// which reuses the already compiled component.
     /// Returns the live instance and the plugin config from `config()`.
+    /// `kv` is attached only after `config()` returns.
     async fn create_instance(
+        component: &Component,
         path: &Path,
         permissions: &[PluginPermission],
         config_vars: &[(String, String)],
         hook_timeout: Duration,
+        limits: PluginLimits,
+        kv: Option<PluginKv>,
     ) -> Result<(PluginInstance, super::PluginConfig), PluginError> {
-        // Read the raw .wasm bytes from disk.
-        let wasm_bytes = std::fs::read(path)
-            .map_err(|e| PluginError::Load(format!("failed to read {}: {e}", path.display())))?;
-
-        // Create a wasmtime engine with resource limits and compile the bytes
-        // into a Component. Fuel metering caps CPU usage.
-        let mut engine_config = wasmtime::Config::new();
-        engine_config.consume_fuel(true);
-        engine_config.async_support(true);
-        engine_config.max_wasm_stack(512 * 1024); // 512KB stack limit
-        let engine = Engine::new(&engine_config)
-            .map_err(|e| PluginError::Load(format!("failed to create engine: {e}")))?;
-
-        let component = Component::new(&engine, &wasm_bytes)
-            .map_err(|e| PluginError::Load(format!("failed to compile {}: {e}", path.display())))?;
+        let engine = component.engine();
 
         // Set up the linker with WASI imports and our custom `log` import.
-        let mut linker: Linker<PluginState> = Linker::new(&engine);
+        let mut linker: Linker<PluginState> = Linker::new(engine);
         wasmtime_wasi::p2::add_to_linker_async(&mut linker)
             .map_err(|e| PluginError::Load(format!("failed to add WASI to linker: {e}")))?;
         Plugin::add_to_linker::<_, HasSelf<_>>(&mut linker, |state| state).map_err(|e| {
@@ -173,6 +230,7 @@ impl PluginRuntime {
         wasi_builder.stderr(stderr.clone());
         apply_permissions(&mut wasi_builder, permissions)?;
         let wasi = wasi_builder.build();
+        let http = PluginHttp::new(permissions, hook_timeout)?;
 
         // Derive a logging name from the file path for use before config() returns.
         let plugin_name = path
@@ -181,17 +239,19 @@ impl PluginRuntime {
             .unwrap_or("unknown")
             .to_string();
 
-        // Memory limits: cap each linear memory at 10MB to prevent OOM.
-        let limits = StoreLimitsBuilder::new()
-            .memory_size(10 * 1024 * 1024)
+        // Memory limits: cap each linear memory (10MB by default) to prevent OOM.
+        let store_limits = StoreLimitsBuilder::new()
+            .memory_size(limits.memory as usize)
             .build();
         let mut store = Store::new(
-            &engine,
+            engine,
             PluginState {
                 wasi,
                 table: wasmtime_wasi::ResourceTable::new(),
-                limits,
+                limits: store_limits,
                 plugin_name,
+                kv: None,
+                http,
             },
         );
         store.limiter(|state| &mut state.limits);
@@ -202,13 +262,13 @@ impl PluginRuntime {
             .fuel_async_yield_interval(Some(FUEL_YIELD_INTERVAL))
             .map_err(|e| PluginError::Load(format!("failed to set yield interval: {e}")))?;
 
-        // Fuel limit: ~10M instructions for the config() call.
+        // Fuel limit (~10M instructions by default) for the config() call.
         store
-            .set_fuel(10_000_000)
+            .set_fuel(limits.fuel)
             .map_err(|e| PluginError::Load(format!("failed to set fuel limit: {e}")))?;
 
         // Instantiate the component asynchronously.
-        let instance = Plugin::instantiate_async(&mut store, &component, &linker)
+        let instance = Plugin::instantiate_async(&mut store, component, &linker)
             .await
             .map_err(|e| PluginError::Runtime(format!("failed to instantiate plugin: {e}")))?;
 
@@ -232,11 +292,12 @@ impl PluginRuntime {
 
         // Refuel the store for future hook calls (config() consumed some fuel).
         store
-            .set_fuel(10_000_000)
+            .set_fuel(limits.fuel)
             .map_err(|e| PluginError::Load(format!("failed to reset fuel: {e}")))?;
 
         // Update the plugin name in the store to the declared name from config().
         store.data_mut().plugin_name = config.name.clone();
+        store.data_mut().kv = kv;
 
         let inst = PluginInstance {
             store,
@@ -262,6 +323,113 @@ impl PluginRuntime {
         &self.target
     }
 
+    /// The registered hook with the given WIT name, e.g. `ip-change` or
+    /// `pre-login`. `schedule` matches the first schedule hook.
+    pub fn registered_hook(&self, name: &str) -> Option<&Hook> {
+        if name == "schedule" {
+            return self
+                .hooks
+                .iter()
+                .find(|hook| matches!(hook, Hook::Schedule(_)));
+        }
+        let hook = parse_hook_name(name)?;
+        self.hooks.iter().find(|registered| **registered == hook)
+    }
+
+    /// Access level for the plugin's HTTP routes, or `None` if it serves none.
+    pub fn http_access(&self) -> Option<HttpAccess> {
+        self.http_access
+    }
+
+    /// The `.wasm` file this plugin was loaded from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn permissions(&self) -> &[PluginPermission] {
+        &self.permissions
+    }
+
+    pub fn stats(&self) -> &PluginStats {
+        &self.stats
+    }
+
+    pub fn breaker(&self) -> &CircuitBreaker {
+        &self.breaker
+    }
+
+    pub fn limits(&self) -> &PluginLimits {
+        &self.limits
+    }
+
+    /// Names of the interfaces the component imports, e.g. `wasi:cli/environment@0.2.6`.
+    pub fn imports(&self) -> Vec<String> {
+        let engine = self.component.engine();
+        self.component
+            .component_type()
+            .imports(engine)
+            .map(|(name, _)| name.to_string())
+            .collect()
+    }
+
+    /// Load a fresh copy of this plugin from disk, keeping its permissions,
+    /// config variables, timeout, limits, fail-closed pre-hooks, circuit
+    /// breaker and kv store.
+    ///
+    /// The running plugin is untouched; callers decide whether to swap in the
+    /// new copy (see [`PluginManager::reload`](super::PluginManager::reload)).
+    pub async fn load_again(&self) -> Result<Self, PluginError> {
+        let mut plugin = Self::load_with_limits(
+            &self.path,
+            &self.permissions,
+            &self.config_vars,
+            self.hook_timeout,
+            self.limits,
+            &self.load_options,
+        )
+        .await?
+        .with_fail_closed(&self.fail_closed)
+        .with_breaker(self.breaker);
+        if let Some(kv) = &self.kv {
+            plugin = plugin.with_kv(kv.db.clone(), kv.quota);
+        }
+        Ok(plugin)
+    }
+
+    /// Deny the given pre-hooks when this plugin fails to answer (timeout or trap).
+    /// By default all pre-hooks fail open.
+    pub fn with_fail_closed(mut self, hooks: &[ServerPreHook]) -> Self {
+        self.fail_closed = hooks.to_vec();
+        self
+    }
+
+    /// Skip this plugin for a while after repeated failures.
+    /// Defaults to [`CircuitBreaker::default`].
+    pub fn with_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.breaker = breaker;
+        self
+    }
+
+    /// Returns true if a failure of this plugin should deny the given pre-hook.
+    pub fn fails_closed(&self, hook: &ServerPreHook) -> bool {
+        self.fail_closed.contains(hook)
+    }
+
+    /// Give this plugin durable key-value storage in `db`, namespaced by its
+    /// declared name and limited to `quota` bytes of keys plus values.
+    pub fn with_kv(mut self, db: Database, quota: u64) -> Self {
+        let kv = PluginKv {
+            db,
+            namespace: self.plugin_name.clone(),
+            quota,
+        };
+        if let Some(inst) = self.instance.get_mut() {
+            inst.store.data_mut().kv = Some(kv.clone());
+        }
+        self.kv = Some(kv);
+        self
+    }
+
     /// Call the plugin's `on-hook` export with the given event.
     ///
     /// Uses the instance created at load time. Refuels the store before each
@@ -269,23 +437,128 @@ impl PluginRuntime {
     /// timeout to catch plugins that block on async host calls (network, filesystem).
     ///
     /// If the call times out, the old instance is dropped (its store is in an
-    /// undefined state) and a fresh instance is created from disk. The timeout
+    /// undefined state) and a fresh instance is created from the compiled
+    /// component. The timeout
     /// error is still returned for this call.
+    ///
+    /// While the circuit breaker is open the plugin is not called and
+    /// [`PluginError::Unavailable`] is returned.
     pub async fn call_hook(&self, event: &HookEvent) -> Result<(), PluginError> {
+        match self.invoke_hook(event).await? {
+            Ok(()) => Ok(()),
+            Err(msg) => {
+                self.stats.record_error(&hook_label(&event.hook));
+                Err(PluginError::Hook(msg))
+            }
+        }
+    }
+
+    /// Like [`call_hook`](Self::call_hook), but keeps the plugin's own answer
+    /// separate from failures to run it.
+    ///
+    /// The outer error means the plugin could not answer (timeout, trap, reload
+    /// failure); the inner result is what `on-hook` returned. Pre-hooks need the
+    /// distinction to tell a deliberate denial from a broken plugin.
+    pub(crate) async fn invoke_hook(
+        &self,
+        event: &HookEvent,
+    ) -> Result<Result<(), String>, PluginError> {
+        let label = hook_label(&event.hook);
+        let mut guard = self.ready_instance(&label, PluginError::Hook).await?;
+        let inst = guard.as_mut().unwrap();
+
+        // Snapshot stderr length so we only read new bytes on error.
+        let stderr_offset = inst.stderr.contents().len();
+
+        let started = Instant::now();
+        let result = tokio::time::timeout(
+            self.hook_timeout,
+            inst.plugin.call_on_hook(&mut inst.store, event),
+        )
+        .await;
+        self.finish_call(
+            &label,
+            "on_hook()",
+            &mut guard,
+            stderr_offset,
+            started,
+            result,
+            PluginError::Hook,
+        )
+    }
+
+    /// Call the plugin's `http-handler` export for a request to one of its routes.
+    ///
+    /// Runs under the same fuel budget and wall-clock timeout as hook calls.
+    /// As with [`invoke_hook`](Self::invoke_hook), the outer error means the
+    /// plugin could not answer and the inner result is what it returned.
+    pub(crate) async fn call_http(
+        &self,
+        request: &HttpRequest,
+    ) -> Result<Result<HttpResponse, String>, PluginError> {
+        let mut guard = self
+            .ready_instance(HTTP_LABEL, PluginError::Runtime)
+            .await?;
+        let inst = guard.as_mut().unwrap();
+        let stderr_offset = inst.stderr.contents().len();
+
+        let started = Instant::now();
+        let result = tokio::time::timeout(
+            self.hook_timeout,
+            inst.plugin.call_http_handler(&mut inst.store, request),
+        )
+        .await;
+        let result = self.finish_call(
+            HTTP_LABEL,
+            "http_handler()",
+            &mut guard,
+            stderr_offset,
+            started,
+            result,
+            PluginError::Runtime,
+        );
+        if let Ok(Err(_)) = &result {
+            self.stats.record_error(HTTP_LABEL);
+        }
+        result
+    }
+
+    /// Lock the instance for a call, reloading it if a previous timeout
+    /// dropped it, and refuel it so every call gets a fresh CPU budget.
+    ///
+    /// Fails without touching the instance while the circuit breaker is open.
+    /// The check happens after locking, so calls queued behind the failure
+    /// that opened it are skipped too.
+    async fn ready_instance(
+        &self,
+        label: &str,
+        error: fn(String) -> PluginError,
+    ) -> Result<MutexGuard<'_, Option<PluginInstance>>, PluginError> {
         let mut guard = self.instance.lock().await;
+        if self.stats.reject_if_open() {
+            return Err(PluginError::Unavailable(format!(
+                "{} is skipped after repeated failures (circuit breaker open)",
+                self.plugin_name
+            )));
+        }
 
         // Reload if a previous timeout left us with no instance.
         if guard.is_none() {
+            let started = Instant::now();
             match Self::create_instance(
+                &self.component,
                 &self.path,
                 &self.permissions,
                 &self.config_vars,
                 self.hook_timeout,
+                self.limits,
+                self.kv.clone(),
             )
             .await
             {
                 Ok((inst, _config)) => {
                     tracing::info!(plugin = %self.plugin_name, "Plugin reloaded after timeout");
+                    self.stats.record_reload();
                     *guard = Some(inst);
                 }
                 Err(e) => {
@@ -294,50 +567,90 @@ impl PluginRuntime {
                         error = %e,
                         "Failed to reload plugin after timeout"
                     );
-                    return Err(PluginError::Hook(format!("plugin reload failed: {e}")));
+                    self.record(label, CallOutcome::Failed, started.elapsed(), 0);
+                    return Err(error(format!("plugin reload failed: {e}")));
                 }
             }
         }
 
-        let inst = guard.as_mut().unwrap();
-
-        // Refuel before each hook call.
-        inst.store
-            .set_fuel(10_000_000)
-            .map_err(|e| PluginError::Hook(format!("failed to set fuel limit: {e}")))?;
-
-        // Snapshot stderr length so we only read new bytes on error.
-        let stderr_offset = inst.stderr.contents().len();
-
-        let timeout = self.hook_timeout;
-        let result =
-            tokio::time::timeout(timeout, inst.plugin.call_on_hook(&mut inst.store, event)).await;
+        guard
+            .as_mut()
+            .unwrap()
+            .store
+            .set_fuel(self.limits.fuel)
+            .map_err(|e| error(format!("failed to set fuel limit: {e}")))?;
+        Ok(guard)
+    }
 
-        if result.is_err() {
+    /// Convert the outcome of a timed export call into a result, recording
+    /// its latency, fuel use and outcome under `label`.
+    #[allow(clippy::too_many_arguments)]
+    fn finish_call<T>(
+        &self,
+        label: &str,
+        call_name: &str,
+        slot: &mut Option<PluginInstance>,
+        stderr_offset: usize,
+        started: Instant,
+        result: Result<wasmtime::Result<T>, tokio::time::error::Elapsed>,
+        error: fn(String) -> PluginError,
+    ) -> Result<T, PluginError> {
+        let latency = started.elapsed();
+        let fuel = slot
+            .as_ref()
+            .and_then(|inst| inst.store.get_fuel().ok())
+            .map_or(0, |left| self.limits.fuel.saturating_sub(left));
+
+        let Ok(result) = result else {
+            self.record(label, CallOutcome::TimedOut, latency, fuel);
             // Timeout fired — the future was dropped mid-execution, leaving the
-            // store in an undefined state. Drop the instance; it will be reloaded
-            // from disk on the next call.
-            *guard = None;
-            return Err(PluginError::Hook(format!(
-                "on_hook() timed out after {}ms, plugin will reload on next call",
-                timeout.as_millis()
+            // store in an undefined state. Drop the instance; a fresh one is
+            // instantiated on the next call.
+            *slot = None;
+            return Err(error(format!(
+                "{call_name} timed out after {}ms, plugin will reload on next call",
+                self.hook_timeout.as_millis()
             )));
-        }
+        };
 
-        let result = result.unwrap().map_err(|e| {
-            let stderr_output = read_new_stderr(&inst.stderr, stderr_offset);
-            let msg = format_trap_error("on_hook()", &e, &stderr_output);
-            PluginError::Hook(msg)
-        });
+        let outcome = match &result {
+            Ok(_) => CallOutcome::Answered,
+            Err(_) => CallOutcome::Failed,
+        };
+        self.record(label, outcome, latency, fuel);
+        result.map_err(|e| {
+            let stderr = slot.as_ref().map(|inst| &inst.stderr);
+            let stderr_output = stderr
+                .map(|pipe| read_new_stderr(pipe, stderr_offset))
+                .unwrap_or_default();
+            error(format_trap_error(call_name, &e, &stderr_output))
+        })
+    }
 
-        match result {
-            Ok(Ok(())) => Ok(()),
-            Ok(Err(msg)) => Err(PluginError::Hook(msg)),
-            Err(e) => Err(e),
+    /// Record a finished call, logging when it opens the circuit breaker.
+    fn record(&self, label: &str, outcome: CallOutcome, latency: Duration, fuel: u64) {
+        if self
+            .stats
+            .record_call(label, outcome, latency, fuel, &self.breaker)
+        {
+            tracing::warn!(
+                plugin = %self.plugin_name,
+                failures = self.breaker.failures,
+                cooldown_ms = self.breaker.cooldown.as_millis() as u64,
+                "Plugin circuit breaker opened, skipping calls until the cool-down ends"
+            );
         }
     }
 }
 
+/// Stats label for calls to the `http-handler` export.
+const HTTP_LABEL: &str = "http";
+
+/// Stats label for calls to `on-hook` with the given hook.
+pub(crate) fn hook_label(hook: &Hook) -> String {
+    format!("{hook:?}")
+}
+
 /// Read only the new bytes written to stderr since `offset`.
 fn read_new_stderr(stderr: &MemoryOutputPipe, offset: usize) -> String {
     let all = stderr.contents();
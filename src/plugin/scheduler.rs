@@ -0,0 +1,407 @@
+//! Time-based plugin hooks.
+//!
+//! Plugins with the `schedule` target declare intervals or cron expressions
+//! in `config()`. Each declared schedule gets its own background task that
+//! sleeps until the next due time (plus a random jitter, so plugins sharing a
+//! schedule don't all wake at once) and then calls the plugin's `on-hook`.
+//! Runs are awaited before the next due time is computed, so a slow run can
+//! never overlap the next one; occurrences missed while a run was in progress
+//! are skipped rather than queued.
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+
+use super::helpers::hook_target;
+use super::manager::{PluginManager, PluginSet};
+use super::runtime::{PluginRuntime, hook_label};
+use super::{Hook, HookEvent, PluginError, Schedule};
+
+/// Upper bound on the random delay added before each run.
+const MAX_JITTER: Duration = Duration::from_secs(30);
+
+/// Longest interval a schedule may declare (one year).
+const MAX_INTERVAL_SECS: u64 = 366 * 86_400;
+
+/// How far ahead to search for the next cron match before giving up
+/// (e.g. `0 0 31 2 *` never matches).
+const CRON_SEARCH_DAYS: u64 = 5 * 366;
+
+/// A validated schedule declared by a plugin.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ParsedSchedule {
+    Interval(Duration),
+    Cron(CronExpr),
+}
+
+impl ParsedSchedule {
+    pub(crate) fn parse(schedule: &Schedule) -> Result<Self, String> {
+        match schedule {
+            Schedule::Interval(0) => Err("schedule interval must be at least 1 second".into()),
+            Schedule::Interval(secs) if *secs > MAX_INTERVAL_SECS => Err(format!(
+                "schedule interval must be at most {MAX_INTERVAL_SECS} seconds"
+            )),
+            Schedule::Interval(secs) => Ok(Self::Interval(Duration::from_secs(*secs))),
+            Schedule::Cron(expr) => CronExpr::parse(expr).map(Self::Cron),
+        }
+    }
+
+    /// Next due time in unix milliseconds after `now_ms`, if there is one.
+    fn next_run(&self, now_ms: u64) -> Option<u64> {
+        match self {
+            Self::Interval(interval) => {
+                now_ms.checked_add(u64::try_from(interval.as_millis()).ok()?)
+            }
+            Self::Cron(cron) => cron
+                .next_after(now_ms / 1000)
+                .and_then(|secs| secs.checked_mul(1000)),
+        }
+    }
+}
+
+/// A parsed five-field cron expression. Each field is a bitmask of allowed values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct CronExpr {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    /// Whether the day-of-month / day-of-week fields were `*`. Cron matches
+    /// either day field when both are restricted, and both otherwise.
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+impl CronExpr {
+    pub(crate) fn parse(expr: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, dom, month, dow] = fields[..] else {
+            return Err(format!(
+                "invalid cron expression '{expr}': expected 5 fields (minute hour day month weekday)"
+            ));
+        };
+        let field = |value: &str, name: &str, min: u32, max: u32| {
+            parse_cron_field(value, min, max).ok_or_else(|| {
+                format!("invalid cron expression '{expr}': bad {name} field '{value}'")
+            })
+        };
+
+        // Day-of-week accepts 7 as an alias for Sunday.
+        let mut days_of_week = field(dow, "day-of-week", 0, 7)?;
+        if days_of_week & (1 << 7) != 0 {
+            days_of_week = (days_of_week & !(1 << 7)) | 1;
+        }
+
+        Ok(Self {
+            minutes: field(minute, "minute", 0, 59)?,
+            hours: field(hour, "hour", 0, 23)?,
+            days_of_month: field(dom, "day-of-month", 1, 31)?,
+            months: field(month, "month", 1, 12)?,
+            days_of_week,
+            any_day_of_month: dom == "*",
+            any_day_of_week: dow == "*",
+        })
+    }
+
+    /// The first matching minute strictly after `unix_secs`, in unix seconds.
+    pub(crate) fn next_after(&self, unix_secs: u64) -> Option<u64> {
+        let mut t = (unix_secs / 60 + 1) * 60;
+        let limit = t + CRON_SEARCH_DAYS * 86_400;
+        while t < limit {
+            let days = t / 86_400;
+            let (month, day) = month_and_day(days);
+            // 1970-01-01 was a Thursday (day 4 with Sunday = 0).
+            let weekday = (days + 4) % 7;
+            if !has(self.months, month) || !self.day_matches(day, weekday) {
+                t = (days + 1) * 86_400;
+                continue;
+            }
+            let hour = t % 86_400 / 3600;
+            if !has(self.hours, hour) {
+                t = days * 86_400 + (hour + 1) * 3600;
+                continue;
+            }
+            if !has(self.minutes, t % 3600 / 60) {
+                t += 60;
+                continue;
+            }
+            return Some(t);
+        }
+        None
+    }
+
+    fn day_matches(&self, day: u64, weekday: u64) -> bool {
+        let dom = has(self.days_of_month, day);
+        let dow = has(self.days_of_week, weekday);
+        if self.any_day_of_month || self.any_day_of_week {
+            dom && dow
+        } else {
+            dom || dow
+        }
+    }
+}
+
+fn has(mask: u64, value: u64) -> bool {
+    mask & (1 << value) != 0
+}
+
+/// Parse one cron field (`*`, `5`, `1-5`, `*/10`, `0-30/5`, or a comma list of
+/// those) into a bitmask. Returns `None` if any value is outside `min..=max`.
+fn parse_cron_field(field: &str, min: u32, max: u32) -> Option<u64> {
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|s| *s > 0)?),
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (a.parse().ok()?, b.parse().ok()?)
+        } else {
+            let value = range.parse().ok()?;
+            // A bare value with a step (`5/15`) runs from the value to the maximum.
+            (value, if step > 1 { max } else { value })
+        };
+        if start < min || end > max || start > end {
+            return None;
+        }
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Some(mask)
+}
+
+/// Convert days since the unix epoch to (month 1-12, day 1-31).
+///
+/// Uses Howard Hinnant's `civil_from_days` algorithm.
+fn month_and_day(days: u64) -> (u64, u64) {
+    let z = days + 719_468;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (month, day)
+}
+
+fn unix_now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Run the plugins' `schedule` hooks in the background.
+///
+/// Spawns one task per declared schedule, and keeps following the manager's
+/// plugin set: plugins loaded or reloaded later get their own tasks, and tasks
+/// of a replaced plugin stop. Disabled plugins keep their tasks but skip runs.
+///
+/// Tasks end once the manager is dropped.
+pub fn spawn_plugin_scheduler(manager: &PluginManager) -> JoinHandle<()> {
+    let mut updates = manager.subscribe();
+    tokio::spawn(async move {
+        let mut scheduled: Vec<Arc<PluginRuntime>> = Vec::new();
+        loop {
+            let set = updates.borrow_and_update().clone();
+            for plugin in set.plugins() {
+                if scheduled.iter().any(|p| Arc::ptr_eq(p, plugin)) {
+                    continue;
+                }
+                for hook in plugin.hooks() {
+                    let Hook::Schedule(schedule) = hook else {
+                        continue;
+                    };
+                    // Schedules are validated when the plugin loads.
+                    let Ok(parsed) = ParsedSchedule::parse(schedule) else {
+                        continue;
+                    };
+                    tokio::spawn(run_schedule(
+                        plugin.clone(),
+                        hook.clone(),
+                        parsed,
+                        updates.clone(),
+                    ));
+                }
+            }
+            scheduled = set.plugins().to_vec();
+
+            if updates.changed().await.is_err() {
+                return;
+            }
+        }
+    })
+}
+
+/// Sleep until each due time and call the plugin, until it is unloaded or replaced.
+async fn run_schedule(
+    plugin: Arc<PluginRuntime>,
+    hook: Hook,
+    schedule: ParsedSchedule,
+    mut updates: watch::Receiver<Arc<PluginSet>>,
+) {
+    if !updates.borrow_and_update().contains(&plugin) {
+        return;
+    }
+    loop {
+        let now_ms = unix_now_ms();
+        let Some(due_ms) = schedule.next_run(now_ms) else {
+            tracing::warn!(plugin = %plugin.name(), hook = ?hook, "Schedule never fires again, stopping");
+            return;
+        };
+        plugin
+            .stats()
+            .record_next_run(&hook_label(&hook), due_ms / 1000);
+
+        let wait = Duration::from_millis(due_ms - now_ms);
+        let max_jitter = MAX_JITTER.min(wait / 10).as_millis() as u64;
+        let jitter = Duration::from_millis(rand::random_range(0..=max_jitter));
+        let deadline = Instant::now() + wait + jitter;
+
+        // Sleep, but stop early if the plugin leaves the set so a replaced
+        // runtime isn't kept alive until its next due time.
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => break,
+                changed = updates.changed() => {
+                    if changed.is_err() || !updates.borrow_and_update().contains(&plugin) {
+                        return;
+                    }
+                }
+            }
+        }
+        if !updates.borrow().is_enabled(&plugin) {
+            continue;
+        }
+
+        let event = HookEvent {
+            hook: hook.clone(),
+            time: unix_now_ms() / 1000,
+            target: hook_target(&hook),
+            values: vec![("scheduled_at".into(), (due_ms / 1000).to_string())],
+        };
+        match plugin.call_hook(&event).await {
+            Ok(()) => {}
+            Err(e @ PluginError::Unavailable(_)) => {
+                tracing::debug!(
+                    plugin = %plugin.name(),
+                    hook = ?hook,
+                    error = %e,
+                    "Scheduled plugin hook skipped"
+                );
+            }
+            Err(e) => {
+                tracing::warn!(
+                    plugin = %plugin.name(),
+                    hook = ?hook,
+                    error = %e,
+                    "Scheduled plugin hook failed"
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2024-01-01 00:00:00 UTC, a Monday.
+    const JAN_1_2024: u64 = 1_704_067_200;
+
+    fn cron(expr: &str) -> CronExpr {
+        CronExpr::parse(expr).unwrap()
+    }
+
+    #[test]
+    fn parse_rejects_invalid_expressions() {
+        for expr in [
+            "",
+            "* * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "a * * * *",
+        ] {
+            assert!(CronExpr::parse(expr).is_err(), "{expr} should be rejected");
+        }
+    }
+
+    #[test]
+    fn every_minute() {
+        assert_eq!(
+            cron("* * * * *").next_after(JAN_1_2024),
+            Some(JAN_1_2024 + 60)
+        );
+        assert_eq!(
+            cron("* * * * *").next_after(JAN_1_2024 + 59),
+            Some(JAN_1_2024 + 60)
+        );
+    }
+
+    #[test]
+    fn daily_at_fixed_time() {
+        let next = cron("30 3 * * *").next_after(JAN_1_2024).unwrap();
+        assert_eq!(next, JAN_1_2024 + 3 * 3600 + 30 * 60);
+        let after = cron("30 3 * * *").next_after(next).unwrap();
+        assert_eq!(after, next + 86_400);
+    }
+
+    #[test]
+    fn steps_and_lists() {
+        let expr = cron("*/15 9,17 * * *");
+        assert_eq!(expr.next_after(JAN_1_2024), Some(JAN_1_2024 + 9 * 3600));
+        assert_eq!(
+            expr.next_after(JAN_1_2024 + 9 * 3600 + 45 * 60),
+            Some(JAN_1_2024 + 17 * 3600)
+        );
+    }
+
+    #[test]
+    fn weekday_and_sunday_alias() {
+        // Next Sunday after Monday 2024-01-01 is 2024-01-07.
+        let sunday = JAN_1_2024 + 6 * 86_400;
+        assert_eq!(cron("0 0 * * 0").next_after(JAN_1_2024), Some(sunday));
+        assert_eq!(cron("0 0 * * 7").next_after(JAN_1_2024), Some(sunday));
+    }
+
+    #[test]
+    fn restricted_day_fields_match_either() {
+        // Day 15 or any Friday: the first Friday is 2024-01-05.
+        let expr = cron("0 0 15 * 5");
+        assert_eq!(expr.next_after(JAN_1_2024), Some(JAN_1_2024 + 4 * 86_400));
+    }
+
+    #[test]
+    fn month_boundaries_and_leap_day() {
+        // 2024-02-29 00:00 UTC
+        let leap_day = JAN_1_2024 + (31 + 28) * 86_400;
+        assert_eq!(cron("0 0 29 2 *").next_after(JAN_1_2024), Some(leap_day));
+    }
+
+    #[test]
+    fn impossible_date_never_fires() {
+        assert_eq!(cron("0 0 31 2 *").next_after(JAN_1_2024), None);
+    }
+
+    #[test]
+    fn interval_schedule() {
+        assert!(ParsedSchedule::parse(&Schedule::Interval(0)).is_err());
+        assert!(ParsedSchedule::parse(&Schedule::Interval(MAX_INTERVAL_SECS + 1)).is_err());
+        assert!(ParsedSchedule::parse(&Schedule::Interval(u64::MAX)).is_err());
+        let schedule = ParsedSchedule::parse(&Schedule::Interval(300)).unwrap();
+        assert_eq!(schedule.next_run(1_000), Some(301_000));
+        // Never wraps around
+        let longest = ParsedSchedule::parse(&Schedule::Interval(MAX_INTERVAL_SECS)).unwrap();
+        assert_eq!(longest.next_run(u64::MAX - 1), None);
+    }
+}
@@ -0,0 +1,240 @@
+//! Detached plugin signatures.
+//!
+//! With at least one trusted publisher key configured, a plugin at
+//! `plugin.wasm` may come with `plugin.wasm.sig`: a signature over the exact
+//! `.wasm` bytes, e.g. from
+//! `openssl pkeyutl -sign -rawin -inkey key.pem -in plugin.wasm -out plugin.wasm.sig`
+//! (Ed25519) or `openssl dgst -sha256 -sign key.pem -out plugin.wasm.sig plugin.wasm`
+//! (RSA, ECDSA). The signature is checked before the file is compiled.
+
+use std::path::{Path, PathBuf};
+
+use openssl::hash::MessageDigest;
+use openssl::pkey::{Id, PKey, Public};
+use openssl::sign::Verifier;
+
+use super::PluginError;
+
+/// A publisher public key plugins may be signed with.
+#[derive(Clone)]
+pub struct TrustedKey {
+    path: PathBuf,
+    key: PKey<Public>,
+}
+
+impl TrustedKey {
+    /// Read a PEM public key (Ed25519, Ed448, RSA or EC).
+    pub fn load(path: &Path) -> Result<Self, PluginError> {
+        let pem = std::fs::read(path)
+            .map_err(|e| PluginError::Load(format!("failed to read {}: {e}", path.display())))?;
+        let key = PKey::public_key_from_pem(&pem).map_err(|e| {
+            PluginError::Load(format!("invalid public key {}: {e}", path.display()))
+        })?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            key,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn verifies(&self, data: &[u8], signature: &[u8]) -> bool {
+        // Edwards curve keys sign the message itself, without a digest.
+        let result = if matches!(self.key.id(), Id::ED25519 | Id::ED448) {
+            Verifier::new_without_digest(&self.key)
+                .and_then(|mut verifier| verifier.verify_oneshot(signature, data))
+        } else {
+            Verifier::new(MessageDigest::sha256(), &self.key).and_then(|mut verifier| {
+                verifier.update(data)?;
+                verifier.verify(signature)
+            })
+        };
+        result.unwrap_or(false)
+    }
+}
+
+/// Which plugins may be loaded, by signature.
+#[derive(Clone, Default)]
+pub struct SignaturePolicy {
+    /// Publisher keys; without any, signatures are not checked.
+    pub trusted_keys: Vec<TrustedKey>,
+    /// Refuse plugins without a `.sig` file instead of loading them with a
+    /// warning.
+    pub require: bool,
+}
+
+/// Path of the detached signature for the plugin at `path`.
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut sig = path.as_os_str().to_owned();
+    sig.push(".sig");
+    PathBuf::from(sig)
+}
+
+/// Verify `wasm_bytes`, read from `path`, against `policy`.
+pub(super) fn verify(
+    policy: &SignaturePolicy,
+    path: &Path,
+    wasm_bytes: &[u8],
+) -> Result<(), PluginError> {
+    if policy.trusted_keys.is_empty() && !policy.require {
+        return Ok(());
+    }
+
+    let sig_path = signature_path(path);
+    let signature = match std::fs::read(&sig_path) {
+        Ok(signature) => signature,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            if policy.require {
+                return Err(PluginError::SignatureMissing(format!(
+                    "{} has no signature file {}",
+                    path.display(),
+                    sig_path.display()
+                )));
+            }
+            tracing::warn!(path = %path.display(), "Loading unsigned plugin");
+            return Ok(());
+        }
+        Err(e) => {
+            return Err(PluginError::Load(format!(
+                "failed to read {}: {e}",
+                sig_path.display()
+            )));
+        }
+    };
+
+    match policy
+        .trusted_keys
+        .iter()
+        .find(|key| key.verifies(wasm_bytes, &signature))
+    {
+        Some(key) => {
+            tracing::debug!(path = %path.display(), key = %key.path().display(), "Plugin signature verified");
+            Ok(())
+        }
+        None => Err(PluginError::SignatureInvalid(format!(
+            "{} does not match {} under any trusted key",
+            sig_path.display(),
+            path.display()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::nid::Nid;
+    use openssl::pkey::Private;
+    use openssl::sign::Signer;
+
+    fn trusted(key: &PKey<Private>) -> TrustedKey {
+        let pem = key.public_key_to_pem().unwrap();
+        TrustedKey {
+            path: PathBuf::from("test.pem"),
+            key: PKey::public_key_from_pem(&pem).unwrap(),
+        }
+    }
+
+    fn sign(key: &PKey<Private>, data: &[u8]) -> Vec<u8> {
+        if key.id() == Id::ED25519 {
+            Signer::new_without_digest(key)
+                .unwrap()
+                .sign_oneshot_to_vec(data)
+                .unwrap()
+        } else {
+            let mut signer = Signer::new(MessageDigest::sha256(), key).unwrap();
+            signer.update(data).unwrap();
+            signer.sign_to_vec().unwrap()
+        }
+    }
+
+    /// Write `plugin.wasm` and, if given, its signature to a fresh directory.
+    fn plugin_file(name: &str, wasm: &[u8], signature: Option<&[u8]>) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "crowchiper-signature-{}-{name}",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("plugin.wasm");
+        std::fs::write(&path, wasm).unwrap();
+        std::fs::remove_file(signature_path(&path)).ok();
+        if let Some(signature) = signature {
+            std::fs::write(signature_path(&path), signature).unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn signature_path_appends_sig() {
+        assert_eq!(
+            signature_path(Path::new("/p/plugin.wasm")),
+            PathBuf::from("/p/plugin.wasm.sig")
+        );
+    }
+
+    #[test]
+    fn verifies_ed25519_and_ecdsa() {
+        let ed = PKey::generate_ed25519().unwrap();
+        let ec = PKey::from_ec_key(
+            EcKey::generate(&EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap()).unwrap(),
+        )
+        .unwrap();
+        let policy = SignaturePolicy {
+            trusted_keys: vec![trusted(&ed), trusted(&ec)],
+            require: true,
+        };
+
+        for (name, key) in [("ed25519", &ed), ("ecdsa", &ec)] {
+            let path = plugin_file(name, b"wasm", Some(&sign(key, b"wasm")));
+            verify(&policy, &path, b"wasm").unwrap();
+            // A modified file no longer matches.
+            let err = verify(&policy, &path, b"wasm!").unwrap_err();
+            assert!(
+                matches!(err, PluginError::SignatureInvalid(_)),
+                "got: {err}"
+            );
+        }
+    }
+
+    #[test]
+    fn untrusted_key_is_invalid() {
+        let publisher = PKey::generate_ed25519().unwrap();
+        let other = PKey::generate_ed25519().unwrap();
+        let policy = SignaturePolicy {
+            trusted_keys: vec![trusted(&publisher)],
+            require: false,
+        };
+        let path = plugin_file("untrusted", b"wasm", Some(&sign(&other, b"wasm")));
+        let err = verify(&policy, &path, b"wasm").unwrap_err();
+        assert!(
+            matches!(err, PluginError::SignatureInvalid(_)),
+            "got: {err}"
+        );
+    }
+
+    #[test]
+    fn missing_signature_depends_on_policy() {
+        let key = PKey::generate_ed25519().unwrap();
+        let path = plugin_file("unsigned", b"wasm", None);
+        let mut policy = SignaturePolicy {
+            trusted_keys: vec![trusted(&key)],
+            require: false,
+        };
+        verify(&policy, &path, b"wasm").unwrap();
+
+        policy.require = true;
+        let err = verify(&policy, &path, b"wasm").unwrap_err();
+        assert!(
+            matches!(err, PluginError::SignatureMissing(_)),
+            "got: {err}"
+        );
+    }
+
+    #[test]
+    fn no_keys_skips_verification() {
+        let path = plugin_file("no-keys", b"wasm", Some(b"garbage"));
+        verify(&SignaturePolicy::default(), &path, b"wasm").unwrap();
+    }
+}
@@ -1,12 +1,31 @@
 use wasmtime::StoreLimits;
 use wasmtime_wasi::{ResourceTable, WasiCtx, WasiCtxView, WasiView};
 
+use super::crowchiper::plugin::kv;
 use super::helpers::sanitize_plugin_output;
+use super::http_client::PluginHttp;
 use super::{LogLevel, PluginImports};
+use crate::db::Database;
 
 /// Maximum bytes of log output per single `log()` call from a plugin.
 const LOG_MESSAGE_LIMIT: usize = 4096;
 
+/// Maximum length in bytes of a key in the plugin key-value store.
+const KV_MAX_KEY_LEN: usize = 512;
+
+/// Maximum number of keys returned by a single `list-prefix` call.
+const KV_LIST_LIMIT: i64 = 1000;
+
+/// A plugin's namespace in the durable key-value store.
+#[derive(Clone)]
+pub(crate) struct PluginKv {
+    pub(crate) db: Database,
+    /// Namespace all keys are stored under (the plugin's declared name).
+    pub(crate) namespace: String,
+    /// Maximum total bytes of keys plus values.
+    pub(crate) quota: u64,
+}
+
 /// Per-instance WASI state for a plugin.
 ///
 /// Each plugin gets its own sandboxed WASI context (filesystem, stdio, env),
@@ -19,6 +38,11 @@ pub(crate) struct PluginState {
     pub(crate) limits: StoreLimits,
     /// Plugin name used for log output attribution (set from file path stem).
     pub(crate) plugin_name: String,
+    /// Key-value store access. `None` until the runtime attaches a database,
+    /// so the store is unavailable during `config()`.
+    pub(crate) kv: Option<PluginKv>,
+    /// Outbound HTTP client. `None` unless the plugin was granted `http=<host>`.
+    pub(crate) http: Option<PluginHttp>,
 }
 
 /// Implements the `WasiView` trait so wasmtime can access the WASI context
@@ -53,3 +77,72 @@ impl PluginImports for PluginState {
         }
     }
 }
+
+impl PluginState {
+    fn kv(&self) -> Result<&PluginKv, String> {
+        self.kv
+            .as_ref()
+            .ok_or_else(|| "kv store is not available".to_string())
+    }
+
+    fn kv_db_error(&self, error: sqlx::Error) -> String {
+        tracing::warn!(plugin = %self.plugin_name, error = %error, "Plugin kv store error");
+        "kv store error".to_string()
+    }
+}
+
+/// Implements the host-provided `kv` interface. Keys are scoped to the
+/// plugin's namespace, and writes are rejected once the quota would be exceeded.
+impl kv::Host for PluginState {
+    async fn get(&mut self, key: String) -> Result<Option<Vec<u8>>, String> {
+        let kv = self.kv()?;
+        let result = kv.db.plugin_kv().get(&kv.namespace, &key).await;
+        result.map_err(|e| self.kv_db_error(e))
+    }
+
+    async fn set(&mut self, key: String, value: Vec<u8>) -> Result<(), String> {
+        if key.is_empty() {
+            return Err("key must not be empty".to_string());
+        }
+        if key.len() > KV_MAX_KEY_LEN {
+            return Err(format!("key exceeds {KV_MAX_KEY_LEN} bytes"));
+        }
+
+        let kv = self.kv()?.clone();
+        let store = kv.db.plugin_kv();
+        let stored = store
+            .set_within_quota(&kv.namespace, &key, &value, kv.quota)
+            .await
+            .map_err(|e| self.kv_db_error(e))?;
+        if stored {
+            return Ok(());
+        }
+
+        // Only for the message; the write above was already refused.
+        let used = store
+            .usage_excluding(&kv.namespace, &key)
+            .await
+            .map_err(|e| self.kv_db_error(e))?;
+        let size = (key.len() + value.len()) as u64;
+        Err(format!(
+            "kv quota exceeded: {used} of {} bytes used, write needs {size}",
+            kv.quota
+        ))
+    }
+
+    async fn delete(&mut self, key: String) -> Result<bool, String> {
+        let kv = self.kv()?;
+        let result = kv.db.plugin_kv().delete(&kv.namespace, &key).await;
+        result.map_err(|e| self.kv_db_error(e))
+    }
+
+    async fn list_prefix(&mut self, prefix: String) -> Result<Vec<String>, String> {
+        let kv = self.kv()?;
+        let result = kv
+            .db
+            .plugin_kv()
+            .list_prefix(&kv.namespace, &prefix, KV_LIST_LIMIT)
+            .await;
+        result.map_err(|e| self.kv_db_error(e))
+    }
+}
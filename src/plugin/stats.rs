@@ -0,0 +1,324 @@
+//! Per-plugin call counters and circuit breaker state, reported by the admin
+//! plugin list.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::permissions::CircuitBreaker;
+
+/// Upper bounds in milliseconds of the latency histogram buckets. Slower
+/// calls land in one more overflow bucket.
+pub const LATENCY_BUCKETS_MS: [u64; 10] = [1, 5, 10, 25, 50, 100, 250, 500, 1000, 5000];
+
+/// How a call into the plugin ended, as far as the host is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum CallOutcome {
+    /// The export returned, whatever it answered.
+    Answered,
+    /// The plugin trapped or could not be reloaded.
+    Failed,
+    /// The wall-clock timeout fired.
+    TimedOut,
+}
+
+/// Counters for calls into a single plugin instance.
+///
+/// A call is any `on-hook` or `http-handler` invocation. It counts as an error
+/// when the plugin could not answer (timeout, trap, failed reload) or returned
+/// an error from a non-blocking hook or HTTP handler. Pre-hook denials are
+/// deliberate answers and are not errors. Calls skipped while the circuit
+/// breaker is open are only counted as `rejected`.
+#[derive(Debug, Default)]
+pub struct PluginStats {
+    calls: AtomicU64,
+    errors: AtomicU64,
+    timeouts: AtomicU64,
+    /// Instances recreated after a timeout.
+    reloads: AtomicU64,
+    rejected: AtomicU64,
+    fuel_consumed: AtomicU64,
+    /// Unix seconds of the most recent call; 0 if never called.
+    last_call_at: AtomicU64,
+    /// Failures since the last answered call.
+    consecutive_failures: AtomicU32,
+    /// Unix milliseconds until which calls are rejected; 0 if closed.
+    circuit_open_until: AtomicU64,
+    /// Per-hook breakdown, keyed by hook (`http` for the HTTP handler).
+    by_call: Mutex<BTreeMap<String, CallStats>>,
+    /// Unix seconds each schedule hook is due next, keyed by hook.
+    next_runs: Mutex<BTreeMap<String, u64>>,
+}
+
+/// Counters for one hook (or the HTTP handler) of a plugin.
+#[derive(Debug, Clone, Default)]
+pub struct CallStats {
+    pub calls: u64,
+    /// Failures plus errors returned by the plugin (see [`PluginStats`]).
+    pub errors: u64,
+    /// Calls the plugin could not answer: timeouts, traps, failed reloads.
+    pub failures: u64,
+    pub timeouts: u64,
+    pub fuel_consumed: u64,
+    pub latency: LatencyHistogram,
+}
+
+/// Call durations bucketed by [`LATENCY_BUCKETS_MS`].
+#[derive(Debug, Clone, Default)]
+pub struct LatencyHistogram {
+    counts: [u64; LATENCY_BUCKETS_MS.len() + 1],
+    sum_ms: u64,
+}
+
+impl LatencyHistogram {
+    fn record(&mut self, latency: Duration) {
+        let ms = latency.as_millis() as u64;
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|&bound| ms <= bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.counts[bucket] += 1;
+        self.sum_ms += ms;
+    }
+
+    /// `(upper bound in ms, count)` per bucket; the overflow bucket has no bound.
+    pub fn buckets(&self) -> impl Iterator<Item = (Option<u64>, u64)> + '_ {
+        LATENCY_BUCKETS_MS
+            .iter()
+            .map(|&bound| Some(bound))
+            .chain([None])
+            .zip(self.counts.iter().copied())
+    }
+
+    pub fn sum_ms(&self) -> u64 {
+        self.sum_ms
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+impl PluginStats {
+    /// Record a finished call to `label` and update the circuit breaker.
+    ///
+    /// Returns true if this call opened the breaker.
+    pub(crate) fn record_call(
+        &self,
+        label: &str,
+        outcome: CallOutcome,
+        latency: Duration,
+        fuel: u64,
+        breaker: &CircuitBreaker,
+    ) -> bool {
+        let now = unix_millis();
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.last_call_at.store(now / 1000, Ordering::Relaxed);
+        self.fuel_consumed.fetch_add(fuel, Ordering::Relaxed);
+        if outcome != CallOutcome::Answered {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        if outcome == CallOutcome::TimedOut {
+            self.timeouts.fetch_add(1, Ordering::Relaxed);
+        }
+
+        {
+            let mut by_call = self.by_call.lock().unwrap();
+            let stats = by_call.entry(label.to_string()).or_default();
+            stats.calls += 1;
+            stats.fuel_consumed += fuel;
+            stats.latency.record(latency);
+            if outcome != CallOutcome::Answered {
+                stats.errors += 1;
+                stats.failures += 1;
+            }
+            if outcome == CallOutcome::TimedOut {
+                stats.timeouts += 1;
+            }
+        }
+
+        if outcome == CallOutcome::Answered {
+            self.consecutive_failures.store(0, Ordering::Relaxed);
+            self.circuit_open_until.store(0, Ordering::Relaxed);
+            return false;
+        }
+        // Not reset when the breaker opens, so a failed trial call after the
+        // cool-down opens it again straight away.
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if breaker.failures == 0 || failures < breaker.failures {
+            return false;
+        }
+        let until = now + breaker.cooldown.as_millis() as u64;
+        self.circuit_open_until.store(until, Ordering::Relaxed);
+        true
+    }
+
+    /// Count an error the plugin returned from a call that otherwise succeeded.
+    pub(crate) fn record_error(&self, label: &str) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        let mut by_call = self.by_call.lock().unwrap();
+        by_call.entry(label.to_string()).or_default().errors += 1;
+    }
+
+    /// Record when the scheduler runs a schedule hook next.
+    pub(crate) fn record_next_run(&self, label: &str, unix_secs: u64) {
+        let mut next_runs = self.next_runs.lock().unwrap();
+        next_runs.insert(label.to_string(), unix_secs);
+    }
+
+    pub(crate) fn record_reload(&self) {
+        self.reloads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns true, and counts the call as rejected, if the breaker is open.
+    pub(crate) fn reject_if_open(&self) -> bool {
+        if self.circuit_open_until().is_none() {
+            return false;
+        }
+        self.rejected.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    /// Close the breaker, e.g. when an admin re-enables the plugin.
+    pub(crate) fn reset_breaker(&self) {
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+        self.circuit_open_until.store(0, Ordering::Relaxed);
+    }
+
+    pub fn calls(&self) -> u64 {
+        self.calls.load(Ordering::Relaxed)
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    pub fn timeouts(&self) -> u64 {
+        self.timeouts.load(Ordering::Relaxed)
+    }
+
+    pub fn reloads(&self) -> u64 {
+        self.reloads.load(Ordering::Relaxed)
+    }
+
+    /// Calls skipped because the circuit breaker was open.
+    pub fn rejected(&self) -> u64 {
+        self.rejected.load(Ordering::Relaxed)
+    }
+
+    pub fn fuel_consumed(&self) -> u64 {
+        self.fuel_consumed.load(Ordering::Relaxed)
+    }
+
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures.load(Ordering::Relaxed)
+    }
+
+    /// Unix seconds of the most recent call, if the plugin was ever called.
+    pub fn last_call_at(&self) -> Option<u64> {
+        match self.last_call_at.load(Ordering::Relaxed) {
+            0 => None,
+            secs => Some(secs),
+        }
+    }
+
+    /// Unix milliseconds until which the circuit breaker rejects calls,
+    /// if it is open now.
+    pub fn circuit_open_until(&self) -> Option<u64> {
+        let until = self.circuit_open_until.load(Ordering::Relaxed);
+        (until > unix_millis()).then_some(until)
+    }
+
+    /// Counters per hook, keyed by hook (`http` for the HTTP handler).
+    pub fn by_call(&self) -> BTreeMap<String, CallStats> {
+        self.by_call.lock().unwrap().clone()
+    }
+
+    /// Unix seconds each scheduled hook is due next, keyed by hook. Empty
+    /// until the scheduler picks the plugin up.
+    pub fn next_runs(&self) -> BTreeMap<String, u64> {
+        self.next_runs.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(failures: u32) -> CircuitBreaker {
+        CircuitBreaker {
+            failures,
+            cooldown: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn latency_histogram_buckets() {
+        let mut histogram = LatencyHistogram::default();
+        histogram.record(Duration::from_micros(500));
+        histogram.record(Duration::from_millis(7));
+        histogram.record(Duration::from_secs(10));
+        let buckets: Vec<_> = histogram.buckets().collect();
+        assert_eq!(buckets.len(), LATENCY_BUCKETS_MS.len() + 1);
+        assert_eq!(buckets[0], (Some(1), 1));
+        assert_eq!(buckets[2], (Some(10), 1));
+        assert_eq!(buckets.last(), Some(&(None, 1)));
+        assert_eq!(histogram.sum_ms(), 10_007);
+    }
+
+    #[test]
+    fn counts_per_hook() {
+        let stats = PluginStats::default();
+        let b = breaker(5);
+        stats.record_call("a", CallOutcome::Answered, Duration::ZERO, 100, &b);
+        stats.record_call("a", CallOutcome::TimedOut, Duration::ZERO, 0, &b);
+        stats.record_call("b", CallOutcome::Answered, Duration::ZERO, 50, &b);
+        stats.record_error("b");
+
+        assert_eq!(stats.calls(), 3);
+        assert_eq!(stats.errors(), 2);
+        assert_eq!(stats.timeouts(), 1);
+        assert_eq!(stats.fuel_consumed(), 150);
+        let by_call = stats.by_call();
+        assert_eq!(by_call["a"].calls, 2);
+        assert_eq!(by_call["a"].failures, 1);
+        assert_eq!(by_call["a"].timeouts, 1);
+        assert_eq!(by_call["b"].errors, 1);
+        assert_eq!(by_call["b"].failures, 0);
+    }
+
+    #[test]
+    fn breaker_opens_after_consecutive_failures() {
+        let stats = PluginStats::default();
+        let b = breaker(3);
+        assert!(!stats.record_call("a", CallOutcome::Failed, Duration::ZERO, 0, &b));
+        assert!(!stats.record_call("a", CallOutcome::Failed, Duration::ZERO, 0, &b));
+        // An answer in between starts the count again.
+        stats.record_call("a", CallOutcome::Answered, Duration::ZERO, 0, &b);
+        assert!(!stats.record_call("a", CallOutcome::Failed, Duration::ZERO, 0, &b));
+        assert!(!stats.record_call("a", CallOutcome::TimedOut, Duration::ZERO, 0, &b));
+        assert!(!stats.reject_if_open());
+        assert!(stats.record_call("a", CallOutcome::Failed, Duration::ZERO, 0, &b));
+
+        assert!(stats.circuit_open_until().is_some());
+        assert!(stats.reject_if_open());
+        assert_eq!(stats.rejected(), 1);
+
+        stats.reset_breaker();
+        assert!(stats.circuit_open_until().is_none());
+        assert_eq!(stats.consecutive_failures(), 0);
+    }
+
+    #[test]
+    fn breaker_off_never_opens() {
+        let stats = PluginStats::default();
+        for _ in 0..100 {
+            assert!(!stats.record_call("a", CallOutcome::Failed, Duration::ZERO, 0, &breaker(0)));
+        }
+        assert!(stats.circuit_open_until().is_none());
+    }
+}
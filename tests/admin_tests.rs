@@ -293,3 +293,149 @@ async fn test_old_encryption_settings_endpoint_removed() {
         "Old /api/encryption/settings endpoint should be removed"
     );
 }
+
+// --- Storage quota tests ---
+
+/// Send a JSON request with an access token. Returns (status, JSON body).
+async fn send_json(
+    app: &axum::Router,
+    access: &str,
+    method: &str,
+    uri: &str,
+    body: Option<serde_json::Value>,
+) -> (StatusCode, serde_json::Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("cookie", format!("access_token={}", access))
+        .header("content-type", "application/json");
+    let body = match body {
+        Some(json) => Body::from(json.to_string()),
+        None => Body::empty(),
+    };
+    let response = app
+        .clone()
+        .oneshot(request.body(body).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null),
+    )
+}
+
+#[tokio::test]
+async fn test_storage_quotas() {
+    let (app, db) = create_test_app().await;
+    let jwt = create_jwt();
+
+    let user_uuid = "00000000-0000-0000-0000-000000000001";
+    let user_id = db.users().create(user_uuid, "alice").await.unwrap();
+    db.users().activate(user_id).await.unwrap();
+    let user = jwt
+        .generate_access_token(user_uuid, "alice", UserRole::User, "127.0.0.1")
+        .unwrap()
+        .token;
+
+    let admin_uuid = "00000000-0000-0000-0000-000000000002";
+    let admin_id = db.users().create_admin(admin_uuid, "admin").await.unwrap();
+    db.users().activate(admin_id).await.unwrap();
+    let admin = jwt
+        .generate_access_token(admin_uuid, "admin", UserRole::Admin, "127.0.0.1")
+        .unwrap()
+        .token;
+
+    // Unlimited by default
+    let (status, usage) = send_json(&app, &user, "GET", "/api/user/usage", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(usage["used"], 0);
+    assert!(usage["quota"].is_null());
+
+    // Only admins manage quotas
+    let body = serde_json::json!({ "default_quota": 1000 });
+    let (status, _) = send_json(&app, &user, "PUT", "/api/admin/quota", Some(body.clone())).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, json) = send_json(&app, &admin, "PUT", "/api/admin/quota", Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["default_quota"], 1000);
+    let (_, json) = send_json(&app, &admin, "GET", "/api/admin/quota", None).await;
+    assert_eq!(json["default_quota"], 1000);
+
+    let post = |content: usize| serde_json::json!({ "title": "t", "content": "x".repeat(content) });
+    let (status, _) = send_json(&app, &user, "POST", "/api/posts", Some(post(599))).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, json) = send_json(&app, &user, "POST", "/api/posts", Some(post(599))).await;
+    assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE);
+    assert!(
+        json["error"]
+            .as_str()
+            .unwrap()
+            .contains("Storage quota exceeded")
+    );
+
+    let (_, usage) = send_json(&app, &user, "GET", "/api/user/usage", None).await;
+    assert_eq!(usage["used"], 600);
+    assert_eq!(usage["posts"], 600);
+    assert_eq!(usage["attachments"], 0);
+    assert_eq!(usage["quota"], 1000);
+
+    // A user's own quota overrides the default
+    let uri = format!("/api/admin/users/{}/quota", user_uuid);
+    let body = serde_json::json!({ "quota": 5000 });
+    let (status, json) = send_json(&app, &admin, "PUT", &uri, Some(body)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["user_quota"], 5000);
+    assert_eq!(json["quota"], 5000);
+    assert_eq!(json["used"], 600);
+    let (status, _) = send_json(&app, &user, "POST", "/api/posts", Some(post(599))).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    // Clearing it falls back to the default
+    let body = serde_json::json!({ "quota": null });
+    let (_, json) = send_json(&app, &admin, "PUT", &uri, Some(body)).await;
+    assert!(json["user_quota"].is_null());
+    assert_eq!(json["quota"], 1000);
+    let usage_uri = format!("/api/admin/users/{}/usage", user_uuid);
+    let (status, json) = send_json(&app, &admin, "GET", &usage_uri, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["used"], 1200);
+
+    let unknown = "/api/admin/users/00000000-0000-0000-0000-000000000009/usage";
+    let (status, _) = send_json(&app, &admin, "GET", unknown, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_post_updates_over_quota() {
+    let (app, db) = create_test_app().await;
+    let jwt = create_jwt();
+
+    let user_uuid = "00000000-0000-0000-0000-000000000001";
+    let user_id = db.users().create(user_uuid, "alice").await.unwrap();
+    db.users().activate(user_id).await.unwrap();
+    let user = jwt
+        .generate_access_token(user_uuid, "alice", UserRole::User, "127.0.0.1")
+        .unwrap()
+        .token;
+
+    let body = serde_json::json!({ "content": "x".repeat(100) });
+    let (status, json) = send_json(&app, &user, "POST", "/api/posts", Some(body)).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let uri = format!("/api/posts/{}", json["uuid"].as_str().unwrap());
+    db.quotas().set_user_quota(user_id, Some(50)).await.unwrap();
+
+    // Already over quota: growing the post fails, shrinking it works
+    let body = serde_json::json!({ "content": "x".repeat(101) });
+    let (status, _) = send_json(&app, &user, "PUT", &uri, Some(body)).await;
+    assert_eq!(status, StatusCode::INSUFFICIENT_STORAGE);
+    let body = serde_json::json!({ "content": "x".repeat(40) });
+    let (status, _) = send_json(&app, &user, "PUT", &uri, Some(body)).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let body = serde_json::json!({ "content": "x".repeat(50) });
+    let (status, _) = send_json(&app, &user, "PUT", &uri, Some(body)).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+}
@@ -6,6 +6,7 @@ use axum::{
     body::Body,
     http::{Request, StatusCode},
 };
+use crowchiper::db::attachments::CreateAttachmentInput;
 #[cfg(feature = "test-mode")]
 use crowchiper::local_ip_extractor;
 use crowchiper::{ServerConfig, create_app, db::Database, jwt::JwtConfig};
@@ -154,14 +155,26 @@ fn build_multipart_body(
     (content_type, body)
 }
 
-/// Upload an attachment via the API and return its UUID.
+/// A PNG whose pixels are `label`, so different labels give different images.
+/// The server accepts it unchanged, as it has no metadata to strip.
+fn test_image(label: &[u8]) -> Vec<u8> {
+    use image::ImageEncoder;
+    let mut png = Vec::new();
+    image::codecs::png::PngEncoder::new(&mut png)
+        .write_image(label, label.len() as u32, 1, image::ExtendedColorType::L8)
+        .unwrap();
+    png
+}
+
+/// Upload `test_image(label)` via the API and return its UUID.
 async fn upload_attachment(
     app: &axum::Router,
     access: &str,
     refresh: &str,
-    image: &[u8],
+    label: &[u8],
 ) -> String {
-    let (content_type, body) = build_multipart_body(image, b"thumb_sm_data", None, None);
+    let (content_type, body) =
+        build_multipart_body(&test_image(label), b"thumb_sm_data", None, None);
 
     let response = app
         .clone()
@@ -203,7 +216,7 @@ async fn test_upload_unencrypted_attachment() {
         .await
         .unwrap()
         .unwrap();
-    assert_eq!(attachment.image_data, b"test_image_data");
+    assert_eq!(attachment.image_data, test_image(b"test_image_data"));
     assert_eq!(attachment.encryption_version, 0);
     assert!(attachment.image_iv.is_none());
     assert_eq!(attachment.reference_count, 0);
@@ -240,7 +253,7 @@ async fn test_get_attachment_via_api() {
     let body = axum::body::to_bytes(response.into_body(), usize::MAX)
         .await
         .unwrap();
-    assert_eq!(&body[..], b"image_bytes_here");
+    assert_eq!(&body[..], test_image(b"image_bytes_here"));
 }
 
 #[tokio::test]
@@ -249,7 +262,7 @@ async fn test_get_thumbnail_via_api() {
     let (_, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
 
     let (content_type, body) = build_multipart_body(
-        b"img",
+        &test_image(b"img"),
         b"small_thumb",
         Some(b"med_thumb"),
         Some(b"large_thumb"),
@@ -277,8 +290,8 @@ async fn test_get_thumbnail_via_api() {
     let json: serde_json::Value = serde_json::from_slice(&resp_body).unwrap();
     let uuid = json["uuid"].as_str().unwrap();
 
-    // Fetch each thumbnail size
-    for (size, expected) in [
+    // Thumbnails sent with an unencrypted image are replaced by rendered ones
+    for (size, sent) in [
         ("sm", b"small_thumb" as &[u8]),
         ("md", b"med_thumb"),
         ("lg", b"large_thumb"),
@@ -306,10 +319,44 @@ async fn test_get_thumbnail_via_api() {
         let body = axum::body::to_bytes(response.into_body(), usize::MAX)
             .await
             .unwrap();
-        assert_eq!(&body[..], expected, "Wrong data for size {}", size);
+        assert_ne!(&body[..], sent, "Client data kept for size {}", size);
+        assert!(
+            image::load_from_memory(&body).is_ok(),
+            "Size {} is not an image",
+            size
+        );
     }
 }
 
+#[tokio::test]
+async fn test_get_thumbnails_renders_missing_sizes() {
+    let (app, db, jwt) = create_test_app().await;
+    let (user_id, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
+
+    let uuid = upload_attachment(&app, &access, &refresh, b"image").await;
+    let uri = format!("/api/attachments/{uuid}/thumbnails");
+    let response = get_with_headers(&app, &access, &refresh, &uri, &[]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = body_bytes(response).await;
+    for size in ["sm", "md", "lg"] {
+        let header = format!("X-Thumbnail-Size: {size}\r\n");
+        assert!(
+            body.windows(header.len()).any(|w| w == header.as_bytes()),
+            "missing part for {size}"
+        );
+    }
+    assert!(!body.windows(13).any(|w| w == b"thumb_sm_data"));
+
+    let thumbnails = db
+        .attachments()
+        .get_thumbnails(&uuid, user_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(thumbnails.md.is_some());
+    assert!(thumbnails.lg.is_some());
+}
+
 #[tokio::test]
 async fn test_cannot_access_other_users_attachment() {
     let (app, db, jwt) = create_test_app().await;
@@ -903,3 +950,1370 @@ async fn test_delete_parent_post_cleans_up_child_attachments() {
         "Child attachment should be cleaned up"
     );
 }
+
+// --- Range and caching ---
+
+/// GET `uri` as the given user with extra request headers.
+async fn get_with_headers(
+    app: &axum::Router,
+    access: &str,
+    refresh: &str,
+    uri: &str,
+    headers: &[(&str, &str)],
+) -> axum::response::Response {
+    let mut request = Request::builder()
+        .method("GET")
+        .uri(uri)
+        .header("cookie", auth_cookies(access, refresh))
+        .header("x-forwarded-for", TEST_IP);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    app.clone()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+}
+
+async fn body_bytes(response: axum::response::Response) -> Vec<u8> {
+    axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap()
+        .to_vec()
+}
+
+#[tokio::test]
+async fn test_attachment_caching_headers() {
+    let (app, db, jwt) = create_test_app().await;
+    let (_, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
+    let data = test_image(b"0123456789");
+    let uuid = upload_attachment(&app, &access, &refresh, b"0123456789").await;
+    let uri = format!("/api/attachments/{}", uuid);
+
+    let response = get_with_headers(&app, &access, &refresh, &uri, &[]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let headers = response.headers().clone();
+    let etag = headers.get("etag").unwrap().to_str().unwrap().to_string();
+    assert!(etag.starts_with('"') && etag.ends_with('"'), "strong ETag");
+    assert_eq!(
+        headers.get("cache-control").unwrap(),
+        "private, max-age=31536000, immutable"
+    );
+    assert_eq!(headers.get("accept-ranges").unwrap(), "bytes");
+    assert_eq!(
+        headers.get("content-length").unwrap(),
+        &data.len().to_string()
+    );
+    assert_eq!(body_bytes(response).await, data);
+
+    // Revalidating with the ETag returns 304 without a body
+    let response =
+        get_with_headers(&app, &access, &refresh, &uri, &[("if-none-match", &etag)]).await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers().get("etag").unwrap(), etag.as_str());
+    assert!(body_bytes(response).await.is_empty());
+
+    // A stale ETag gets the full content
+    let response = get_with_headers(
+        &app,
+        &access,
+        &refresh,
+        &uri,
+        &[("if-none-match", "\"stale\"")],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // Thumbnails carry their own ETag
+    let thumb_uri = format!("/api/attachments/{}/thumbnail/sm", uuid);
+    let response = get_with_headers(&app, &access, &refresh, &thumb_uri, &[]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let thumb_etag = response.headers().get("etag").unwrap().clone();
+    assert_ne!(thumb_etag, etag.as_str());
+    let response = get_with_headers(
+        &app,
+        &access,
+        &refresh,
+        &thumb_uri,
+        &[("if-none-match", thumb_etag.to_str().unwrap())],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+}
+
+#[tokio::test]
+async fn test_attachment_range_requests() {
+    let (app, db, jwt) = create_test_app().await;
+    let (_, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
+    let data = test_image(b"0123456789");
+    let len = data.len();
+    let uuid = upload_attachment(&app, &access, &refresh, b"0123456789").await;
+    let uri = format!("/api/attachments/{}", uuid);
+
+    let cases = [
+        ("bytes=2-5".to_string(), 2..6),
+        (format!("bytes={}-", len - 3), len - 3..len),
+        ("bytes=-3".to_string(), len - 3..len),
+        (format!("bytes={}-{}", len - 2, len + 100), len - 2..len),
+        (format!("bytes=-{}", len + 50), 0..len),
+    ];
+    for (range, expected) in cases {
+        let response =
+            get_with_headers(&app, &access, &refresh, &uri, &[("range", range.as_str())]).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT, "{range}");
+        assert_eq!(
+            response.headers().get("content-range").unwrap(),
+            &format!("bytes {}-{}/{len}", expected.start, expected.end - 1)
+        );
+        assert_eq!(
+            response.headers().get("content-length").unwrap(),
+            &expected.len().to_string()
+        );
+        assert_eq!(body_bytes(response).await, &data[expected], "{range}");
+    }
+
+    // Past the end
+    let past_end = format!("bytes={len}-");
+    let response = get_with_headers(
+        &app,
+        &access,
+        &refresh,
+        &uri,
+        &[("range", past_end.as_str())],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(
+        response.headers().get("content-range").unwrap(),
+        &format!("bytes */{len}")
+    );
+
+    // Malformed and multi-range requests get the whole attachment
+    for range in ["bytes=5-2", "items=0-1", "bytes=0-1,4-5"] {
+        let response = get_with_headers(&app, &access, &refresh, &uri, &[("range", range)]).await;
+        assert_eq!(response.status(), StatusCode::OK, "{range}");
+        assert_eq!(body_bytes(response).await, data);
+    }
+
+    // If-Range with a different ETag ignores the range
+    let response = get_with_headers(
+        &app,
+        &access,
+        &refresh,
+        &uri,
+        &[("range", "bytes=0-1"), ("if-range", "\"other\"")],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let etag = get_with_headers(&app, &access, &refresh, &uri, &[])
+        .await
+        .headers()
+        .get("etag")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    let response = get_with_headers(
+        &app,
+        &access,
+        &refresh,
+        &uri,
+        &[("range", "bytes=0-1"), ("if-range", &etag)],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(body_bytes(response).await, &data[..2]);
+}
+
+// --- File attachments ---
+
+/// Build a multipart/form-data body from (name, value) fields.
+fn build_fields_body(fields: &[(&str, &[u8])]) -> (String, Vec<u8>) {
+    let boundary = "----TestBoundary12345";
+    let mut body = Vec::new();
+    for (name, value) in fields {
+        body.extend_from_slice(format!("--{}\r\n", boundary).as_bytes());
+        body.extend_from_slice(
+            format!("Content-Disposition: form-data; name=\"{}\"\r\n\r\n", name).as_bytes(),
+        );
+        body.extend_from_slice(value);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+    (format!("multipart/form-data; boundary={}", boundary), body)
+}
+
+async fn post_upload(
+    app: &axum::Router,
+    access: &str,
+    refresh: &str,
+    fields: &[(&str, &[u8])],
+) -> axum::response::Response {
+    let (content_type, body) = build_fields_body(fields);
+    app.clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri("/api/attachments")
+                .header("content-type", &content_type)
+                .header("cookie", auth_cookies(access, refresh))
+                .header("x-forwarded-for", TEST_IP)
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+}
+
+async fn upload_file(
+    app: &axum::Router,
+    access: &str,
+    refresh: &str,
+    data: &[u8],
+    filename: &str,
+    mime_type: &str,
+) -> String {
+    let response = post_upload(
+        app,
+        access,
+        refresh,
+        &[
+            ("file", data),
+            ("file_iv", b""),
+            ("filename", filename.as_bytes()),
+            ("filename_iv", b""),
+            ("mime_type", mime_type.as_bytes()),
+            ("encryption_version", b"0"),
+        ],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let json: serde_json::Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+    json["uuid"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_upload_and_download_file_attachment() {
+    let (app, db, jwt) = create_test_app().await;
+    let (_, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
+
+    let uuid = upload_file(
+        &app,
+        &access,
+        &refresh,
+        b"%PDF-1.7 test",
+        "report.pdf",
+        "Application/PDF",
+    )
+    .await;
+
+    let response = get_with_headers(
+        &app,
+        &access,
+        &refresh,
+        &format!("/api/attachments/{}/info", uuid),
+        &[],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let info: serde_json::Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+    assert_eq!(info["kind"], "file");
+    assert_eq!(info["filename"], "report.pdf");
+    assert!(info["filename_iv"].is_null());
+    assert_eq!(info["mime_type"], "application/pdf");
+    assert_eq!(info["has_thumbnails"], false);
+
+    let download_uri = format!("/api/attachments/{}/download", uuid);
+    let response = get_with_headers(&app, &access, &refresh, &download_uri, &[]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get("content-type").unwrap(),
+        "application/pdf"
+    );
+    assert_eq!(
+        response.headers().get("content-disposition").unwrap(),
+        "attachment; filename=\"report.pdf\"; filename*=UTF-8''report.pdf"
+    );
+    assert_eq!(
+        response.headers().get("x-content-type-options").unwrap(),
+        "nosniff"
+    );
+    assert_eq!(body_bytes(response).await, b"%PDF-1.7 test");
+
+    // Ranges work on downloads too
+    let response = get_with_headers(
+        &app,
+        &access,
+        &refresh,
+        &download_uri,
+        &[("range", "bytes=0-3")],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(body_bytes(response).await, b"%PDF");
+
+    // No preview was uploaded
+    let response = get_with_headers(
+        &app,
+        &access,
+        &refresh,
+        &format!("/api/attachments/{}/thumbnail/sm", uuid),
+        &[],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_file_download_encodes_filename() {
+    let (app, db, jwt) = create_test_app().await;
+    let (_, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
+
+    let uuid = upload_file(&app, &access, &refresh, b"PK", "Übung \"1\".zip", "").await;
+
+    let response = get_with_headers(
+        &app,
+        &access,
+        &refresh,
+        &format!("/api/attachments/{}/download", uuid),
+        &[],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    // Missing MIME types default to application/octet-stream
+    assert_eq!(
+        response.headers().get("content-type").unwrap(),
+        "application/octet-stream"
+    );
+    assert_eq!(
+        response.headers().get("content-disposition").unwrap(),
+        "attachment; filename=\"_bung _1_.zip\"; filename*=UTF-8''%C3%9Cbung%20%221%22.zip"
+    );
+}
+
+#[tokio::test]
+async fn test_file_attachment_with_preview() {
+    let (app, db, jwt) = create_test_app().await;
+    let (_, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
+
+    let response = post_upload(
+        &app,
+        &access,
+        &refresh,
+        &[
+            ("file", b"audio bytes"),
+            ("filename", b"song.mp3"),
+            ("mime_type", b"audio/mpeg"),
+            ("thumb_sm", b"cover art"),
+            ("thumb_sm_iv", b""),
+            ("encryption_version", b"0"),
+        ],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let json: serde_json::Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+    let uuid = json["uuid"].as_str().unwrap();
+
+    let response = get_with_headers(
+        &app,
+        &access,
+        &refresh,
+        &format!("/api/attachments/{}/thumbnail/sm", uuid),
+        &[],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(body_bytes(response).await, b"cover art");
+}
+
+#[tokio::test]
+async fn test_file_upload_validation() {
+    let (app, db, jwt) = create_test_app().await;
+    let (_, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
+
+    let cases: &[(&[(&str, &[u8])], &str)] = &[
+        (
+            &[("file", b"x"), ("encryption_version", b"0")],
+            "Missing filename field",
+        ),
+        (
+            &[
+                ("file", b"x"),
+                ("image", b"x"),
+                ("filename", b"a.txt"),
+                ("encryption_version", b"0"),
+            ],
+            "Send either an image or a file, not both",
+        ),
+        (
+            &[
+                ("file", b"x"),
+                ("filename", b"a.txt"),
+                ("mime_type", b"not a type"),
+                ("encryption_version", b"0"),
+            ],
+            "Invalid mime_type",
+        ),
+        (
+            &[
+                ("file", b"x"),
+                ("filename", b"a.txt"),
+                ("filename_iv", b"some-iv"),
+                ("encryption_version", b"0"),
+            ],
+            "filename_iv must be set exactly when the file is encrypted",
+        ),
+        (
+            &[
+                ("file", b"x"),
+                ("filename", b"a.txt"),
+                ("thumb_md", b"x"),
+                ("encryption_version", b"0"),
+            ],
+            "A file preview needs a small thumbnail",
+        ),
+    ];
+    for (fields, error) in cases {
+        let response = post_upload(&app, &access, &refresh, fields).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{error}");
+        let body = String::from_utf8(body_bytes(response).await).unwrap();
+        assert!(body.contains(error), "expected '{error}', got {body}");
+    }
+}
+
+#[tokio::test]
+async fn test_encrypted_upload_requires_ivs() {
+    let (app, db, jwt) = create_test_app().await;
+    let (user_id, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
+    db.encryption_settings()
+        .create(user_id, b"prf-salt")
+        .await
+        .unwrap();
+
+    let cases: &[(&[(&str, &[u8])], &str)] = &[
+        (
+            &[
+                ("file", b"x"),
+                ("filename", b"name"),
+                ("filename_iv", b"name-iv"),
+                ("encryption_version", b"1"),
+            ],
+            "file_iv must be set exactly when the file is encrypted",
+        ),
+        (
+            &[
+                ("file", b"x"),
+                ("file_iv", b"file-iv"),
+                ("filename", b"name"),
+                ("filename_iv", b"name-iv"),
+                ("thumb_sm", b"x"),
+                ("thumb_sm_iv", b""),
+                ("encryption_version", b"1"),
+            ],
+            "Missing thumb_sm_iv field",
+        ),
+        (
+            &[
+                ("image", b"x"),
+                ("image_iv", b"image-iv"),
+                ("thumb_sm", b"x"),
+                ("thumb_sm_iv", b"sm-iv"),
+                ("thumb_md", b"x"),
+                ("encryption_version", b"1"),
+            ],
+            "Missing thumb_md_iv field",
+        ),
+        (
+            &[
+                ("image", b"x"),
+                ("image_iv", b"image-iv\r\nX-Injected: 1"),
+                ("thumb_sm", b"x"),
+                ("thumb_sm_iv", b"sm-iv"),
+                ("encryption_version", b"1"),
+            ],
+            "Invalid image_iv",
+        ),
+        (
+            &[
+                ("image", b"x"),
+                ("image_iv", b"image-iv"),
+                ("thumb_sm", b"x"),
+                ("thumb_sm_iv", &[b'a'; 65]),
+                ("encryption_version", b"1"),
+            ],
+            "Invalid thumb_sm_iv",
+        ),
+    ];
+    for (fields, error) in cases {
+        let response = post_upload(&app, &access, &refresh, fields).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{error}");
+        let body = String::from_utf8(body_bytes(response).await).unwrap();
+        assert!(body.contains(error), "expected '{error}', got {body}");
+    }
+}
+
+#[tokio::test]
+async fn test_file_size_limit_depends_on_type() {
+    let (app, db, jwt) = create_test_app().await;
+    let (_, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
+
+    // Over the 25MB default for files, but within the 50MB audio limit
+    let data = vec![0u8; 25 * 1024 * 1024 + 1];
+    let upload = |mime_type: &'static [u8]| {
+        let (app, access, refresh, data) = (&app, &access, &refresh, &data);
+        async move {
+            post_upload(
+                app,
+                access,
+                refresh,
+                &[
+                    ("file", data),
+                    ("filename", b"big"),
+                    ("mime_type", mime_type),
+                    ("encryption_version", b"0"),
+                ],
+            )
+            .await
+        }
+    };
+
+    let response = upload(b"application/zip").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = String::from_utf8(body_bytes(response).await).unwrap();
+    assert!(body.contains("File too large (max 25MB)"), "{body}");
+
+    let response = upload(b"audio/ogg").await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+}
+
+// --- Resumable uploads ---
+
+/// Send a request to the uploads API as the given user.
+async fn upload_request(
+    app: &axum::Router,
+    access: &str,
+    refresh: &str,
+    method: &str,
+    uri: &str,
+    headers: &[(&str, &str)],
+    body: Vec<u8>,
+) -> axum::response::Response {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("cookie", auth_cookies(access, refresh))
+        .header("x-forwarded-for", TEST_IP);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    app.clone()
+        .oneshot(request.body(Body::from(body)).unwrap())
+        .await
+        .unwrap()
+}
+
+/// Start a resumable upload and return its URL.
+async fn start_upload(app: &axum::Router, access: &str, refresh: &str, length: usize) -> String {
+    let response = upload_request(
+        app,
+        access,
+        refresh,
+        "POST",
+        "/api/attachments/uploads",
+        &[("upload-length", &length.to_string())],
+        Vec::new(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers().get("upload-offset").unwrap(), "0");
+    let location = response
+        .headers()
+        .get("location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    let json: serde_json::Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+    assert_eq!(
+        location,
+        format!(
+            "/api/attachments/uploads/{}",
+            json["upload_id"].as_str().unwrap()
+        )
+    );
+    location
+}
+
+async fn send_chunk(
+    app: &axum::Router,
+    access: &str,
+    refresh: &str,
+    location: &str,
+    offset: usize,
+    chunk: &[u8],
+) -> axum::response::Response {
+    upload_request(
+        app,
+        access,
+        refresh,
+        "PATCH",
+        location,
+        &[
+            ("content-type", "application/offset+octet-stream"),
+            ("upload-offset", &offset.to_string()),
+        ],
+        chunk.to_vec(),
+    )
+    .await
+}
+
+async fn upload_offset(app: &axum::Router, access: &str, refresh: &str, location: &str) -> String {
+    let response = upload_request(app, access, refresh, "HEAD", location, &[], Vec::new()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers().get("cache-control").unwrap(), "no-store");
+    response
+        .headers()
+        .get("upload-offset")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+async fn finalize_upload(
+    app: &axum::Router,
+    access: &str,
+    refresh: &str,
+    location: &str,
+    fields: &[(&str, &[u8])],
+) -> axum::response::Response {
+    let (content_type, body) = build_fields_body(fields);
+    upload_request(
+        app,
+        access,
+        refresh,
+        "POST",
+        &format!("{}/finalize", location),
+        &[("content-type", &content_type)],
+        body,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_resumable_file_upload() {
+    let (app, db, jwt) = create_test_app().await;
+    let (_, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
+
+    let location = start_upload(&app, &access, &refresh, 11).await;
+
+    let response = send_chunk(&app, &access, &refresh, &location, 0, b"hello ").await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(response.headers().get("upload-offset").unwrap(), "6");
+    assert_eq!(response.headers().get("upload-length").unwrap(), "11");
+
+    // A retried chunk at a stale offset is rejected; HEAD says where to resume
+    let response = send_chunk(&app, &access, &refresh, &location, 0, b"hello ").await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(upload_offset(&app, &access, &refresh, &location).await, "6");
+
+    let file_fields: &[(&str, &[u8])] = &[
+        ("kind", b"file"),
+        ("file_iv", b""),
+        ("filename", b"notes.txt"),
+        ("filename_iv", b""),
+        ("mime_type", b"text/plain"),
+        ("encryption_version", b"0"),
+    ];
+    let response = finalize_upload(&app, &access, &refresh, &location, file_fields).await;
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let response = send_chunk(&app, &access, &refresh, &location, 6, b"world").await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(response.headers().get("upload-offset").unwrap(), "11");
+
+    let response = finalize_upload(&app, &access, &refresh, &location, file_fields).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let json: serde_json::Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+    let uuid = json["uuid"].as_str().unwrap();
+
+    let response = get_with_headers(
+        &app,
+        &access,
+        &refresh,
+        &format!("/api/attachments/{}/download", uuid),
+        &[],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get("content-type").unwrap(),
+        "text/plain"
+    );
+    assert_eq!(body_bytes(response).await, b"hello world");
+
+    // The upload is gone once finalized
+    let response =
+        upload_request(&app, &access, &refresh, "HEAD", &location, &[], Vec::new()).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_resumable_encrypted_image_upload() {
+    let (app, db, jwt) = create_test_app().await;
+    let (user_id, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
+    db.encryption_settings()
+        .create(user_id, b"prf-salt")
+        .await
+        .unwrap();
+
+    let ciphertext = b"encrypted image bytes";
+    let location = start_upload(&app, &access, &refresh, ciphertext.len()).await;
+    for (offset, chunk) in [(0, &ciphertext[..9]), (9, &ciphertext[9..])] {
+        let response = send_chunk(&app, &access, &refresh, &location, offset, chunk).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+
+    // Finalizing runs the usual encryption checks
+    let mut fields: Vec<(&str, &[u8])> = vec![
+        ("kind", b"image"),
+        ("image_iv", b"image-iv"),
+        ("thumb_sm", b"encrypted thumb"),
+        ("thumb_sm_iv", b"thumb-iv"),
+        ("encryption_version", b"0"),
+    ];
+    let response = finalize_upload(&app, &access, &refresh, &location, &fields).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    fields.pop();
+    fields.push(("encryption_version", b"1"));
+    let response = finalize_upload(&app, &access, &refresh, &location, &fields).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let json: serde_json::Value = serde_json::from_slice(&body_bytes(response).await).unwrap();
+    let uuid = json["uuid"].as_str().unwrap();
+
+    let response = get_with_headers(
+        &app,
+        &access,
+        &refresh,
+        &format!("/api/attachments/{}", uuid),
+        &[],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers().get("x-encryption-iv").unwrap(),
+        "image-iv"
+    );
+    assert_eq!(body_bytes(response).await, ciphertext);
+}
+
+#[tokio::test]
+async fn test_resumable_upload_validation() {
+    let (app, db, jwt) = create_test_app().await;
+    let (_, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
+    let (_, bob_access, bob_refresh) = create_authenticated_user(&db, &jwt, "bob").await;
+
+    // Larger than any attachment may be
+    let response = upload_request(
+        &app,
+        &access,
+        &refresh,
+        "POST",
+        "/api/attachments/uploads",
+        &[("upload-length", "1099511627776")],
+        Vec::new(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let location = start_upload(&app, &access, &refresh, 4).await;
+
+    // Chunks must not run past Upload-Length
+    let response = send_chunk(&app, &access, &refresh, &location, 0, b"12345").await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(upload_offset(&app, &access, &refresh, &location).await, "0");
+
+    // Chunks need the tus content type
+    let response = upload_request(
+        &app,
+        &access,
+        &refresh,
+        "PATCH",
+        &location,
+        &[("upload-offset", "0")],
+        b"1234".to_vec(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = send_chunk(&app, &access, &refresh, &location, 0, b"1234").await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    // The data only comes in chunks, and the kind must be given
+    let response = finalize_upload(
+        &app,
+        &access,
+        &refresh,
+        &location,
+        &[
+            ("kind", b"file"),
+            ("file", b"1234"),
+            ("filename", b"a.bin"),
+            ("encryption_version", b"0"),
+        ],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = finalize_upload(
+        &app,
+        &access,
+        &refresh,
+        &location,
+        &[("filename", b"a.bin"), ("encryption_version", b"0")],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // Other users cannot see, write to or delete the upload
+    let response = upload_request(
+        &app,
+        &bob_access,
+        &bob_refresh,
+        "HEAD",
+        &location,
+        &[],
+        Vec::new(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = upload_request(
+        &app,
+        &bob_access,
+        &bob_refresh,
+        "DELETE",
+        &location,
+        &[],
+        Vec::new(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = upload_request(
+        &app,
+        &access,
+        &refresh,
+        "DELETE",
+        &location,
+        &[],
+        Vec::new(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response =
+        upload_request(&app, &access, &refresh, "HEAD", &location, &[], Vec::new()).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+// --- Storage quota ---
+
+#[tokio::test]
+async fn test_uploads_respect_storage_quota() {
+    let (app, db, jwt) = create_test_app().await;
+    let (user_id, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
+    db.quotas()
+        .set_user_quota(user_id, Some(100))
+        .await
+        .unwrap();
+
+    let file = |data: &'static [u8]| -> [(&'static str, &'static [u8]); 3] {
+        [
+            ("file", data),
+            ("filename", b"a.bin"),
+            ("encryption_version", b"0"),
+        ]
+    };
+    let response = post_upload(&app, &access, &refresh, &file(&[0; 60])).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let response = post_upload(&app, &access, &refresh, &file(&[0; 60])).await;
+    assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+
+    // A resumable upload holds its full length against the quota
+    let response = upload_request(
+        &app,
+        &access,
+        &refresh,
+        "POST",
+        "/api/attachments/uploads",
+        &[("upload-length", "41")],
+        Vec::new(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+    let location = start_upload(&app, &access, &refresh, 40).await;
+    let response = post_upload(&app, &access, &refresh, &file(&[0; 1])).await;
+    assert_eq!(response.status(), StatusCode::INSUFFICIENT_STORAGE);
+
+    // and finalizing it does not count it twice
+    let response = send_chunk(&app, &access, &refresh, &location, 0, &[0; 40]).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    let response = finalize_upload(
+        &app,
+        &access,
+        &refresh,
+        &location,
+        &[
+            ("kind", b"file"),
+            ("filename", b"b.bin"),
+            ("encryption_version", b"0"),
+        ],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(db.quotas().usage(user_id).await.unwrap().total(), 100);
+}
+
+// --- Attachment library ---
+
+async fn json_body(response: axum::response::Response) -> serde_json::Value {
+    serde_json::from_slice(&body_bytes(response).await).unwrap()
+}
+
+#[tokio::test]
+async fn test_attachment_library() {
+    let (app, db, jwt) = create_test_app().await;
+    let (_, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
+    let (_, other_access, other_refresh) = create_authenticated_user(&db, &jwt, "bob").await;
+
+    let used = upload_attachment(&app, &access, &refresh, b"used_image").await;
+    let unused = upload_attachment(&app, &access, &refresh, b"unused").await;
+    upload_attachment(&app, &other_access, &other_refresh, b"bobs_image").await;
+
+    let response = upload_request(
+        &app,
+        &access,
+        &refresh,
+        "POST",
+        "/api/posts",
+        &[("content-type", "application/json")],
+        br#"{"title": "Holiday", "content": "initial"}"#.to_vec(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let post_uuid = json_body(response).await["uuid"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let response = upload_request(
+        &app,
+        &access,
+        &refresh,
+        "PUT",
+        &format!("/api/posts/{post_uuid}"),
+        &[("content-type", "application/json")],
+        format!(
+            r#"{{"title": "Holiday", "content": "with image", "attachment_uuids": ["{used}"]}}"#
+        )
+        .into_bytes(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    // Newest first, one per page
+    let response = upload_request(
+        &app,
+        &access,
+        &refresh,
+        "GET",
+        "/api/attachments?limit=1",
+        &[],
+        Vec::new(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = json_body(response).await;
+    assert_eq!(json["total"], 2);
+    assert_eq!(json["has_more"], true);
+    assert_eq!(json["attachments"].as_array().unwrap().len(), 1);
+    assert_eq!(json["attachments"][0]["uuid"], unused.as_str());
+    assert_eq!(json["attachments"][0]["reference_count"], 0);
+    assert_eq!(json["attachments"][0]["posts"], serde_json::json!([]));
+
+    let response = upload_request(
+        &app,
+        &access,
+        &refresh,
+        "GET",
+        "/api/attachments?limit=1&offset=1",
+        &[],
+        Vec::new(),
+    )
+    .await;
+    let json = json_body(response).await;
+    assert_eq!(json["has_more"], false);
+    let attachment = &json["attachments"][0];
+    assert_eq!(attachment["uuid"], used.as_str());
+    assert_eq!(attachment["kind"], "image");
+    assert_eq!(attachment["size"], test_image(b"used_image").len() + 13);
+    assert_eq!(attachment["encryption_version"], 0);
+    assert_eq!(attachment["reference_count"], 1);
+    assert_eq!(attachment["posts"][0]["uuid"], post_uuid.as_str());
+    assert_eq!(attachment["posts"][0]["title"], "Holiday");
+
+    // Find the posts using an attachment
+    let response = upload_request(
+        &app,
+        &access,
+        &refresh,
+        "GET",
+        &format!("/api/attachments/{used}/posts"),
+        &[],
+        Vec::new(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let json = json_body(response).await;
+    assert_eq!(json.as_array().unwrap().len(), 1);
+    assert_eq!(json[0]["uuid"], post_uuid.as_str());
+    let response = upload_request(
+        &app,
+        &other_access,
+        &other_refresh,
+        "GET",
+        &format!("/api/attachments/{used}/posts"),
+        &[],
+        Vec::new(),
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // Attachments in use can't be deleted, unused ones only by their owner
+    let delete = |uuid: String, access: String, refresh: String| {
+        let app = app.clone();
+        async move {
+            upload_request(
+                &app,
+                &access,
+                &refresh,
+                "DELETE",
+                &format!("/api/attachments/{uuid}"),
+                &[],
+                Vec::new(),
+            )
+            .await
+            .status()
+        }
+    };
+    assert_eq!(
+        delete(used.clone(), access.clone(), refresh.clone()).await,
+        StatusCode::CONFLICT
+    );
+    assert_eq!(
+        delete(unused.clone(), other_access.clone(), other_refresh.clone()).await,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        delete(unused.clone(), access.clone(), refresh.clone()).await,
+        StatusCode::NO_CONTENT
+    );
+    assert_eq!(
+        delete(unused.clone(), access.clone(), refresh.clone()).await,
+        StatusCode::NOT_FOUND
+    );
+
+    let response = upload_request(
+        &app,
+        &access,
+        &refresh,
+        "GET",
+        "/api/attachments",
+        &[],
+        Vec::new(),
+    )
+    .await;
+    let json = json_body(response).await;
+    assert_eq!(json["total"], 1);
+    assert_eq!(json["attachments"][0]["uuid"], used.as_str());
+}
+
+// --- Server-side image processing ---
+
+/// A JPEG of the given size with an EXIF block holding `exif`.
+fn jpeg_with_exif(width: u32, height: u32, exif: &[u8]) -> Vec<u8> {
+    use image::ImageEncoder;
+    let pixels = vec![128u8; (width * height * 3) as usize];
+    let mut jpeg = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, 90)
+        .write_image(&pixels, width, height, image::ExtendedColorType::Rgb8)
+        .unwrap();
+
+    let mut payload = b"Exif\0\0".to_vec();
+    payload.extend_from_slice(exif);
+    let mut out = jpeg[..2].to_vec();
+    out.extend_from_slice(&[0xFF, 0xE1]);
+    out.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+    out.extend_from_slice(&payload);
+    out.extend_from_slice(&jpeg[2..]);
+    out
+}
+
+#[tokio::test]
+async fn test_unencrypted_image_processed_by_server() {
+    let (app, db, jwt) = create_test_app().await;
+    let (user_id, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
+
+    // A scripted upload: no IVs, no thumbnails
+    let image = jpeg_with_exif(1000, 500, b"GPSLatitude 52.37");
+    let response = post_upload(
+        &app,
+        &access,
+        &refresh,
+        &[("image", image.as_slice()), ("encryption_version", b"0")],
+    )
+    .await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let uuid = json_body(response).await["uuid"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    // Stored without the EXIF block, with a rendered small thumbnail
+    let attachment = db
+        .attachments()
+        .get_by_uuid(&uuid, user_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(attachment.image_data.len() < image.len());
+    assert!(!attachment.image_data.windows(3).any(|w| w == b"GPS"));
+    let thumbnails = attachment.thumbnails.unwrap();
+    let sm = image::load_from_memory(&thumbnails.sm.data).unwrap();
+    assert_eq!((sm.width(), sm.height()), (200, 100));
+    assert!(thumbnails.sm.iv.is_none());
+    assert!(thumbnails.md.is_none());
+
+    // Other sizes are rendered on first request and then kept
+    let uri = format!("/api/attachments/{uuid}/thumbnail/lg");
+    let response = get_with_headers(&app, &access, &refresh, &uri, &[]).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response.headers().get("etag").unwrap().clone();
+    let lg = image::load_from_memory(&body_bytes(response).await).unwrap();
+    assert_eq!((lg.width(), lg.height()), (800, 400));
+    let response = get_with_headers(&app, &access, &refresh, &uri, &[]).await;
+    assert_eq!(response.headers().get("etag").unwrap(), &etag);
+    let thumbnails = db
+        .attachments()
+        .get_thumbnails(&uuid, user_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(thumbnails.lg.is_some());
+    assert!(thumbnails.md.is_none());
+}
+
+#[tokio::test]
+async fn test_invalid_stored_iv_is_an_error() {
+    let (app, db, jwt) = create_test_app().await;
+    let (user_id, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
+
+    // Stored before the server checked IVs
+    let uuid = db
+        .attachments()
+        .create(CreateAttachmentInput {
+            user_id,
+            image_data: b"encrypted",
+            image_iv: Some("iv\r\nX-Injected: 1"),
+            thumb_sm: b"thumb",
+            thumb_sm_iv: Some("thumb-iv"),
+            thumb_md: None,
+            thumb_lg: None,
+            encryption_version: 1,
+        })
+        .await
+        .unwrap();
+
+    let uri = format!("/api/attachments/{uuid}");
+    let response = get_with_headers(&app, &access, &refresh, &uri, &[]).await;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(response.headers().get("x-injected").is_none());
+}
+
+#[tokio::test]
+async fn test_undecodable_image_thumbnail_not_retried() {
+    let (app, db, jwt) = create_test_app().await;
+    let (user_id, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
+
+    // Stored before the server checked unencrypted images
+    let uuid = db
+        .attachments()
+        .create(CreateAttachmentInput {
+            user_id,
+            image_data: b"not an image",
+            image_iv: None,
+            thumb_sm: b"thumb",
+            thumb_sm_iv: None,
+            thumb_md: None,
+            thumb_lg: None,
+            encryption_version: 0,
+        })
+        .await
+        .unwrap();
+
+    let uri = format!("/api/attachments/{uuid}/thumbnail/md");
+    let response = get_with_headers(&app, &access, &refresh, &uri, &[]).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let info = db
+        .attachments()
+        .get_info(&uuid, user_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(info.thumbnail_failed);
+
+    // Other sizes are not attempted either
+    let uri = format!("/api/attachments/{uuid}/thumbnail/lg");
+    let response = get_with_headers(&app, &access, &refresh, &uri, &[]).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_unencrypted_image_validation() {
+    let (app, db, jwt) = create_test_app().await;
+    let (_, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
+
+    let heic = b"\0\0\0\x18ftypheic\0\0\0\0mif1heic";
+    let truncated = jpeg_with_exif(16, 16, b"");
+    let cases: &[(&[u8], &str)] = &[
+        (b"not an image", "Unsupported image format"),
+        (heic, "HEIC images must be converted before uploading"),
+        (&truncated[..60], "Invalid image"),
+    ];
+    for &(image, expected) in cases {
+        let response = post_upload(
+            &app,
+            &access,
+            &refresh,
+            &[("image", image), ("encryption_version", b"0")],
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{expected}");
+        let error = json_body(response).await["error"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert!(error.starts_with(expected), "{error}");
+    }
+}
+
+// --- Deduplication ---
+
+#[tokio::test]
+async fn test_unencrypted_image_reused() {
+    let (app, db, jwt) = create_test_app().await;
+    let (user_id, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
+    let (_, other_access, other_refresh) = create_authenticated_user(&db, &jwt, "bob").await;
+
+    let upload = |image: Vec<u8>, access: String, refresh: String| {
+        let app = app.clone();
+        async move {
+            let response = post_upload(
+                &app,
+                &access,
+                &refresh,
+                &[("image", image.as_slice()), ("encryption_version", b"0")],
+            )
+            .await;
+            let status = response.status();
+            (status, json_body(response).await)
+        }
+    };
+
+    let screenshot = jpeg_with_exif(32, 16, b"GPSLatitude 52.37");
+    let (status, first) = upload(screenshot.clone(), access.clone(), refresh.clone()).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_eq!(first["reused"], false);
+    let uuid = first["uuid"].as_str().unwrap().to_string();
+    let usage = db.quotas().usage(user_id).await.unwrap();
+
+    // The same image again, also with other metadata that gets stripped
+    let (status, second) = upload(screenshot.clone(), access.clone(), refresh.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(second["reused"], true);
+    assert_eq!(second["uuid"], uuid.as_str());
+    let other_metadata = jpeg_with_exif(32, 16, b"GPSLatitude 48.85");
+    let (status, third) = upload(other_metadata, access.clone(), refresh.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(third["uuid"], uuid.as_str());
+    assert_eq!(db.quotas().usage(user_id).await.unwrap(), usage);
+    assert_eq!(db.attachments().count(user_id).await.unwrap(), 1);
+
+    // Other users and other images get their own attachments
+    let (status, bobs) = upload(screenshot, other_access, other_refresh).await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert_ne!(bobs["uuid"], uuid.as_str());
+    let (status, _) = upload(test_image(b"other"), access.clone(), refresh.clone()).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    // Shared by two posts, the attachment lives until both drop it
+    let mut posts = Vec::new();
+    for _ in 0..2 {
+        let response = upload_request(
+            &app,
+            &access,
+            &refresh,
+            "POST",
+            "/api/posts",
+            &[("content-type", "application/json")],
+            br#"{"content": "note"}"#.to_vec(),
+        )
+        .await;
+        let post_uuid = json_body(response).await["uuid"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        let update = |attachments: &str| {
+            format!(r#"{{"content": "note", "attachment_uuids": [{attachments}]}}"#).into_bytes()
+        };
+        let response = upload_request(
+            &app,
+            &access,
+            &refresh,
+            "PUT",
+            &format!("/api/posts/{post_uuid}"),
+            &[("content-type", "application/json")],
+            update(&format!(r#""{uuid}""#)),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        posts.push((post_uuid, update("")));
+    }
+    let attachment = db
+        .attachments()
+        .get_by_uuid(&uuid, user_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(attachment.reference_count, 2);
+
+    for (i, (post_uuid, body)) in posts.into_iter().enumerate() {
+        let response = upload_request(
+            &app,
+            &access,
+            &refresh,
+            "PUT",
+            &format!("/api/posts/{post_uuid}"),
+            &[("content-type", "application/json")],
+            body,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let exists = db
+            .attachments()
+            .get_by_uuid(&uuid, user_id)
+            .await
+            .unwrap()
+            .is_some();
+        assert_eq!(exists, i == 0);
+    }
+}
@@ -0,0 +1,379 @@
+//! Tests for pluggable attachment storage: the fs and S3 blob stores, and
+//! moving blobs out of the database with `crowchiper storage migrate`.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    Router,
+    body::Bytes,
+    extract::{Path as UrlPath, State},
+    http::{HeaderMap, Method, StatusCode},
+    routing::any,
+};
+use crowchiper::blob::{BlobStore, FsBlobStore, S3BlobStore, S3Config, content_key};
+use crowchiper::db::Database;
+use crowchiper::db::attachments::CreateAttachmentInput;
+use futures::TryStreamExt;
+use url::Url;
+
+type Objects = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+/// Minimal S3 stand-in: stores objects in memory and rejects unsigned requests.
+async fn s3_object(
+    State(objects): State<Objects>,
+    UrlPath((bucket, key)): UrlPath<(String, String)>,
+    method: Method,
+    headers: HeaderMap,
+    body: Bytes,
+) -> (StatusCode, Vec<u8>) {
+    let signed = headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("AWS4-HMAC-SHA256 Credential=test-key/"));
+    if !signed || !headers.contains_key("x-amz-date") {
+        return (StatusCode::FORBIDDEN, Vec::new());
+    }
+    let name = format!("{bucket}/{key}");
+    let mut objects = objects.lock().unwrap();
+    match method {
+        Method::PUT => {
+            objects.insert(name, body.to_vec());
+            (StatusCode::OK, Vec::new())
+        }
+        Method::GET | Method::HEAD => match objects.get(&name) {
+            Some(data) => match headers.get("range").and_then(|v| v.to_str().ok()) {
+                Some(range) => {
+                    let (start, end) = range
+                        .strip_prefix("bytes=")
+                        .and_then(|r| r.split_once('-'))
+                        .unwrap();
+                    let (start, end): (usize, usize) =
+                        (start.parse().unwrap(), end.parse().unwrap());
+                    (StatusCode::PARTIAL_CONTENT, data[start..=end].to_vec())
+                }
+                None => (StatusCode::OK, data.clone()),
+            },
+            None => (StatusCode::NOT_FOUND, Vec::new()),
+        },
+        Method::DELETE => {
+            objects.remove(&name);
+            (StatusCode::NO_CONTENT, Vec::new())
+        }
+        _ => (StatusCode::METHOD_NOT_ALLOWED, Vec::new()),
+    }
+}
+
+/// Start the S3 stand-in and return its config and object map.
+async fn start_s3() -> (S3Config, Objects) {
+    let objects = Objects::default();
+    let app = Router::new()
+        .route("/{bucket}/{*key}", any(s3_object))
+        .with_state(objects.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let config = S3Config {
+        endpoint: Url::parse(&format!("http://{addr}")).unwrap(),
+        region: "us-east-1".to_string(),
+        access_key: "test-key".to_string(),
+        secret_key: "test-secret".to_string(),
+    };
+    (config, objects)
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "crowchiper-blob-{name}-{}",
+        uuid::Uuid::new_v4().simple()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+async fn create_user(db: &Database, username: &str) -> i64 {
+    let uuid = uuid::Uuid::new_v4().to_string();
+    let id = db.users().create(&uuid, username).await.unwrap();
+    db.users().activate(id).await.unwrap();
+    id
+}
+
+async fn create_attachment(db: &Database, user_id: i64, image: &[u8]) -> String {
+    db.attachments()
+        .create(CreateAttachmentInput {
+            user_id,
+            image_data: image,
+            image_iv: None,
+            thumb_sm: b"thumb_sm",
+            thumb_sm_iv: None,
+            thumb_md: Some((b"thumb_md", None)),
+            thumb_lg: None,
+            encryption_version: 0,
+        })
+        .await
+        .unwrap()
+}
+
+/// Number of rows in the database blob store.
+async fn database_blob_count(db: &Database) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM blobs")
+        .fetch_one(db.pool())
+        .await
+        .unwrap()
+}
+
+// ── S3 store ──
+
+#[tokio::test]
+async fn test_s3_store_round_trip() {
+    let (config, objects) = start_s3().await;
+    let store = S3BlobStore::new(config, "notes", "prod").unwrap();
+    let key = content_key(b"hello");
+
+    assert_eq!(store.get(&key).await.unwrap(), None);
+    store.put(&key, b"hello").await.unwrap();
+    assert_eq!(store.get(&key).await.unwrap(), Some(b"hello".to_vec()));
+    assert_eq!(store.size(&key).await.unwrap(), Some(5));
+    let chunks: Vec<_> = store
+        .read_range(&key, 1..3)
+        .await
+        .unwrap()
+        .unwrap()
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(chunks.concat(), b"el");
+    assert!(
+        objects
+            .lock()
+            .unwrap()
+            .contains_key(&format!("notes/prod/{key}"))
+    );
+
+    store.delete(&key).await.unwrap();
+    assert_eq!(store.get(&key).await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_s3_store_reports_rejected_requests() {
+    let (mut config, _objects) = start_s3().await;
+    config.access_key = "wrong-key".to_string();
+    let store = S3BlobStore::new(config, "notes", "").unwrap();
+
+    let err = store.put(&content_key(b"x"), b"x").await.unwrap_err();
+    assert!(err.to_string().contains("403"), "error: {err}");
+}
+
+// ── Attachments on external stores ──
+
+#[tokio::test]
+async fn test_attachments_on_s3_store() {
+    let (config, objects) = start_s3().await;
+    let store = S3BlobStore::new(config, "notes", "").unwrap();
+    let db = Database::open(":memory:")
+        .await
+        .unwrap()
+        .with_blob_store(Arc::new(store));
+    let user_id = create_user(&db, "alice").await;
+
+    let uuid = create_attachment(&db, user_id, b"image on s3").await;
+    assert_eq!(database_blob_count(&db).await, 0);
+    assert_eq!(objects.lock().unwrap().len(), 3);
+
+    let attachment = db
+        .attachments()
+        .get_by_uuid(&uuid, user_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(attachment.image_data, b"image on s3");
+    let thumb = db
+        .attachments()
+        .get_thumbnail_by_size(&uuid, user_id, "md")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(thumb.data, b"thumb_md");
+
+    assert!(db.attachments().delete(&uuid, user_id).await.unwrap());
+    assert_eq!(db.attachments().purge_deleted_blobs().await.unwrap(), 3);
+    assert!(objects.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_attachments_on_fs_store_share_content() {
+    let dir = temp_dir("fs");
+    let db = Database::open(":memory:")
+        .await
+        .unwrap()
+        .with_blob_store(Arc::new(FsBlobStore::new(&dir).unwrap()));
+    let user_id = create_user(&db, "alice").await;
+
+    let first = create_attachment(&db, user_id, b"same image").await;
+    let second = create_attachment(&db, user_id, b"same image").await;
+    let image_path = dir
+        .join(&content_key(b"same image")[..2])
+        .join(content_key(b"same image"));
+    assert!(image_path.is_file());
+
+    // The blob stays while another attachment still uses it.
+    assert!(db.attachments().delete(&first, user_id).await.unwrap());
+    db.attachments().purge_deleted_blobs().await.unwrap();
+    assert!(image_path.is_file());
+    let attachment = db
+        .attachments()
+        .get_by_uuid(&second, user_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(attachment.image_data, b"same image");
+
+    assert!(db.attachments().delete(&second, user_id).await.unwrap());
+    db.attachments().purge_deleted_blobs().await.unwrap();
+    assert!(!image_path.exists());
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[tokio::test]
+async fn test_backfill_sizes_reads_fs_store() {
+    let dir = temp_dir("backfill");
+    let db = Database::open(":memory:")
+        .await
+        .unwrap()
+        .with_blob_store(Arc::new(FsBlobStore::new(&dir).unwrap()));
+    let user_id = create_user(&db, "alice").await;
+    create_attachment(&db, user_id, b"image on disk").await;
+
+    // What the v8 migration leaves behind when blobs are no longer in the database.
+    sqlx::query("UPDATE attachments SET size = 0")
+        .execute(db.pool())
+        .await
+        .unwrap();
+    assert_eq!(db.quotas().usage(user_id).await.unwrap().attachments, 0);
+
+    assert_eq!(db.attachments().backfill_sizes().await.unwrap(), 1);
+    let expected = (b"image on disk".len() + b"thumb_sm".len() + b"thumb_md".len()) as u64;
+    assert_eq!(
+        db.quotas().usage(user_id).await.unwrap().attachments,
+        expected
+    );
+    // Already recorded sizes are left alone.
+    assert_eq!(db.attachments().backfill_sizes().await.unwrap(), 0);
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+// ── storage migrate ──
+
+fn cargo_bin() -> PathBuf {
+    let mut path = std::env::current_exe().unwrap();
+    path.pop(); // Remove test binary name
+    path.pop(); // Remove deps
+    path.push("crowchiper");
+    path
+}
+
+/// Run `crowchiper --database <db> storage <args>`. Returns (stdout, success).
+fn run_storage(db_path: &Path, args: &[&str]) -> (String, bool) {
+    let output = Command::new(cargo_bin())
+        .arg("--database")
+        .arg(db_path)
+        .arg("storage")
+        .args(args)
+        .env_remove("AWS_ACCESS_KEY_ID")
+        .env_remove("AWS_SECRET_ACCESS_KEY")
+        .output()
+        .expect("Failed to run binary");
+    (
+        String::from_utf8_lossy(&output.stdout).to_string(),
+        output.status.success(),
+    )
+}
+
+#[tokio::test]
+async fn test_storage_migrate_moves_blobs_to_fs() {
+    let dir = temp_dir("migrate");
+    let db_path = dir.join("crowchiper.db");
+    let blob_dir = dir.join("blobs");
+
+    let db = Database::open(db_path.to_str().unwrap()).await.unwrap();
+    let user_id = create_user(&db, "alice").await;
+    let uuid = create_attachment(&db, user_id, b"image in sqlite").await;
+    assert_eq!(database_blob_count(&db).await, 3);
+    db.pool().close().await;
+
+    let to = format!("fs:{}", blob_dir.display());
+    let (stdout, ok) = run_storage(&db_path, &["migrate", "--to", &to]);
+    assert!(ok, "stdout: {stdout}");
+    assert!(stdout.contains("moved 3 blobs"), "stdout: {stdout}");
+
+    let db = Database::open(db_path.to_str().unwrap())
+        .await
+        .unwrap()
+        .with_blob_store(Arc::new(FsBlobStore::new(&blob_dir).unwrap()));
+    assert_eq!(database_blob_count(&db).await, 0);
+    let attachment = db
+        .attachments()
+        .get_by_uuid(&uuid, user_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(attachment.image_data, b"image in sqlite");
+    db.pool().close().await;
+
+    // Running it again finds nothing left to move.
+    let (stdout, ok) = run_storage(&db_path, &["migrate", "--to", &to]);
+    assert!(ok, "stdout: {stdout}");
+    assert!(stdout.contains("moved 0 blobs"), "stdout: {stdout}");
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[tokio::test]
+async fn test_storage_migrate_fails_on_lost_blobs() {
+    let dir = temp_dir("migrate-lost");
+    let db_path = dir.join("crowchiper.db");
+    let blob_dir = dir.join("blobs");
+
+    let db = Database::open(db_path.to_str().unwrap()).await.unwrap();
+    let user_id = create_user(&db, "alice").await;
+    create_attachment(&db, user_id, b"lost image").await;
+    let lost = content_key(b"lost image");
+    sqlx::query("DELETE FROM blobs WHERE key = ?")
+        .bind(&lost)
+        .execute(db.pool())
+        .await
+        .unwrap();
+    db.pool().close().await;
+
+    let to = format!("fs:{}", blob_dir.display());
+    let (stdout, ok) = run_storage(&db_path, &["migrate", "--to", &to]);
+    assert!(!ok, "stdout: {stdout}");
+    assert!(stdout.contains("moved 2 blobs"), "stdout: {stdout}");
+    assert!(
+        stdout.contains(&format!("missing: {lost}")),
+        "stdout: {stdout}"
+    );
+
+    // Blobs moved by the first run are skipped, the lost one still fails.
+    let (stdout, ok) = run_storage(&db_path, &["migrate", "--to", &to]);
+    assert!(!ok, "stdout: {stdout}");
+    assert!(
+        stdout.contains("2 blobs were already in"),
+        "stdout: {stdout}"
+    );
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_storage_migrate_to_s3_needs_credentials() {
+    let dir = temp_dir("s3-creds");
+    let (_, ok) = run_storage(&dir.join("crowchiper.db"), &["migrate", "--to", "s3:notes"]);
+    assert!(!ok);
+    std::fs::remove_dir_all(&dir).ok();
+}
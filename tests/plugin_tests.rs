@@ -5,7 +5,10 @@ use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::time::{Duration, Instant};
 
-use crowchiper::plugin::{DEFAULT_HOOK_TIMEOUT, PluginError, PluginPermission, PluginRuntime};
+use crowchiper::plugin::{
+    DEFAULT_HOOK_TIMEOUT, LoadOptions, PluginError, PluginLimits, PluginPermission, PluginRuntime,
+    SignaturePolicy, TrustedKey,
+};
 
 fn wasm_dir() -> PathBuf {
     Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/plugins/wasm")
@@ -729,6 +732,36 @@ async fn test_net_success_without_permission_fails() {
     );
 }
 
+#[tokio::test]
+async fn test_net_success_with_matching_net_rule() {
+    let spec = crowchiper::plugin::parse_plugin_spec("x.wasm:net=127.0.0.0/8:1").unwrap();
+    let plugin = PluginRuntime::load(
+        &wasm_path("net-success"),
+        &spec.permissions,
+        &[],
+        DEFAULT_HOOK_TIMEOUT,
+    )
+    .await
+    .expect("net-success should load when its address is allowlisted");
+    assert_eq!(plugin.name(), "net-success");
+}
+
+#[tokio::test]
+async fn test_net_success_with_other_port_fails() {
+    let spec = crowchiper::plugin::parse_plugin_spec("x.wasm:net=127.0.0.1:2-1024").unwrap();
+    let result = PluginRuntime::load(
+        &wasm_path("net-success"),
+        &spec.permissions,
+        &[],
+        DEFAULT_HOOK_TIMEOUT,
+    )
+    .await;
+    assert!(
+        result.is_err(),
+        "net-success should fail when its port is outside the rule"
+    );
+}
+
 // ── CLI: permissions ─────────────────────────────────────────────────
 
 #[test]
@@ -769,6 +802,337 @@ fn test_cli_fs_permission_nonexistent_dir() {
     );
 }
 
+// ── CLI: plugin config file ──────────────────────────────────────────
+
+/// Write a `--plugin-config` file into a fresh temp directory.
+fn write_plugin_config(contents: &str) -> PathBuf {
+    let dir = unique_fs_test_dir().join("plugin-config");
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("plugins.toml");
+    std::fs::write(&path, contents).unwrap();
+    path
+}
+
+#[test]
+fn test_cli_plugin_config_file_loads() {
+    let config = write_plugin_config(&format!(
+        "[[plugin]]\npath = {:?}\npermissions = [\"net-dns\"]\ntimeout = \"500ms\"\nfuel = 20000000\n\n[plugin.vars]\nhome = {{ env = \"HOME\" }}\n",
+        wasm_path("good").to_str().unwrap()
+    ));
+    let (stdout, _stderr) =
+        spawn_expect_listening(&["--plugin-config", config.to_str().unwrap(), "--port", "0"]);
+    assert!(
+        stdout.contains("Plugin loaded"),
+        "plugin from config file should load, got: {stdout}"
+    );
+}
+
+#[test]
+fn test_cli_plugin_config_missing_secret_fails() {
+    let config = write_plugin_config(&format!(
+        "[[plugin]]\npath = {:?}\nvars = {{ token = {{ env = \"CROWCHIPER_TEST_UNSET_SECRET\" }} }}\n",
+        wasm_path("good").to_str().unwrap()
+    ));
+    let (stdout, _stderr, success) =
+        run_cli(&["--plugin-config", config.to_str().unwrap(), "--port", "0"]);
+    assert!(!success, "unresolvable secret should cause exit");
+    assert!(
+        stdout.contains("Failed to read plugin config")
+            && stdout.contains("CROWCHIPER_TEST_UNSET_SECRET"),
+        "error should name the missing variable, got: {stdout}"
+    );
+}
+
+// ── CLI: plugin subcommands ──────────────────────────────────────────
+
+#[test]
+fn test_cli_plugin_inspect_prints_config_and_imports() {
+    let (stdout, _stderr, success) =
+        run_cli(&["plugin", "inspect", wasm_path("good").to_str().unwrap()]);
+    assert!(success, "inspect should succeed, got: {stdout}");
+    assert!(stdout.contains("name:    good"), "got: {stdout}");
+    assert!(stdout.contains("hooks:"), "got: {stdout}");
+    assert!(
+        stdout.contains("crowchiper:plugin/"),
+        "imports should list the host interfaces, got: {stdout}"
+    );
+}
+
+#[test]
+fn test_cli_plugin_inspect_reports_load_errors() {
+    let (stdout, _stderr, success) = run_cli(&[
+        "plugin",
+        "inspect",
+        wasm_path("empty-name").to_str().unwrap(),
+    ]);
+    assert!(!success);
+    assert!(stdout.contains("Failed to load plugin"), "got: {stdout}");
+}
+
+#[test]
+fn test_cli_plugin_validate_spec() {
+    let (stdout, _stderr, success) =
+        run_cli(&["plugin", "validate", "a.wasm:timeout=500ms,breaker=off,net"]);
+    assert!(success, "got: {stdout}");
+    assert!(
+        stdout.contains("a.wasm:net,timeout=500ms,breaker=off"),
+        "got: {stdout}"
+    );
+
+    let (stdout, _stderr, success) = run_cli(&["plugin", "validate", "a.wasm:bogus"]);
+    assert!(!success);
+    assert!(stdout.contains("unknown permission"), "got: {stdout}");
+}
+
+#[test]
+fn test_cli_plugin_fire_prints_logs_and_result() {
+    let (stdout, _stderr, success) = run_cli(&[
+        "plugin",
+        "fire",
+        wasm_path("hook-echo").to_str().unwrap(),
+        "--hook",
+        "ip-change",
+        "--value",
+        "new_ip=10.0.0.1",
+    ]);
+    assert!(success, "got: {stdout}");
+    assert!(
+        stdout.contains("new_ip=10.0.0.1"),
+        "plugin log missing: {stdout}"
+    );
+    assert!(stdout.contains("result:  ok"), "got: {stdout}");
+}
+
+#[test]
+fn test_cli_plugin_fire_pre_hook_denial() {
+    let (stdout, _stderr, success) = run_cli(&[
+        "plugin",
+        "fire",
+        wasm_path("pre-hook").to_str().unwrap(),
+        "--hook",
+        "pre-login",
+        "--value",
+        "username=mallory",
+    ]);
+    assert!(!success);
+    assert!(
+        stdout.contains("result:  denied: username mallory is not allowed"),
+        "got: {stdout}"
+    );
+}
+
+#[test]
+fn test_cli_plugin_fire_unregistered_hook() {
+    let (stdout, _stderr, success) = run_cli(&[
+        "plugin",
+        "fire",
+        wasm_path("hook-echo").to_str().unwrap(),
+        "--hook",
+        "logout",
+    ]);
+    assert!(!success);
+    assert!(stdout.contains("does not register"), "got: {stdout}");
+}
+
+// ── Compiled component cache ─────────────────────────────────────────
+
+fn cache_entries(dir: &Path) -> Vec<PathBuf> {
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .map(|entry| entry.unwrap().path())
+                .filter(|path| path.extension().is_some_and(|ext| ext == "cwasm"))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[test]
+fn test_cli_plugin_cache_dir_stores_and_reuses_components() {
+    let dir = unique_fs_test_dir().join("cache");
+    std::fs::remove_dir_all(&dir).ok();
+    let args = [
+        "--plugin",
+        wasm_path("good").to_str().unwrap(),
+        "--plugin-cache-dir",
+        dir.to_str().unwrap(),
+        "--port",
+        "0",
+    ];
+
+    let (stdout, _stderr) = spawn_expect_listening(&args);
+    assert!(stdout.contains("Plugin loaded"), "got: {stdout}");
+    let entries = cache_entries(&dir);
+    assert_eq!(entries.len(), 1, "expected one cached component");
+
+    // A corrupt entry is ignored and replaced with a fresh compile.
+    std::fs::write(&entries[0], b"not a component").unwrap();
+    let (stdout, _stderr) = spawn_expect_listening(&args);
+    assert!(stdout.contains("Plugin loaded"), "got: {stdout}");
+    assert!(
+        stdout.contains("unusable compiled plugin cache entry"),
+        "got: {stdout}"
+    );
+    assert_ne!(std::fs::read(&entries[0]).unwrap(), b"not a component");
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+// ── Plugin signatures ────────────────────────────────────────────────
+
+/// Copy the good plugin to a fresh directory with a publisher key.
+/// Returns (plugin path, private key, public key PEM path).
+fn signed_plugin_setup(
+    name: &str,
+) -> (
+    PathBuf,
+    openssl::pkey::PKey<openssl::pkey::Private>,
+    PathBuf,
+) {
+    let dir = unique_fs_test_dir().join(name);
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir_all(&dir).unwrap();
+    let plugin = dir.join("good.wasm");
+    std::fs::copy(wasm_path("good"), &plugin).unwrap();
+
+    let key = openssl::pkey::PKey::generate_ed25519().unwrap();
+    let pem = dir.join("publisher.pem");
+    std::fs::write(&pem, key.public_key_to_pem().unwrap()).unwrap();
+    (plugin, key, pem)
+}
+
+fn sign_plugin(plugin: &Path, key: &openssl::pkey::PKey<openssl::pkey::Private>) {
+    let wasm = std::fs::read(plugin).unwrap();
+    let signature = openssl::sign::Signer::new_without_digest(key)
+        .unwrap()
+        .sign_oneshot_to_vec(&wasm)
+        .unwrap();
+    std::fs::write(crowchiper::plugin::signature_path(plugin), signature).unwrap();
+}
+
+#[test]
+fn test_cli_signed_plugin_loads() {
+    let (plugin, key, pem) = signed_plugin_setup("signed");
+    sign_plugin(&plugin, &key);
+
+    let (stdout, _stderr, success) = run_cli(&[
+        "--plugin-trusted-key",
+        pem.to_str().unwrap(),
+        "--plugin-signatures",
+        "required",
+        "plugin",
+        "inspect",
+        plugin.to_str().unwrap(),
+    ]);
+    assert!(success, "signed plugin should load, got: {stdout}");
+    assert!(stdout.contains("name:    good"), "got: {stdout}");
+}
+
+#[test]
+fn test_cli_tampered_plugin_is_refused() {
+    let (plugin, key, pem) = signed_plugin_setup("tampered");
+    sign_plugin(&plugin, &key);
+    let mut wasm = std::fs::read(&plugin).unwrap();
+    wasm.push(0);
+    std::fs::write(&plugin, wasm).unwrap();
+
+    // Even when unsigned plugins are allowed, a bad signature is an error.
+    let (stdout, _stderr, success) = run_cli(&[
+        "--plugin-trusted-key",
+        pem.to_str().unwrap(),
+        "plugin",
+        "inspect",
+        plugin.to_str().unwrap(),
+    ]);
+    assert!(!success);
+    assert!(stdout.contains("plugin signature invalid"), "got: {stdout}");
+}
+
+#[test]
+fn test_cli_unsigned_plugin_depends_on_policy() {
+    let (plugin, _key, pem) = signed_plugin_setup("unsigned");
+    let inspect = |mode: &str| {
+        run_cli(&[
+            "--plugin-trusted-key",
+            pem.to_str().unwrap(),
+            "--plugin-signatures",
+            mode,
+            "plugin",
+            "inspect",
+            plugin.to_str().unwrap(),
+        ])
+    };
+
+    let (stdout, _stderr, success) = inspect("optional");
+    assert!(success, "got: {stdout}");
+    assert!(stdout.contains("Loading unsigned plugin"), "got: {stdout}");
+
+    let (stdout, _stderr, success) = inspect("required");
+    assert!(!success);
+    assert!(stdout.contains("plugin signature missing"), "got: {stdout}");
+}
+
+#[test]
+fn test_cli_required_signatures_need_a_key() {
+    let (stdout, _stderr, success) = run_cli(&[
+        "--plugin-signatures",
+        "required",
+        "plugin",
+        "inspect",
+        wasm_path("good").to_str().unwrap(),
+    ]);
+    assert!(!success);
+    assert!(stdout.contains("--plugin-trusted-key"), "got: {stdout}");
+}
+
+async fn load_with_options(
+    path: &Path,
+    options: &LoadOptions,
+) -> Result<PluginRuntime, PluginError> {
+    PluginRuntime::load_with_limits(
+        path,
+        &[],
+        &[],
+        DEFAULT_HOOK_TIMEOUT,
+        PluginLimits::default(),
+        options,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_signature_policy_is_per_load() {
+    let (plugin, key, pem) = signed_plugin_setup("per-load");
+    let required = LoadOptions {
+        signature_policy: std::sync::Arc::new(SignaturePolicy {
+            trusted_keys: vec![TrustedKey::load(&pem).unwrap()],
+            require: true,
+        }),
+        cache_dir: None,
+    };
+
+    let err = load_with_options(&plugin, &required).await.unwrap_err();
+    assert!(
+        matches!(err, PluginError::SignatureMissing(_)),
+        "got: {err}"
+    );
+    // Other loads in the same process keep their own policy.
+    load_with_options(&plugin, &LoadOptions::default())
+        .await
+        .unwrap();
+
+    sign_plugin(&plugin, &key);
+    let loaded = load_with_options(&plugin, &required).await.unwrap();
+
+    // Reloads are checked against the policy the plugin was loaded with.
+    std::fs::write(crowchiper::plugin::signature_path(&plugin), b"garbage").unwrap();
+    let err = loaded.load_again().await.unwrap_err();
+    assert!(
+        matches!(err, PluginError::SignatureInvalid(_)),
+        "got: {err}"
+    );
+}
+
 // ── Resource exhaustion ──────────────────────────────────────────────
 
 #[tokio::test]
@@ -811,6 +1175,78 @@ async fn test_stack_overflow_is_caught() {
     );
 }
 
+#[tokio::test]
+async fn test_fuel_limit_is_per_plugin() {
+    let limits = PluginLimits {
+        fuel: 1,
+        ..PluginLimits::default()
+    };
+    let err = PluginRuntime::load_with_limits(
+        &wasm_path("good"),
+        &[],
+        &[],
+        DEFAULT_HOOK_TIMEOUT,
+        limits,
+        &LoadOptions::default(),
+    )
+    .await
+    .unwrap_err();
+    let msg = err.to_string();
+    assert!(
+        msg.contains("fuel") || msg.contains("wasm backtrace"),
+        "config() should run out of fuel, got: {msg}"
+    );
+
+    // Other plugins keep the default budget.
+    PluginRuntime::load(&wasm_path("good"), &[], &[], DEFAULT_HOOK_TIMEOUT)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_memory_limit_is_per_plugin() {
+    let limits = PluginLimits {
+        memory: 64 * 1024,
+        ..PluginLimits::default()
+    };
+    let err = PluginRuntime::load_with_limits(
+        &wasm_path("good"),
+        &[],
+        &[],
+        DEFAULT_HOOK_TIMEOUT,
+        limits,
+        &LoadOptions::default(),
+    )
+    .await
+    .unwrap_err();
+    assert!(
+        matches!(&err, PluginError::Load(_) | PluginError::Runtime(_)),
+        "a 64 KiB memory cap should stop the plugin, got: {err}"
+    );
+}
+
+#[tokio::test]
+async fn test_larger_stack_still_catches_overflow() {
+    let limits = PluginLimits {
+        stack: 2 * 1024 * 1024,
+        ..PluginLimits::default()
+    };
+    let err = PluginRuntime::load_with_limits(
+        &wasm_path("stack-overflow"),
+        &[],
+        &[],
+        DEFAULT_HOOK_TIMEOUT,
+        limits,
+        &LoadOptions::default(),
+    )
+    .await
+    .unwrap_err();
+    assert!(
+        matches!(&err, PluginError::Runtime(_)),
+        "stack overflow should cause a runtime error, got: {err}"
+    );
+}
+
 // ── Path validation ──────────────────────────────────────────────────
 
 #[test]
@@ -891,7 +1327,7 @@ async fn test_call_hook_returns_plugin_error() {
 #[tokio::test]
 async fn test_fire_hook_skips_unregistered_plugin() {
     // Verify PluginManager doesn't panic with no matching plugins
-    let manager = PluginManager::new(vec![]);
+    let manager = PluginManager::new(vec![]).unwrap();
     manager
         .fire_hook(
             Hook::Server(ServerHook::IpChange),
@@ -901,12 +1337,47 @@ async fn test_fire_hook_skips_unregistered_plugin() {
     // No panic = success
 }
 
+#[tokio::test]
+async fn test_call_hook_with_lifecycle_hook() {
+    let plugin = PluginRuntime::load(&wasm_path("hook-echo"), &[], &[], DEFAULT_HOOK_TIMEOUT)
+        .await
+        .unwrap();
+
+    let event = crowchiper::plugin::HookEvent {
+        hook: Hook::Server(ServerHook::PostCreated),
+        time: 0,
+        target: crowchiper::plugin::HookTarget::Server,
+        values: vec![
+            ("user_uuid".into(), "test-uuid".into()),
+            ("post_uuid".into(), "post-uuid".into()),
+            ("encrypted".into(), "false".into()),
+        ],
+    };
+    let result = plugin.call_hook(&event).await;
+    assert!(
+        result.is_ok(),
+        "hook-echo should accept post-created, got: {result:?}"
+    );
+}
+
+#[tokio::test]
+async fn test_spawn_server_hook_skips_values_without_listener() {
+    let manager = std::sync::Arc::new(PluginManager::new(vec![]).unwrap());
+    // Values must not be built when no plugin is registered for the hook
+    crowchiper::plugin::spawn_server_hook(Some(&manager), ServerHook::UserDeleted, || {
+        panic!("values should not be built without a listener")
+    });
+    crowchiper::plugin::spawn_server_hook(None, ServerHook::UserDeleted, || {
+        panic!("values should not be built without a plugin manager")
+    });
+}
+
 #[tokio::test]
 async fn test_fire_hook_logs_error_but_does_not_panic() {
     let plugin = PluginRuntime::load(&wasm_path("hook-error"), &[], &[], DEFAULT_HOOK_TIMEOUT)
         .await
         .unwrap();
-    let manager = PluginManager::new(vec![plugin]);
+    let manager = PluginManager::new(vec![plugin]).unwrap();
     // Should not panic even though the plugin returns an error
     manager
         .fire_hook(
@@ -1006,6 +1477,31 @@ async fn test_slow_hook_times_out() {
     );
 }
 
+#[tokio::test]
+async fn test_timeout_reload_reuses_compiled_component() {
+    let dir = unique_fs_test_dir();
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("slow-hook.wasm");
+    std::fs::copy(wasm_path("slow-hook"), &path).unwrap();
+
+    let plugin = PluginRuntime::load(&path, &[], &[], Duration::from_millis(50))
+        .await
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let event = ip_change_event(vec![]);
+    plugin.call_hook(&event).await.unwrap_err();
+    // The instance is recreated without the file, so this is a timeout
+    // again rather than a reload failure.
+    let err = plugin.call_hook(&event).await.unwrap_err();
+    assert!(
+        err.to_string().contains("timed out"),
+        "reload should not need the .wasm file, got: {err}"
+    );
+
+    std::fs::remove_dir_all(&dir).ok();
+}
+
 #[test]
 fn test_cli_log_plugin_output_appears_in_server_log() {
     let (stdout, _stderr) = spawn_expect_listening(&[
@@ -1019,3 +1515,949 @@ fn test_cli_log_plugin_output_appears_in_server_log() {
         "server log should contain plugin log output from config(), got: {stdout}"
     );
 }
+
+// ── Plugin metrics and circuit breaker ───────────────────────────────
+
+use crowchiper::plugin::CircuitBreaker;
+
+#[tokio::test]
+async fn test_stats_track_calls_fuel_and_latency_per_hook() {
+    let plugin = PluginRuntime::load(&wasm_path("hook-echo"), &[], &[], DEFAULT_HOOK_TIMEOUT)
+        .await
+        .unwrap();
+    let event = ip_change_event(vec![]);
+    for _ in 0..3 {
+        plugin.call_hook(&event).await.unwrap();
+    }
+
+    let stats = plugin.stats();
+    assert_eq!(stats.calls(), 3);
+    assert_eq!(stats.errors(), 0);
+    assert!(stats.fuel_consumed() > 0, "hook calls should burn fuel");
+
+    let by_call = stats.by_call();
+    let hook = &by_call["Server(IpChange)"];
+    assert_eq!(hook.calls, 3);
+    assert_eq!(hook.fuel_consumed, stats.fuel_consumed());
+    let counted: u64 = hook.latency.buckets().map(|(_, count)| count).sum();
+    assert_eq!(counted, 3);
+}
+
+#[tokio::test]
+async fn test_circuit_breaker_skips_failing_plugin() {
+    let breaker = CircuitBreaker {
+        failures: 2,
+        cooldown: Duration::from_millis(300),
+    };
+    let plugin = PluginRuntime::load(&wasm_path("slow-hook"), &[], &[], Duration::from_millis(50))
+        .await
+        .unwrap()
+        .with_breaker(breaker);
+    let event = ip_change_event(vec![]);
+
+    for _ in 0..2 {
+        let err = plugin.call_hook(&event).await.unwrap_err();
+        assert!(err.to_string().contains("timed out"), "got: {err}");
+    }
+    let start = Instant::now();
+    let err = plugin.call_hook(&event).await.unwrap_err();
+    assert!(
+        matches!(&err, PluginError::Unavailable(_)),
+        "open breaker should skip the plugin, got: {err}"
+    );
+    assert!(start.elapsed() < Duration::from_millis(50));
+
+    let stats = plugin.stats();
+    assert_eq!(stats.timeouts(), 2);
+    assert_eq!(stats.reloads(), 1);
+    assert_eq!(stats.rejected(), 1);
+    assert!(stats.circuit_open_until().is_some());
+    assert_eq!(stats.by_call()["Server(IpChange)"].timeouts, 2);
+
+    // After the cool-down one trial call runs; failing again reopens at once.
+    tokio::time::sleep(Duration::from_millis(350)).await;
+    let err = plugin.call_hook(&event).await.unwrap_err();
+    assert!(err.to_string().contains("timed out"), "got: {err}");
+    let err = plugin.call_hook(&event).await.unwrap_err();
+    assert!(matches!(&err, PluginError::Unavailable(_)), "got: {err}");
+}
+
+#[tokio::test]
+async fn test_enabling_plugin_closes_circuit_breaker() {
+    let plugin = PluginRuntime::load(&wasm_path("slow-hook"), &[], &[], Duration::from_millis(50))
+        .await
+        .unwrap()
+        .with_breaker(CircuitBreaker {
+            failures: 1,
+            cooldown: Duration::from_secs(60),
+        });
+    let name = plugin.name().to_string();
+    let manager = PluginManager::new(vec![plugin]).unwrap();
+    let plugin = manager.get(&name).unwrap();
+
+    plugin
+        .call_hook(&ip_change_event(vec![]))
+        .await
+        .unwrap_err();
+    assert!(plugin.stats().circuit_open_until().is_some());
+
+    manager.set_enabled(&name, true).await.unwrap();
+    assert!(plugin.stats().circuit_open_until().is_none());
+}
+
+// ── Pre-hooks ────────────────────────────────────────────────────────
+
+use crowchiper::plugin::ServerPreHook;
+
+async fn pre_hook_manager(timeout: Duration, fail_closed: &[ServerPreHook]) -> PluginManager {
+    let plugin = PluginRuntime::load(&wasm_path("pre-hook"), &[], &[], timeout)
+        .await
+        .expect("pre-hook plugin should load")
+        .with_fail_closed(fail_closed);
+    PluginManager::new(vec![plugin]).unwrap()
+}
+
+fn username(name: &str) -> Vec<(String, String)> {
+    vec![("username".into(), name.into())]
+}
+
+#[tokio::test]
+async fn test_pre_hook_allows_action() {
+    let manager = pre_hook_manager(DEFAULT_HOOK_TIMEOUT, &[]).await;
+    let result = manager
+        .check_pre_hook(ServerPreHook::PreRegister, username("alice"))
+        .await;
+    assert_eq!(result, Ok(()));
+}
+
+#[tokio::test]
+async fn test_pre_hook_denial_returns_plugin_reason() {
+    let manager = pre_hook_manager(DEFAULT_HOOK_TIMEOUT, &[]).await;
+    let reason = manager
+        .check_pre_hook(ServerPreHook::PreRegister, username("mallory"))
+        .await
+        .unwrap_err();
+    assert_eq!(reason, "username mallory is not allowed");
+}
+
+#[tokio::test]
+async fn test_pre_hook_timeout_fails_open_by_default() {
+    let manager = pre_hook_manager(Duration::from_millis(50), &[]).await;
+    let result = manager
+        .check_pre_hook(ServerPreHook::PreLogin, username("sleepy"))
+        .await;
+    assert_eq!(result, Ok(()), "timeout should allow the action by default");
+}
+
+#[tokio::test]
+async fn test_pre_hook_timeout_fails_closed_when_configured() {
+    let manager = pre_hook_manager(Duration::from_millis(50), &[ServerPreHook::PreLogin]).await;
+    let reason = manager
+        .check_pre_hook(ServerPreHook::PreLogin, username("sleepy"))
+        .await
+        .unwrap_err();
+    assert!(
+        !reason.contains("timed out"),
+        "fail-closed reason should not leak plugin errors, got: {reason}"
+    );
+
+    // Other pre-hooks on the same plugin still fail open
+    let result = manager
+        .check_pre_hook(ServerPreHook::PreRegister, username("sleepy"))
+        .await;
+    assert_eq!(result, Ok(()));
+}
+
+#[tokio::test]
+async fn test_pre_hook_ignores_plugins_not_registered() {
+    // hook-error denies every event, but only registers for ip-change
+    let plugin = PluginRuntime::load(&wasm_path("hook-error"), &[], &[], DEFAULT_HOOK_TIMEOUT)
+        .await
+        .unwrap();
+    let manager = PluginManager::new(vec![plugin]).unwrap();
+    let result = manager
+        .check_pre_hook(ServerPreHook::PreUpload, vec![])
+        .await;
+    assert_eq!(result, Ok(()));
+}
+
+// ── Plugin key-value store ───────────────────────────────────────────
+
+use crowchiper::db::Database;
+use crowchiper::plugin::DEFAULT_KV_QUOTA;
+
+fn ip_change_event(values: Vec<(&str, &str)>) -> crowchiper::plugin::HookEvent {
+    crowchiper::plugin::HookEvent {
+        hook: Hook::Server(ServerHook::IpChange),
+        time: 0,
+        target: crowchiper::plugin::HookTarget::Server,
+        values: values
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+    }
+}
+
+#[tokio::test]
+async fn test_kv_values_persist_in_database() {
+    let db = Database::open(":memory:").await.unwrap();
+    let plugin = PluginRuntime::load(&wasm_path("kv-store"), &[], &[], DEFAULT_HOOK_TIMEOUT)
+        .await
+        .unwrap()
+        .with_kv(db.clone(), DEFAULT_KV_QUOTA);
+
+    let event = ip_change_event(vec![("user_uuid", "u1"), ("new_ip", "1.2.3.4")]);
+    plugin.call_hook(&event).await.unwrap();
+
+    let stored = db
+        .plugin_kv()
+        .get("kv-store", "ip:u1:1.2.3.4")
+        .await
+        .unwrap();
+    assert_eq!(stored, Some(b"seen".to_vec()));
+
+    // Another plugin name sees nothing
+    let other = db.plugin_kv().get("other", "ip:u1:1.2.3.4").await.unwrap();
+    assert_eq!(other, None);
+}
+
+#[tokio::test]
+async fn test_kv_quota_rejects_oversized_write() {
+    let db = Database::open(":memory:").await.unwrap();
+    let plugin = PluginRuntime::load(&wasm_path("kv-store"), &[], &[], DEFAULT_HOOK_TIMEOUT)
+        .await
+        .unwrap()
+        .with_kv(db.clone(), 64);
+
+    let event = ip_change_event(vec![("payload_size", "100")]);
+    let msg = plugin.call_hook(&event).await.unwrap_err().to_string();
+    assert!(msg.contains("quota exceeded"), "got: {msg}");
+    assert_eq!(
+        db.plugin_kv().get("kv-store", "payload").await.unwrap(),
+        None
+    );
+
+    // A write within the quota still succeeds
+    let event = ip_change_event(vec![("payload_size", "16")]);
+    plugin.call_hook(&event).await.unwrap();
+    assert!(
+        db.plugin_kv()
+            .get("kv-store", "payload")
+            .await
+            .unwrap()
+            .is_some()
+    );
+}
+
+#[tokio::test]
+async fn test_kv_unavailable_without_database() {
+    let plugin = PluginRuntime::load(&wasm_path("kv-store"), &[], &[], DEFAULT_HOOK_TIMEOUT)
+        .await
+        .unwrap();
+
+    let event = ip_change_event(vec![("user_uuid", "u1"), ("new_ip", "1.2.3.4")]);
+    let msg = plugin.call_hook(&event).await.unwrap_err().to_string();
+    assert!(msg.contains("not available"), "got: {msg}");
+}
+
+// ── Plugin HTTP import ───────────────────────────────────────────────
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Start a local webhook receiver and return its base URL and hit counter.
+async fn start_webhook_server() -> (String, Arc<AtomicUsize>) {
+    use axum::routing::post;
+
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counter = hits.clone();
+    let app = axum::Router::new()
+        .route(
+            "/hook",
+            post(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                async { "ok" }
+            }),
+        )
+        .route("/big", post(|| async { vec![b'x'; 2 * 1024 * 1024] }))
+        .route(
+            "/redirect",
+            post(|| async { axum::response::Redirect::temporary("/hook") }),
+        );
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    (format!("http://{addr}"), hits)
+}
+
+async fn call_http_plugin(perms: &[PluginPermission], url: &str) -> Result<(), PluginError> {
+    let plugin = PluginRuntime::load(&wasm_path("http-client"), perms, &[], DEFAULT_HOOK_TIMEOUT)
+        .await
+        .expect("http-client plugin should load");
+    plugin.call_hook(&ip_change_event(vec![("url", url)])).await
+}
+
+#[tokio::test]
+async fn test_http_request_to_allowed_host() {
+    let (base, hits) = start_webhook_server().await;
+    let perms = [PluginPermission::Http("127.0.0.1".into())];
+
+    call_http_plugin(&perms, &format!("{base}/hook"))
+        .await
+        .expect("request to allowlisted host should succeed");
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_http_request_to_unlisted_host_rejected() {
+    let (base, hits) = start_webhook_server().await;
+    let perms = [PluginPermission::Http("hooks.example.com".into())];
+
+    let msg = call_http_plugin(&perms, &format!("{base}/hook"))
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(
+        msg.contains("not in the plugin's http allowlist"),
+        "got: {msg}"
+    );
+    assert_eq!(hits.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn test_http_without_permission_rejected() {
+    let (base, hits) = start_webhook_server().await;
+
+    let msg = call_http_plugin(&[], &format!("{base}/hook"))
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(msg.contains("http is not permitted"), "got: {msg}");
+    assert_eq!(hits.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn test_http_response_size_capped() {
+    let (base, _) = start_webhook_server().await;
+    let perms = [PluginPermission::Http("127.0.0.1".into())];
+
+    let msg = call_http_plugin(&perms, &format!("{base}/big"))
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(msg.contains("response body exceeds"), "got: {msg}");
+}
+
+#[tokio::test]
+async fn test_http_redirects_not_followed() {
+    let (base, hits) = start_webhook_server().await;
+    let perms = [PluginPermission::Http("127.0.0.1".into())];
+
+    let msg = call_http_plugin(&perms, &format!("{base}/redirect"))
+        .await
+        .unwrap_err()
+        .to_string();
+    assert!(msg.contains("status 307"), "got: {msg}");
+    assert_eq!(hits.load(Ordering::SeqCst), 0);
+}
+
+// ── Plugin HTTP routes ───────────────────────────────────────────────
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use crowchiper::db::UserRole;
+use crowchiper::{ServerConfig, create_app};
+use tower::ServiceExt;
+
+/// Build an app with the http-routes plugin loaded with the given access level.
+async fn create_plugin_route_app(access: &str) -> (axum::Router, Database) {
+    let db = Database::open(":memory:").await.unwrap();
+    let plugin = PluginRuntime::load(
+        &wasm_path("http-routes"),
+        &[],
+        &[("access".into(), access.into())],
+        Duration::from_millis(500),
+    )
+    .await
+    .expect("http-routes plugin should load")
+    // Low threshold so the circuit breaker test doesn't wait on many timeouts.
+    .with_breaker(CircuitBreaker {
+        failures: 2,
+        cooldown: Duration::from_secs(60),
+    });
+    let good = PluginRuntime::load(&wasm_path("good"), &[], &[], DEFAULT_HOOK_TIMEOUT)
+        .await
+        .unwrap();
+
+    let config = ServerConfig {
+        base: None,
+        db: db.clone(),
+        rp_id: "localhost".to_string(),
+        rp_origin: url::Url::parse("http://localhost").unwrap(),
+        jwt_secret: b"test-jwt-secret".to_vec(),
+        secure_cookies: false,
+        no_signup: false,
+        csp_nonce: false,
+        ip_extractor: None,
+        plugin_manager: Some(Arc::new(PluginManager::new(vec![plugin, good]).unwrap())),
+    };
+    (create_app(&config), db)
+}
+
+/// Create an activated user and return their UUID and access token cookie.
+async fn plugin_route_user(db: &Database, role: UserRole) -> (String, String) {
+    let uuid = "00000000-0000-0000-0000-000000000001";
+    let id = db.users().create(uuid, "alice").await.unwrap();
+    db.users().activate(id).await.unwrap();
+    db.users().set_role(id, role).await.unwrap();
+    let access = crowchiper::jwt::JwtConfig::new(b"test-jwt-secret")
+        .generate_access_token(uuid, "alice", role, "127.0.0.1")
+        .unwrap();
+    (uuid.to_string(), format!("access_token={}", access.token))
+}
+
+async fn plugin_route_request(
+    app: &axum::Router,
+    method: &str,
+    uri: &str,
+    cookie: Option<&str>,
+    body: &str,
+) -> (StatusCode, axum::http::HeaderMap, String) {
+    let mut builder = Request::builder().method(method).uri(uri);
+    if let Some(cookie) = cookie {
+        builder = builder.header("cookie", cookie);
+    }
+    let response = app
+        .clone()
+        .oneshot(builder.body(Body::from(body.to_string())).unwrap())
+        .await
+        .unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (
+        status,
+        headers,
+        String::from_utf8_lossy(&bytes).into_owned(),
+    )
+}
+
+#[tokio::test]
+async fn test_plugin_route_forwards_public_request() {
+    let (app, _db) = create_plugin_route_app("public").await;
+
+    let (status, headers, body) = plugin_route_request(
+        &app,
+        "POST",
+        "/api/plugins/http-routes/hooks/github?x=1",
+        Some("access_token=secret"),
+        "payload",
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body,
+        r#"POST /hooks/github query=Some("x=1") user=None cookie=false body=payload"#
+    );
+    assert_eq!(headers.get("content-type").unwrap(), "text/plain");
+    assert_eq!(headers.get("x-plugin-name").unwrap(), "http-routes");
+    assert!(
+        headers.get("set-cookie").is_none(),
+        "plugins must not set cookies"
+    );
+    assert!(headers.get("access-control-allow-origin").is_none());
+    assert_eq!(headers.get("content-security-policy").unwrap(), "sandbox");
+    assert_eq!(headers.get("x-content-type-options").unwrap(), "nosniff");
+}
+
+#[tokio::test]
+async fn test_plugin_route_root_path() {
+    let (app, _db) = create_plugin_route_app("public").await;
+
+    let (status, _, body) =
+        plugin_route_request(&app, "GET", "/api/plugins/http-routes", None, "").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.starts_with("GET / "), "got: {body}");
+}
+
+#[tokio::test]
+async fn test_plugin_route_user_access_requires_login() {
+    let (app, db) = create_plugin_route_app("user").await;
+
+    let (status, _, _) =
+        plugin_route_request(&app, "GET", "/api/plugins/http-routes/settings", None, "").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (uuid, cookie) = plugin_route_user(&db, UserRole::User).await;
+    let (status, _, body) = plugin_route_request(
+        &app,
+        "GET",
+        "/api/plugins/http-routes/settings",
+        Some(&cookie),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(
+        body.contains(&format!("user=Some(\"{uuid}\")")),
+        "got: {body}"
+    );
+}
+
+#[tokio::test]
+async fn test_plugin_route_admin_access_rejects_regular_user() {
+    let (app, db) = create_plugin_route_app("admin").await;
+    let (_, cookie) = plugin_route_user(&db, UserRole::User).await;
+
+    let (status, _, _) = plugin_route_request(
+        &app,
+        "GET",
+        "/api/plugins/http-routes/settings",
+        Some(&cookie),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_plugin_route_admin_access_allows_admin() {
+    let (app, db) = create_plugin_route_app("admin").await;
+    let (_, cookie) = plugin_route_user(&db, UserRole::Admin).await;
+
+    let (status, _, _) = plugin_route_request(
+        &app,
+        "GET",
+        "/api/plugins/http-routes/settings",
+        Some(&cookie),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_plugin_route_unknown_or_disabled_plugin_not_found() {
+    let (app, _db) = create_plugin_route_app("public").await;
+
+    let (status, _, _) = plugin_route_request(&app, "GET", "/api/plugins/nope/x", None, "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // "good" is loaded but doesn't declare HTTP routes
+    let (status, _, _) = plugin_route_request(&app, "GET", "/api/plugins/good/x", None, "").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_plugin_route_errors_and_timeouts_return_500() {
+    let (app, _db) = create_plugin_route_app("public").await;
+
+    let (status, _, body) =
+        plugin_route_request(&app, "GET", "/api/plugins/http-routes/fail", None, "").await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(
+        !body.contains("handler failed"),
+        "plugin errors must not leak"
+    );
+
+    let (status, _, _) =
+        plugin_route_request(&app, "GET", "/api/plugins/http-routes/slow", None, "").await;
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+
+    // The plugin reloads after the timeout and keeps serving
+    let (status, _, _) =
+        plugin_route_request(&app, "GET", "/api/plugins/http-routes/ok", None, "").await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_plugin_route_open_circuit_returns_503() {
+    let (app, _db) = create_plugin_route_app("public").await;
+
+    for _ in 0..2 {
+        let (status, _, _) =
+            plugin_route_request(&app, "GET", "/api/plugins/http-routes/slow", None, "").await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    let (status, _, _) =
+        plugin_route_request(&app, "GET", "/api/plugins/http-routes/ok", None, "").await;
+    assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[tokio::test]
+async fn test_plugin_route_body_limit() {
+    let (app, _db) = create_plugin_route_app("public").await;
+    let big = "x".repeat(2 * 1024 * 1024);
+
+    let (status, _, _) =
+        plugin_route_request(&app, "POST", "/api/plugins/http-routes/upload", None, &big).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+// ── Scheduled hooks ──────────────────────────────────────────────────
+
+use crowchiper::plugin::{parse_plugin_spec, spawn_plugin_scheduler};
+
+#[tokio::test]
+async fn test_scheduled_interval_hook_runs() {
+    let db = Database::open(":memory:").await.unwrap();
+    let plugin = PluginRuntime::load(&wasm_path("scheduled"), &[], &[], DEFAULT_HOOK_TIMEOUT)
+        .await
+        .unwrap()
+        .with_kv(db.clone(), DEFAULT_KV_QUOTA);
+    let manager = PluginManager::new(vec![plugin]).unwrap();
+
+    let scheduler = spawn_plugin_scheduler(&manager);
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    scheduler.abort();
+
+    let runs = db.plugin_kv().get("scheduled", "runs").await.unwrap();
+    let runs: u64 = String::from_utf8(runs.expect("schedule never ran"))
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(runs >= 1, "expected at least one run, got {runs}");
+}
+
+#[tokio::test]
+async fn test_scheduled_cron_hook_is_registered() {
+    let db = Database::open(":memory:").await.unwrap();
+    let vars = [("cron".to_string(), "*/5 * * * 1-5".to_string())];
+    let plugin = PluginRuntime::load(&wasm_path("scheduled"), &[], &vars, DEFAULT_HOOK_TIMEOUT)
+        .await
+        .unwrap()
+        .with_kv(db, DEFAULT_KV_QUOTA);
+    assert_eq!(plugin.hooks().len(), 2);
+    let manager = PluginManager::new(vec![plugin]).unwrap();
+
+    let scheduler = spawn_plugin_scheduler(&manager);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    scheduler.abort();
+
+    let next_runs = manager.get("scheduled").unwrap().stats().next_runs();
+    assert_eq!(next_runs.len(), 2, "got: {next_runs:?}");
+    let (_, &cron_due) = next_runs
+        .iter()
+        .find(|(hook, _)| hook.contains("Cron"))
+        .expect("cron hook was not scheduled");
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    assert!(cron_due > now, "cron run must be in the future");
+    assert_eq!(cron_due % 300, 0, "cron run must be on a 5 minute mark");
+    // Weekdays only: 1970-01-01 was a Thursday
+    let weekday = (cron_due / 86_400 + 4) % 7;
+    assert!((1..=5).contains(&weekday), "got weekday {weekday}");
+}
+
+#[tokio::test]
+async fn test_scheduled_invalid_cron_rejected_at_load() {
+    let vars = [("cron".to_string(), "61 * * * *".to_string())];
+    let err = PluginRuntime::load(&wasm_path("scheduled"), &[], &vars, DEFAULT_HOOK_TIMEOUT)
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(&err, PluginError::InvalidConfig(_)),
+        "invalid cron should cause an InvalidConfig error, got: {err}"
+    );
+    assert!(err.to_string().contains("minute"), "got: {err}");
+}
+
+#[tokio::test]
+async fn test_scheduled_hook_skipped_while_disabled() {
+    let db = Database::open(":memory:").await.unwrap();
+    let plugin = PluginRuntime::load(&wasm_path("scheduled"), &[], &[], DEFAULT_HOOK_TIMEOUT)
+        .await
+        .unwrap()
+        .with_kv(db.clone(), DEFAULT_KV_QUOTA);
+    let manager = PluginManager::new(vec![plugin]).unwrap();
+    manager.set_enabled("scheduled", false).await.unwrap();
+
+    let scheduler = spawn_plugin_scheduler(&manager);
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    scheduler.abort();
+
+    let runs = db.plugin_kv().get("scheduled", "runs").await.unwrap();
+    assert_eq!(runs, None, "disabled plugin should not run");
+}
+
+// ── Runtime plugin management ────────────────────────────────────────
+
+#[tokio::test]
+async fn test_disable_and_enable_plugin() {
+    let plugin = PluginRuntime::load(&wasm_path("good"), &[], &[], DEFAULT_HOOK_TIMEOUT)
+        .await
+        .unwrap();
+    let manager = PluginManager::new(vec![plugin]).unwrap();
+    let hook = Hook::Server(ServerHook::IpChange);
+
+    manager.set_enabled("good", false).await.unwrap();
+    assert!(!manager.has_hook(&hook));
+    assert!(manager.get("good").is_none());
+    let list = manager.list();
+    assert_eq!(list.len(), 1, "disabled plugins stay loaded");
+    assert!(!list[0].enabled);
+
+    manager.set_enabled("good", true).await.unwrap();
+    assert!(manager.has_hook(&hook));
+    assert!(manager.get("good").is_some());
+
+    let err = manager.set_enabled("missing", false).await.unwrap_err();
+    assert!(matches!(err, PluginError::NotFound(_)), "got: {err}");
+}
+
+#[tokio::test]
+async fn test_reload_swaps_runtime_and_keeps_old_one_usable() {
+    let plugin = PluginRuntime::load(&wasm_path("good"), &[], &[], DEFAULT_HOOK_TIMEOUT)
+        .await
+        .unwrap();
+    let manager = PluginManager::new(vec![plugin]).unwrap();
+
+    let before = manager.get("good").unwrap();
+    let reloaded = manager.reload("good").await.unwrap();
+    let after = manager.get("good").unwrap();
+    assert!(Arc::ptr_eq(&reloaded, &after));
+    assert!(!Arc::ptr_eq(&before, &after));
+
+    // A caller still holding the old runtime (an in-flight call) can finish.
+    before
+        .call_hook(&ip_change_event(vec![("new_ip", "1.2.3.4")]))
+        .await
+        .unwrap();
+    assert_eq!(before.stats().calls(), 1);
+    assert_eq!(after.stats().calls(), 0);
+
+    let err = manager.reload("missing").await.unwrap_err();
+    assert!(matches!(err, PluginError::NotFound(_)), "got: {err}");
+}
+
+#[tokio::test]
+async fn test_reload_keeps_disabled_state() {
+    let plugin = PluginRuntime::load(&wasm_path("good"), &[], &[], DEFAULT_HOOK_TIMEOUT)
+        .await
+        .unwrap();
+    let manager = PluginManager::new(vec![plugin]).unwrap();
+    manager.set_enabled("good", false).await.unwrap();
+
+    manager.reload("good").await.unwrap();
+    assert!(manager.get("good").is_none());
+    assert!(!manager.list()[0].enabled);
+}
+
+#[tokio::test]
+async fn test_load_plugin_from_plugin_dir() {
+    let db = Database::open(":memory:").await.unwrap();
+    let manager = PluginManager::new(vec![])
+        .unwrap()
+        .with_plugin_dir(wasm_dir());
+
+    let spec = parse_plugin_spec("good.wasm").unwrap();
+    let plugin = manager.load(&spec, db.clone()).await.unwrap();
+    assert_eq!(plugin.name(), "good");
+    assert!(manager.has_hook(&Hook::Server(ServerHook::IpChange)));
+
+    let err = manager.load(&spec, db.clone()).await.unwrap_err();
+    assert!(matches!(err, PluginError::AlreadyLoaded(_)), "got: {err}");
+}
+
+#[tokio::test]
+async fn test_plugin_manager_rejects_duplicate_names() {
+    let load = || PluginRuntime::load(&wasm_path("good"), &[], &[], DEFAULT_HOOK_TIMEOUT);
+    let plugins = vec![load().await.unwrap(), load().await.unwrap()];
+
+    let err = PluginManager::new(plugins).err().unwrap();
+    assert!(matches!(err, PluginError::AlreadyLoaded(_)), "got: {err}");
+}
+
+#[tokio::test]
+async fn test_load_plugin_outside_plugin_dir_rejected() {
+    let db = Database::open(":memory:").await.unwrap();
+    let manager = PluginManager::new(vec![])
+        .unwrap()
+        .with_plugin_dir(wasm_dir().join("..").join("examples"));
+
+    for spec in ["../wasm/good.wasm", wasm_path("good").to_str().unwrap()] {
+        let spec = parse_plugin_spec(spec).unwrap();
+        let err = manager.load(&spec, db.clone()).await.unwrap_err();
+        assert!(
+            err.to_string().contains("outside the plugin directory"),
+            "got: {err}"
+        );
+    }
+    assert!(manager.list().is_empty());
+}
+
+#[tokio::test]
+async fn test_load_plugin_without_plugin_dir_rejected() {
+    let db = Database::open(":memory:").await.unwrap();
+    let manager = PluginManager::new(vec![]).unwrap();
+    let spec = parse_plugin_spec("good.wasm").unwrap();
+    let err = manager.load(&spec, db).await.unwrap_err();
+    assert!(
+        err.to_string().contains("no plugin directory"),
+        "got: {err}"
+    );
+}
+
+/// Build an app with the good plugin loaded and `tests/plugins/wasm` as plugin dir.
+async fn create_plugin_admin_app() -> (axum::Router, Database) {
+    let db = Database::open(":memory:").await.unwrap();
+    let good = PluginRuntime::load(&wasm_path("good"), &[], &[], DEFAULT_HOOK_TIMEOUT)
+        .await
+        .unwrap();
+    let config = ServerConfig {
+        base: None,
+        db: db.clone(),
+        rp_id: "localhost".to_string(),
+        rp_origin: url::Url::parse("http://localhost").unwrap(),
+        jwt_secret: b"test-jwt-secret".to_vec(),
+        secure_cookies: false,
+        no_signup: false,
+        csp_nonce: false,
+        ip_extractor: None,
+        plugin_manager: Some(Arc::new(
+            PluginManager::new(vec![good])
+                .unwrap()
+                .with_plugin_dir(wasm_dir()),
+        )),
+    };
+    (create_app(&config), db)
+}
+
+#[tokio::test]
+async fn test_admin_plugin_endpoints_require_admin() {
+    let (app, db) = create_plugin_admin_app().await;
+    let (_, cookie) = plugin_route_user(&db, UserRole::User).await;
+
+    let (status, _, _) = plugin_route_request(&app, "GET", "/api/admin/plugins", None, "").await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _, _) = plugin_route_request(
+        &app,
+        "POST",
+        "/api/admin/plugins/good/disable",
+        Some(&cookie),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn test_admin_plugin_list_disable_and_reload() {
+    let (app, db) = create_plugin_admin_app().await;
+    let (_, cookie) = plugin_route_user(&db, UserRole::Admin).await;
+
+    let (status, _, body) =
+        plugin_route_request(&app, "GET", "/api/admin/plugins", Some(&cookie), "").await;
+    assert_eq!(status, StatusCode::OK);
+    let list: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(list[0]["name"], "good");
+    assert_eq!(list[0]["enabled"], true);
+    assert_eq!(list[0]["calls"], 0);
+    assert!(list[0]["last_call_at"].is_null());
+    assert_eq!(list[0]["breaker"], "5/60");
+    assert!(list[0]["circuit_open_until"].is_null());
+    assert_eq!(list[0]["calls_by_hook"], serde_json::json!({}));
+
+    let (status, _, body) = plugin_route_request(
+        &app,
+        "POST",
+        "/api/admin/plugins/good/disable",
+        Some(&cookie),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let info: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(info["enabled"], false);
+
+    let (status, _, body) = plugin_route_request(
+        &app,
+        "POST",
+        "/api/admin/plugins/good/reload",
+        Some(&cookie),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let info: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(info["enabled"], false);
+
+    let (status, _, _) = plugin_route_request(
+        &app,
+        "POST",
+        "/api/admin/plugins/nope/enable",
+        Some(&cookie),
+        "",
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+/// Send a JSON POST as the given cookie.
+async fn plugin_admin_post(
+    app: &axum::Router,
+    uri: &str,
+    cookie: &str,
+    body: serde_json::Value,
+) -> (StatusCode, String) {
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("cookie", cookie)
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[tokio::test]
+async fn test_admin_load_plugin() {
+    let (app, db) = create_plugin_admin_app().await;
+    let (_, cookie) = plugin_route_user(&db, UserRole::Admin).await;
+
+    let (status, body) = plugin_admin_post(
+        &app,
+        "/api/admin/plugins",
+        &cookie,
+        serde_json::json!({ "spec": "hook-echo.wasm:timeout=2" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED, "body: {body}");
+    let info: serde_json::Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(info["name"], "hook-echo");
+
+    let (status, _) = plugin_admin_post(
+        &app,
+        "/api/admin/plugins",
+        &cookie,
+        serde_json::json!({ "spec": "good.wasm" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (status, _) = plugin_admin_post(
+        &app,
+        "/api/admin/plugins",
+        &cookie,
+        serde_json::json!({ "spec": "../Cargo.toml" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
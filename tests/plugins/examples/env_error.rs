@@ -13,12 +13,17 @@ impl Guest for EnvErrorPlugin {
             version: "0.1.0".to_string(),
             target: HookTarget::Server,
             hooks: vec![],
+            http: None,
         }
     }
 
     fn on_hook(_event: HookEvent) -> Result<(), String> {
         Ok(())
     }
+
+    fn http_handler(_req: HttpRequest) -> Result<HttpResponse, String> {
+        Err("no http routes".to_string())
+    }
 }
 
 export!(EnvErrorPlugin);
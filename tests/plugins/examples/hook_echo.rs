@@ -1,7 +1,7 @@
-wit_bindgen::generate!({
-    world: "plugin",
-    path: "../../wit/plugin.wit",
-});
+// Built on the plugin SDK rather than raw bindings, so the tests cover the
+// SDK's `export_plugin!` path too.
+
+use crowchiper_plugin_sdk::*;
 
 struct HookEchoPlugin;
 
@@ -12,19 +12,21 @@ impl Guest for HookEchoPlugin {
             version: "1.0.0".to_string(),
             target: HookTarget::Server,
             hooks: vec![Hook::Server(ServerHook::IpChange)],
+            http: None,
         }
     }
 
     fn on_hook(event: HookEvent) -> Result<(), String> {
-        log(
-            LogLevel::Info,
-            &format!("hook={:?} time={}", event.hook, event.time),
-        );
+        info!("hook={:?} time={}", event.hook, event.time);
         for (key, value) in &event.values {
-            log(LogLevel::Info, &format!("  {key}={value}"));
+            info!("  {key}={value}");
         }
         Ok(())
     }
+
+    fn http_handler(_req: HttpRequest) -> Result<HttpResponse, String> {
+        Err("no http routes".to_string())
+    }
 }
 
-export!(HookEchoPlugin);
+export_plugin!(HookEchoPlugin);
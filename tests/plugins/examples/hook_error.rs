@@ -12,12 +12,17 @@ impl Guest for HookErrorPlugin {
             version: "1.0.0".to_string(),
             target: HookTarget::Server,
             hooks: vec![Hook::Server(ServerHook::IpChange)],
+            http: None,
         }
     }
 
     fn on_hook(_event: HookEvent) -> Result<(), String> {
         Err("test hook error".to_string())
     }
+
+    fn http_handler(_req: HttpRequest) -> Result<HttpResponse, String> {
+        Err("no http routes".to_string())
+    }
 }
 
 export!(HookErrorPlugin);
@@ -0,0 +1,52 @@
+wit_bindgen::generate!({
+    world: "plugin",
+    path: "../../wit/plugin.wit",
+});
+
+use crowchiper::plugin::http;
+
+struct HttpClientPlugin;
+
+impl Guest for HttpClientPlugin {
+    fn config(_config: Vec<(String, String)>) -> PluginConfig {
+        PluginConfig {
+            name: "http-client".to_string(),
+            version: "1.0.0".to_string(),
+            target: HookTarget::Server,
+            hooks: vec![Hook::Server(ServerHook::IpChange)],
+            http: None,
+        }
+    }
+
+    fn on_hook(event: HookEvent) -> Result<(), String> {
+        let url = event
+            .values
+            .iter()
+            .find(|(k, _)| k == "url")
+            .map(|(_, v)| v.clone())
+            .ok_or("missing url")?;
+
+        // POST the event to the given URL like a webhook notifier would.
+        let response = http::send(&http::Request {
+            method: "POST".to_string(),
+            url,
+            headers: vec![("content-type".to_string(), "text/plain".to_string())],
+            body: b"ip-change".to_vec(),
+        })?;
+
+        log(
+            LogLevel::Info,
+            &format!("status={} bytes={}", response.status, response.body.len()),
+        );
+        if !(200..300).contains(&response.status) {
+            return Err(format!("status {}", response.status));
+        }
+        Ok(())
+    }
+
+    fn http_handler(_req: HttpRequest) -> Result<HttpResponse, String> {
+        Err("no http routes".to_string())
+    }
+}
+
+export!(HttpClientPlugin);
@@ -0,0 +1,60 @@
+wit_bindgen::generate!({
+    world: "plugin",
+    path: "../../wit/plugin.wit",
+});
+
+struct HttpRoutesPlugin;
+
+impl Guest for HttpRoutesPlugin {
+    fn config(config: Vec<(String, String)>) -> PluginConfig {
+        let access = match config.iter().find(|(k, _)| k == "access").map(|(_, v)| v.as_str()) {
+            Some("user") => HttpAccess::User,
+            Some("admin") => HttpAccess::Admin,
+            _ => HttpAccess::Public,
+        };
+        PluginConfig {
+            name: "http-routes".to_string(),
+            version: "1.0.0".to_string(),
+            target: HookTarget::Server,
+            hooks: vec![],
+            http: Some(access),
+        }
+    }
+
+    fn on_hook(_event: HookEvent) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn http_handler(req: HttpRequest) -> Result<HttpResponse, String> {
+        match req.path.as_str() {
+            "/slow" => std::thread::sleep(std::time::Duration::from_secs(60)),
+            "/fail" => return Err("handler failed".to_string()),
+            _ => {}
+        }
+
+        // Echo what the host forwarded so tests can check it.
+        let has_cookie = req.headers.iter().any(|(k, _)| k == "cookie");
+        let body = format!(
+            "{} {} query={:?} user={:?} cookie={} body={}",
+            req.method,
+            req.path,
+            req.query,
+            req.user_uuid,
+            has_cookie,
+            String::from_utf8_lossy(&req.body),
+        );
+        Ok(HttpResponse {
+            status: 200,
+            headers: vec![
+                ("content-type".to_string(), "text/plain".to_string()),
+                ("x-plugin-name".to_string(), "http-routes".to_string()),
+                ("set-cookie".to_string(), "access_token=evil".to_string()),
+                ("content-security-policy".to_string(), "default-src *".to_string()),
+                ("access-control-allow-origin".to_string(), "*".to_string()),
+            ],
+            body: body.into_bytes(),
+        })
+    }
+}
+
+export!(HttpRoutesPlugin);
@@ -0,0 +1,50 @@
+wit_bindgen::generate!({
+    world: "plugin",
+    path: "../../wit/plugin.wit",
+});
+
+use crowchiper::plugin::kv;
+
+struct KvStorePlugin;
+
+impl Guest for KvStorePlugin {
+    fn config(_config: Vec<(String, String)>) -> PluginConfig {
+        PluginConfig {
+            name: "kv-store".to_string(),
+            version: "1.0.0".to_string(),
+            target: HookTarget::Server,
+            hooks: vec![Hook::Server(ServerHook::IpChange)],
+            http: None,
+        }
+    }
+
+    fn on_hook(event: HookEvent) -> Result<(), String> {
+        let value = |key: &str| {
+            event
+                .values
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str())
+        };
+
+        // Remember every IP seen per user and report how many are known.
+        if let (Some(user), Some(ip)) = (value("user_uuid"), value("new_ip")) {
+            kv::set(&format!("ip:{user}:{ip}"), b"seen")?;
+            let known = kv::list_prefix(&format!("ip:{user}:"))?;
+            log(LogLevel::Info, &format!("known_ips={}", known.len()));
+        }
+
+        // Write a blob of the requested size to exercise the quota.
+        if let Some(size) = value("payload_size") {
+            let size: usize = size.parse().map_err(|_| "bad payload_size")?;
+            kv::set("payload", &vec![0u8; size])?;
+        }
+        Ok(())
+    }
+
+    fn http_handler(_req: HttpRequest) -> Result<HttpResponse, String> {
+        Err("no http routes".to_string())
+    }
+}
+
+export!(KvStorePlugin);
@@ -13,6 +13,7 @@ impl Guest for LogTestPlugin {
             version: "1.0.0".to_string(),
             target: HookTarget::Server,
             hooks: vec![Hook::Server(ServerHook::IpChange)],
+            http: None,
         }
     }
 
@@ -23,6 +24,10 @@ impl Guest for LogTestPlugin {
         log(LogLevel::Error, "error message");
         Ok(())
     }
+
+    fn http_handler(_req: HttpRequest) -> Result<HttpResponse, String> {
+        Err("no http routes".to_string())
+    }
 }
 
 export!(LogTestPlugin);
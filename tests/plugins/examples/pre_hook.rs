@@ -0,0 +1,49 @@
+wit_bindgen::generate!({
+    world: "plugin",
+    path: "../../wit/plugin.wit",
+});
+
+struct PreHookPlugin;
+
+impl Guest for PreHookPlugin {
+    fn config(_config: Vec<(String, String)>) -> PluginConfig {
+        PluginConfig {
+            name: "pre-hook".to_string(),
+            version: "1.0.0".to_string(),
+            target: HookTarget::Server,
+            hooks: vec![
+                Hook::ServerPre(ServerPreHook::PreLogin),
+                Hook::ServerPre(ServerPreHook::PreRegister),
+                Hook::ServerPre(ServerPreHook::PreUpload),
+                Hook::ServerPre(ServerPreHook::PreTokenRefresh),
+            ],
+            http: None,
+        }
+    }
+
+    fn on_hook(event: HookEvent) -> Result<(), String> {
+        let value = |key: &str| {
+            event
+                .values
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, v)| v.as_str())
+        };
+
+        // Hang so the host's timeout policy decides the outcome.
+        if value("username") == Some("sleepy") {
+            std::thread::sleep(std::time::Duration::from_secs(60));
+        }
+
+        if value("username") == Some("mallory") {
+            return Err("username mallory is not allowed".to_string());
+        }
+        Ok(())
+    }
+
+    fn http_handler(_req: HttpRequest) -> Result<HttpResponse, String> {
+        Err("no http routes".to_string())
+    }
+}
+
+export!(PreHookPlugin);
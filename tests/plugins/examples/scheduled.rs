@@ -0,0 +1,42 @@
+wit_bindgen::generate!({
+    world: "plugin",
+    path: "../../wit/plugin.wit",
+});
+
+use crowchiper::plugin::kv;
+
+struct ScheduledPlugin;
+
+impl Guest for ScheduledPlugin {
+    fn config(config: Vec<(String, String)>) -> PluginConfig {
+        let mut hooks = vec![Hook::Schedule(Schedule::Interval(1))];
+        if let Some((_, cron)) = config.iter().find(|(k, _)| k == "cron") {
+            hooks.push(Hook::Schedule(Schedule::Cron(cron.clone())));
+        }
+        PluginConfig {
+            name: "scheduled".to_string(),
+            version: "1.0.0".to_string(),
+            target: HookTarget::Schedule,
+            hooks,
+            http: None,
+        }
+    }
+
+    fn on_hook(event: HookEvent) -> Result<(), String> {
+        // Count interval runs so the host can check the schedule fired.
+        if let Hook::Schedule(Schedule::Interval(_)) = event.hook {
+            let runs = kv::get("runs")?
+                .and_then(|v| String::from_utf8(v).ok())
+                .and_then(|v| v.parse::<u64>().ok())
+                .unwrap_or(0);
+            kv::set("runs", (runs + 1).to_string().as_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn http_handler(_req: HttpRequest) -> Result<HttpResponse, String> {
+        Err("no http routes".to_string())
+    }
+}
+
+export!(ScheduledPlugin);
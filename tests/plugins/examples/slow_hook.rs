@@ -12,6 +12,7 @@ impl Guest for SlowHookPlugin {
             version: "1.0.0".to_string(),
             target: HookTarget::Server,
             hooks: vec![Hook::Server(ServerHook::IpChange)],
+            http: None,
         }
     }
 
@@ -21,6 +22,10 @@ impl Guest for SlowHookPlugin {
         std::thread::sleep(std::time::Duration::from_secs(60));
         Ok(())
     }
+
+    fn http_handler(_req: HttpRequest) -> Result<HttpResponse, String> {
+        Err("no http routes".to_string())
+    }
 }
 
 export!(SlowHookPlugin);
@@ -18,6 +18,10 @@ impl Guest for StackOverflowPlugin {
     fn on_hook(_event: HookEvent) -> Result<(), String> {
         Ok(())
     }
+
+    fn http_handler(_req: HttpRequest) -> Result<HttpResponse, String> {
+        Err("no http routes".to_string())
+    }
 }
 
 export!(StackOverflowPlugin);
@@ -1,3 +1,3 @@
 // Shared bindings for all test plugins.
 // Each example generates its own bindings since cdylib examples
-// are independent compilation units.
+// are independent compilation units; hook-echo uses the plugin SDK's.
@@ -1105,3 +1105,298 @@ async fn test_list_posts_includes_position() {
     assert_eq!(posts.len(), 1);
     assert_eq!(posts[0]["position"], 0);
 }
+
+#[tokio::test]
+async fn test_update_post_with_stale_revision_conflicts() {
+    let (app, db, jwt) = create_test_app().await;
+    let (user_id, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
+
+    let post_uuid = db
+        .posts()
+        .create(
+            user_id,
+            Some("Original"),
+            false,
+            None,
+            "Original content",
+            false,
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+    // First tab saves based on revision 1
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("PUT")
+                .uri(format!("/api/posts/{}", post_uuid))
+                .header("content-type", "application/json")
+                .header("cookie", auth_cookies(&access, &refresh))
+                .header("x-forwarded-for", TEST_IP)
+                .body(Body::from(
+                    r#"{"content": "First tab", "expected_revision": 1}"#,
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(response.headers()["etag"], "\"2\"");
+
+    // Second tab still thinks it is editing revision 1
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("PUT")
+                .uri(format!("/api/posts/{}", post_uuid))
+                .header("content-type", "application/json")
+                .header("cookie", auth_cookies(&access, &refresh))
+                .header("x-forwarded-for", TEST_IP)
+                .body(Body::from(
+                    r#"{"content": "Second tab", "expected_revision": 1}"#,
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["current"]["revision"], 2);
+    assert_eq!(json["current"]["content"], "First tab");
+
+    let post = db
+        .posts()
+        .get_by_uuid(&post_uuid, user_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(post.content, "First tab");
+}
+
+#[tokio::test]
+async fn test_update_post_if_match_header() {
+    let (app, db, jwt) = create_test_app().await;
+    let (user_id, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
+
+    let post_uuid = db
+        .posts()
+        .create(
+            user_id,
+            Some("Original"),
+            false,
+            None,
+            "Original content",
+            false,
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("GET")
+                .uri(format!("/api/posts/{}", post_uuid))
+                .header("cookie", auth_cookies(&access, &refresh))
+                .header("x-forwarded-for", TEST_IP)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response.headers()["etag"].to_str().unwrap().to_string();
+    assert_eq!(etag, "\"1\"");
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("PUT")
+                .uri(format!("/api/posts/{}", post_uuid))
+                .header("content-type", "application/json")
+                .header("cookie", auth_cookies(&access, &refresh))
+                .header("x-forwarded-for", TEST_IP)
+                .header("if-match", &etag)
+                .body(Body::from(r#"{"content": "Updated"}"#))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    // Reusing the old ETag is now stale
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("PUT")
+                .uri(format!("/api/posts/{}", post_uuid))
+                .header("content-type", "application/json")
+                .header("cookie", auth_cookies(&access, &refresh))
+                .header("x-forwarded-for", TEST_IP)
+                .header("if-match", &etag)
+                .body(Body::from(r#"{"content": "Overwrite"}"#))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn test_sync_returns_changes_since_cursor() {
+    let (app, db, jwt) = create_test_app().await;
+    let (user_id, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
+
+    let sync = |since: i64| {
+        let app = app.clone();
+        let cookies = auth_cookies(&access, &refresh);
+        async move {
+            let response = app
+                .oneshot(
+                    Request::builder()
+                        .method("GET")
+                        .uri(format!("/api/sync?since={}", since))
+                        .header("cookie", cookies)
+                        .header("x-forwarded-for", TEST_IP)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap()
+        }
+    };
+
+    let kept = db
+        .posts()
+        .create(
+            user_id,
+            Some("Kept"),
+            false,
+            None,
+            "",
+            false,
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    let removed = db
+        .posts()
+        .create(
+            user_id,
+            Some("Removed"),
+            false,
+            None,
+            "",
+            false,
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let json = sync(0).await;
+    assert_eq!(json["changes"].as_array().unwrap().len(), 2);
+    assert_eq!(json["has_more"], false);
+    assert_eq!(json["reset"], false);
+    let cursor = json["cursor"].as_i64().unwrap();
+
+    // Nothing changed since the cursor
+    let json = sync(cursor).await;
+    assert!(json["changes"].as_array().unwrap().is_empty());
+    assert_eq!(json["cursor"].as_i64().unwrap(), cursor);
+
+    db.delete_post_with_attachments(&removed, user_id)
+        .await
+        .unwrap();
+    db.posts()
+        .update(
+            &kept,
+            user_id,
+            Some("Renamed"),
+            false,
+            None,
+            "",
+            false,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let json = sync(cursor).await;
+    let changes = json["changes"].as_array().unwrap();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(changes[0]["uuid"], kept.as_str());
+    assert_eq!(changes[0]["title"], "Renamed");
+    assert_eq!(changes[0]["revision"], 2);
+    assert!(changes[0].get("content").is_none());
+    assert_eq!(json["deleted"], serde_json::json!([removed]));
+}
+
+#[tokio::test]
+async fn test_sync_unknown_cursor_resets() {
+    let (app, db, jwt) = create_test_app().await;
+    let (user_id, access, refresh) = create_authenticated_user(&db, &jwt, "alice").await;
+
+    db.posts()
+        .create(
+            user_id,
+            Some("Post"),
+            false,
+            None,
+            "",
+            false,
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("GET")
+                .uri("/api/sync?since=9999")
+                .header("cookie", auth_cookies(&access, &refresh))
+                .header("x-forwarded-for", TEST_IP)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+    assert_eq!(json["reset"], true);
+    assert_eq!(json["changes"].as_array().unwrap().len(), 1);
+}
@@ -88,6 +88,8 @@ export type SetupResponse = v.InferOutput<typeof SetupResponseSchema>;
 
 export const UploadAttachmentResponseSchema = v.object({
   uuid: v.string(),
+  /** An existing unencrypted attachment with the same image was returned */
+  reused: v.boolean(),
 });
 
 export type UploadAttachmentResponse = v.InferOutput<